@@ -0,0 +1,422 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module containing the configuration of the CLI and where each of its
+//! values comes from.
+//!
+//! The configuration is built from layers, each one overriding the values
+//! set on the ones before it:
+//!
+//! 1. The defaults.
+//! 2. `config.toml` on the user config directory, that is
+//!    `$XDG_CONFIG_HOME/customer_locator/` or `~/.config/customer_locator/`.
+//! 3. `customer_locator.toml` on the current (project) directory.
+//! 4. `CUSTOMER_LOCATOR_*` environment variables, like `CUSTOMER_LOCATOR_RADIUS`.
+//! 5. The CLI flags.
+//!
+//! # Examples
+//!
+//! A config file sets any of the keys in `KEYS`:
+//!
+//! ```toml
+//! file = "/srv/exports/customers.json"
+//! radius = 50
+//! unit = "mi"
+//! location = "office:cork"
+//! places = "/srv/config/offices.csv"
+//! ```
+//!
+
+use std::collections::BTreeMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use toml;
+
+use customer_datasource::InputFormat;
+use customer_output::OutputFormat;
+use location::DistanceModel;
+use units::DistanceUnit;
+
+/// The keys that can be configured, as written on config files.
+pub const KEYS: &[&str] = &["file", "format", "radius", "unit", "location", "places", "output", "distance_model"];
+
+const DEFAULT_FILE: &str = "data/customers.json";
+const DEFAULT_RADIUS: f64 = 100f64;
+const DEFAULT_LOCATION: &str = "dublin";
+
+const PROJECT_CONFIG_FILE: &str = "customer_locator.toml";
+const USER_CONFIG_DIR: &str = "customer_locator";
+const USER_CONFIG_FILE: &str = "config.toml";
+const ENVIRONMENT_PREFIX: &str = "CUSTOMER_LOCATOR_";
+
+/// Where the value of a configuration key comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    Default,
+    File(PathBuf),
+    Environment,
+    Flags,
+}
+
+impl ConfigSource {
+    // Describes the source of `key`, naming the variable or flag setting it.
+    fn describe(&self, key: &str) -> String {
+        match *self {
+            ConfigSource::Default => String::from("default"),
+            ConfigSource::File(ref path) => path.display().to_string(),
+            ConfigSource::Environment => format!("{}{}", ENVIRONMENT_PREFIX, key.to_uppercase()),
+            ConfigSource::Flags => format!("--{}", key.replace('_', "-")),
+        }
+    }
+}
+
+/// An error encapsulating the things that can go wrong when loading the
+/// configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, IoError),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref path, ref err) => write!(f, "Config file IO error on {}: {}", path.display(), err),
+            ConfigError::Invalid(ref reason) => write!(f, "Config error: {}", reason),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ConfigError::Io(_, ref err) => Some(err),
+            ConfigError::Invalid(_) => None,
+        }
+    }
+}
+
+/// The values a single source sets, `None` for the ones it leaves alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigLayer {
+    pub file: Option<PathBuf>,
+    pub format: Option<InputFormat>,
+    pub radius: Option<f64>,
+    pub unit: Option<DistanceUnit>,
+    pub location: Option<String>,
+    // an empty path clears the places file set on an earlier layer.
+    pub places: Option<PathBuf>,
+    pub output: Option<OutputFormat>,
+    pub distance_model: Option<DistanceModel>,
+}
+
+impl ConfigLayer {
+    /// Parses `value` and sets it on `key`.
+    ///
+    /// # Errors
+    ///
+    /// When `key` is not one of `KEYS` or `value` is not valid for it.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fn parse<T: FromStr>(value: &str) -> Result<Option<T>, String>
+            where T::Err: fmt::Display
+        {
+            T::from_str(value).map(Some).map_err(|err| err.to_string())
+        }
+
+        match key {
+            "file" => self.file = Some(PathBuf::from(value)),
+            "format" => self.format = parse(value)?,
+            "radius" => {
+                let radius = f64::from_str(value.trim()).map_err(|err| err.to_string())?;
+                if !(radius >= 0f64 && radius.is_finite()) {
+                    return Err(String::from("the radius must be a non-negative number"));
+                }
+                self.radius = Some(radius);
+            }
+            "unit" => self.unit = parse(value)?,
+            "location" => self.location = Some(value.to_string()),
+            "places" => self.places = Some(PathBuf::from(value)),
+            "output" => self.output = parse(value)?,
+            "distance_model" => self.distance_model = parse(value)?,
+            _ => return Err(format!("unknown key '{}'", key)),
+        }
+
+        Ok(())
+    }
+
+    /// Builds a layer from the contents of a TOML config file.
+    pub fn from_toml(contents: &str) -> Result<ConfigLayer, String> {
+        let table: toml::Table = contents.parse().map_err(|err: toml::de::Error| err.message().to_string())?;
+
+        let mut layer = ConfigLayer::default();
+        for (key, value) in &table {
+            let value = match *value {
+                toml::Value::String(ref value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                _ => return Err(format!("'{}' must be a string or a number", key)),
+            };
+            layer.set(key, &value).map_err(|err| format!("invalid '{}': {}", key, err))?;
+        }
+
+        Ok(layer)
+    }
+
+    /// Builds a layer from the config file at `path`.
+    pub fn from_file(path: &Path) -> Result<ConfigLayer, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+        ConfigLayer::from_toml(&contents).map_err(|err| ConfigError::Invalid(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Builds a layer from the `CUSTOMER_LOCATOR_*` variables in `variables`.
+    pub fn from_environment<I>(variables: I) -> Result<ConfigLayer, ConfigError>
+        where I: IntoIterator<Item = (String, String)>
+    {
+        let mut layer = ConfigLayer::default();
+        for (name, value) in variables {
+            let key = match name.strip_prefix(ENVIRONMENT_PREFIX) {
+                Some(key) => key.to_lowercase(),
+                None => continue,
+            };
+            if KEYS.contains(&key.as_str()) {
+                layer.set(&key, &value).map_err(|err| ConfigError::Invalid(format!("invalid {}: {}", name, err)))?;
+            }
+        }
+
+        Ok(layer)
+    }
+}
+
+/// The effective configuration along with where each value comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub file: PathBuf,
+    pub format: InputFormat,
+    pub radius: f64,
+    pub unit: DistanceUnit,
+    pub location: String,
+    pub places: Option<PathBuf>,
+    pub output: OutputFormat,
+    pub distance_model: DistanceModel,
+    sources: BTreeMap<&'static str, ConfigSource>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            file: PathBuf::from(DEFAULT_FILE),
+            format: InputFormat::Json,
+            radius: DEFAULT_RADIUS,
+            unit: DistanceUnit::Kilometers,
+            location: String::from(DEFAULT_LOCATION),
+            places: None,
+            output: OutputFormat::Text,
+            distance_model: DistanceModel::Haversine,
+            sources: KEYS.iter().map(|key| (*key, ConfigSource::Default)).collect(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the config files and the environment,
+    /// with `flags` on top.
+    ///
+    /// # Errors
+    ///
+    /// When a config file can't be read or any source has an invalid value.
+    pub fn load(flags: ConfigLayer) -> Result<Config, ConfigError> {
+        let mut layers = Vec::new();
+        for path in config_file_paths() {
+            if path.is_file() {
+                let layer = ConfigLayer::from_file(&path)?;
+                layers.push((ConfigSource::File(path), layer));
+            }
+        }
+        layers.push((ConfigSource::Environment, ConfigLayer::from_environment(env::vars())?));
+        layers.push((ConfigSource::Flags, flags));
+
+        Ok(Config::from_layers(layers))
+    }
+
+    /// Builds the configuration from the defaults and `layers`, the later
+    /// layers overriding the earlier ones.
+    pub fn from_layers<I>(layers: I) -> Config
+        where I: IntoIterator<Item = (ConfigSource, ConfigLayer)>
+    {
+        let mut config = Config::default();
+        for (source, layer) in layers {
+            macro_rules! apply {
+                ($key:ident) => {
+                    if let Some(value) = layer.$key {
+                        config.$key = value;
+                        config.sources.insert(stringify!($key), source.clone());
+                    }
+                }
+            }
+            apply!(file);
+            apply!(format);
+            apply!(radius);
+            apply!(unit);
+            apply!(location);
+            apply!(output);
+            if let Some(places) = layer.places {
+                config.places = if places.as_os_str().is_empty() { None } else { Some(places) };
+                config.sources.insert("places", source.clone());
+            }
+            apply!(distance_model);
+        }
+
+        config
+    }
+
+    /// Returns where the value of `key` comes from.
+    pub fn source(&self, key: &str) -> Option<&ConfigSource> {
+        self.sources.get(key)
+    }
+}
+
+/// Prints the configuration as a TOML config file noting where each value
+/// comes from.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values = [
+            toml::Value::String(self.file.display().to_string()),
+            toml::Value::String(self.format.to_string()),
+            toml::Value::Float(self.radius),
+            toml::Value::String(self.unit.to_string()),
+            toml::Value::String(self.location.clone()),
+            toml::Value::String(self.places.as_ref().map(|places| places.display().to_string()).unwrap_or_default()),
+            toml::Value::String(self.output.to_string()),
+            toml::Value::String(self.distance_model.to_string()),
+        ];
+
+        for (key, value) in KEYS.iter().zip(values.iter()) {
+            let source = self.source(key).map(|source| source.describe(key)).unwrap_or_default();
+            writeln!(f, "{} = {} # {}", key, value, source)?;
+        }
+        Ok(())
+    }
+}
+
+// The config files that are looked for, the user one first so the project
+// one overrides it.
+fn config_file_paths() -> Vec<PathBuf> {
+    let user_config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+
+    let mut paths = Vec::new();
+    if let Some(user_config_dir) = user_config_dir {
+        paths.push(user_config_dir.join(USER_CONFIG_DIR).join(USER_CONFIG_FILE));
+    }
+    paths.push(PathBuf::from(PROJECT_CONFIG_FILE));
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG_TOML_FILE: &str = "tests/fixtures/customer_locator.toml";
+
+    fn variables(variables: &[(&str, &str)]) -> Vec<(String, String)> {
+        variables.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn from_file_reads_every_key() {
+        let layer = ConfigLayer::from_file(Path::new(CONFIG_TOML_FILE)).unwrap();
+        let expected_layer = ConfigLayer {
+            file: Some(PathBuf::from("data/customers.json")),
+            format: Some(InputFormat::JsonMmap),
+            radius: Some(50f64),
+            unit: Some(DistanceUnit::Miles),
+            location: Some(String::from("office:cork")),
+            places: Some(PathBuf::from("tests/fixtures/places.csv")),
+            output: Some(OutputFormat::Csv),
+            distance_model: Some(DistanceModel::Haversine),
+        };
+
+        assert_eq!(layer, expected_layer);
+    }
+
+    #[test]
+    fn from_toml_fails_on_unknown_keys_and_invalid_values() {
+        assert_eq!(ConfigLayer::from_toml("radios = 10"), Err(String::from("invalid 'radios': unknown key 'radios'")));
+        assert_eq!(ConfigLayer::from_toml("radius = -10"), Err(String::from("invalid 'radius': the radius must be a non-negative number")));
+        assert_eq!(ConfigLayer::from_toml("unit = \"ly\""),
+                   Err(String::from("invalid 'unit': Distance unit parse error: unknown distance unit 'ly'")));
+        assert_eq!(ConfigLayer::from_toml("output = [\"csv\"]"), Err(String::from("'output' must be a string or a number")));
+    }
+
+    #[test]
+    fn from_environment_only_reads_the_prefixed_variables() {
+        let layer = ConfigLayer::from_environment(variables(&[
+            ("CUSTOMER_LOCATOR_RADIUS", "25.5"),
+            ("CUSTOMER_LOCATOR_OUTPUT", "json"),
+            ("CUSTOMER_LOCATOR_CACHE", "ignored"),
+            ("RADIUS", "1"),
+        ])).unwrap();
+
+        assert_eq!(layer, ConfigLayer { radius: Some(25.5f64), output: Some(OutputFormat::Json), ..ConfigLayer::default() });
+
+        match ConfigLayer::from_environment(variables(&[("CUSTOMER_LOCATOR_UNIT", "parsecs")])) {
+            Err(ConfigError::Invalid(ref reason)) if reason.starts_with("invalid CUSTOMER_LOCATOR_UNIT") => (),
+            other => panic!("expected an invalid unit error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_layers_lets_later_layers_override_earlier_ones() {
+        let file_path = PathBuf::from("customer_locator.toml");
+        let config = Config::from_layers(vec![
+            (ConfigSource::File(file_path.clone()), ConfigLayer {
+                radius: Some(50f64),
+                location: Some(String::from("51.8985,-8.4756")),
+                ..ConfigLayer::default()
+            }),
+            (ConfigSource::Environment, ConfigLayer { radius: Some(25f64), unit: Some(DistanceUnit::Miles), ..ConfigLayer::default() }),
+            (ConfigSource::Flags, ConfigLayer { radius: Some(10f64), ..ConfigLayer::default() }),
+        ]);
+
+        assert_eq!(config.radius, 10f64);
+        assert_eq!(config.unit, DistanceUnit::Miles);
+        assert_eq!(config.location, "51.8985,-8.4756");
+        assert_eq!(config.file, PathBuf::from(DEFAULT_FILE));
+        assert_eq!(config.source("radius"), Some(&ConfigSource::Flags));
+        assert_eq!(config.source("unit"), Some(&ConfigSource::Environment));
+        assert_eq!(config.source("location"), Some(&ConfigSource::File(file_path)));
+        assert_eq!(config.source("file"), Some(&ConfigSource::Default));
+    }
+
+    #[test]
+    fn display_prints_each_value_with_its_source() {
+        let config = Config::from_layers(vec![
+            (ConfigSource::Environment, ConfigLayer { unit: Some(DistanceUnit::Miles), ..ConfigLayer::default() }),
+            (ConfigSource::Flags, ConfigLayer { distance_model: Some(DistanceModel::Haversine), ..ConfigLayer::default() }),
+        ]);
+        let expected_output = "file = \"data/customers.json\" # default\n\
+                               format = \"json\" # default\n\
+                               radius = 100.0 # default\n\
+                               unit = \"mi\" # CUSTOMER_LOCATOR_UNIT\n\
+                               location = \"dublin\" # default\n\
+                               places = \"\" # default\n\
+                               output = \"text\" # default\n\
+                               distance_model = \"haversine\" # --distance-model\n";
+
+        assert_eq!(config.to_string(), expected_output);
+        assert_eq!(ConfigLayer::from_toml(&config.to_string()).unwrap().unit, Some(DistanceUnit::Miles));
+    }
+}
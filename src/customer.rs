@@ -6,7 +6,15 @@
 // This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::str::FromStr;
+
+use serde::{de, Deserializer};
+
+use customer_attribute::{AttributeValue, Attributes};
 
 use location::Location;
 use units::Kilometers;
@@ -19,6 +27,10 @@ use units::Kilometers;
 /// Serialize and Deserialize traits so it can be easily built
 /// from JSON and written to JSON strings.
 ///
+/// Any field besides `user_id`, `name`, `latitude` and `longitude`
+/// is kept on `attributes` so it survives until the output. The
+/// coordinates can come as JSON numbers or as strings holding them.
+///
 /// # Examples
 ///
 /// You can explicitly create a [`Customer`] with [`new`]:
@@ -34,8 +46,12 @@ use units::Kilometers;
 pub struct Customer {
     pub user_id: i64,
     pub name: String,
+    #[serde(deserialize_with = "deserialize_coordinate")]
     pub latitude: f64,
+    #[serde(deserialize_with = "deserialize_coordinate")]
     pub longitude: f64,
+    #[serde(flatten)]
+    pub attributes: Attributes,
 }
 
 impl Customer {
@@ -53,13 +69,34 @@ impl Customer {
     #[allow(dead_code)]
     pub fn new(user_id: i64, name: &str, location: &Location) -> Customer {
         Customer {
-            user_id: user_id,
+            user_id,
             name: name.into(),
             latitude: location.latitude,
             longitude: location.longitude,
+            attributes: Attributes::new(),
         }
     }
 
+    /// Returns the customer with the attribute `key` set to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let customer = Customer::new(1, "Jose Narvaez", &Location::new(53.3393, -6.2576841))
+    ///     .with_attribute("email", "jose@example.com");
+    ///
+    /// assert_eq!(customer.attribute("email"), Some(&AttributeValue::from("jose@example.com")));
+    /// ```
+    pub fn with_attribute<V: Into<AttributeValue>>(mut self, key: &str, value: V) -> Customer {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+
+    /// Returns the value of the attribute `key` if the customer has it.
+    pub fn attribute(&self, key: &str) -> Option<&AttributeValue> {
+        self.attributes.get(key)
+    }
+
     /// Returns a `Location` object built from customer's latitude and longitude.
     ///
     /// # Examples
@@ -91,8 +128,42 @@ impl Customer {
     /// assert_eq!(customer.distance_from(other_location), Kilometers(10.553));
     /// ```
     pub fn distance_from(&self, location: &Location) -> Kilometers {
-        self.location().distance_from(&location)
+        self.location().distance_from(location)
+    }
+}
+
+// Customer exports encode the coordinates as strings ("52.986375") but
+// we also accept plain numbers which is what we write back.
+pub fn deserialize_coordinate<'de, D>(deserializer: D) -> Result<f64, D::Error>
+    where D: Deserializer<'de>
+{
+    struct CoordinateVisitor;
+
+    impl<'de> de::Visitor<'de> for CoordinateVisitor {
+        type Value = f64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a coordinate as a number or a string")
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<f64, E> {
+            Ok(value)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<f64, E> {
+            f64::from_str(value.trim()).map_err(E::custom)
+        }
     }
+
+    deserializer.deserialize_any(CoordinateVisitor)
 }
 
 /// Struct representing a list of customers.
@@ -114,7 +185,7 @@ impl Customer {
 /// You can explicitly create a [`CustomerList`] with `from_vec` like:
 ///
 /// ```
-/// let dublin =  Location::dublin();
+/// let dublin =  Location::new(53.3393, -6.2576841);
 /// let santiago =  Location::new(33.4489f64, 70.6693f64);
 ///
 /// let jose: Customer = Customer::new(1i64, String::from("Jose Narvaez"), dublin);
@@ -123,16 +194,31 @@ impl Customer {
 /// let customer_list = CustomerList::from_vec(vec![jose, carlos]);
 /// ```
 ///
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
 pub struct CustomerList(Vec<Customer>);
 
+/// The key used to order a `CustomerList` with `CustomerList::sort_by`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey<'l> {
+    UserId,
+    Name,
+    DistanceFrom(&'l Location),
+}
+
+/// The direction in which a `CustomerList` gets sorted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
 impl CustomerList {
     /// Constructs a `CustomerList` instance from a vector of customers.
     ///
     ///  # Examples
     ///
     /// ```
-    /// let dublin =  Location::dublin();
+    /// let dublin =  Location::new(53.3393, -6.2576841);
     /// let santiago =  Location::new(33.4489f64, 70.6693f64);
     ///
     /// let jose: Customer = Customer::new(1i64, String::from("Jose Narvaez"), dublin);
@@ -144,12 +230,50 @@ impl CustomerList {
         CustomerList(customers)
     }
 
+    /// Returns the number of customers in the list.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the list holds no customers.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns an iterator over references to the customers in the list.
+    ///
+    ///  # Examples
+    ///
+    /// ```
+    /// let customer_list = CustomerList::from_vec(vec![jose, carlos]);
+    ///
+    /// for customer in customer_list.iter() {
+    ///     println!("{}", customer);
+    /// }
+    /// ```
+    pub fn iter(&self) -> ::std::slice::Iter<'_, Customer> {
+        self.0.iter()
+    }
+
+    /// Returns the first customer in the list with the given `user_id`, if any.
+    ///
+    ///  # Examples
+    ///
+    /// ```
+    /// let customer_list = CustomerList::from_vec(vec![jose.clone(), carlos]);
+    /// assert_eq!(customer_list.get(1), Some(&jose));
+    /// assert_eq!(customer_list.get(1000), None);
+    /// ```
+    pub fn get(&self, user_id: i64) -> Option<&Customer> {
+        self.0.iter().find(|customer| customer.user_id == user_id)
+    }
+
     /// Sorts in-place the `CustomerList` by the `user_id`.
     ///
     ///  # Examples
     ///
     /// ```
-    /// let dublin =  Location::dublin();
+    /// let dublin =  Location::new(53.3393, -6.2576841);
     /// let santiago =  Location::new(-33.4489f64, -70.6693f64);
     ///
     /// let jose: Customer = Customer::new(2i64, String::from("Jose Narvaez"), dublin);
@@ -161,7 +285,149 @@ impl CustomerList {
     /// assert_eq!(customer_list, CustomerList::from_vec(vec![carlos, jose]));
     /// ```
     pub fn sort_by_user_id(&mut self) {
-         self.0.as_mut_slice().sort_by(|first, second| first.user_id.cmp(&second.user_id));
+        self.sort_by(SortKey::UserId, SortOrder::Ascending);
+    }
+
+    /// Sorts in-place the `CustomerList` by the given `SortKey` in the given `SortOrder`.
+    ///
+    /// The sort is stable so customers comparing equal keep their relative order.
+    ///
+    ///  # Examples
+    ///
+    /// ```
+    /// let mut customer_list = CustomerList::from_vec(vec![jose, carlos]);
+    ///
+    /// customer_list.sort_by(SortKey::Name, SortOrder::Ascending);
+    /// customer_list.sort_by(SortKey::DistanceFrom(&Location::new(53.3393, -6.2576841)), SortOrder::Descending);
+    /// ```
+    pub fn sort_by(&mut self, key: SortKey, order: SortOrder) {
+        let descending = order == SortOrder::Descending;
+        self.0.sort_by(|first, second| {
+            let (first, second) = if descending { (second, first) } else { (first, second) };
+            match key {
+                SortKey::UserId => first.user_id.cmp(&second.user_id),
+                SortKey::Name => first.name.cmp(&second.name),
+                SortKey::DistanceFrom(location) => first.distance_from(location).0.total_cmp(&second.distance_from(location).0),
+            }
+        });
+    }
+
+    /// Returns a new `CustomerList` with the customers for which `predicate`
+    /// returns `true`.
+    ///
+    ///  # Examples
+    ///
+    /// ```
+    /// let customer_list = CustomerList::from_vec(vec![jose, carlos]);
+    /// let narvaez = customer_list.filter(|customer| customer.name.ends_with("Narvaez"));
+    /// ```
+    pub fn filter<P>(&self, mut predicate: P) -> CustomerList
+        where P: FnMut(&Customer) -> bool
+    {
+        self.iter().filter(|customer| predicate(customer)).cloned().collect()
+    }
+
+    /// Splits the list in two, the first one holding the customers for which
+    /// `predicate` returns `true` and the second one the rest of them.
+    ///
+    ///  # Examples
+    ///
+    /// ```
+    /// let customer_list = CustomerList::from_vec(vec![jose, carlos]);
+    /// let (near, far) = customer_list.partition(|customer| customer.distance_from(&dublin) < Kilometers(100.0));
+    /// ```
+    pub fn partition<P>(self, predicate: P) -> (CustomerList, CustomerList)
+        where P: FnMut(&Customer) -> bool
+    {
+        let (matching, rest): (Vec<Customer>, Vec<Customer>) = self.0.into_iter().partition(predicate);
+        (CustomerList(matching), CustomerList(rest))
+    }
+
+    /// Groups the customers by the key returned by `key_fn`.
+    ///
+    /// Customers keep their relative order within each group.
+    ///
+    ///  # Examples
+    ///
+    /// ```
+    /// let customer_list = CustomerList::from_vec(vec![jose, carlos]);
+    /// let by_initial = customer_list.group_by(|customer| customer.name.chars().next());
+    /// ```
+    pub fn group_by<K, F>(&self, mut key_fn: F) -> BTreeMap<K, CustomerList>
+        where K: Ord,
+              F: FnMut(&Customer) -> K
+    {
+        let mut groups: BTreeMap<K, CustomerList> = BTreeMap::new();
+        for customer in self.iter() {
+            groups.entry(key_fn(customer)).or_default().0.push(customer.clone());
+        }
+
+        groups
+    }
+
+    /// Returns an iterator over slices of `size` customers. The last slice
+    /// may be shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn chunks(&self, size: usize) -> ::std::slice::Chunks<'_, Customer> {
+        self.0.chunks(size)
+    }
+
+    /// Returns the customers on the page `page` (zero based) when the list is
+    /// split on pages of `page_size` customers. Pages past the end are empty.
+    ///
+    ///  # Examples
+    ///
+    /// ```
+    /// let customer_list = CustomerList::from_vec(vec![jose, carlos, maho]);
+    /// assert_eq!(customer_list.page(1, 2), &[maho]);
+    /// ```
+    pub fn page(&self, page: usize, page_size: usize) -> &[Customer] {
+        let start = page.saturating_mul(page_size).min(self.0.len());
+        let end = start.saturating_add(page_size).min(self.0.len());
+        &self.0[start..end]
+    }
+
+    /// Merges `other` into the list, skipping the customers whose `user_id`
+    /// is already present. Use `extend` to append unconditionally.
+    ///
+    ///  # Examples
+    ///
+    /// ```
+    /// let mut customer_list = CustomerList::from_vec(vec![jose.clone()]);
+    /// customer_list.merge(CustomerList::from_vec(vec![jose, carlos]));
+    /// assert_eq!(customer_list.len(), 2);
+    /// ```
+    pub fn merge(&mut self, other: CustomerList) {
+        let mut user_ids: HashSet<i64> = self.iter().map(|customer| customer.user_id).collect();
+        for customer in other {
+            if user_ids.insert(customer.user_id) {
+                self.0.push(customer);
+            }
+        }
+    }
+
+    /// Returns the spherical centroid of the customers, `None` when there
+    /// are none. See `Location::centroid`.
+    pub fn centroid(&self) -> Option<Location> {
+        Location::centroid(&self.locations())
+    }
+
+    /// Returns the sum of the distances from the customers to `location`.
+    pub fn total_distance_from(&self, location: &Location) -> Kilometers {
+        Kilometers(self.iter().map(|customer| customer.distance_from(location).0).sum())
+    }
+
+    /// Returns the distance from the customer furthest away to `location`,
+    /// zero for an empty list.
+    pub fn max_distance_from(&self, location: &Location) -> Kilometers {
+        Kilometers(self.iter().map(|customer| customer.distance_from(location).0).fold(0f64, f64::max))
+    }
+
+    fn locations(&self) -> Vec<Location> {
+        self.iter().map(|customer| customer.location()).collect()
     }
 }
 
@@ -175,6 +441,35 @@ impl IntoIterator for CustomerList {
     }
 }
 
+impl<'a> IntoIterator for &'a CustomerList {
+    type Item = &'a Customer;
+    type IntoIter = ::std::slice::Iter<'a, Customer>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl Index<usize> for CustomerList {
+    type Output = Customer;
+
+    fn index(&self, index: usize) -> &Customer {
+        &self.0[index]
+    }
+}
+
+impl FromIterator<Customer> for CustomerList {
+    fn from_iter<I: IntoIterator<Item = Customer>>(iter: I) -> Self {
+        CustomerList(iter.into_iter().collect())
+    }
+}
+
+impl Extend<Customer> for CustomerList {
+    fn extend<I: IntoIterator<Item = Customer>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
 impl fmt::Display for Customer {
      fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
          write!(f, "Customer(\"{}\": {}) located at ({}, {})", self.name, self.user_id, self.latitude, self.longitude)
@@ -184,46 +479,50 @@ impl fmt::Display for Customer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
     const NY_LAT: f64 = 40.7128f64;
     const NY_LONG: f64 = -74.0059f64;
     const DUB_NY_DIST_IN_KM: Kilometers = Kilometers(5116.751541958293);
 
     #[test]
     fn customer_new_builds_a_correct_instance() {
-        let actual_customer = Customer::new(1000i64, "Jose Narvaez",&Location::dublin());
+        let actual_customer = Customer::new(1000i64, "Jose Narvaez",&DUBLIN);
         let expected_customer = Customer {
             user_id: 1000i64,
             name: String::from("Jose Narvaez"),
-            latitude: Location::dublin().latitude,
-            longitude: Location::dublin().longitude,
+            latitude: DUBLIN.latitude,
+            longitude: DUBLIN.longitude,
+            attributes: Attributes::new(),
         };
         assert_eq!(expected_customer, actual_customer);
     }
 
     #[test]
     fn customer_location_returns_a_correct_instance_of_location() {
-        let expected_location = Location::dublin();
+        let expected_location = DUBLIN;
         let customer = Customer::new(1000i64, "Jose Narvaez", &expected_location);
         assert_eq!(customer.location(), expected_location);
     }
 
      #[test]
     fn customer_distance_from_calculates_distance_between_diff_points_long() {
-        let customer = Customer::new(1000i64, "Jose Narvaez", &Location::dublin());
+        let customer = Customer::new(1000i64, "Jose Narvaez", &DUBLIN);
         let new_york = Location::new(NY_LAT, NY_LONG);
         assert_eq!(customer.distance_from(&new_york), DUB_NY_DIST_IN_KM);
     }
 
     #[test]
     fn customer_distance_from_calculates_distance_same_point() {
-        let customer = Customer::new(1000i64, "Jose Narvaez", &Location::dublin());
-        let dublin = Location::dublin();
+        let customer = Customer::new(1000i64, "Jose Narvaez", &DUBLIN);
+        let dublin = DUBLIN;
         assert_eq!(customer.distance_from(&dublin), Kilometers(0f64));
     }
 
     #[test]
     fn customer_list_from_vec_builds_a_correct_instance() {
-        let jose = Customer::new(1, "Jose Narvaez", &Location::dublin());
+        let jose = Customer::new(1, "Jose Narvaez", &DUBLIN);
         let carlos = Customer::new(2, "Carlos Narvaez", &Location::new(-33.4489, -70.6693));
         let expected_cust_list = CustomerList(vec![jose.clone(), carlos.clone()]);
         let actual_cust_list = CustomerList::from_vec(vec![jose, carlos]);
@@ -233,7 +532,7 @@ mod tests {
     #[test]
     fn customer_list_sort_by_user_id_sorts_the_list_in_place() {
         let santiago = Location::new(-33.4489, -70.6693);
-        let jose = Customer::new(3, "Jose Narvaez", &Location::dublin());
+        let jose = Customer::new(3, "Jose Narvaez", &DUBLIN);
         let carlos = Customer::new(2, "Carlos Narvaez", &santiago);
         let maho = Customer::new(1, "Maholys Narvaez", &santiago);
         let expected_list = CustomerList(vec![maho.clone(), carlos.clone(), jose.clone()]);
@@ -242,4 +541,155 @@ mod tests {
 
         assert_eq!(expected_list, sorted_list);
     }
+
+    fn generate_customer_list() -> CustomerList {
+        let santiago = Location::new(-33.4489, -70.6693);
+        let new_york = Location::new(NY_LAT, NY_LONG);
+        CustomerList(vec![
+            Customer::new(2, "Carlos Narvaez", &santiago),
+            Customer::new(3, "Jose Narvaez", &DUBLIN),
+            Customer::new(1, "Maholys Narvaez", &new_york),
+        ])
+    }
+
+    #[test]
+    fn customer_list_len_and_is_empty_report_the_number_of_customers() {
+        assert_eq!(generate_customer_list().len(), 3);
+        assert!(!generate_customer_list().is_empty());
+        assert!(CustomerList::from_vec(vec![]).is_empty());
+    }
+
+    #[test]
+    fn customer_list_iter_does_not_consume_the_list() {
+        let customer_list = generate_customer_list();
+        let user_ids: Vec<i64> = customer_list.iter().map(|customer| customer.user_id).collect();
+        let names: Vec<&str> = (&customer_list).into_iter().map(|customer| customer.name.as_str()).collect();
+
+        assert_eq!(user_ids, vec![2, 3, 1]);
+        assert_eq!(names, vec!["Carlos Narvaez", "Jose Narvaez", "Maholys Narvaez"]);
+    }
+
+    #[test]
+    fn customer_list_get_finds_customers_by_user_id() {
+        let customer_list = generate_customer_list();
+        assert_eq!(customer_list.get(3).map(|customer| customer.name.as_str()), Some("Jose Narvaez"));
+        assert_eq!(customer_list.get(1000), None);
+    }
+
+    #[test]
+    fn customer_list_sort_by_name_sorts_in_both_orders() {
+        let mut customer_list = generate_customer_list();
+        customer_list.sort_by(SortKey::Name, SortOrder::Descending);
+        let user_ids: Vec<i64> = customer_list.iter().map(|customer| customer.user_id).collect();
+        assert_eq!(user_ids, vec![1, 3, 2]);
+
+        customer_list.sort_by(SortKey::Name, SortOrder::Ascending);
+        let user_ids: Vec<i64> = customer_list.iter().map(|customer| customer.user_id).collect();
+        assert_eq!(user_ids, vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn customer_list_sort_by_keeps_equal_customers_in_order_in_both_orders() {
+        let mut customer_list = CustomerList::from_vec(vec![
+            Customer::new(1, "Narvaez", &DUBLIN),
+            Customer::new(2, "Kehoe", &DUBLIN),
+            Customer::new(3, "Narvaez", &DUBLIN),
+            Customer::new(4, "Dempsey", &DUBLIN),
+        ]);
+        customer_list.sort_by(SortKey::Name, SortOrder::Descending);
+        let user_ids: Vec<i64> = customer_list.iter().map(|customer| customer.user_id).collect();
+        assert_eq!(user_ids, vec![1, 3, 2, 4]);
+
+        customer_list.sort_by(SortKey::DistanceFrom(&DUBLIN), SortOrder::Descending);
+        let user_ids: Vec<i64> = customer_list.iter().map(|customer| customer.user_id).collect();
+        assert_eq!(user_ids, vec![1, 3, 2, 4]);
+    }
+
+    #[test]
+    fn customer_list_sort_by_distance_sorts_nearest_first_when_ascending() {
+        let mut customer_list = generate_customer_list();
+        let dublin = DUBLIN;
+        customer_list.sort_by(SortKey::DistanceFrom(&dublin), SortOrder::Ascending);
+        let user_ids: Vec<i64> = customer_list.iter().map(|customer| customer.user_id).collect();
+        assert_eq!(user_ids, vec![3, 1, 2]);
+
+        customer_list.sort_by(SortKey::UserId, SortOrder::Descending);
+        let user_ids: Vec<i64> = customer_list.iter().map(|customer| customer.user_id).collect();
+        assert_eq!(user_ids, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn customer_list_filter_and_partition_split_by_predicate() {
+        let customer_list = generate_customer_list();
+        let dublin = DUBLIN;
+        let near = customer_list.filter(|customer| customer.distance_from(&dublin) < Kilometers(6000.0));
+        assert_eq!(near.iter().map(|customer| customer.user_id).collect::<Vec<i64>>(), vec![3, 1]);
+
+        let (odd, even) = customer_list.partition(|customer| customer.user_id % 2 == 1);
+        assert_eq!(odd.iter().map(|customer| customer.user_id).collect::<Vec<i64>>(), vec![3, 1]);
+        assert_eq!(even.iter().map(|customer| customer.user_id).collect::<Vec<i64>>(), vec![2]);
+    }
+
+    #[test]
+    fn customer_list_group_by_groups_keeping_the_order() {
+        let groups = generate_customer_list().group_by(|customer| customer.user_id % 2);
+        assert_eq!(groups.keys().cloned().collect::<Vec<i64>>(), vec![0, 1]);
+        assert_eq!(groups[&1].iter().map(|customer| customer.user_id).collect::<Vec<i64>>(), vec![3, 1]);
+    }
+
+    #[test]
+    fn customer_list_chunks_and_page_paginate_the_list() {
+        let customer_list = generate_customer_list();
+        let chunk_sizes: Vec<usize> = customer_list.chunks(2).map(|chunk| chunk.len()).collect();
+        assert_eq!(chunk_sizes, vec![2, 1]);
+
+        assert_eq!(customer_list.page(0, 2).len(), 2);
+        assert_eq!(customer_list.page(1, 2)[0].user_id, 1);
+        assert!(customer_list.page(5, 2).is_empty());
+    }
+
+    #[test]
+    fn customer_list_merge_skips_already_present_user_ids() {
+        let mut customer_list = generate_customer_list();
+        let other = CustomerList(vec![
+            Customer::new(3, "Duplicated Jose", &DUBLIN),
+            Customer::new(4, "Maria Narvaez", &DUBLIN),
+        ]);
+        customer_list.merge(other.clone());
+        assert_eq!(customer_list.len(), 4);
+        assert_eq!(customer_list.get(3).map(|customer| customer.name.as_str()), Some("Jose Narvaez"));
+
+        customer_list.extend(other);
+        assert_eq!(customer_list.len(), 6);
+    }
+
+    #[test]
+    fn customer_list_can_be_collected_from_an_iterator() {
+        let customers = vec![Customer::new(1, "Jose Narvaez", &DUBLIN)];
+        let customer_list: CustomerList = customers.clone().into_iter().collect();
+        assert_eq!(customer_list, CustomerList::from_vec(customers));
+    }
+
+    #[test]
+    fn customer_deserializes_coordinates_from_strings_and_numbers() {
+        let from_strings: Customer = serde_json::from_str(r#"{"latitude": "52.986375", "user_id": 1, "name": "Jose Narvaez", "longitude": "-6.043701"}"#).unwrap();
+        let from_numbers: Customer = serde_json::from_str(r#"{"latitude": 52.986375, "user_id": 1, "name": "Jose Narvaez", "longitude": -6.043701}"#).unwrap();
+        let expected_customer = Customer::new(1, "Jose Narvaez", &Location::new(52.986375, -6.043701));
+
+        assert_eq!(from_strings, expected_customer);
+        assert_eq!(from_numbers, expected_customer);
+    }
+
+    #[test]
+    fn customer_keeps_unknown_fields_as_attributes_through_a_round_trip() {
+        let json = r#"{"latitude": "52.986375", "user_id": 1, "name": "Jose Narvaez", "longitude": "-6.043701", "email": "jose@example.com", "tier": 2}"#;
+        let customer: Customer = serde_json::from_str(json).unwrap();
+        let expected_customer = Customer::new(1, "Jose Narvaez", &Location::new(52.986375, -6.043701))
+            .with_attribute("email", "jose@example.com")
+            .with_attribute("tier", 2i64);
+        assert_eq!(customer, expected_customer);
+
+        let round_tripped: Customer = serde_json::from_str(&serde_json::to_string(&customer).unwrap()).unwrap();
+        assert_eq!(round_tripped, expected_customer);
+    }
 }
\ No newline at end of file
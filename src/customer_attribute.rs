@@ -0,0 +1,137 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module holding the extra attributes a `Customer` may carry besides
+//! the ones the locator needs (`user_id`, `name`, `latitude` and
+//! `longitude`). Things like an email, a phone number or a tier that
+//! come on the customer exports and have to survive until the output.
+//!
+//! # Examples
+//!
+//! ```
+//! let mut attributes = Attributes::new();
+//! attributes.insert(String::from("tier"), AttributeValue::Text(String::from("gold")));
+//! ```
+//!
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json;
+
+/// The extra attributes of a `Customer` keyed by their name.
+///
+/// A `BTreeMap` is used so attributes are always printed in the same order.
+pub type Attributes = BTreeMap<String, AttributeValue>;
+
+/// A single typed value of a customer attribute.
+///
+/// Arrays and objects are kept as they come, and displayed as their JSON
+/// text where a single value is expected (a CSV cell for instance).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Array(Vec<AttributeValue>),
+    Object(Attributes),
+}
+
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AttributeValue::Null => Ok(()),
+            AttributeValue::Bool(value) => write!(f, "{}", value),
+            AttributeValue::Integer(value) => write!(f, "{}", value),
+            AttributeValue::Float(value) => write!(f, "{}", value),
+            AttributeValue::Text(ref value) => write!(f, "{}", value),
+            AttributeValue::Array(_) | AttributeValue::Object(_) => {
+                write!(f, "{}", serde_json::to_string(self).map_err(|_| fmt::Error)?)
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a str> for AttributeValue {
+    fn from(value: &'a str) -> Self {
+        AttributeValue::Text(value.into())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::Text(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Integer(value)
+    }
+}
+
+impl From<f64> for AttributeValue {
+    fn from(value: f64) -> Self {
+        AttributeValue::Float(value)
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        AttributeValue::Bool(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn attribute_values_are_deserialized_to_their_type() {
+        let json = r#"{"email": "jose@example.com", "visits": 3, "score": 4.5, "vip": true, "phone": null}"#;
+        let attributes: Attributes = serde_json::from_str(json).unwrap();
+
+        assert_eq!(attributes["email"], AttributeValue::Text(String::from("jose@example.com")));
+        assert_eq!(attributes["visits"], AttributeValue::Integer(3));
+        assert_eq!(attributes["score"], AttributeValue::Float(4.5));
+        assert_eq!(attributes["vip"], AttributeValue::Bool(true));
+        assert_eq!(attributes["phone"], AttributeValue::Null);
+    }
+
+    #[test]
+    fn nested_attribute_values_are_kept() {
+        let json = r#"{"tags": ["vip", 2], "address": {"city": "Dublin", "zip": null}}"#;
+        let attributes: Attributes = serde_json::from_str(json).unwrap();
+
+        assert_eq!(attributes["tags"], AttributeValue::Array(vec![AttributeValue::from("vip"), AttributeValue::Integer(2)]));
+        let mut address = Attributes::new();
+        address.insert(String::from("city"), AttributeValue::from("Dublin"));
+        address.insert(String::from("zip"), AttributeValue::Null);
+        assert_eq!(attributes["address"], AttributeValue::Object(address));
+    }
+
+    #[test]
+    fn attribute_values_are_displayed_bare() {
+        assert_eq!(format!("{}", AttributeValue::from("gold")), "gold");
+        assert_eq!(format!("{}", AttributeValue::from(3i64)), "3");
+        assert_eq!(format!("{}", AttributeValue::Null), "");
+    }
+
+    #[test]
+    fn nested_attribute_values_are_displayed_as_json() {
+        let attributes: Attributes = serde_json::from_str(r#"{"tags": ["vip", "early"], "address": {"city": "Dublin"}}"#).unwrap();
+
+        assert_eq!(format!("{}", attributes["tags"]), r#"["vip","early"]"#);
+        assert_eq!(format!("{}", attributes["address"]), r#"{"city":"Dublin"}"#);
+    }
+}
@@ -0,0 +1,727 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module implementing density based clustering of a `CustomerList` with
+//! great-circle distances, to find where customers gather rather than who
+//! is around a known location.
+//!
+//! [DBSCAN](https://en.wikipedia.org/wiki/DBSCAN) groups customers having
+//! at least `min_points` customers within `eps` of them, along with the
+//! ones within `eps` of those. Neighbours are looked up on a grid so it
+//! scales with the customers around each one rather than with all of them.
+//!
+//! [HDBSCAN](https://hdbscan.readthedocs.io/en/latest/how_hdbscan_works.html)
+//! needs no `eps`: it keeps the clusters that persist the longest across
+//! every density, which finds both dense city clusters and sparse rural
+//! ones. It calculates every distance between customers though, so it's
+//! meant for thousands of customers rather than millions.
+//!
+//! # Examples
+//!
+//! ```
+//! let clustering = Dbscan::new(Kilometers(5f64), 10).cluster(&customers);
+//! for cluster in &clustering.clusters {
+//!     println!("{} customers around {}", cluster.customers.len(), cluster.centroid);
+//! }
+//! println!("{} customers on their own", clustering.noise.len());
+//! ```
+//!
+
+use std::collections::{HashMap, VecDeque};
+use std::error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use customer::CustomerList;
+use customer_locator::LocatedCustomer;
+use location::{DistanceModel, EARTH_RADIUS_IN_KM, Location};
+use units::Kilometers;
+
+// Distances under this are taken as this, so customers at the same spot
+// don't make HDBSCAN densities infinite.
+const MIN_DISTANCE_IN_KM: f64 = 1e-6;
+
+/// The clustering algorithms customers can be grouped with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClusterAlgorithm {
+    Dbscan,
+    Hdbscan,
+}
+
+impl ClusterAlgorithm {
+    /// The names accepted by `ClusterAlgorithm::from_str`.
+    pub const NAMES: &'static [&'static str] = &["dbscan", "hdbscan"];
+}
+
+/// An error when trying to build a `ClusterAlgorithm` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseClusterAlgorithmError(String);
+
+impl FromStr for ClusterAlgorithm {
+    type Err = ParseClusterAlgorithmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "dbscan" => Ok(ClusterAlgorithm::Dbscan),
+            "hdbscan" => Ok(ClusterAlgorithm::Hdbscan),
+            other => Err(ParseClusterAlgorithmError(format!("unknown cluster algorithm '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for ClusterAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClusterAlgorithm::Dbscan => write!(f, "dbscan"),
+            ClusterAlgorithm::Hdbscan => write!(f, "hdbscan"),
+        }
+    }
+}
+
+impl fmt::Display for ParseClusterAlgorithmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cluster algorithm parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseClusterAlgorithmError {}
+
+/// A group of customers found close together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cluster {
+    /// Clusters are numbered from 1, in the order their first customer
+    /// comes in the clustered list.
+    pub id: usize,
+    /// The spherical centroid of the customers of the cluster.
+    pub centroid: Location,
+    /// The customers of the cluster with their distance to the centroid.
+    pub customers: Vec<LocatedCustomer>,
+}
+
+/// The result of clustering a `CustomerList`: the clusters found and the
+/// customers left out of every one of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clustering {
+    pub clusters: Vec<Cluster>,
+    pub noise: CustomerList,
+}
+
+impl Clustering {
+    /// Returns the id of the cluster of the customer with `user_id`, `None`
+    /// for noise and unknown customers.
+    #[cfg(test)]
+    pub fn cluster_of(&self, user_id: i64) -> Option<usize> {
+        self.clusters
+            .iter()
+            .find(|cluster| cluster.customers.iter().any(|located| located.customer.user_id == user_id))
+            .map(|cluster| cluster.id)
+    }
+
+    /// Builds the clustering from the label of each customer, whatever the
+    /// numbering of the labels was, the customers without one being noise.
+    /// Distances to the centroids are calculated with `model`.
+    pub fn from_labels(customers: &CustomerList, labels: &[Option<usize>], model: DistanceModel) -> Clustering {
+        let mut ids = HashMap::new();
+        let mut members: Vec<Vec<usize>> = Vec::new();
+        let mut noise = Vec::new();
+        for (index, label) in labels.iter().enumerate() {
+            match *label {
+                Some(label) => {
+                    let id = *ids.entry(label).or_insert_with(|| {
+                        members.push(Vec::new());
+                        members.len() - 1
+                    });
+                    members[id].push(index);
+                }
+                None => noise.push(index),
+            }
+        }
+
+        let list = customers.iter().collect::<Vec<_>>();
+        let clusters = members.into_iter()
+            .enumerate()
+            .map(|(id, indexes)| {
+                let locations = indexes.iter().map(|&index| list[index].location()).collect::<Vec<_>>();
+                // nearly antipodal clusters have no centroid, their first customer stands for it.
+                let centroid = Location::centroid(&locations).unwrap_or(locations[0]);
+                Cluster {
+                    id: id + 1,
+                    centroid,
+                    customers: indexes.into_iter()
+                        .zip(locations)
+                        .map(|(index, location)| LocatedCustomer { customer: list[index].clone(), distance: model.distance(&location, &centroid) })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Clustering {
+            clusters,
+            noise: noise.into_iter().map(|index| list[index].clone()).collect(),
+        }
+    }
+}
+
+/// The [DBSCAN](https://en.wikipedia.org/wiki/DBSCAN) clustering algorithm.
+///
+/// A customer with at least `min_points` customers within `eps`, itself
+/// included, is a core customer. Core customers within `eps` of each other
+/// share a cluster, along with the customers within `eps` of them. Any
+/// other customer is noise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dbscan {
+    eps: Kilometers,
+    min_points: usize,
+    model: DistanceModel,
+}
+
+impl Dbscan {
+    pub fn new(eps: Kilometers, min_points: usize) -> Dbscan {
+        Dbscan { eps, min_points, model: DistanceModel::Haversine }
+    }
+
+    /// Calculates the distances with `model` instead of the haversine one.
+    pub fn with_model(mut self, model: DistanceModel) -> Dbscan {
+        self.model = model;
+        self
+    }
+
+    /// Clusters `customers`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let clustering = Dbscan::new(Kilometers(5f64), 10).cluster(&customers);
+    /// ```
+    pub fn cluster(&self, customers: &CustomerList) -> Clustering {
+        let locations = customers.iter().map(|customer| customer.location()).collect::<Vec<_>>();
+        let grid = NeighbourGrid::new(&locations, self.eps, self.model);
+
+        let mut labels: Vec<Option<usize>> = vec![None; locations.len()];
+        let mut visited = vec![false; locations.len()];
+        let mut clusters = 0;
+        for index in 0..locations.len() {
+            if visited[index] {
+                continue;
+            }
+            visited[index] = true;
+            let neighbours = grid.neighbours(index);
+            if neighbours.len() < self.min_points {
+                continue;
+            }
+
+            // expanding the cluster from the core customer, border customers
+            // already on another cluster stay on it.
+            let cluster = clusters;
+            clusters += 1;
+            labels[index] = Some(cluster);
+            let mut pending: VecDeque<usize> = neighbours.into_iter().collect();
+            while let Some(neighbour) = pending.pop_front() {
+                if labels[neighbour].is_none() {
+                    labels[neighbour] = Some(cluster);
+                }
+                if visited[neighbour] {
+                    continue;
+                }
+                visited[neighbour] = true;
+                let neighbours = grid.neighbours(neighbour);
+                if neighbours.len() >= self.min_points {
+                    pending.extend(neighbours);
+                }
+            }
+        }
+
+        Clustering::from_labels(customers, &labels, self.model)
+    }
+}
+
+/// The [HDBSCAN](https://hdbscan.readthedocs.io) clustering algorithm.
+///
+/// Clusters have at least `min_cluster_size` customers. `min_samples`, the
+/// same as `min_cluster_size` unless given, is how many customers around
+/// one, itself included, its density is measured with: the higher, the more
+/// customers are left as noise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hdbscan {
+    min_cluster_size: usize,
+    min_samples: Option<usize>,
+    model: DistanceModel,
+}
+
+impl Hdbscan {
+    pub fn new(min_cluster_size: usize) -> Hdbscan {
+        Hdbscan { min_cluster_size, min_samples: None, model: DistanceModel::Haversine }
+    }
+
+    /// Calculates the distances with `model` instead of the haversine one.
+    pub fn with_model(mut self, model: DistanceModel) -> Hdbscan {
+        self.model = model;
+        self
+    }
+
+    /// Measures the density around each customer with `min_samples`
+    /// customers instead of `min_cluster_size`.
+    pub fn with_min_samples(mut self, min_samples: usize) -> Hdbscan {
+        self.min_samples = Some(min_samples);
+        self
+    }
+
+    /// Clusters `customers`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let clustering = Hdbscan::new(10).with_min_samples(5).cluster(&customers);
+    /// ```
+    pub fn cluster(&self, customers: &CustomerList) -> Clustering {
+        let locations = customers.iter().map(|customer| customer.location()).collect::<Vec<_>>();
+        let min_cluster_size = self.min_cluster_size.max(2);
+        let min_samples = self.min_samples.unwrap_or(min_cluster_size).max(1);
+        if locations.len() < min_cluster_size {
+            return Clustering::from_labels(customers, &vec![None; locations.len()], self.model);
+        }
+
+        let distance = |first: usize, second: usize| {
+            self.model.distance(&locations[first], &locations[second]).0.max(MIN_DISTANCE_IN_KM)
+        };
+        let core_distances = core_distances(locations.len(), min_samples, &distance);
+        let reachability = |first: usize, second: usize| {
+            distance(first, second).max(core_distances[first]).max(core_distances[second])
+        };
+
+        let tree = LinkageTree::new(locations.len(), minimum_spanning_tree(locations.len(), reachability));
+        let labels = CondensedTree::new(&tree, min_cluster_size).labels();
+
+        Clustering::from_labels(customers, &labels, self.model)
+    }
+}
+
+/// A grid over the unit vectors of some locations with cells as wide as
+/// the chord of `radius`, so every location within `radius` of another one
+/// is on its cell or on one of the 26 around it. Unlike a grid over
+/// latitudes and longitudes it needs no care at the poles or across the
+/// antimeridian. Distances are calculated with `model`, the cells being
+/// as wide as the haversine radius covering `radius` under it.
+pub struct NeighbourGrid<'l> {
+    locations: &'l [Location],
+    vectors: Vec<[f64; 3]>,
+    cell_size: f64,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+    radius: Kilometers,
+    model: DistanceModel,
+}
+
+impl<'l> NeighbourGrid<'l> {
+    pub fn new(locations: &'l [Location], radius: Kilometers, model: DistanceModel) -> NeighbourGrid<'l> {
+        let angle = (model.haversine_radius_covering(radius).0 / EARTH_RADIUS_IN_KM).clamp(0f64, PI);
+        let cell_size = (2f64 * (angle / 2f64).sin()).max(1e-9);
+
+        let vectors = locations.iter().map(|location| location.to_vector()).collect::<Vec<_>>();
+        let mut cells: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        for (index, vector) in vectors.iter().enumerate() {
+            cells.entry(cell_of(vector, cell_size)).or_default().push(index);
+        }
+
+        NeighbourGrid { locations, vectors, cell_size, cells, radius, model }
+    }
+
+    /// The indexes of the locations within `radius` of the one at `index`,
+    /// itself included, in ascending order.
+    pub fn neighbours(&self, index: usize) -> Vec<usize> {
+        self.search(&self.vectors[index], &self.locations[index])
+    }
+
+    /// The indexes of the locations within `radius` of `location`, in
+    /// ascending order.
+    pub fn within(&self, location: &Location) -> Vec<usize> {
+        self.search(&location.to_vector(), location)
+    }
+
+    fn search(&self, vector: &[f64; 3], location: &Location) -> Vec<usize> {
+        let (x, y, z) = cell_of(vector, self.cell_size);
+
+        let mut found = Vec::new();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(indexes) = self.cells.get(&(x + dx, y + dy, z + dz)) {
+                        found.extend(indexes.iter().filter(|&&other| {
+                            self.model.distance(location, &self.locations[other]) <= self.radius
+                        }));
+                    }
+                }
+            }
+        }
+        found.sort_unstable();
+
+        found
+    }
+}
+
+fn cell_of(vector: &[f64; 3], cell_size: f64) -> (i64, i64, i64) {
+    ((vector[0] / cell_size).floor() as i64,
+     (vector[1] / cell_size).floor() as i64,
+     (vector[2] / cell_size).floor() as i64)
+}
+
+// The distance from each point to its `min_samples`-th nearest one, itself
+// included.
+fn core_distances<F: Fn(usize, usize) -> f64>(len: usize, min_samples: usize, distance: &F) -> Vec<f64> {
+    (0..len)
+        .map(|index| {
+            let mut distances = (0..len)
+                .map(|other| if other == index { 0f64 } else { distance(index, other) })
+                .collect::<Vec<_>>();
+            let nth = min_samples.min(len) - 1;
+            *distances.select_nth_unstable_by(nth, |first, second| first.total_cmp(second)).1
+        })
+        .collect()
+}
+
+// The edges (first, second, weight) of the minimum spanning tree of the
+// complete graph over `len` points, with Prim's algorithm.
+fn minimum_spanning_tree<F: Fn(usize, usize) -> f64>(len: usize, weight: F) -> Vec<(usize, usize, f64)> {
+    let mut in_tree = vec![false; len];
+    let mut best = vec![(f64::INFINITY, 0); len];
+    let mut edges = Vec::with_capacity(len.saturating_sub(1));
+
+    let mut current = 0;
+    in_tree[current] = true;
+    for _ in 1..len {
+        let mut next = None;
+        for other in 0..len {
+            if in_tree[other] {
+                continue;
+            }
+            let weight = weight(current, other);
+            if weight < best[other].0 {
+                best[other] = (weight, current);
+            }
+            if next.is_none_or(|next: usize| best[other].0 < best[next].0) {
+                next = Some(other);
+            }
+        }
+
+        let next = next.unwrap();
+        edges.push((best[next].1, next, best[next].0));
+        in_tree[next] = true;
+        current = next;
+    }
+
+    edges
+}
+
+/// The single linkage tree of some points: leaves are the points, each
+/// other node joins two with the distance they're joined at.
+struct LinkageTree {
+    leaves: usize,
+    // the children, distance and size of each node past the leaves.
+    nodes: Vec<(usize, usize, f64, usize)>,
+}
+
+impl LinkageTree {
+    fn new(leaves: usize, mut edges: Vec<(usize, usize, f64)>) -> LinkageTree {
+        edges.sort_by(|first, second| first.2.total_cmp(&second.2));
+
+        let mut parents = (0..2 * leaves).collect::<Vec<_>>();
+        let mut sizes = vec![1; 2 * leaves];
+        let mut nodes = Vec::with_capacity(edges.len());
+        for (first, second, distance) in edges {
+            let (first, second) = (root_of(&mut parents, first), root_of(&mut parents, second));
+            let node = leaves + nodes.len();
+            sizes[node] = sizes[first] + sizes[second];
+            parents[first] = node;
+            parents[second] = node;
+            nodes.push((first, second, distance, sizes[node]));
+        }
+
+        LinkageTree { leaves, nodes }
+    }
+
+    fn root(&self) -> usize {
+        self.leaves + self.nodes.len() - 1
+    }
+
+    fn size(&self, node: usize) -> usize {
+        if node < self.leaves { 1 } else { self.nodes[node - self.leaves].3 }
+    }
+
+    fn leaves_of(&self, node: usize) -> Vec<usize> {
+        let mut leaves = Vec::new();
+        let mut pending = vec![node];
+        while let Some(node) = pending.pop() {
+            if node < self.leaves {
+                leaves.push(node);
+            } else {
+                let (first, second, _, _) = self.nodes[node - self.leaves];
+                pending.push(first);
+                pending.push(second);
+            }
+        }
+
+        leaves
+    }
+}
+
+/// The root of the set of `node` on the disjoint sets of `parents`,
+/// compressing the path to it.
+pub fn root_of(parents: &mut [usize], node: usize) -> usize {
+    let mut root = node;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut node = node;
+    while parents[node] != root {
+        let parent = parents[node];
+        parents[node] = root;
+        node = parent;
+    }
+
+    root
+}
+
+/// A cluster of the condensed tree, born when its parent split at
+/// `birth` density (the inverse of a distance).
+struct CondensedCluster {
+    parent: Option<usize>,
+    birth: f64,
+    stability: f64,
+    children: Vec<usize>,
+}
+
+/// The linkage tree condensed to the splits leaving two clusters of at
+/// least `min_cluster_size` points, each point recording the cluster it
+/// fell out of.
+struct CondensedTree {
+    clusters: Vec<CondensedCluster>,
+    points: Vec<usize>,
+}
+
+impl CondensedTree {
+    fn new(tree: &LinkageTree, min_cluster_size: usize) -> CondensedTree {
+        let mut clusters = vec![CondensedCluster { parent: None, birth: 0f64, stability: 0f64, children: Vec::new() }];
+        let mut points = vec![0; tree.leaves];
+
+        let mut pending = vec![(tree.root(), 0)];
+        while let Some((node, cluster)) = pending.pop() {
+            if node < tree.leaves {
+                points[node] = cluster;
+                continue;
+            }
+            let (first, second, distance, size) = tree.nodes[node - tree.leaves];
+            let density = 1f64 / distance;
+            let birth = clusters[cluster].birth;
+
+            let big = |child: usize| tree.size(child) >= min_cluster_size;
+            if big(first) && big(second) {
+                clusters[cluster].stability += (density - birth) * size as f64;
+                for child in [first, second] {
+                    let id = clusters.len();
+                    clusters.push(CondensedCluster { parent: Some(cluster), birth: density, stability: 0f64, children: Vec::new() });
+                    clusters[cluster].children.push(id);
+                    pending.push((child, id));
+                }
+                continue;
+            }
+
+            // the small side falls out of the cluster, the big one carries on with it.
+            for child in [first, second] {
+                if big(child) {
+                    pending.push((child, cluster));
+                } else {
+                    for leaf in tree.leaves_of(child) {
+                        points[leaf] = cluster;
+                        clusters[cluster].stability += density - birth;
+                    }
+                }
+            }
+        }
+
+        CondensedTree { clusters, points }
+    }
+
+    // Selects the most stable clusters, which never include the root, and
+    // labels each point with the selected cluster it falls out of.
+    fn labels(&self) -> Vec<Option<usize>> {
+        let mut selected = vec![false; self.clusters.len()];
+        let mut stability = self.clusters.iter().map(|cluster| cluster.stability).collect::<Vec<_>>();
+        // children are always created after their parent.
+        for id in (1..self.clusters.len()).rev() {
+            let children = &self.clusters[id].children;
+            let children_stability: f64 = children.iter().map(|&child| stability[child]).sum();
+            if children.is_empty() || stability[id] >= children_stability {
+                selected[id] = true;
+                let mut descendants = children.clone();
+                while let Some(descendant) = descendants.pop() {
+                    selected[descendant] = false;
+                    descendants.extend(&self.clusters[descendant].children);
+                }
+            } else {
+                stability[id] = children_stability;
+            }
+        }
+
+        self.points
+            .iter()
+            .map(|&cluster| {
+                let mut cluster = Some(cluster);
+                while let Some(id) = cluster {
+                    if selected[id] {
+                        return Some(id);
+                    }
+                    cluster = self.clusters[id].parent;
+                }
+                None
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use customer::Customer;
+
+    // `count` customers on a ring of `radius` kilometers around `center`,
+    // their user ids starting at `first_id`.
+    fn ring(first_id: i64, center: &Location, radius: f64, count: usize) -> Vec<Customer> {
+        (0..count)
+            .map(|index| {
+                let angle = 2f64 * PI * index as f64 / count as f64;
+                let latitude = center.latitude + (radius * angle.sin() / EARTH_RADIUS_IN_KM).to_degrees();
+                let longitude = center.longitude +
+                                (radius * angle.cos() / EARTH_RADIUS_IN_KM / center.latitude.to_radians().cos()).to_degrees();
+                Customer::new(first_id + index as i64, "Customer", &Location::new(latitude, longitude))
+            })
+            .collect()
+    }
+
+    fn customers() -> CustomerList {
+        let mut customers = ring(1, &Location::new(53.3393, -6.2576841), 2.5f64, 12);
+        customers.extend(ring(101, &Location::new(51.8969, -8.4863), 1f64, 8));
+        customers.push(Customer::new(1001, "Hermit", &Location::new(52.5, -7.5)));
+        customers.push(Customer::new(1002, "Islander", &Location::new(53.1, -9.7)));
+        CustomerList::from_vec(customers)
+    }
+
+    fn user_ids(customers: &CustomerList) -> Vec<i64> {
+        customers.iter().map(|customer| customer.user_id).collect()
+    }
+
+    fn located_user_ids(customers: &[LocatedCustomer]) -> Vec<i64> {
+        customers.iter().map(|located| located.customer.user_id).collect()
+    }
+
+    #[test]
+    fn dbscan_finds_the_dense_groups_and_the_noise() {
+        let clustering = Dbscan::new(Kilometers(2f64), 3).cluster(&customers());
+
+        assert_eq!(clustering.clusters.len(), 2);
+        assert_eq!(clustering.clusters[0].id, 1);
+        assert_eq!(located_user_ids(&clustering.clusters[0].customers), (1..13).collect::<Vec<_>>());
+        assert_eq!(located_user_ids(&clustering.clusters[1].customers), (101..109).collect::<Vec<_>>());
+        assert_eq!(user_ids(&clustering.noise), vec![1001, 1002]);
+
+        let dublin = clustering.clusters[0].centroid;
+        assert!(dublin.distance_from(&Location::new(53.3393, -6.2576841)).0 < 0.1);
+        assert_eq!(clustering.cluster_of(105), Some(2));
+        assert_eq!(clustering.cluster_of(1001), None);
+    }
+
+    #[test]
+    fn clusters_carry_the_distances_to_their_centroid_of_the_model() {
+        let clustering = Dbscan::new(Kilometers(2f64), 3).with_model(DistanceModel::Vincenty).cluster(&customers());
+
+        for cluster in &clustering.clusters {
+            for located in &cluster.customers {
+                assert_eq!(located.distance, DistanceModel::Vincenty.distance(&located.customer.location(), &cluster.centroid));
+            }
+        }
+    }
+
+    #[test]
+    fn dbscan_needs_min_points_within_eps() {
+        // only the two customers next to each one of the ring of Cork are within 1 km,
+        // the ones of the ring of Dublin are further apart.
+        let clustering = Dbscan::new(Kilometers(1f64), 4).cluster(&customers());
+        assert_eq!(clustering.clusters.len(), 0);
+        assert_eq!(clustering.noise.len(), 22);
+
+        let clustering = Dbscan::new(Kilometers(1f64), 3).cluster(&customers());
+        assert_eq!(located_user_ids(&clustering.clusters[0].customers), (101..109).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dbscan_clusters_across_the_antimeridian() {
+        let customers = CustomerList::from_vec(vec![
+            Customer::new(1, "Taveuni", &Location::new(-16.9, 179.99)),
+            Customer::new(2, "Rabi", &Location::new(-16.9, -179.99)),
+            Customer::new(3, "Qamea", &Location::new(-16.91, 179.995)),
+            Customer::new(4, "Suva", &Location::new(-18.14, 178.44)),
+        ]);
+
+        let clustering = Dbscan::new(Kilometers(5f64), 3).cluster(&customers);
+
+        assert_eq!(located_user_ids(&clustering.clusters[0].customers), vec![1, 2, 3]);
+        assert!(clustering.clusters[0].centroid.longitude.abs() > 179.9);
+        assert_eq!(user_ids(&clustering.noise), vec![4]);
+    }
+
+    #[test]
+    fn neighbour_grid_finds_the_same_neighbours_as_a_scan() {
+        let locations = customers().iter().map(|customer| customer.location()).collect::<Vec<_>>();
+        for model in [DistanceModel::Haversine, DistanceModel::Vincenty] {
+            for radius in [0.5, 2.0, 150.0, 20_000.0] {
+                let grid = NeighbourGrid::new(&locations, Kilometers(radius), model);
+                for index in 0..locations.len() {
+                    let scanned = (0..locations.len())
+                        .filter(|&other| model.distance(&locations[index], &locations[other]).0 <= radius)
+                        .collect::<Vec<_>>();
+                    assert_eq!(grid.neighbours(index), scanned, "{:?} radius {} index {}", model, radius, index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hdbscan_finds_clusters_of_different_densities() {
+        let mut customers = ring(1, &Location::new(53.3393, -6.2576841), 0.5f64, 10);
+        customers.extend(ring(101, &Location::new(51.8969, -8.4863), 8f64, 10));
+        // customers closer to a cluster than the clusters are to each other
+        // belong to it, only the ones further away than that are noise.
+        customers.push(Customer::new(1001, "Islander", &Location::new(55.5, -12.0)));
+        let customers = CustomerList::from_vec(customers);
+
+        let clustering = Hdbscan::new(5).cluster(&customers);
+
+        assert_eq!(clustering.clusters.len(), 2);
+        assert_eq!(located_user_ids(&clustering.clusters[0].customers), (1..11).collect::<Vec<_>>());
+        assert_eq!(located_user_ids(&clustering.clusters[1].customers), (101..111).collect::<Vec<_>>());
+        assert_eq!(user_ids(&clustering.noise), vec![1001]);
+    }
+
+    #[test]
+    fn hdbscan_leaves_too_few_customers_as_noise() {
+        let clustering = Hdbscan::new(5).cluster(&CustomerList::from_vec(ring(1, &Location::new(53.3393, -6.2576841), 1f64, 3)));
+
+        assert!(clustering.clusters.is_empty());
+        assert_eq!(clustering.noise.len(), 3);
+    }
+
+    #[test]
+    fn cluster_algorithm_parses_its_names() {
+        for name in ClusterAlgorithm::NAMES {
+            assert_eq!(ClusterAlgorithm::from_str(name).unwrap().to_string(), *name);
+        }
+        assert_eq!(ClusterAlgorithm::from_str(" HDBSCAN "), Ok(ClusterAlgorithm::Hdbscan));
+        assert!(ClusterAlgorithm::from_str("kmeans").is_err());
+    }
+}
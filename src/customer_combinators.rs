@@ -0,0 +1,373 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module containing the combinators `CustomerDatasource`s are composed
+//! with, in the spirit of the `Iterator` adapters. They are built with the
+//! `chain`, `merge`, `filter`, `map`, `limit` and `boxed` methods of the
+//! trait.
+//!
+//! # Examples
+//!
+//! "All customers from the EU file plus the UK one, minus test accounts,
+//! with coordinates rounded":
+//!
+//! ```
+//! let datasource = CustomerJsonFile::new(Path::new("eu.json"))
+//!     .merge(CustomerJsonFile::new(Path::new("uk.json")))
+//!     .filter(|customer| !customer.name.starts_with("Test "))
+//!     .map(|mut customer| {
+//!         customer.latitude = (customer.latitude * 1000f64).round() / 1000f64;
+//!         customer.longitude = (customer.longitude * 1000f64).round() / 1000f64;
+//!         customer
+//!     });
+//!
+//! match datasource.customers() {
+//!     Ok(customers) => println!("{} customers", customers.len()),
+//!     Err(CombinedError::First(err)) => println!("reading the EU file: {}", err),
+//!     Err(CombinedError::Second(err)) => println!("reading the UK file: {}", err),
+//! }
+//! ```
+//!
+
+use std::error;
+use std::fmt;
+
+use customer::{Customer, CustomerList};
+use customer_datasource::CustomerDatasource;
+
+/// An error of one of the two datasources a `Chain` or a `Merge` reads,
+/// telling which one failed along with its own error.
+#[derive(Debug)]
+pub enum CombinedError<A, B> {
+    First(A),
+    Second(B),
+}
+
+impl<A: fmt::Display, B: fmt::Display> fmt::Display for CombinedError<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CombinedError::First(ref err) => write!(f, "First datasource error: {}", err),
+            CombinedError::Second(ref err) => write!(f, "Second datasource error: {}", err),
+        }
+    }
+}
+
+impl<A, B> error::Error for CombinedError<A, B>
+    where A: error::Error + 'static,
+          B: error::Error + 'static
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CombinedError::First(ref err) => Some(err),
+            CombinedError::Second(ref err) => Some(err),
+        }
+    }
+}
+
+/// A datasource with the customers of the first one followed by the ones of
+/// the second one. See `CustomerDatasource::chain`.
+#[derive(Debug)]
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Chain<A, B> {
+    pub fn new(first: A, second: B) -> Chain<A, B> {
+        Chain { first, second }
+    }
+}
+
+impl<A, B> CustomerDatasource for Chain<A, B>
+    where A: CustomerDatasource,
+          B: CustomerDatasource,
+          A::Err: 'static,
+          B::Err: 'static
+{
+    type Err = CombinedError<A::Err, B::Err>;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let mut customers = self.first.customers().map_err(CombinedError::First)?;
+        customers.extend(self.second.customers().map_err(CombinedError::Second)?);
+
+        Ok(customers)
+    }
+}
+
+/// A datasource with the customers of two others, only keeping the first
+/// customer read with each `user_id`. See `CustomerDatasource::merge`.
+#[derive(Debug)]
+pub struct Merge<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Merge<A, B> {
+    pub fn new(first: A, second: B) -> Merge<A, B> {
+        Merge { first, second }
+    }
+}
+
+impl<A, B> CustomerDatasource for Merge<A, B>
+    where A: CustomerDatasource,
+          B: CustomerDatasource,
+          A::Err: 'static,
+          B::Err: 'static
+{
+    type Err = CombinedError<A::Err, B::Err>;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let mut customers = CustomerList::from_vec(Vec::new());
+        customers.merge(self.first.customers().map_err(CombinedError::First)?);
+        customers.merge(self.second.customers().map_err(CombinedError::Second)?);
+
+        Ok(customers)
+    }
+}
+
+/// A datasource with the customers of another one `predicate` holds for.
+/// See `CustomerDatasource::filter`.
+pub struct Filter<D, P> {
+    datasource: D,
+    predicate: P,
+}
+
+impl<D, P> Filter<D, P> {
+    pub fn new(datasource: D, predicate: P) -> Filter<D, P> {
+        Filter { datasource, predicate }
+    }
+}
+
+impl<D, P> CustomerDatasource for Filter<D, P>
+    where D: CustomerDatasource,
+          P: Fn(&Customer) -> bool
+{
+    type Err = D::Err;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let customers = self.datasource.customers()?;
+        Ok(customers.into_iter().filter(|customer| (self.predicate)(customer)).collect())
+    }
+}
+
+/// A datasource with the customers of another one transformed by `f`.
+/// See `CustomerDatasource::map`.
+pub struct Map<D, F> {
+    datasource: D,
+    f: F,
+}
+
+impl<D, F> Map<D, F> {
+    pub fn new(datasource: D, f: F) -> Map<D, F> {
+        Map { datasource, f }
+    }
+}
+
+impl<D, F> CustomerDatasource for Map<D, F>
+    where D: CustomerDatasource,
+          F: Fn(Customer) -> Customer
+{
+    type Err = D::Err;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let customers = self.datasource.customers()?;
+        Ok(customers.into_iter().map(&self.f).collect())
+    }
+}
+
+/// A datasource with, at most, the first `limit` customers of another one.
+/// See `CustomerDatasource::limit`.
+#[derive(Debug)]
+pub struct Limit<D> {
+    datasource: D,
+    limit: usize,
+}
+
+impl<D> Limit<D> {
+    pub fn new(datasource: D, limit: usize) -> Limit<D> {
+        Limit { datasource, limit }
+    }
+}
+
+impl<D: CustomerDatasource> CustomerDatasource for Limit<D> {
+    type Err = D::Err;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let customers = self.datasource.customers()?;
+        Ok(customers.into_iter().take(self.limit).collect())
+    }
+}
+
+/// A datasource of any type with its errors boxed, for combining datasources
+/// only known at runtime, like the inputs given on the command line. See
+/// `CustomerDatasource::boxed`.
+pub struct Boxed<'a> {
+    customers: Box<dyn Fn() -> Result<CustomerList, BoxedError> + 'a>,
+}
+
+impl<'a> Boxed<'a> {
+    pub fn new<D>(datasource: D) -> Boxed<'a>
+        where D: CustomerDatasource + 'a,
+              D::Err: 'static
+    {
+        Boxed { customers: Box::new(move || datasource.customers().map_err(|err| BoxedError(Box::new(err)))) }
+    }
+}
+
+impl<'a> CustomerDatasource for Boxed<'a> {
+    type Err = BoxedError;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        (self.customers)()
+    }
+}
+
+/// The error of the datasource a `Boxed` one boxes, displayed as it is.
+#[derive(Debug)]
+pub struct BoxedError(Box<dyn error::Error>);
+
+impl fmt::Display for BoxedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl error::Error for BoxedError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::error::Error;
+
+    use location::Location;
+
+    // A datasource handing over the customers it was built with, or failing
+    // with its name.
+    struct StaticDatasource {
+        name: &'static str,
+        customers: Option<Vec<Customer>>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct StaticDatasourceError(&'static str);
+
+    impl fmt::Display for StaticDatasourceError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} is unavailable", self.0)
+        }
+    }
+
+    impl error::Error for StaticDatasourceError {}
+
+    impl CustomerDatasource for StaticDatasource {
+        type Err = StaticDatasourceError;
+
+        fn customers(&self) -> Result<CustomerList, Self::Err> {
+            match self.customers {
+                Some(ref customers) => Ok(CustomerList::from_vec(customers.clone())),
+                None => Err(StaticDatasourceError(self.name)),
+            }
+        }
+    }
+
+    fn eu() -> StaticDatasource {
+        StaticDatasource {
+            name: "eu",
+            customers: Some(vec![
+                Customer::new(1, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
+                Customer::new(2, "Test Account", &Location::new(53.1302756, -6.2397222)),
+                Customer::new(3, "Theresa Enright", &Location::new(53.1229599, -6.2705202)),
+            ]),
+        }
+    }
+
+    fn uk() -> StaticDatasource {
+        StaticDatasource {
+            name: "uk",
+            customers: Some(vec![
+                Customer::new(3, "Theresa Enright (UK)", &Location::new(54.5972850, -5.9301200)),
+                Customer::new(4, "Patricia Cahill", &Location::new(54.180238, -5.920898)),
+            ]),
+        }
+    }
+
+    fn unavailable(name: &'static str) -> StaticDatasource {
+        StaticDatasource { name, customers: None }
+    }
+
+    fn user_ids(customers: &CustomerList) -> Vec<i64> {
+        customers.iter().map(|customer| customer.user_id).collect()
+    }
+
+    #[test]
+    fn chain_appends_the_customers_of_the_second_datasource() {
+        let customers = eu().chain(uk()).customers().unwrap();
+
+        assert_eq!(user_ids(&customers), vec![1, 2, 3, 3, 4]);
+    }
+
+    #[test]
+    fn merge_keeps_the_first_customer_read_with_each_user_id() {
+        let customers = eu().merge(uk()).customers().unwrap();
+
+        assert_eq!(user_ids(&customers), vec![1, 2, 3, 4]);
+        assert_eq!(customers.get(3).unwrap().name, "Theresa Enright");
+    }
+
+    #[test]
+    fn filter_map_and_limit_compose() {
+        let datasource = eu().merge(uk())
+            .filter(|customer| !customer.name.starts_with("Test "))
+            .map(|mut customer| {
+                customer.latitude = (customer.latitude * 10f64).round() / 10f64;
+                customer
+            });
+
+        let customers = datasource.customers().unwrap();
+        let limited = datasource.limit(2).customers().unwrap();
+
+        assert_eq!(user_ids(&customers), vec![1, 3, 4]);
+        assert_eq!(customers.get(1).unwrap().latitude, 53.2);
+        assert_eq!(user_ids(&limited), vec![1, 3]);
+    }
+
+    #[test]
+    fn combined_errors_tell_which_datasource_failed() {
+        match eu().chain(unavailable("uk")).customers() {
+            Err(CombinedError::Second(err)) => assert_eq!(err, StaticDatasourceError("uk")),
+            other => panic!("expected an error on the second datasource, got {:?}", other),
+        }
+
+        let err = unavailable("eu").merge(uk()).chain(unavailable("us")).limit(10).customers().unwrap_err();
+        match err {
+            CombinedError::First(CombinedError::First(ref err)) => assert_eq!(*err, StaticDatasourceError("eu")),
+            ref other => panic!("expected an error on the first datasource, got {:?}", other),
+        }
+        assert_eq!(err.to_string(), "First datasource error: First datasource error: eu is unavailable");
+        assert_eq!(err.source().unwrap().source().unwrap().to_string(), "eu is unavailable");
+    }
+
+    #[test]
+    fn boxed_datasources_of_different_types_combine() {
+        let mut datasource = eu().boxed();
+        for next in [uk().limit(1).boxed(), unavailable("us").boxed()] {
+            datasource = datasource.chain(next).boxed();
+        }
+
+        let err = datasource.customers().unwrap_err();
+        assert_eq!(err.to_string(), "Second datasource error: us is unavailable");
+        let customers = eu().boxed().merge(uk().boxed()).customers().unwrap();
+        assert_eq!(user_ids(&customers), vec![1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::convert::From;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::io::Error as IoError;
+use std::path::Path;
+use std::str::FromStr;
+
+use csv;
+use csv::Error as CsvError;
+
+use customer::Customer;
+use customer::CustomerList;
+use customer_attribute::AttributeValue;
+use customer_datasource::CustomerDatasource;
+use location::Location;
+
+/// Struct abstracting the idea of a CSV file containing customer data.
+///
+/// The file has a header with `user_id`, `name`, `latitude` and `longitude`
+/// columns, in any order, and any other column is read as an attribute of
+/// the customers: the same layout `--output csv` writes.
+///
+/// ```csv
+/// user_id,name,latitude,longitude,email
+/// 25,David Behan,52.833502,-8.522366,david@example.com
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// let customers_csv_file = CustomerCsvFile::new(Path::new("customers.csv"));
+///
+/// // Errors handling omitted for brevity
+/// let locator = CustomerLocator::from_source(customers_csv_file).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Opening or reading the file fails with `CustomerCsvFileError::Io`, a
+/// malformed CSV with `CustomerCsvFileError::Csv` and a missing column, or a
+/// cell that isn't a valid user id or coordinate, with
+/// `CustomerCsvFileError::InvalidValue`.
+pub struct CustomerCsvFile<'f> {
+    file_path: &'f Path,
+}
+
+impl<'f> CustomerCsvFile<'f> {
+    pub fn new(file_path: &'f Path) -> CustomerCsvFile<'f> {
+        CustomerCsvFile { file_path }
+    }
+}
+
+/// An error encapsulating the things that can go wrong when trying to read
+/// customers in CSV and build a `CustomerList`.
+#[derive(Debug)]
+pub enum CustomerCsvFileError {
+    Io(IoError),
+    Csv(CsvError),
+    InvalidValue(String),
+}
+
+impl fmt::Display for CustomerCsvFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CustomerCsvFileError::Io(ref err) => write!(f, "Customer CSV file IO error: {}", err),
+            CustomerCsvFileError::Csv(ref err) => write!(f, "Customer CSV file parsing error: {}", err),
+            CustomerCsvFileError::InvalidValue(ref message) => write!(f, "Customer CSV file invalid value: {}", message),
+        }
+    }
+}
+
+impl error::Error for CustomerCsvFileError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CustomerCsvFileError::Io(ref err) => Some(err),
+            CustomerCsvFileError::Csv(ref err) => Some(err),
+            CustomerCsvFileError::InvalidValue(_) => None,
+        }
+    }
+}
+
+impl From<IoError> for CustomerCsvFileError {
+    fn from(err: IoError) -> Self {
+        CustomerCsvFileError::Io(err)
+    }
+}
+
+impl From<CsvError> for CustomerCsvFileError {
+    fn from(err: CsvError) -> Self {
+        CustomerCsvFileError::Csv(err)
+    }
+}
+
+impl<'f> CustomerDatasource for CustomerCsvFile<'f> {
+    type Err = CustomerCsvFileError;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let file = File::open(self.file_path)?;
+        read_csv(BufReader::new(file))
+    }
+}
+
+/// Reads the customers of a CSV file, or of any other CSV `reader`, like a
+/// body being downloaded.
+pub fn read_csv<R: Read>(reader: R) -> Result<CustomerList, CustomerCsvFileError> {
+    let mut csv_reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    let header: Vec<String> = csv_reader.headers()?.iter().map(String::from).collect();
+    let column = |name: &str| {
+        header.iter()
+            .position(|column| column == name)
+            .ok_or_else(|| CustomerCsvFileError::InvalidValue(format!("missing column '{}'", name)))
+    };
+    let (user_id, name, latitude, longitude) = (column("user_id")?, column("name")?, column("latitude")?, column("longitude")?);
+
+    let mut customers = Vec::new();
+    for (row, record) in csv_reader.records().enumerate() {
+        let record = record?;
+        let field = |index: usize| record.get(index).unwrap_or("");
+        let number = |index: usize| {
+            f64::from_str(field(index)).map_err(|_| {
+                CustomerCsvFileError::InvalidValue(format!("row {}: '{}' is not a valid {}", row + 1, field(index), header[index]))
+            })
+        };
+        let id = i64::from_str(field(user_id)).map_err(|_| {
+            CustomerCsvFileError::InvalidValue(format!("row {}: '{}' is not a valid user_id", row + 1, field(user_id)))
+        })?;
+
+        let mut customer = Customer::new(id, field(name), &Location::new(number(latitude)?, number(longitude)?));
+        for (index, column) in header.iter().enumerate() {
+            // a missing attribute is written as an empty cell, see `customer_output`.
+            if [user_id, name, latitude, longitude].contains(&index) || field(index).is_empty() {
+                continue;
+            }
+            customer.attributes.insert(column.clone(), csv_attribute_value(field(index)));
+        }
+        customers.push(customer);
+    }
+
+    Ok(CustomerList::from_vec(customers))
+}
+
+// CSV cells are untyped, so numbers and booleans are told apart from text by how they parse.
+fn csv_attribute_value(value: &str) -> AttributeValue {
+    if let Ok(value) = i64::from_str(value) {
+        AttributeValue::Integer(value)
+    } else if let Ok(value) = f64::from_str(value) {
+        AttributeValue::Float(value)
+    } else if let Ok(value) = bool::from_str(value) {
+        AttributeValue::Bool(value)
+    } else {
+        AttributeValue::Text(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    const CUSTOMERS_CSV_FILE: &str = "tests/fixtures/customers.csv";
+
+    #[test]
+    fn customer_csv_file_builds_a_customer_list_from_a_csv_file() {
+        let customers = CustomerCsvFile::new(Path::new(CUSTOMERS_CSV_FILE)).customers().unwrap();
+
+        let expected_customers = CustomerList::from_vec(vec![
+            Customer::new(1, "Jose Narvaez", &Location::new(52.986375, -6.043701))
+                .with_attribute("email", "jose@example.com")
+                .with_attribute("tier", 1i64),
+            Customer::new(2, "Narvaez, Carlos", &Location::new(51.92893, -10.27699))
+                .with_attribute("vip", true),
+            Customer::new(3, "Maholys Narvaez", &Location::new(51.8856167, -10.4240951))
+                .with_attribute("tier", 2.5f64),
+        ]);
+        assert_eq!(customers, expected_customers);
+    }
+
+    #[test]
+    fn customer_csv_file_returns_io_error_when_io_occurs() {
+        match CustomerCsvFile::new(Path::new("unexistent_customer_file.csv")).customers() {
+            Err(CustomerCsvFileError::Io(_)) => (),
+            other => panic!("expected an io error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_csv_rows_are_invalid_values() {
+        let err = read_csv(Cursor::new("user_id,name,latitude,longitude\n1,Nora Dempsey,north,-6.2\n")).unwrap_err();
+        assert_eq!(err.to_string(), "Customer CSV file invalid value: row 1: 'north' is not a valid latitude");
+
+        let err = read_csv(Cursor::new("id,name,latitude,longitude\n")).unwrap_err();
+        assert_eq!(err.to_string(), "Customer CSV file invalid value: missing column 'user_id'");
+    }
+}
@@ -6,9 +6,153 @@
 // This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::error;
 use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
-use customer::CustomerList;
+use customer::{Customer, CustomerList};
+use customer_combinators::{Boxed, Chain, Filter, Limit, Map, Merge};
+
+/// The formats customers can be read from.
+///
+/// `Json` reads a JSON object per line with `CustomerJsonFile` and
+/// `JsonMmap` reads the same format through a memory map with
+/// `CustomerJsonMmapFile`. `Csv` reads a CSV file with `CustomerCsvFile`.
+/// `Sqlite` reads a SQLite database with `CustomerSqlite` and `Postgres`
+/// the PostgreSQL database the input connection string points to with
+/// `CustomerPostgres`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Json,
+    JsonMmap,
+    Csv,
+    Sqlite,
+    Postgres,
+}
+
+impl InputFormat {
+    /// The names accepted by `InputFormat::from_str`.
+    pub const NAMES: &'static [&'static str] = &["json", "json-mmap", "csv", "sqlite", "postgres"];
+}
+
+/// An error when trying to build an `InputFormat` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseInputFormatError(String);
+
+impl FromStr for InputFormat {
+    type Err = ParseInputFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(InputFormat::Json),
+            "json-mmap" => Ok(InputFormat::JsonMmap),
+            "csv" => Ok(InputFormat::Csv),
+            "sqlite" => Ok(InputFormat::Sqlite),
+            "postgres" => Ok(InputFormat::Postgres),
+            other => Err(ParseInputFormatError(format!("unknown input format '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InputFormat::Json => write!(f, "json"),
+            InputFormat::JsonMmap => write!(f, "json-mmap"),
+            InputFormat::Csv => write!(f, "csv"),
+            InputFormat::Sqlite => write!(f, "sqlite"),
+            InputFormat::Postgres => write!(f, "postgres"),
+        }
+    }
+}
+
+impl fmt::Display for ParseInputFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Input format parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseInputFormatError {}
+
+/// Where the customers are read from on a SQL database: every row of a
+/// table or the rows a query returns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlSource {
+    Table(String),
+    Query(String),
+}
+
+/// The names of the columns holding each of the customer fields.
+///
+/// Any other column is read as an extra attribute of the customer.
+///
+/// # Examples
+///
+/// ```
+/// let columns = ColumnMapping::from_str("user_id=id, latitude=lat, longitude=lng").unwrap();
+/// assert_eq!(columns.name, "name");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMapping {
+    pub user_id: String,
+    pub name: String,
+    pub latitude: String,
+    pub longitude: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> ColumnMapping {
+        ColumnMapping {
+            user_id: String::from("user_id"),
+            name: String::from("name"),
+            latitude: String::from("latitude"),
+            longitude: String::from("longitude"),
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Returns the mapped columns, in the order of the customer fields.
+    pub fn columns(&self) -> [&str; 4] {
+        [&self.user_id, &self.name, &self.latitude, &self.longitude]
+    }
+}
+
+/// An error when trying to build a `ColumnMapping` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseColumnMappingError(String);
+
+impl FromStr for ColumnMapping {
+    type Err = ParseColumnMappingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut columns = ColumnMapping::default();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (field, column) = match pair.split_once('=') {
+                Some((field, column)) if !column.trim().is_empty() => (field.trim(), column.trim().to_string()),
+                _ => return Err(ParseColumnMappingError(format!("expected field=column, got '{}'", pair))),
+            };
+            match field {
+                "user_id" => columns.user_id = column,
+                "name" => columns.name = column,
+                "latitude" => columns.latitude = column,
+                "longitude" => columns.longitude = column,
+                other => return Err(ParseColumnMappingError(format!("unknown customer field '{}'", other))),
+            }
+        }
+
+        Ok(columns)
+    }
+}
+
+impl fmt::Display for ParseColumnMappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Column mapping parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseColumnMappingError {}
 
 ///
 /// Trait used to decouple the format of the customer's file
@@ -19,7 +163,8 @@ use customer::CustomerList;
 /// This is one of the core tenets of the library as it allows
 /// implementors to extend the range of supported input formats
 /// allowed. `CustomerDatasource` has a single `customers` method
-/// that returns a `CustomerList`.
+/// that returns a `CustomerList`, the rest are combinators composing
+/// datasources like `Iterator` adapters do (see `customer_combinators`).
 ///
 /// # Examples
 ///
@@ -61,4 +206,69 @@ use customer::CustomerList;
 pub trait CustomerDatasource {
     type Err: Error;
     fn customers(&self) -> Result<CustomerList, Self::Err>;
-}
\ No newline at end of file
+
+    /// Returns a datasource with the customers of `self` followed by the
+    /// ones of `other`.
+    fn chain<O: CustomerDatasource>(self, other: O) -> Chain<Self, O>
+        where Self: Sized
+    {
+        Chain::new(self, other)
+    }
+
+    /// Returns a datasource with the customers of `self` and `other`, only
+    /// keeping the first one read with each `user_id`.
+    fn merge<O: CustomerDatasource>(self, other: O) -> Merge<Self, O>
+        where Self: Sized
+    {
+        Merge::new(self, other)
+    }
+
+    /// Returns a datasource with the customers of `self` that `predicate`
+    /// holds for.
+    fn filter<P: Fn(&Customer) -> bool>(self, predicate: P) -> Filter<Self, P>
+        where Self: Sized
+    {
+        Filter::new(self, predicate)
+    }
+
+    /// Returns a datasource with the customers of `self` transformed by `f`.
+    fn map<F: Fn(Customer) -> Customer>(self, f: F) -> Map<Self, F>
+        where Self: Sized
+    {
+        Map::new(self, f)
+    }
+
+    /// Returns a datasource with, at most, the first `limit` customers of `self`.
+    fn limit(self, limit: usize) -> Limit<Self>
+        where Self: Sized
+    {
+        Limit::new(self, limit)
+    }
+
+    /// Returns a datasource boxing `self`, so datasources whose types are
+    /// only known at runtime can be combined one after another.
+    fn boxed<'a>(self) -> Boxed<'a>
+        where Self: Sized + 'a,
+              Self::Err: 'static
+    {
+        Boxed::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_mapping_from_str_overrides_the_given_fields() {
+        let columns = ColumnMapping::from_str("user_id=id, latitude = lat").unwrap();
+
+        assert_eq!(columns.user_id, "id");
+        assert_eq!(columns.latitude, "lat");
+        assert_eq!(columns.name, "name");
+        assert_eq!(ColumnMapping::from_str("email=mail"),
+                   Err(ParseColumnMappingError(String::from("unknown customer field 'email'"))));
+        assert_eq!(ColumnMapping::from_str("user_id"),
+                   Err(ParseColumnMappingError(String::from("expected field=column, got 'user_id'"))));
+    }
+}
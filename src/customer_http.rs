@@ -0,0 +1,848 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module implementing a `CustomerDatasource` reading the customers from
+//! an HTTP(S) URL.
+//!
+//! The body is parsed while it's being downloaded, either as JSON lines
+//! (the format of the customer files) or as CSV, read like a
+//! `CustomerCsvFile`. Gzip and deflate bodies are decompressed
+//! on the fly, whether the server compressed them on the wire or the file
+//! itself is a `.gz` one.
+//!
+//! With a cache directory the last body downloaded is kept along with its
+//! `ETag` and `Last-Modified` headers, which are sent back on the next run
+//! so an unchanged file is answered with a `304 Not Modified` and read from
+//! the cache instead of downloaded again.
+//!
+
+use std::convert::From;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use csv;
+use csv::Error as CsvError;
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use serde_json;
+use serde_json::Error as JsonError;
+use ureq;
+
+use customer::Customer;
+use customer::CustomerList;
+use customer_csv_file;
+use customer_csv_file::CustomerCsvFileError;
+use customer_datasource::CustomerDatasource;
+use customer_snapshot::Fnv1a;
+
+const DEFAULT_TIMEOUT_IN_SECS: u64 = 30;
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_RETRY_DELAY_IN_MILLIS: u64 = 500;
+const ACCEPT_ENCODING: &str = "gzip, deflate";
+
+/// Tells whether `input` is an HTTP(S) URL rather than a file path.
+pub fn is_url(input: &str) -> bool {
+    let input = input.trim_start().to_lowercase();
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// The formats a customers body can come in.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BodyFormat {
+    JsonLines,
+    Csv,
+}
+
+impl BodyFormat {
+    /// The names accepted by `BodyFormat::from_str`.
+    pub const NAMES: &'static [&'static str] = &["json-lines", "csv"];
+
+    /// Guesses the format of a body from its `Content-Type`, falling back to
+    /// the extension of the URL, and to JSON lines when neither tells.
+    pub fn detect(content_type: Option<&str>, url: &str) -> BodyFormat {
+        let content_type = content_type.unwrap_or("").to_lowercase();
+        if content_type.contains("csv") {
+            return BodyFormat::Csv;
+        }
+        if content_type.contains("json") {
+            return BodyFormat::JsonLines;
+        }
+
+        let path = url_path(url).to_lowercase();
+        if path.trim_end_matches(".gz").ends_with(".csv") {
+            BodyFormat::Csv
+        } else {
+            BodyFormat::JsonLines
+        }
+    }
+}
+
+/// An error when trying to build a `BodyFormat` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseBodyFormatError(String);
+
+impl FromStr for BodyFormat {
+    type Err = ParseBodyFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "json-lines" => Ok(BodyFormat::JsonLines),
+            "csv" => Ok(BodyFormat::Csv),
+            other => Err(ParseBodyFormatError(format!("unknown body format '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for ParseBodyFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Body format parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseBodyFormatError {}
+
+/// Struct abstracting the idea of a customers file served over HTTP(S).
+///
+/// # Examples
+///
+/// ```
+/// let datasource = CustomerHttp::new("https://example.com/customers.csv.gz")
+///     .with_cache_dir(Path::new("/var/cache/customer_locator"))
+///     .with_timeout(Duration::from_secs(10))
+///     .with_retries(5);
+///
+/// // Errors handling omitted for brevity
+/// let locator = CustomerLocator::from_source(datasource).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// A server answering with an error status, or a `304 Not Modified` when
+/// nothing was cached, is a `CustomerHttpError::Status` while not reaching it
+/// at all, a connection dropped or a timeout is a
+/// `CustomerHttpError::Transport`. A connection dropped or timed out while
+/// the body streams is an `Io` error instead. They are retried, starting the
+/// download over with a delay doubling after each attempt, when they might be
+/// transient: transport and those `Io` failures and the `408`, `429` and `5xx`
+/// statuses. A body that can't be decompressed or parsed fails with the
+/// `Encoding`, `Io`, `Json`, `Csv` or `InvalidValue` variants, which are not
+/// retried.
+#[derive(Debug)]
+pub struct CustomerHttp {
+    url: String,
+    cache_dir: Option<PathBuf>,
+    format: Option<BodyFormat>,
+    timeout: Duration,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl CustomerHttp {
+    pub fn new(url: &str) -> CustomerHttp {
+        CustomerHttp {
+            url: url.trim().to_string(),
+            cache_dir: None,
+            format: None,
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_IN_SECS),
+            retries: DEFAULT_RETRIES,
+            retry_delay: Duration::from_millis(DEFAULT_RETRY_DELAY_IN_MILLIS),
+        }
+    }
+
+    /// Keeps the last body downloaded on `cache_dir`, revalidating it with
+    /// the server instead of downloading it again.
+    pub fn with_cache_dir(mut self, cache_dir: &Path) -> CustomerHttp {
+        self.cache_dir = Some(cache_dir.to_path_buf());
+        self
+    }
+
+    /// Parses the body as `format` instead of guessing it.
+    pub fn with_format(mut self, format: BodyFormat) -> CustomerHttp {
+        self.format = Some(format);
+        self
+    }
+
+    /// Gives up on connecting, or on a body that stops arriving, after
+    /// `timeout`. 30 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> CustomerHttp {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Tries the download up to `retries` more times on transient failures.
+    /// 3 by default.
+    pub fn with_retries(mut self, retries: u32) -> CustomerHttp {
+        self.retries = retries;
+        self
+    }
+
+    /// The delay before the first retry, doubled on each of the next ones.
+    /// Half a second by default.
+    #[cfg(test)]
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> CustomerHttp {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    // Sends the request, revalidating the `cached` body if there's one.
+    fn fetch(&self, agent: &ureq::Agent, cached: Option<&CachedResponse>) -> Result<ureq::Response, CustomerHttpError> {
+        let mut request = agent.get(&self.url).set("Accept-Encoding", ACCEPT_ENCODING);
+        if let Some(cached) = cached {
+            if let Some(ref etag) = cached.etag {
+                request = request.set("If-None-Match", etag);
+            }
+            if let Some(ref last_modified) = cached.last_modified {
+                request = request.set("If-Modified-Since", last_modified);
+            }
+        }
+
+        match request.call() {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(status, _)) => Err(CustomerHttpError::Status(self.url.clone(), status)),
+            Err(ureq::Error::Transport(transport)) => Err(CustomerHttpError::Transport(Box::new(transport))),
+        }
+    }
+
+    // Downloads and parses the customers once, reading them from the cache
+    // when the server says the `cached` body is still good.
+    fn download(&self,
+                agent: &ureq::Agent,
+                cached: Option<&CachedResponse>,
+                cache_paths: Option<&(PathBuf, PathBuf)>)
+                -> Result<CustomerList, CustomerHttpError> {
+        let response = self.fetch(agent, cached)?;
+        if response.status() == 304 {
+            return match (cached, cache_paths) {
+                (Some(cached), Some((_, body_path))) => read_customers(BufReader::new(File::open(body_path)?), cached.format),
+                _ => Err(CustomerHttpError::Status(self.url.clone(), 304)),
+            };
+        }
+
+        let content_type = response.header("Content-Type").map(String::from);
+        let format = self.format.unwrap_or_else(|| BodyFormat::detect(content_type.as_deref(), &self.url));
+        let etag = response.header("ETag").map(String::from);
+        let last_modified = response.header("Last-Modified").map(String::from);
+        let encoding = response.header("Content-Encoding").unwrap_or("").trim().to_lowercase();
+        let gzip_file = content_type.as_deref().is_some_and(|content_type| content_type.contains("gzip")) ||
+                        url_path(&self.url).to_lowercase().ends_with(".gz");
+        let body = decompress(response.into_reader(), &encoding, gzip_file)?;
+
+        // a body without validators can't be revalidated, so there's no point on keeping it.
+        let (meta_path, body_path) = match cache_paths {
+            Some(paths) if etag.is_some() || last_modified.is_some() => paths,
+            _ => return read_customers(BufReader::new(body), format),
+        };
+
+        // the body is written to the cache as it's parsed, and only kept once it parsed fine.
+        if let Some(cache_dir) = body_path.parent() {
+            fs::create_dir_all(cache_dir)?;
+        }
+        let partial_path = body_path.with_extension("partial");
+        let customers = {
+            let tee = TeeReader { reader: body, writer: File::create(&partial_path)? };
+            read_customers(BufReader::new(tee), format)
+        };
+        let customers = match customers {
+            Ok(customers) => customers,
+            Err(err) => {
+                let _ = fs::remove_file(&partial_path);
+                return Err(err);
+            }
+        };
+        fs::rename(&partial_path, body_path)?;
+        CachedResponse { url: self.url.clone(), etag, last_modified, format }.write(meta_path)?;
+
+        Ok(customers)
+    }
+
+    fn cache_paths(&self) -> Option<(PathBuf, PathBuf)> {
+        self.cache_dir.as_ref().map(|cache_dir| {
+            let mut hasher = Fnv1a::new();
+            hasher.update(self.url.as_bytes());
+            let name = format!("{:016x}", hasher.finish());
+            (cache_dir.join(format!("{}.json", name)), cache_dir.join(format!("{}.body", name)))
+        })
+    }
+}
+
+/// An error encapsulating the things that can go wrong when trying to
+/// download and parse a customers body and build a `CustomerList`.
+#[derive(Debug)]
+pub enum CustomerHttpError {
+    Status(String, u16),
+    Transport(Box<ureq::Transport>),
+    Encoding(String),
+    Io(IoError),
+    Json(JsonError),
+    Csv(CsvError),
+    InvalidValue(String),
+}
+
+impl CustomerHttpError {
+    /// Tells whether trying the download again might succeed.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            CustomerHttpError::Status(_, status) => status == 408 || status == 429 || status >= 500,
+            CustomerHttpError::Transport(_) => true,
+            CustomerHttpError::Io(ref err) => is_transient_io(err),
+            CustomerHttpError::Csv(ref err) => match *err.kind() {
+                csv::ErrorKind::Io(ref err) => is_transient_io(err),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+// The body stopped arriving: the connection was dropped or timed out.
+fn is_transient_io(err: &IoError) -> bool {
+    matches!(err.kind(),
+             IoErrorKind::TimedOut | IoErrorKind::WouldBlock | IoErrorKind::UnexpectedEof |
+             IoErrorKind::ConnectionReset | IoErrorKind::ConnectionAborted | IoErrorKind::BrokenPipe |
+             IoErrorKind::Interrupted)
+}
+
+impl fmt::Display for CustomerHttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CustomerHttpError::Status(ref url, status) => write!(f, "Customer HTTP status error: {} answered {}", url, status),
+            CustomerHttpError::Transport(ref err) => write!(f, "Customer HTTP transport error: {}", err),
+            CustomerHttpError::Encoding(ref encoding) => write!(f, "Customer HTTP encoding error: unsupported content encoding '{}'", encoding),
+            CustomerHttpError::Io(ref err) => write!(f, "Customer HTTP IO error: {}", err),
+            CustomerHttpError::Json(ref err) => write!(f, "Customer HTTP JSON parsing error: {}", err),
+            CustomerHttpError::Csv(ref err) => write!(f, "Customer HTTP CSV parsing error: {}", err),
+            CustomerHttpError::InvalidValue(ref message) => write!(f, "Customer HTTP invalid value: {}", message),
+        }
+    }
+}
+
+impl error::Error for CustomerHttpError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CustomerHttpError::Transport(ref err) => Some(err.as_ref()),
+            CustomerHttpError::Io(ref err) => Some(err),
+            CustomerHttpError::Json(ref err) => Some(err),
+            CustomerHttpError::Csv(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<IoError> for CustomerHttpError {
+    fn from(err: IoError) -> Self {
+        CustomerHttpError::Io(err)
+    }
+}
+
+impl From<JsonError> for CustomerHttpError {
+    fn from(err: JsonError) -> Self {
+        CustomerHttpError::Json(err)
+    }
+}
+
+impl From<CsvError> for CustomerHttpError {
+    fn from(err: CsvError) -> Self {
+        CustomerHttpError::Csv(err)
+    }
+}
+
+impl From<CustomerCsvFileError> for CustomerHttpError {
+    fn from(err: CustomerCsvFileError) -> Self {
+        match err {
+            CustomerCsvFileError::Io(err) => CustomerHttpError::Io(err),
+            CustomerCsvFileError::Csv(err) => CustomerHttpError::Csv(err),
+            CustomerCsvFileError::InvalidValue(message) => CustomerHttpError::InvalidValue(message),
+        }
+    }
+}
+
+impl CustomerDatasource for CustomerHttp {
+    type Err = CustomerHttpError;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let cache_paths = self.cache_paths();
+        let cached = cache_paths.as_ref().and_then(|(meta_path, body_path)| {
+            CachedResponse::read(meta_path).filter(|cached| cached.url == self.url && body_path.exists())
+        });
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(self.timeout)
+            .timeout_read(self.timeout)
+            .timeout_write(self.timeout)
+            .build();
+
+        // transient failures start the download over with an exponential backoff.
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            let err = match self.download(&agent, cached.as_ref(), cache_paths.as_ref()) {
+                Ok(customers) => return Ok(customers),
+                Err(err) => err,
+            };
+            if attempt >= self.retries || !err.is_transient() {
+                return Err(err);
+            }
+
+            thread::sleep(delay);
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// What's recorded about the body kept on the cache.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    format: BodyFormat,
+}
+
+impl CachedResponse {
+    // A missing or unreadable record is just a cache miss.
+    fn read(path: &Path) -> Option<CachedResponse> {
+        let file = File::open(path).ok()?;
+        serde_json::from_reader(BufReader::new(file)).ok()
+    }
+
+    fn write(&self, path: &Path) -> Result<(), CustomerHttpError> {
+        let file = File::create(path)?;
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+}
+
+// Decompresses the body as its `Content-Encoding` says, or as a gzip file
+// when it isn't encoded but is one.
+fn decompress<R: Read + 'static>(body: R, encoding: &str, gzip_file: bool) -> Result<Box<dyn Read>, CustomerHttpError> {
+    match encoding {
+        "gzip" | "x-gzip" => Ok(Box::new(MultiGzDecoder::new(body))),
+        "deflate" => Ok(Box::new(ZlibDecoder::new(body))),
+        "" | "identity" if gzip_file => Ok(Box::new(MultiGzDecoder::new(body))),
+        "" | "identity" => Ok(Box::new(body)),
+        other => Err(CustomerHttpError::Encoding(other.to_string())),
+    }
+}
+
+fn read_customers<R: BufRead>(reader: R, format: BodyFormat) -> Result<CustomerList, CustomerHttpError> {
+    match format {
+        BodyFormat::JsonLines => read_json_lines(reader),
+        BodyFormat::Csv => Ok(customer_csv_file::read_csv(reader)?),
+    }
+}
+
+fn read_json_lines<R: BufRead>(reader: R) -> Result<CustomerList, CustomerHttpError> {
+    let mut customers = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let customer: Customer = serde_json::from_str(&line)?;
+        customers.push(customer);
+    }
+
+    Ok(CustomerList::from_vec(customers))
+}
+
+// The path of `url`, without its query string or fragment.
+fn url_path(url: &str) -> &str {
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    &url[..end]
+}
+
+/// A reader copying everything read through it to `writer`.
+struct TeeReader<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        let read = self.reader.read(buf)?;
+        self.writer.write_all(&buf[..read])?;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::io::Cursor;
+    use std::process;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use tiny_http::{Header, Response, Server};
+
+    use customer_attribute::AttributeValue;
+
+    const CUSTOMERS_JSON_LINES: &str = "\
+{\"user_id\": 1, \"name\": \"Nora Dempsey\", \"latitude\": \"53.1302756\", \"longitude\": \"-6.2397222\"}
+{\"user_id\": 2, \"name\": \"Eoin Ahearn\", \"latitude\": \"54.0894797\", \"longitude\": \"-6.18671\"}
+";
+
+    const CUSTOMERS_CSV: &str = "\
+user_id,name,latitude,longitude,email,tier,vip
+1,Nora Dempsey,53.1302756,-6.2397222,nora@example.com,1,true
+2,Eoin Ahearn,54.0894797,-6.18671,,2.5,false
+";
+
+    // A response the mock server answers with.
+    struct MockResponse {
+        status: u16,
+        headers: Vec<(&'static str, String)>,
+        body: Vec<u8>,
+        delay: Duration,
+    }
+
+    fn respond(status: u16, headers: &[(&'static str, &str)], body: &[u8]) -> MockResponse {
+        MockResponse {
+            status,
+            headers: headers.iter().map(|(name, value)| (*name, value.to_string())).collect(),
+            body: body.to_vec(),
+            delay: Duration::from_secs(0),
+        }
+    }
+
+    type RequestHeaders = Vec<(String, String)>;
+
+    // A local HTTP server answering each request with the next response of
+    // its script, and recording the headers of the requests it got.
+    struct MockServer {
+        url: String,
+        requests: Arc<Mutex<Vec<RequestHeaders>>>,
+    }
+
+    impl MockServer {
+        fn start(path: &str, script: Vec<MockResponse>) -> MockServer {
+            let server = Server::http("127.0.0.1:0").unwrap();
+            let url = format!("http://{}{}", server.server_addr().to_ip().unwrap(), path);
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let recorded = Arc::clone(&requests);
+            thread::spawn(move || {
+                for mock in script {
+                    let request = match server.recv() {
+                        Ok(request) => request,
+                        Err(_) => return,
+                    };
+                    let headers = request.headers()
+                        .iter()
+                        .map(|header| (header.field.to_string().to_lowercase(), header.value.to_string()))
+                        .collect();
+                    recorded.lock().unwrap().push(headers);
+
+                    thread::sleep(mock.delay);
+                    let mut response = Response::from_data(mock.body).with_status_code(mock.status);
+                    for (name, value) in mock.headers {
+                        response.add_header(Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap());
+                    }
+                    let _ = request.respond(response);
+                }
+            });
+
+            MockServer { url, requests }
+        }
+
+        fn request_count(&self) -> usize {
+            self.requests.lock().unwrap().len()
+        }
+
+        fn request_header(&self, request: usize, name: &str) -> Option<String> {
+            self.requests.lock().unwrap()[request]
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.clone())
+        }
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn temporary_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("customer_locator_http_{}_{}", process::id(), name))
+    }
+
+    fn user_ids(customers: &CustomerList) -> Vec<i64> {
+        customers.iter().map(|customer| customer.user_id).collect()
+    }
+
+    #[test]
+    fn is_url_only_accepts_http_and_https() {
+        assert!(is_url("https://example.com/customers.json"));
+        assert!(is_url("HTTP://example.com/customers.json"));
+        assert!(!is_url("data/customers.json"));
+        assert!(!is_url("postgresql://localhost/crm"));
+    }
+
+    #[test]
+    fn body_format_from_str_accepts_the_names() {
+        assert_eq!(BodyFormat::from_str("json-lines"), Ok(BodyFormat::JsonLines));
+        assert_eq!(BodyFormat::from_str(" CSV "), Ok(BodyFormat::Csv));
+        assert_eq!(BodyFormat::from_str("xml"), Err(ParseBodyFormatError(String::from("unknown body format 'xml'"))));
+    }
+
+    #[test]
+    fn body_format_is_detected_from_the_content_type_then_the_url() {
+        assert_eq!(BodyFormat::detect(Some("text/csv; charset=utf-8"), "http://x/customers"), BodyFormat::Csv);
+        assert_eq!(BodyFormat::detect(Some("application/x-ndjson"), "http://x/customers.csv"), BodyFormat::JsonLines);
+        assert_eq!(BodyFormat::detect(Some("application/gzip"), "http://x/customers.csv.gz?v=2"), BodyFormat::Csv);
+        assert_eq!(BodyFormat::detect(None, "http://x/customers"), BodyFormat::JsonLines);
+    }
+
+    #[test]
+    fn reads_json_lines() {
+        let server = MockServer::start("/customers.json", vec![
+            respond(200, &[("Content-Type", "application/x-ndjson")], CUSTOMERS_JSON_LINES.as_bytes()),
+        ]);
+
+        let customers = CustomerHttp::new(&server.url).customers().unwrap();
+
+        assert_eq!(user_ids(&customers), vec![1, 2]);
+        assert_eq!(customers.get(2).unwrap().latitude, 54.0894797);
+        assert_eq!(server.request_header(0, "accept-encoding").unwrap(), ACCEPT_ENCODING);
+    }
+
+    #[test]
+    fn reads_csv_with_attributes_compressed_on_the_wire() {
+        let server = MockServer::start("/customers", vec![
+            respond(200, &[("Content-Type", "text/csv"), ("Content-Encoding", "gzip")], &gzip(CUSTOMERS_CSV.as_bytes())),
+        ]);
+
+        let customers = CustomerHttp::new(&server.url).customers().unwrap();
+
+        let nora = customers.get(1).unwrap();
+        assert_eq!(nora.name, "Nora Dempsey");
+        assert_eq!(nora.attribute("email"), Some(&AttributeValue::from("nora@example.com")));
+        assert_eq!(nora.attribute("tier"), Some(&AttributeValue::Integer(1)));
+        assert_eq!(nora.attribute("vip"), Some(&AttributeValue::Bool(true)));
+        let eoin = customers.get(2).unwrap();
+        assert_eq!(eoin.attribute("email"), None);
+        assert_eq!(eoin.attribute("tier"), Some(&AttributeValue::Float(2.5)));
+    }
+
+    #[test]
+    fn reads_deflated_bodies_and_gzip_files() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(CUSTOMERS_JSON_LINES.as_bytes()).unwrap();
+        let deflated = encoder.finish().unwrap();
+        let server = MockServer::start("/customers.json", vec![respond(200, &[("Content-Encoding", "deflate")], &deflated)]);
+        assert_eq!(user_ids(&CustomerHttp::new(&server.url).customers().unwrap()), vec![1, 2]);
+
+        let server = MockServer::start("/customers.csv.gz", vec![
+            respond(200, &[("Content-Type", "application/gzip")], &gzip(CUSTOMERS_CSV.as_bytes())),
+        ]);
+        assert_eq!(user_ids(&CustomerHttp::new(&server.url).customers().unwrap()), vec![1, 2]);
+    }
+
+    #[test]
+    fn unsupported_encodings_fail() {
+        let server = MockServer::start("/customers.json", vec![respond(200, &[("Content-Encoding", "br")], b"??")]);
+
+        match CustomerHttp::new(&server.url).customers() {
+            Err(CustomerHttpError::Encoding(ref encoding)) => assert_eq!(encoding, "br"),
+            other => panic!("expected an encoding error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unchanged_bodies_are_read_from_the_cache() {
+        let cache_dir = temporary_path("cache");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let server = MockServer::start("/customers.csv", vec![
+            respond(200, &[("ETag", "\"v1\""), ("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT")], CUSTOMERS_CSV.as_bytes()),
+            respond(304, &[("ETag", "\"v1\"")], b""),
+        ]);
+        let datasource = CustomerHttp::new(&server.url).with_cache_dir(&cache_dir);
+
+        let downloaded = datasource.customers().unwrap();
+        let cached = datasource.customers().unwrap();
+
+        assert_eq!(server.request_count(), 2);
+        assert_eq!(server.request_header(0, "if-none-match"), None);
+        assert_eq!(server.request_header(1, "if-none-match").unwrap(), "\"v1\"");
+        assert_eq!(server.request_header(1, "if-modified-since").unwrap(), "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(user_ids(&cached), user_ids(&downloaded));
+        assert_eq!(cached.get(1).unwrap().attribute("tier"), Some(&AttributeValue::Integer(1)));
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn changed_bodies_replace_the_cached_ones() {
+        let cache_dir = temporary_path("changed_cache");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let changed = format!("{}{}\n", CUSTOMERS_JSON_LINES,
+                              r#"{"user_id": 3, "name": "Ian Kehoe", "latitude": "53.2451022", "longitude": "-6.238335"}"#);
+        let server = MockServer::start("/customers.json", vec![
+            respond(200, &[("ETag", "\"v1\"")], CUSTOMERS_JSON_LINES.as_bytes()),
+            respond(200, &[("ETag", "\"v2\"")], changed.as_bytes()),
+            respond(304, &[], b""),
+        ]);
+        let datasource = CustomerHttp::new(&server.url).with_cache_dir(&cache_dir);
+
+        assert_eq!(user_ids(&datasource.customers().unwrap()), vec![1, 2]);
+        assert_eq!(user_ids(&datasource.customers().unwrap()), vec![1, 2, 3]);
+        assert_eq!(user_ids(&datasource.customers().unwrap()), vec![1, 2, 3]);
+        assert_eq!(server.request_header(2, "if-none-match").unwrap(), "\"v2\"");
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn transient_statuses_are_retried() {
+        let server = MockServer::start("/customers.json", vec![
+            respond(503, &[], b"busy"),
+            respond(429, &[], b"slow down"),
+            respond(200, &[], CUSTOMERS_JSON_LINES.as_bytes()),
+        ]);
+
+        let customers = CustomerHttp::new(&server.url)
+            .with_retry_delay(Duration::from_millis(1))
+            .customers()
+            .unwrap();
+
+        assert_eq!(user_ids(&customers), vec![1, 2]);
+        assert_eq!(server.request_count(), 3);
+    }
+
+    #[test]
+    fn bodies_cut_short_are_retried() {
+        // the first response promises the whole body but stops halfway through it.
+        let body = CUSTOMERS_JSON_LINES.as_bytes();
+        let content_length = body.len().to_string();
+        let server = MockServer::start("/customers.json", vec![
+            respond(200, &[("Content-Length", &content_length)], &body[..body.len() / 2]),
+            respond(200, &[], body),
+        ]);
+
+        let customers = CustomerHttp::new(&server.url)
+            .with_timeout(Duration::from_millis(200))
+            .with_retry_delay(Duration::from_millis(1))
+            .customers()
+            .unwrap();
+
+        assert_eq!(user_ids(&customers), vec![1, 2]);
+        assert_eq!(server.request_count(), 2);
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        let server = MockServer::start("/missing.json", vec![respond(404, &[], b"not found"), respond(200, &[], b"")]);
+
+        let err = CustomerHttp::new(&server.url)
+            .with_retry_delay(Duration::from_millis(1))
+            .customers()
+            .unwrap_err();
+
+        match err {
+            CustomerHttpError::Status(ref url, 404) => assert_eq!(*url, server.url),
+            ref other => panic!("expected a status error, got {:?}", other),
+        }
+        assert!(!err.is_transient());
+        assert_eq!(server.request_count(), 1);
+    }
+
+    #[test]
+    fn retries_give_up_with_the_last_error() {
+        let server = MockServer::start("/customers.json", vec![respond(500, &[], b""), respond(502, &[], b"")]);
+
+        let err = CustomerHttp::new(&server.url)
+            .with_retries(1)
+            .with_retry_delay(Duration::from_millis(1))
+            .customers()
+            .unwrap_err();
+
+        match err {
+            CustomerHttpError::Status(_, 502) => {}
+            ref other => panic!("expected a 502, got {:?}", other),
+        }
+        assert_eq!(server.request_count(), 2);
+    }
+
+    #[test]
+    fn bodies_cut_short_are_transient() {
+        assert!(CustomerHttpError::Io(IoError::new(IoErrorKind::UnexpectedEof, "cut short")).is_transient());
+        assert!(CustomerHttpError::Io(IoError::new(IoErrorKind::ConnectionReset, "reset")).is_transient());
+        assert!(!CustomerHttpError::Io(IoError::new(IoErrorKind::PermissionDenied, "cache")).is_transient());
+    }
+
+    #[test]
+    fn not_modified_without_a_cache_is_a_status_error() {
+        let server = MockServer::start("/customers.json", vec![respond(304, &[], b"")]);
+
+        match CustomerHttp::new(&server.url).with_retries(0).customers() {
+            Err(CustomerHttpError::Status(_, 304)) => {}
+            other => panic!("expected a 304 status error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unreachable_servers_are_transport_errors() {
+        // binding and dropping a server leaves a port nobody listens on.
+        let address = Server::http("127.0.0.1:0").unwrap().server_addr().to_ip().unwrap();
+
+        let err = CustomerHttp::new(&format!("http://{}/customers.json", address))
+            .with_retries(2)
+            .with_retry_delay(Duration::from_millis(1))
+            .customers()
+            .unwrap_err();
+
+        match err {
+            CustomerHttpError::Transport(_) => assert!(err.is_transient()),
+            ref other => panic!("expected a transport error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn slow_servers_time_out() {
+        let mut slow = respond(200, &[], CUSTOMERS_JSON_LINES.as_bytes());
+        slow.delay = Duration::from_secs(2);
+        let server = MockServer::start("/customers.json", vec![slow]);
+
+        let started = Instant::now();
+        let err = CustomerHttp::new(&server.url)
+            .with_timeout(Duration::from_millis(200))
+            .with_retries(0)
+            .customers()
+            .unwrap_err();
+
+        match err {
+            CustomerHttpError::Transport(_) => assert!(started.elapsed() < Duration::from_secs(2)),
+            ref other => panic!("expected a transport error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_csv_rows_are_invalid_values() {
+        let err = read_customers(Cursor::new("user_id,name,latitude,longitude\n1,Nora Dempsey,north,-6.2\n"), BodyFormat::Csv).unwrap_err();
+        assert_eq!(err.to_string(), "Customer HTTP invalid value: row 1: 'north' is not a valid latitude");
+
+        let err = read_customers(Cursor::new("id,name,latitude,longitude\n"), BodyFormat::Csv).unwrap_err();
+        assert_eq!(err.to_string(), "Customer HTTP invalid value: missing column 'user_id'");
+    }
+}
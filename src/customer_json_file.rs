@@ -62,7 +62,7 @@ pub struct CustomerJsonFile<'f> {
 
 impl<'f> CustomerJsonFile<'f> {
     pub fn new(file_path: &'f Path) -> CustomerJsonFile<'f> {
-        CustomerJsonFile { file_path: file_path }
+        CustomerJsonFile { file_path }
     }
 }
 
@@ -84,14 +84,7 @@ impl fmt::Display for CustomerJsonFileError {
 }
 
 impl error::Error for CustomerJsonFileError {
-    fn description(&self) -> &str {
-        match *self {
-            CustomerJsonFileError::Io(ref err) => err.description(),
-            CustomerJsonFileError::Json(ref err) => err.description()
-        }
-    }
-
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
             CustomerJsonFileError::Io(ref err) => Some(err),
             CustomerJsonFileError::Json(ref err) => Some(err)
@@ -132,10 +125,13 @@ mod tests {
     use super::*;
     use customer::Customer;
     use customer::CustomerList;
+    use customer_attribute::AttributeValue;
     use location::Location;
 
-    const CUSTOMERS_OK_JSON_FILE: &'static str = "tests/fixtures/customers.json";
-    const CUSTOMERS_BAD_JSON_FILE: &'static str = "tests/fixtures/customers_malformed.json";
+    const CUSTOMERS_OK_JSON_FILE: &str = "tests/fixtures/customers.json";
+    const CUSTOMERS_BAD_JSON_FILE: &str = "tests/fixtures/customers_malformed.json";
+    const CUSTOMERS_ATTRIBUTES_JSON_FILE: &str = "tests/fixtures/customers_attributes.json";
+    const CUSTOMERS_NESTED_JSON_FILE: &str = "tests/fixtures/customers_nested.json";
 
     #[test]
     fn customer_json_file_builds_a_customer_list_from_a_json_file() {
@@ -151,14 +147,43 @@ mod tests {
         assert_eq!(expected_customers, actual_customers);
     }
 
+    #[test]
+    fn customer_json_file_keeps_the_extra_fields_as_attributes() {
+        let customers_json_file = CustomerJsonFile::new(Path::new(CUSTOMERS_ATTRIBUTES_JSON_FILE));
+
+        let expected_customers = CustomerList::from_vec(vec![
+            Customer::new(1, "Jose Narvaez", &Location::new(52.986375, -6.043701))
+                .with_attribute("email", "jose@example.com")
+                .with_attribute("tier", "gold")
+                .with_attribute("account_manager", "Maria"),
+            Customer::new(2, "Carlos Narvaez", &Location::new(51.92893, -10.27699))
+                .with_attribute("email", "carlos@example.com")
+                .with_attribute("phone", "+353 1 555 1234")
+        ]);
+
+        let actual_customers = customers_json_file.customers().unwrap();
+        assert_eq!(expected_customers, actual_customers);
+    }
+
+    #[test]
+    fn customer_json_file_keeps_nested_fields_as_attributes() {
+        let customers_json_file = CustomerJsonFile::new(Path::new(CUSTOMERS_NESTED_JSON_FILE));
+        let customers = customers_json_file.customers().unwrap();
+
+        let jose = customers.get(1).unwrap();
+        assert_eq!(jose.attribute("tags").map(|tags| tags.to_string()), Some(String::from(r#"["vip","early"]"#)));
+        assert_eq!(jose.attribute("address").map(|address| address.to_string()), Some(String::from(r#"{"city":"Wicklow","eircode":null}"#)));
+        assert_eq!(customers.get(2).unwrap().attribute("tags"), Some(&AttributeValue::Array(Vec::new())));
+    }
+
     #[test]
     fn customer_json_file_returns_io_error_when_io_occurs() {
         let customers_json_file = CustomerJsonFile::new(Path::new("unexistent_customer_file.json"));
 
         match customers_json_file.customers() {
-            Err(CustomerJsonFileError::Io(_)) => assert!(true),
-            Err(CustomerJsonFileError::Json(_)) => assert!(false, "this was not supposed to return json error"),
-            Ok(_) => assert!(false, "this was supposed to fail")
+            Err(CustomerJsonFileError::Io(_)) => (),
+            Err(CustomerJsonFileError::Json(_)) => panic!("this was not supposed to return json error"),
+            Ok(_) => panic!("this was supposed to fail")
         }
     }
 
@@ -167,9 +192,9 @@ mod tests {
         let customers_json_file = CustomerJsonFile::new(Path::new(CUSTOMERS_BAD_JSON_FILE));
 
         match customers_json_file.customers() {
-            Err(CustomerJsonFileError::Io(_)) => assert!(false, "this was not supposed to return io error"),
-            Err(CustomerJsonFileError::Json(_)) => assert!(true),
-            Ok(_) => assert!(false, "this was supposed to fail")
+            Err(CustomerJsonFileError::Io(_)) => panic!("this was not supposed to return io error"),
+            Err(CustomerJsonFileError::Json(_)) => (),
+            Ok(_) => panic!("this was supposed to fail")
         }
     }
 }
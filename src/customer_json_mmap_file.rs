@@ -0,0 +1,293 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+use serde::de;
+use serde::{Deserialize, Deserializer};
+use serde_json;
+
+use customer::{deserialize_coordinate, Customer, CustomerList};
+use customer_attribute::AttributeValue;
+use customer_datasource::CustomerDatasource;
+use customer_json_file::CustomerJsonFileError;
+
+/// A customer deserialized straight from a memory mapped JSON file.
+///
+/// The `name` and the attribute names borrow from the mapped file whenever
+/// they hold no escape sequences. The attribute values are still allocated,
+/// so only customers without attributes are looked at without allocating.
+/// It can be turned into an owned `Customer` with `into_customer` when it
+/// has to be kept.
+#[derive(Debug, PartialEq)]
+pub struct CustomerRef<'a> {
+    pub user_id: i64,
+    pub name: Cow<'a, str>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub attributes: BTreeMap<Cow<'a, str>, AttributeValue>,
+}
+
+impl<'a> CustomerRef<'a> {
+    /// Builds an owned `Customer` out of the borrowed one.
+    pub fn into_customer(self) -> Customer {
+        Customer {
+            user_id: self.user_id,
+            name: self.name.into_owned(),
+            latitude: self.latitude,
+            longitude: self.longitude,
+            attributes: self.attributes.into_iter().map(|(key, value)| (key.into_owned(), value)).collect(),
+        }
+    }
+}
+
+// Wrapper so `deserialize_coordinate` can be used through `next_value`.
+struct Coordinate(f64);
+
+impl<'de> Deserialize<'de> for Coordinate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_coordinate(deserializer).map(Coordinate)
+    }
+}
+
+// Hand written so the customer fields are read in a single pass without
+// buffering the whole object like `#[serde(flatten)]` does.
+impl<'de: 'a, 'a> Deserialize<'de> for CustomerRef<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CustomerRefVisitor;
+
+        impl<'de> de::Visitor<'de> for CustomerRefVisitor {
+            type Value = CustomerRef<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a customer JSON object")
+            }
+
+            fn visit_map<M: de::MapAccess<'de>>(self, mut map: M) -> Result<Self::Value, M::Error> {
+                let mut user_id = None;
+                let mut name = None;
+                let mut latitude = None;
+                let mut longitude = None;
+                let mut attributes = BTreeMap::new();
+
+                while let Some(BorrowedStr(key)) = map.next_key::<BorrowedStr<'de>>()? {
+                    match key.as_ref() {
+                        "user_id" => user_id = Some(map.next_value()?),
+                        "name" => name = Some(map.next_value::<BorrowedStr<'de>>()?.0),
+                        "latitude" => latitude = Some(map.next_value::<Coordinate>()?.0),
+                        "longitude" => longitude = Some(map.next_value::<Coordinate>()?.0),
+                        _ => {
+                            let value = map.next_value::<AttributeValue>()?;
+                            attributes.insert(key, value);
+                        }
+                    }
+                }
+
+                Ok(CustomerRef {
+                    user_id: user_id.ok_or_else(|| de::Error::missing_field("user_id"))?,
+                    name: name.ok_or_else(|| de::Error::missing_field("name"))?,
+                    latitude: latitude.ok_or_else(|| de::Error::missing_field("latitude"))?,
+                    longitude: longitude.ok_or_else(|| de::Error::missing_field("longitude"))?,
+                    attributes,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(CustomerRefVisitor)
+    }
+}
+
+// `Cow<str>` always deserializes to an owned string, this one borrows
+// when the input allows it.
+struct BorrowedStr<'a>(Cow<'a, str>);
+
+impl<'de> Deserialize<'de> for BorrowedStr<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BorrowedStrVisitor;
+
+        impl<'de> de::Visitor<'de> for BorrowedStrVisitor {
+            type Value = BorrowedStr<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a string")
+            }
+
+            fn visit_borrowed_str<E: de::Error>(self, value: &'de str) -> Result<Self::Value, E> {
+                Ok(BorrowedStr(Cow::Borrowed(value)))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(BorrowedStr(Cow::Owned(value.into())))
+            }
+        }
+
+        deserializer.deserialize_str(BorrowedStrVisitor)
+    }
+}
+
+/// Struct abstracting the idea of a JSON file containing customer data
+/// that is read through a memory map.
+///
+/// It reads the same format as `CustomerJsonFile` but instead of reading
+/// the file line by line into freshly allocated `String`s it maps the file
+/// in memory and deserializes the customers straight from it, borrowing
+/// their names from the mapped bytes and reusing the parser buffers.
+///
+/// Besides implementing `CustomerDatasource` it allows visiting the
+/// customers as `CustomerRef`s so the ones that are not needed are never
+/// allocated.
+///
+/// # Examples
+///
+/// ```
+/// let customers_file = CustomerJsonMmapFile::new(Path::new(input_file_path));
+///
+/// // Errors handling omitted for brevity
+/// let near_dublin = customers_file.filter_customers(|customer| {
+///     let location = Location::new(customer.latitude, customer.longitude);
+///     location.distance_from(&Location::new(53.3393, -6.2576841)) < Kilometers(100.0)
+/// }).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// The same ones as `CustomerJsonFile`, an `std::io::Error` when the file
+/// can't be opened or mapped and a `serde_json::Error` when a customer is
+/// malformed.
+///
+/// The file must not be truncated or modified while it's being read.
+pub struct CustomerJsonMmapFile<'f> {
+    file_path: &'f Path,
+}
+
+impl<'f> CustomerJsonMmapFile<'f> {
+    pub fn new(file_path: &'f Path) -> CustomerJsonMmapFile<'f> {
+        CustomerJsonMmapFile { file_path }
+    }
+
+    /// Calls `visitor` with every customer of the file in order.
+    pub fn for_each_customer<F>(&self, mut visitor: F) -> Result<(), CustomerJsonFileError>
+        where F: FnMut(CustomerRef)
+    {
+        let file = File::open(self.file_path)?;
+        // mapping an empty file fails on some platforms and there's nothing to read anyway.
+        if file.metadata()?.len() == 0 {
+            return Ok(());
+        }
+
+        // Safe as long as the file is not modified while mapped, see the docs above.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let stream = serde_json::Deserializer::from_slice(&mmap).into_iter::<CustomerRef>();
+        for customer in stream {
+            visitor(customer?);
+        }
+
+        Ok(())
+    }
+
+    /// Returns a `CustomerList` with the customers for which `predicate` returns
+    /// `true`. Only those customers get allocated.
+    pub fn filter_customers<P>(&self, mut predicate: P) -> Result<CustomerList, CustomerJsonFileError>
+        where P: FnMut(&CustomerRef) -> bool
+    {
+        let mut customers = Vec::new();
+        self.for_each_customer(|customer| if predicate(&customer) {
+            customers.push(customer.into_customer());
+        })?;
+
+        Ok(CustomerList::from_vec(customers))
+    }
+}
+
+impl<'f> CustomerDatasource for CustomerJsonMmapFile<'f> {
+    type Err = CustomerJsonFileError;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        self.filter_customers(|_| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use customer_json_file::CustomerJsonFile;
+
+    const CUSTOMERS_OK_JSON_FILE: &str = "tests/fixtures/customers.json";
+    const CUSTOMERS_BAD_JSON_FILE: &str = "tests/fixtures/customers_malformed.json";
+    const CUSTOMERS_ATTRIBUTES_JSON_FILE: &str = "tests/fixtures/customers_attributes.json";
+    const CUSTOMERS_NESTED_JSON_FILE: &str = "tests/fixtures/customers_nested.json";
+
+    #[test]
+    fn customer_json_mmap_file_loads_the_same_customers_as_customer_json_file() {
+        for file in &[CUSTOMERS_OK_JSON_FILE, CUSTOMERS_ATTRIBUTES_JSON_FILE, CUSTOMERS_NESTED_JSON_FILE] {
+            let expected_customers = CustomerJsonFile::new(Path::new(file)).customers().unwrap();
+            let actual_customers = CustomerJsonMmapFile::new(Path::new(file)).customers().unwrap();
+            assert_eq!(expected_customers, actual_customers);
+        }
+    }
+
+    #[test]
+    fn customer_ref_borrows_names_without_escapes() {
+        let json = br#"{"latitude": "52.986375", "user_id": 1, "name": "Jose Narvaez", "longitude": -6.043701}
+                       {"latitude": "51.92893", "user_id": 2, "name": "Carlos \"Charlie\" Narvaez", "longitude": "-10.27699"}"#;
+        let customers: Vec<CustomerRef> = serde_json::Deserializer::from_slice(json)
+            .into_iter::<CustomerRef>()
+            .map(|customer| customer.unwrap())
+            .collect();
+
+        match customers[0].name {
+            Cow::Borrowed("Jose Narvaez") => (),
+            ref other => panic!("expected a borrowed name, got {:?}", other),
+        }
+        assert_eq!(customers[1].name, "Carlos \"Charlie\" Narvaez");
+        assert_eq!((customers[0].latitude, customers[0].longitude), (52.986375, -6.043701));
+    }
+
+    #[test]
+    fn customer_ref_borrows_attribute_names_without_escapes() {
+        let json = br#"{"latitude": 52.986375, "user_id": 1, "name": "Jose", "longitude": -6.043701, "tier": "gold"}"#;
+        let customer: CustomerRef = serde_json::from_slice(json).unwrap();
+
+        match customer.attributes.keys().next() {
+            Some(&Cow::Borrowed("tier")) => (),
+            other => panic!("expected a borrowed attribute name, got {:?}", other),
+        }
+        assert_eq!(customer.into_customer().attributes.get("tier"), Some(&AttributeValue::Text(String::from("gold"))));
+    }
+
+    #[test]
+    fn customer_json_mmap_file_filter_customers_only_keeps_the_matching_ones() {
+        let customers_file = CustomerJsonMmapFile::new(Path::new(CUSTOMERS_OK_JSON_FILE));
+        let customers = customers_file.filter_customers(|customer| customer.user_id != 2).unwrap();
+
+        assert_eq!(customers.iter().map(|customer| customer.user_id).collect::<Vec<i64>>(), vec![1, 3]);
+    }
+
+    #[test]
+    fn customer_json_mmap_file_returns_io_error_when_io_occurs() {
+        match CustomerJsonMmapFile::new(Path::new("unexistent_customer_file.json")).customers() {
+            Err(CustomerJsonFileError::Io(_)) => (),
+            Err(CustomerJsonFileError::Json(_)) => panic!("this was not supposed to return json error"),
+            Ok(_) => panic!("this was supposed to fail")
+        }
+    }
+
+    #[test]
+    fn customer_json_mmap_file_returns_json_error_when_malformed_json_occurs() {
+        match CustomerJsonMmapFile::new(Path::new(CUSTOMERS_BAD_JSON_FILE)).customers() {
+            Err(CustomerJsonFileError::Io(_)) => panic!("this was not supposed to return io error"),
+            Err(CustomerJsonFileError::Json(_)) => (),
+            Ok(_) => panic!("this was supposed to fail")
+        }
+    }
+}
@@ -6,11 +6,95 @@
 // This file may not be copied, modified, or distributed
 // except according to those terms
 
-use customer::Customer;
-use customer::CustomerList;
-use location::Location;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use customer::{Customer, CustomerList};
+use location::{BoundingBox, DistanceModel, Location, Ring};
 use units::Kilometers;
 use customer_datasource::{CustomerDatasource};
+use customer_store::{CompactCustomerList, CoordinatePrecision, CustomerStore};
+
+/// A `Customer` found by a `CustomerLocator` along with its distance to
+/// the `Location` of the search.
+///
+/// It's serialized as the customer fields plus a `distance` one so it can
+/// be handed over as is on structured outputs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LocatedCustomer {
+    #[serde(flatten)]
+    pub customer: Customer,
+    pub distance: Kilometers,
+}
+
+/// A `Customer` found by a search around several centres along with its
+/// distance to each one of them and the ones it was found for.
+///
+/// Centres are referred to by their index in the slice given to the
+/// search, so callers can attach names or anything else to them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttributedCustomer {
+    #[serde(flatten)]
+    pub customer: Customer,
+    /// The centres whose ring the customer is in, in the order they were
+    /// given, or the nearest centre of the customer when assigning them.
+    pub centres: Vec<usize>,
+    /// The distance from the customer to every centre, in the order they
+    /// were given.
+    pub distances: Vec<Kilometers>,
+}
+
+/// How a search around several centres picks its customers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CentreMatch {
+    /// The customers near any of the centres.
+    Any,
+    /// The customers near every one of the centres.
+    All,
+    /// Every customer, assigned to its nearest centre.
+    Nearest,
+}
+
+impl CentreMatch {
+    /// The names accepted by `CentreMatch::from_str`.
+    pub const NAMES: &'static [&'static str] = &["any", "all", "nearest"];
+}
+
+/// An error when trying to build a `CentreMatch` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseCentreMatchError(String);
+
+impl FromStr for CentreMatch {
+    type Err = ParseCentreMatchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "any" => Ok(CentreMatch::Any),
+            "all" => Ok(CentreMatch::All),
+            "nearest" => Ok(CentreMatch::Nearest),
+            other => Err(ParseCentreMatchError(format!("unknown centre match '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for CentreMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CentreMatch::Any => write!(f, "any"),
+            CentreMatch::All => write!(f, "all"),
+            CentreMatch::Nearest => write!(f, "nearest"),
+        }
+    }
+}
+
+impl fmt::Display for ParseCentreMatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Centre match parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseCentreMatchError {}
 
 /// Struct used to lookup customers in different locations.
 ///
@@ -23,12 +107,19 @@ use customer_datasource::{CustomerDatasource};
 /// decoupled from the concrete implementetion choosen for
 /// the `Customer`s list.
 ///
+/// The customers are kept on a `CustomerStore` which is a `CustomerList`
+/// by default. A `CompactCustomerList` can be used instead to hold big
+/// amounts of customers with a fraction of the memory.
+///
+/// Distances are calculated with the Haversine formula unless another
+/// `DistanceModel` is given with `with_model`.
+///
 /// # Examples
 ///
 /// You can explicitly create a [`CustomerLocator`] with [`new`]:
 ///
 /// ```
-/// let dublin =  Location::dublin();
+/// let dublin =  Location::new(53.3393, -6.2576841);
 /// let santiago =  Location::new(33.4489f64, 70.6693f64);
 ///
 /// let jose: Customer = Customer::new(1, String::from("Jose Narvaez"), dublin);
@@ -39,11 +130,13 @@ use customer_datasource::{CustomerDatasource};
 /// let locator = CustomerLocator::new(customer_list);
 /// ```
 #[derive(Debug, PartialEq)]
-pub struct CustomerLocator {
-    customers: CustomerList,
+pub struct CustomerLocator<S = CustomerList> {
+    customers: S,
+    rejected: usize,
+    model: DistanceModel,
 }
 
-impl CustomerLocator {
+impl<S: CustomerStore> CustomerLocator<S> {
     /// Constructs a new `CustomerLocator` given a raw `CustomerList`.
     ///
     /// # Examples
@@ -51,7 +144,7 @@ impl CustomerLocator {
     /// You can explicitly create a [`CustomerLocator`] with [`new`]:
     ///
     /// ```
-    /// let dublin =  Location::dublin();
+    /// let dublin =  Location::new(53.3393, -6.2576841);
     /// let santiago =  Location::new(33.4489f64, 70.6693f64);
     ///
     /// let jose: Customer = Customer::new(1, String::from("Jose Narvaez"), dublin);
@@ -61,10 +154,241 @@ impl CustomerLocator {
     ///
     /// let locator = CustomerLocator::new(customer_list);
     /// ```
-    pub fn new(customers: CustomerList) -> CustomerLocator {
-        CustomerLocator { customers: customers }
+    pub fn new(customers: S) -> CustomerLocator<S> {
+        CustomerLocator { customers, rejected: 0, model: DistanceModel::Haversine }
     }
 
+    /// Makes the searches of the locator calculate distances with `model`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let locator = CustomerLocator::from_source(customers_json_file).unwrap().with_model(DistanceModel::Vincenty);
+    /// ```
+    pub fn with_model(mut self, model: DistanceModel) -> CustomerLocator<S> {
+        self.model = model;
+        self
+    }
+
+    /// Returns the `DistanceModel` the searches of the locator calculate
+    /// distances with.
+    pub fn model(&self) -> DistanceModel {
+        self.model
+    }
+
+    /// Returns the `CustomerStore` holding the customers of the locator.
+    pub fn customers(&self) -> &S {
+        &self.customers
+    }
+
+    /// Returns how many customers read from the source were left out for
+    /// having coordinates that aren't on earth. See `from_source`.
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+
+    ///
+    /// Returns all the customers from the internal `CustomerList` that are
+    /// within the area of the `radius` in `Kilomenters` of the given
+    /// `Location` in `location`, along with their distance to it under the
+    /// model of the locator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// // we are using a JSON customer file for demostration purposes.
+    /// let customers_json_file = CustomerJsonFile::new(Path::new("customers.json"));
+    ///
+    /// // error handling skipped for brevity
+    /// locator = CustomerLocator::from_source(customers_json_file).unwrap();
+    /// ```
+    pub fn locate_within(&self, radius: &Kilometers, location: &Location) -> Vec<LocatedCustomer> {
+        self.locate_in_ring(&Ring::within(*radius), location)
+    }
+
+    /// Returns the customers in `ring` around `location`, like the ones from
+    /// 50 to 150 km away, in the order they are stored along with their
+    /// distance to `location`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let regional = Ring::new(Kilometers(50f64), Kilometers(150f64)).with_max_boundary(Boundary::Inclusive);
+    /// let customers = locator.locate_in_ring(&regional, &Location::new(53.3393, -6.2576841));
+    /// ```
+    pub fn locate_in_ring(&self, ring: &Ring, location: &Location) -> Vec<LocatedCustomer> {
+        self.indexes_in_ring(ring, location)
+            .into_iter()
+            .map(|index| LocatedCustomer { customer: self.customers.customer_at(index), distance: self.distance_at(index, location) })
+            .collect()
+    }
+
+    /// Returns the customers in each of `bands` around `location`, along
+    /// with their distance to it, calculating the distance to every customer
+    /// once. A customer in more than one band is only returned in the first
+    /// one, customers in none of them are left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let edges = [Kilometers(0f64), Kilometers(10f64), Kilometers(50f64), Kilometers(100f64)];
+    /// let bands = Ring::bands(&edges, Boundary::Exclusive);
+    /// for (band, customers) in bands.iter().zip(locator.locate_in_bands(&bands, &dublin)) {
+    ///     println!("{} customers from {} to {}", customers.len(), band.min_radius, band.max_radius);
+    /// }
+    /// ```
+    pub fn locate_in_bands(&self, bands: &[Ring], location: &Location) -> Vec<Vec<LocatedCustomer>> {
+        let mut banded = vec![Vec::new(); bands.len()];
+        for index in 0..self.customers.len() {
+            let distance = self.distance_at(index, location);
+            if let Some(band) = bands.iter().position(|band| band.contains(distance)) {
+                banded[band].push(LocatedCustomer { customer: self.customers.customer_at(index), distance });
+            }
+        }
+
+        banded
+    }
+
+    /// Returns the customers in `ring` around any of `centres`, like the
+    /// ones within 100 km of some office, in the order they are stored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let offices = [dublin, cork, galway];
+    /// for attributed in locator.locate_near_any(&Ring::within(Kilometers(100f64)), &offices) {
+    ///     println!("{} is near {} offices", attributed.customer, attributed.centres.len());
+    /// }
+    /// ```
+    pub fn locate_near_any(&self, ring: &Ring, centres: &[Location]) -> Vec<AttributedCustomer> {
+        self.attribute(centres, |distances| {
+            let near: Vec<usize> = (0..centres.len()).filter(|&centre| ring.contains(distances[centre])).collect();
+            Some(near).filter(|near| !near.is_empty())
+        })
+    }
+
+    /// Returns the customers in `ring` around every one of `centres`, like
+    /// the ones within 100 km of both Dublin and Galway, in the order they
+    /// are stored. With no centres there are no customers.
+    pub fn locate_near_all(&self, ring: &Ring, centres: &[Location]) -> Vec<AttributedCustomer> {
+        self.attribute(centres, |distances| {
+            let near = !distances.is_empty() && distances.iter().all(|distance| ring.contains(*distance));
+            Some((0..centres.len()).collect()).filter(|_| near)
+        })
+    }
+
+    /// Returns every customer assigned to the centre nearest to it, which
+    /// partitions them as the spherical Voronoi diagram of `centres` does.
+    /// Customers as far from two centres go to the first one given.
+    pub fn assign_to_nearest(&self, centres: &[Location]) -> Vec<AttributedCustomer> {
+        self.attribute(centres, |distances| {
+            (0..distances.len())
+                .reduce(|nearest, centre| if distances[centre] < distances[nearest] { centre } else { nearest })
+                .map(|nearest| vec![nearest])
+        })
+    }
+
+    /// Runs the search around several `centres` picked by `matching`, with
+    /// `ring` around each one of them when they aren't assigned.
+    pub fn locate_by_centres(&self, matching: CentreMatch, ring: &Ring, centres: &[Location]) -> Vec<AttributedCustomer> {
+        match matching {
+            CentreMatch::Any => self.locate_near_any(ring, centres),
+            CentreMatch::All => self.locate_near_all(ring, centres),
+            CentreMatch::Nearest => self.assign_to_nearest(centres),
+        }
+    }
+
+    // Calculates the distances from every customer to `centres` keeping the
+    // customers `matched` returns the centres of.
+    fn attribute<F>(&self, centres: &[Location], matched: F) -> Vec<AttributedCustomer>
+        where F: Fn(&[Kilometers]) -> Option<Vec<usize>>
+    {
+        (0..self.customers.len())
+            .filter_map(|index| {
+                let distances: Vec<Kilometers> = centres.iter()
+                    .map(|centre| self.distance_at(index, centre))
+                    .collect();
+                matched(&distances).map(|centres| AttributedCustomer {
+                    customer: self.customers.customer_at(index),
+                    centres,
+                    distances,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the `k` customers closest to `location`, nearest first, along
+    /// with their distance to it. Customers at the same distance are returned
+    /// in the order they are stored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let locator = CustomerLocator::from_source(customers_json_file).unwrap();
+    /// let nearest = locator.nearest(5, &Location::new(53.3393, -6.2576841));
+    /// ```
+    pub fn nearest(&self, k: usize, location: &Location) -> Vec<LocatedCustomer> {
+        let mut distances: Vec<(usize, Kilometers)> = (0..self.customers.len())
+            .map(|index| (index, self.distance_at(index, location)))
+            .collect();
+        let by_distance = |first: &(usize, Kilometers), second: &(usize, Kilometers)| {
+            (first.1).0.total_cmp(&(second.1).0).then(first.0.cmp(&second.0))
+        };
+
+        if k < distances.len() {
+            if k == 0 {
+                return Vec::new();
+            }
+            distances.select_nth_unstable_by(k - 1, by_distance);
+            distances.truncate(k);
+        }
+        distances.sort_by(by_distance);
+
+        distances.into_iter()
+            .map(|(index, distance)| LocatedCustomer { customer: self.customers.customer_at(index), distance })
+            .collect()
+    }
+
+    /// Returns a `CustomerList` with all the customers inside `bounding_box`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let ireland = BoundingBox::new(Location::new(51.4, -10.7), Location::new(55.4, -5.4));
+    /// let customers = locator.locate_in(&ireland);
+    /// ```
+    pub fn locate_in(&self, bounding_box: &BoundingBox) -> CustomerList {
+        (0..self.customers.len())
+            .filter(|index| bounding_box.contains(&self.customers.location_at(*index)))
+            .map(|index| self.customers.customer_at(index))
+            .collect()
+    }
+
+    /// Returns the customer with the given `user_id`, if any.
+    pub fn find(&self, user_id: i64) -> Option<Customer> {
+        (0..self.customers.len())
+            .find(|index| self.customers.user_id_at(*index) == user_id)
+            .map(|index| self.customers.customer_at(index))
+    }
+
+    // Returns the distance between the customer at `index` and `location`
+    // under the model of the locator, leaving Haversine ones to the store.
+    fn distance_at(&self, index: usize, location: &Location) -> Kilometers {
+        match self.model {
+            DistanceModel::Haversine => self.customers.distance_at(index, location),
+            model => model.distance(&self.customers.location_at(index), location),
+        }
+    }
+
+    fn indexes_in_ring(&self, ring: &Ring, location: &Location) -> Vec<usize> {
+        match self.model {
+            DistanceModel::Haversine => self.customers.indexes_in_ring(ring, location),
+            _ => (0..self.customers.len()).filter(|&index| ring.contains(self.distance_at(index, location))).collect(),
+        }
+    }
+}
+
+impl CustomerLocator {
     /// Constructs a new `CustomerLocator` given a type that implements
     /// the `CustomerDatasource` trait.
     ///
@@ -91,46 +415,49 @@ impl CustomerLocator {
     /// data in order to build `Customer` instances. `CustomerDatasource` has an `Err`
     /// associated type which has to be provided and will be built accordingly.
     ///
-    pub fn from_source<S: CustomerDatasource>(source: S) -> Result<CustomerLocator, S::Err> {
+    /// Customers whose coordinates are out of range or not numbers can't be
+    /// located so they are left out rather than failing the whole source,
+    /// `rejected` tells how many were.
+    ///
+    pub fn from_source<D: CustomerDatasource>(source: D) -> Result<CustomerLocator, D::Err> {
         match source.customers() {
-            Ok(customer_list) => Ok(Self::new(customer_list)),
+            Ok(customer_list) => {
+                let (customers, rejected) = customer_list.partition(|customer| customer.location().is_valid());
+                Ok(CustomerLocator { rejected: rejected.len(), ..CustomerLocator::new(customers) })
+            }
             Err(err) => Err(err)
         }
     }
 
-    ///
-    /// Returns a `CustomerList` with all the customers
-    /// from the internal `CustomerList` that are within
-    /// the area of the `radius` in `Kilomenters` of the given
-    /// `Location` in `location`.
+    /// Turns the locator into one backed by a `CompactCustomerList` storing the
+    /// coordinates with the given `precision`.
     ///
     /// # Examples
     ///
     /// ```
-    /// // we are using a JSON customer file for demostration purposes.
-    /// let customers_json_file = CustomerJsonFile::new(Path::new("customers.json"));
-    ///
-    /// // error handling skipped for brevity
-    /// locator = CustomerLocator::from_source(customers_json_file).unwrap();
+    /// let locator = CustomerLocator::from_source(customers_json_file).unwrap();
+    /// let compact_locator = locator.into_compact(CoordinatePrecision::Exact);
     /// ```
-    pub fn locate_within(&self, radius: &Kilometers, location: &Location) -> CustomerList {
-        let customers_vec = self.customers
-            .clone()
-            .into_iter()
-            .filter(|customer| customer.distance_from(location) < *radius)
-            .collect::<Vec<Customer>>();
-
-        CustomerList::from_vec(customers_vec)
+    pub fn into_compact(self, precision: CoordinatePrecision) -> CustomerLocator<CompactCustomerList> {
+        CustomerLocator {
+            customers: CompactCustomerList::new(self.customers, precision),
+            rejected: self.rejected,
+            model: self.model,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use location::Boundary;
+    use customer::Customer;
     use customer::CustomerList;
     use customer_datasource::CustomerDatasource;
     use std::{error,fmt};
 
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+
     // Boilerplate so we statisfy all trait bounds
     #[derive(Debug)]
     struct DummyCustomersDataFile {
@@ -139,7 +466,7 @@ mod tests {
 
     impl DummyCustomersDataFile {
         fn new(should_fail: bool) ->  DummyCustomersDataFile {
-            DummyCustomersDataFile { should_fail: should_fail }
+            DummyCustomersDataFile { should_fail }
         }
     }
 
@@ -165,7 +492,7 @@ mod tests {
             "DummyCustomersDataFileError"
         }
 
-        fn cause(&self) -> Option<&error::Error> {
+        fn cause(&self) -> Option<&dyn error::Error> {
             Some(self)
         }
     }
@@ -185,16 +512,37 @@ mod tests {
     // helper functions
     fn generate_customer_list() -> CustomerList {
         let santiago = Location::new(-33.4489, -70.6693);
-        let jose = Customer::new(3, "Jose Narvaez", &Location::dublin());
+        let jose = Customer::new(3, "Jose Narvaez", &DUBLIN);
         let carlos = Customer::new(2, "Carlos Narvaez", &santiago);
         CustomerList::from_vec(vec![carlos, jose])
     }
 
+    fn generate_irish_customer_list() -> CustomerList {
+        CustomerList::from_vec(vec![
+            Customer::new(1, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
+            Customer::new(2, "Nora Dempsey", &Location::new(53.1302756, -6.2397222)),
+            Customer::new(3, "Theresa Enright", &Location::new(53.1229599, -6.2705202)),
+            Customer::new(4, "Eoin Ahearn" , &Location::new(54.0894797, -6.18671)),
+            Customer::new(5, "Richard Finnegan" , &Location::new(53.008769, -6.1056711)),
+            Customer::new(6, "Christina McArdle", &Location::new(52.986375, -6.043701)),
+            Customer::new(7, "Olive Ahearn", &Location::new(53.00, -7.00)),
+            Customer::new(8, "Michael Ahearn", &Location::new(52.966, -6.463)),
+            Customer::new(9, "Patricia Cahill", &Location::new(54.180238, -5.920898)),
+            Customer::new(10, "Eoin Gallagher",&Location::new(54.080556, -6.361944)),
+            Customer::new(11, "Rose Enright", &Location::new(54.133333, -6.433333)),
+            Customer::new(12, "Stephen McArdle", &Location::new(53.038056, -7.653889)),
+            Customer::new(13, "Oliver Ahearn", &Location::new(53.74452, -7.11167)),
+            Customer::new(14, "Nick Enright", &Location::new(53.761389, -7.2875)),
+            Customer::new(15, "Alan Behan", &Location::new(53.1489345, -6.8422408)),
+            Customer::new(16, "Lisa Ahearn", &Location::new(53.0033946, -6.3877505))
+        ])
+    }
+
     // actual tests
     #[test]
     fn new_builds_a_correct_instance_from_a_customer_list() {
         let customer_list = generate_customer_list();
-        let expected_locator = CustomerLocator { customers: customer_list.clone() };
+        let expected_locator = CustomerLocator { customers: customer_list.clone(), rejected: 0, model: DistanceModel::Haversine };
         let actual_locator = CustomerLocator::new(customer_list);
 
         assert_eq!(expected_locator, actual_locator);
@@ -202,12 +550,27 @@ mod tests {
 
     #[test]
     fn from_source_builds_a_correct_instance_from_any_type_impl_datasource() {
-        let expected_locator = CustomerLocator { customers: generate_customer_list() };
+        let expected_locator = CustomerLocator { customers: generate_customer_list(), rejected: 0, model: DistanceModel::Haversine };
         let actual_locator = CustomerLocator::from_source(DummyCustomersDataFile::new(false)).unwrap();
 
         assert_eq!(expected_locator, actual_locator);
     }
 
+    #[test]
+    fn from_source_rejects_the_customers_off_the_earth() {
+        let source = DummyCustomersDataFile::new(false).map(|mut customer| {
+            if customer.user_id == 2 {
+                customer.latitude = 123.0;
+            }
+            customer
+        });
+        let locator = CustomerLocator::from_source(source).unwrap();
+
+        assert_eq!(locator.customers().iter().map(|customer| customer.user_id).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(locator.rejected(), 1);
+        assert_eq!(locator.into_compact(CoordinatePrecision::Exact).rejected(), 1);
+    }
+
     #[test]
     fn from_source_propagates_the_error_from_the_datasource() {
         let expected_error = DummyCustomersDataFileError(String::from("unrecoverable error"));
@@ -218,26 +581,9 @@ mod tests {
 
     #[test]
     fn locate_within_locates_the_users_within_the_give_radius() {
-        let all_customers = CustomerList::from_vec(vec![
-            Customer::new(1, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
-            Customer::new(2, "Nora Dempsey", &Location::new(53.1302756, -6.2397222)),
-            Customer::new(3, "Theresa Enright", &Location::new(53.1229599, -6.2705202)),
-            Customer::new(4, "Eoin Ahearn" , &Location::new(54.0894797, -6.18671)),
-            Customer::new(5, "Richard Finnegan" , &Location::new(53.008769, -6.1056711)),
-            Customer::new(6, "Christina McArdle", &Location::new(52.986375, -6.043701)),
-            Customer::new(7, "Olive Ahearn", &Location::new(53.00, -7.00)),
-            Customer::new(8, "Michael Ahearn", &Location::new(52.966, -6.463)),
-            Customer::new(9, "Patricia Cahill", &Location::new(54.180238, -5.920898)),
-            Customer::new(10, "Eoin Gallagher",&Location::new(54.080556, -6.361944)),
-            Customer::new(11, "Rose Enright", &Location::new(54.133333, -6.433333)),
-            Customer::new(12, "Stephen McArdle", &Location::new(53.038056, -7.653889)),
-            Customer::new(13, "Oliver Ahearn", &Location::new(53.74452, -7.11167)),
-            Customer::new(14, "Nick Enright", &Location::new(53.761389, -7.2875)),
-            Customer::new(15, "Alan Behan", &Location::new(53.1489345, -6.8422408)),
-            Customer::new(16, "Lisa Ahearn", &Location::new(53.0033946, -6.3877505))
-        ]);
+        let all_customers = generate_irish_customer_list();
 
-        let expected_customers = CustomerList::from_vec(vec![
+        let expected_customers = vec![
             Customer::new(1, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
             Customer::new(2, "Nora Dempsey", &Location::new(53.1302756, -6.2397222)),
             Customer::new(3, "Theresa Enright", &Location::new(53.1229599, -6.2705202)),
@@ -246,10 +592,204 @@ mod tests {
             Customer::new(8, "Michael Ahearn", &Location::new(52.966, -6.463)),
             Customer::new(15, "Alan Behan", &Location::new(53.1489345, -6.8422408)),
             Customer::new(16, "Lisa Ahearn", &Location::new(53.0033946, -6.3877505))
-        ]);
+        ];
 
         let locator = CustomerLocator::new(all_customers);
-        let actual_customers = locator.locate_within(&Kilometers(50.00), &Location::dublin());
-        assert_eq!(expected_customers, actual_customers);
+        let actual_customers = locator.locate_within(&Kilometers(50.00), &DUBLIN);
+        assert_eq!(expected_customers, actual_customers.iter().map(|located| located.customer.clone()).collect::<Vec<Customer>>());
+        assert!(actual_customers.iter().all(|located| located.distance == located.customer.distance_from(&DUBLIN)));
+    }
+
+    #[test]
+    fn locate_within_on_a_compact_locator_locates_the_same_users() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let compact_locator = CustomerLocator::new(generate_irish_customer_list()).into_compact(CoordinatePrecision::Exact);
+
+        for radius in &[0.0, 10.0, 25.0, 50.0, 100.0, 1000.0] {
+            let expected_customers = locator.locate_within(&Kilometers(*radius), &DUBLIN);
+            let actual_customers = compact_locator.locate_within(&Kilometers(*radius), &DUBLIN);
+            assert_eq!(expected_customers, actual_customers);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn locate_within_on_a_quantised_compact_locator_locates_the_same_users() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let compact_locator = CustomerLocator::new(generate_irish_customer_list()).into_compact(CoordinatePrecision::MicroDegrees);
+
+        let expected_user_ids = located_user_ids(&locator.locate_within(&Kilometers(50.0), &DUBLIN));
+        let actual_user_ids = located_user_ids(&compact_locator.locate_within(&Kilometers(50.0), &DUBLIN));
+        assert_eq!(expected_user_ids, actual_user_ids);
+    }
+
+    #[test]
+    fn locate_within_calculates_distances_with_the_model_of_the_locator() {
+        let customers = generate_irish_customer_list();
+        let haversine = customers[0].distance_from(&DUBLIN);
+        let vincenty = DistanceModel::Vincenty.distance(&customers[0].location(), &DUBLIN);
+        assert!(haversine != vincenty);
+        let radius = Kilometers((haversine.0 + vincenty.0) / 2.0);
+
+        let haversine_locator = CustomerLocator::new(customers.clone());
+        let vincenty_locator = CustomerLocator::new(customers.clone()).with_model(DistanceModel::Vincenty);
+        let compact_vincenty_locator = CustomerLocator::new(customers.clone())
+            .into_compact(CoordinatePrecision::Exact)
+            .with_model(DistanceModel::Vincenty);
+        let finds_first = |found: Vec<LocatedCustomer>| found.iter().any(|located| located.customer.user_id == customers[0].user_id);
+
+        assert_eq!(finds_first(haversine_locator.locate_within(&radius, &DUBLIN)), haversine < vincenty);
+        assert_eq!(finds_first(vincenty_locator.locate_within(&radius, &DUBLIN)), vincenty < haversine);
+        assert_eq!(compact_vincenty_locator.locate_within(&radius, &DUBLIN), vincenty_locator.locate_within(&radius, &DUBLIN));
+
+        let vincenty_distance = |located: &LocatedCustomer| located.distance == DistanceModel::Vincenty.distance(&located.customer.location(), &DUBLIN);
+        assert!(vincenty_locator.nearest(customers.len(), &DUBLIN).iter().all(vincenty_distance));
+        assert!(vincenty_locator.locate_within(&Kilometers(100.0), &DUBLIN).iter().all(vincenty_distance));
+        let bands = Ring::bands(&[Kilometers(0.0), Kilometers(50.0), Kilometers(100.0)], Boundary::Exclusive);
+        assert!(vincenty_locator.locate_in_bands(&bands, &DUBLIN).iter().flatten().all(vincenty_distance));
+    }
+
+    #[test]
+    fn locate_in_ring_locates_the_users_between_both_radiuses() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let compact_locator = CustomerLocator::new(generate_irish_customer_list()).into_compact(CoordinatePrecision::Exact);
+        let ring = Ring::new(Kilometers(20.0), Kilometers(50.0));
+
+        assert_eq!(located_user_ids(&locator.locate_in_ring(&ring, &DUBLIN)), vec![2, 3, 5, 6, 8, 15, 16]);
+        assert_eq!(compact_locator.locate_in_ring(&ring, &DUBLIN), locator.locate_in_ring(&ring, &DUBLIN));
+    }
+
+    #[test]
+    fn locate_in_ring_includes_the_users_on_an_inclusive_edge() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let radius = locator.customers()[0].distance_from(&DUBLIN);
+
+        assert!(!located_user_ids(&locator.locate_within(&radius, &DUBLIN)).contains(&1));
+        let ring = Ring::within(radius).with_max_boundary(Boundary::Inclusive);
+        assert!(located_user_ids(&locator.locate_in_ring(&ring, &DUBLIN)).contains(&1));
+        let ring = Ring::new(radius, Kilometers(100.0)).with_min_boundary(Boundary::Exclusive);
+        assert!(!located_user_ids(&locator.locate_in_ring(&ring, &DUBLIN)).contains(&1));
+    }
+
+    #[test]
+    fn locate_in_bands_puts_each_user_in_its_band() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let edges = [Kilometers(0.0), Kilometers(20.0), Kilometers(50.0), Kilometers(100.0)];
+        let bands = Ring::bands(&edges, Boundary::Exclusive);
+
+        let banded = locator.locate_in_bands(&bands, &DUBLIN);
+        assert_eq!(banded.len(), 3);
+        for (band, customers) in bands.iter().zip(&banded) {
+            assert_eq!(customers, &locator.locate_in_ring(band, &DUBLIN));
+        }
+        assert_eq!(banded.iter().map(|customers| customers.len()).sum::<usize>(),
+                   locator.locate_within(&Kilometers(100.0), &DUBLIN).len());
+    }
+
+    const CORK: Location = Location { latitude: 51.8985, longitude: -8.4756 };
+    const GALWAY: Location = Location { latitude: 53.2707, longitude: -9.0568 };
+
+    fn located_user_ids(located: &[LocatedCustomer]) -> Vec<i64> {
+        located.iter().map(|located| located.customer.user_id).collect()
+    }
+
+    fn attributed_user_ids(attributed: &[AttributedCustomer]) -> Vec<i64> {
+        attributed.iter().map(|attributed| attributed.customer.user_id).collect()
+    }
+
+    #[test]
+    fn centre_match_from_str_parses_known_matches() {
+        assert_eq!(CentreMatch::from_str(" ALL "), Ok(CentreMatch::All));
+        assert_eq!(CentreMatch::from_str("nearest"), Ok(CentreMatch::Nearest));
+        assert!(CentreMatch::from_str("some").is_err());
+    }
+
+    #[test]
+    fn locate_near_any_locates_the_users_near_some_centre() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let ring = Ring::within(Kilometers(50.0));
+        let centres = [DUBLIN, Location::new(54.1, -6.3)];
+
+        let near_any = locator.locate_near_any(&ring, &centres);
+        let mut expected: Vec<i64> = attributed_user_ids(&locator.locate_near_any(&ring, &centres[..1]));
+        expected.extend(located_user_ids(&locator.locate_within(&Kilometers(50.0), &centres[1])));
+        expected.sort();
+        expected.dedup();
+        assert_eq!(attributed_user_ids(&near_any), expected);
+
+        let eoin = near_any.iter().find(|attributed| attributed.customer.user_id == 4).unwrap();
+        assert_eq!(eoin.centres, vec![1]);
+        assert_eq!(eoin.distances, vec![eoin.customer.distance_from(&DUBLIN), eoin.customer.distance_from(&centres[1])]);
+    }
+
+    #[test]
+    fn locate_near_all_locates_the_users_near_every_centre() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let ring = Ring::within(Kilometers(100.0));
+
+        let near_all = locator.locate_near_all(&ring, &[DUBLIN, GALWAY]);
+        for attributed in &near_all {
+            assert_eq!(attributed.centres, vec![0, 1]);
+            assert!(attributed.distances.iter().all(|distance| distance.0 < 100.0));
+        }
+        let near_galway = located_user_ids(&locator.locate_within(&Kilometers(100.0), &GALWAY));
+        let expected: Vec<i64> = located_user_ids(&locator.locate_within(&Kilometers(100.0), &DUBLIN))
+            .into_iter()
+            .filter(|user_id| near_galway.contains(user_id))
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(attributed_user_ids(&near_all), expected);
+        assert!(locator.locate_near_all(&ring, &[]).is_empty());
+    }
+
+    #[test]
+    fn assign_to_nearest_partitions_the_users_among_the_centres() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let centres = [DUBLIN, CORK, GALWAY];
+
+        let assigned = locator.assign_to_nearest(&centres);
+        assert_eq!(assigned.len(), 16);
+        for attributed in &assigned {
+            let nearest = attributed.centres[0];
+            assert!(attributed.distances.iter().all(|distance| attributed.distances[nearest] <= *distance));
+        }
+        let to_galway: Vec<i64> = assigned.iter()
+            .filter(|attributed| attributed.centres == vec![2])
+            .map(|attributed| attributed.customer.user_id)
+            .collect();
+        assert_eq!(to_galway, vec![12]);
+        assert!(locator.assign_to_nearest(&[]).is_empty());
+    }
+
+    #[test]
+    fn nearest_returns_the_k_closest_users_nearest_first() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let compact_locator = CustomerLocator::new(generate_irish_customer_list()).into_compact(CoordinatePrecision::Exact);
+
+        let nearest = locator.nearest(3, &DUBLIN);
+        let user_ids: Vec<i64> = nearest.iter().map(|located| located.customer.user_id).collect();
+        assert_eq!(user_ids, vec![1, 2, 3]);
+        assert!(nearest.windows(2).all(|pair| pair[0].distance <= pair[1].distance));
+        assert_eq!(nearest[0].distance, nearest[0].customer.distance_from(&DUBLIN));
+
+        assert_eq!(compact_locator.nearest(3, &DUBLIN), nearest);
+        assert_eq!(locator.nearest(100, &DUBLIN).len(), 16);
+        assert!(locator.nearest(0, &DUBLIN).is_empty());
+    }
+
+    #[test]
+    fn locate_in_locates_the_users_inside_the_bounding_box() {
+        let locator = CustomerLocator::new(generate_irish_customer_list());
+        let bounding_box = BoundingBox::new(Location::new(53.7, -7.5), Location::new(54.2, -6.0));
+
+        let user_ids: Vec<i64> = locator.locate_in(&bounding_box).iter().map(|customer| customer.user_id).collect();
+        assert_eq!(user_ids, vec![4, 10, 11, 13, 14]);
+    }
+
+    #[test]
+    fn find_returns_the_user_with_the_given_id() {
+        let locator = CustomerLocator::new(generate_irish_customer_list()).into_compact(CoordinatePrecision::Exact);
+
+        assert_eq!(locator.find(12), Some(Customer::new(12, "Stephen McArdle", &Location::new(53.038056, -7.653889))));
+        assert_eq!(locator.find(42), None);
+    }
+}
@@ -0,0 +1,448 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module computing the distances between every origin and every
+//! destination, usually the customers of a search, and writing them out for
+//! optimisation tools.
+//!
+//! The matrix is computed a block of rows at a time, the rows of a block
+//! split between several threads, and each block is written out before the
+//! next one is computed. Memory stays bounded by the size of a block however
+//! many origins there are.
+//!
+//! Between the customers themselves the matrix is symmetric, and with a zero
+//! diagonal, so `DistanceMatrix::upper_triangle` only computes and writes the
+//! distances from each customer to the ones after it.
+//!
+//! # Examples
+//!
+//! ```
+//! let locations: Vec<Location> = customers.iter().map(|customer| customer.location()).collect();
+//! let labels: Vec<String> = customers.iter().map(|customer| customer.user_id.to_string()).collect();
+//! DistanceMatrix::upper_triangle(locations)
+//!     .with_threads(4)
+//!     .write(io::stdout(), MatrixFormat::Npy, &labels, &labels)?;
+//! ```
+//!
+
+use std::error;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::thread;
+
+use csv;
+use serde_json;
+
+use customer_output::CustomerOutputError;
+use location::{DistanceModel, Location};
+
+/// How many distances a block of rows holds by default, 8MB worth of them.
+pub const DEFAULT_BLOCK_CELLS: usize = 1 << 20;
+
+// The magic string starting every NPY file, followed by the 1.0 version.
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+
+// NPY headers are padded so the data starts on this alignment.
+const NPY_ALIGNMENT: usize = 64;
+
+/// The formats in which a `DistanceMatrix` can be written out.
+///
+/// `Csv` writes a header row with the destinations followed by a row per
+/// origin. `Npy` writes the [NumPy array format]
+/// (https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html)
+/// of little endian `f64`s. `Json` writes a single object with the
+/// `origins`, the `destinations` and the `distances` as an array per
+/// origin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatrixFormat {
+    Csv,
+    Npy,
+    Json,
+}
+
+impl MatrixFormat {
+    /// The names accepted by `MatrixFormat::from_str`.
+    pub const NAMES: &'static [&'static str] = &["csv", "npy", "json"];
+}
+
+/// An error when trying to build a `MatrixFormat` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseMatrixFormatError(String);
+
+impl FromStr for MatrixFormat {
+    type Err = ParseMatrixFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(MatrixFormat::Csv),
+            "npy" => Ok(MatrixFormat::Npy),
+            "json" => Ok(MatrixFormat::Json),
+            other => Err(ParseMatrixFormatError(format!("unknown matrix format '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for MatrixFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MatrixFormat::Csv => write!(f, "csv"),
+            MatrixFormat::Npy => write!(f, "npy"),
+            MatrixFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl fmt::Display for ParseMatrixFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Matrix format parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseMatrixFormatError {}
+
+/// Struct computing the distances in Km from every origin to every
+/// destination.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceMatrix {
+    origins: Vec<Location>,
+    destinations: Vec<Location>,
+    upper_triangle: bool,
+    model: DistanceModel,
+    threads: usize,
+    block_cells: usize,
+}
+
+impl DistanceMatrix {
+    /// Constructs the matrix of the distances from every one of `origins`
+    /// to every one of `destinations`, a row per origin.
+    pub fn new(origins: Vec<Location>, destinations: Vec<Location>) -> DistanceMatrix {
+        DistanceMatrix {
+            origins,
+            destinations,
+            upper_triangle: false,
+            model: DistanceModel::Haversine,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+            block_cells: DEFAULT_BLOCK_CELLS,
+        }
+    }
+
+    /// Constructs the matrix of the distances between `locations`, only
+    /// keeping the upper triangle: the row of each location holds the
+    /// distances to the locations after it.
+    pub fn upper_triangle(locations: Vec<Location>) -> DistanceMatrix {
+        let mut matrix = DistanceMatrix::new(locations.clone(), locations);
+        matrix.upper_triangle = true;
+        matrix
+    }
+
+    /// Calculates the distances with `model` instead of the haversine one.
+    pub fn with_model(mut self, model: DistanceModel) -> DistanceMatrix {
+        self.model = model;
+        self
+    }
+
+    /// Splits the rows of each block between `threads` threads instead of
+    /// one per CPU.
+    pub fn with_threads(mut self, threads: usize) -> DistanceMatrix {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Keeps blocks of rows of up to `block_cells` distances in memory,
+    /// and always at least a row.
+    pub fn with_block_cells(mut self, block_cells: usize) -> DistanceMatrix {
+        self.block_cells = block_cells.max(1);
+        self
+    }
+
+    /// Returns how many distances the matrix holds.
+    pub fn len(&self) -> usize {
+        if self.upper_triangle {
+            let locations = self.origins.len();
+            locations * locations.saturating_sub(1) / 2
+        } else {
+            self.origins.len() * self.destinations.len()
+        }
+    }
+
+    /// Calls `block` with the index of the first row and the rows of each
+    /// block in order, stopping at the first error.
+    pub fn for_each_block<F, E>(&self, mut block: F) -> Result<(), E>
+        where F: FnMut(usize, Vec<Vec<f64>>) -> Result<(), E>
+    {
+        let rows_per_block = (self.block_cells / self.destinations.len().max(1)).max(1);
+        let mut first = 0;
+        while first < self.origins.len() {
+            let last = (first + rows_per_block).min(self.origins.len());
+            block(first, self.rows(first, last))?;
+            first = last;
+        }
+
+        Ok(())
+    }
+
+    // Computes the rows from `first` to `last`, excluded, splitting them
+    // between the threads.
+    fn rows(&self, first: usize, last: usize) -> Vec<Vec<f64>> {
+        let rows_per_thread = (last - first).div_ceil(self.threads);
+        if self.threads == 1 || rows_per_thread == last - first {
+            return (first..last).map(|row| self.row(row)).collect();
+        }
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = (first..last)
+                .step_by(rows_per_thread)
+                .map(|start| {
+                    let end = (start + rows_per_thread).min(last);
+                    scope.spawn(move || (start..end).map(|row| self.row(row)).collect::<Vec<_>>())
+                })
+                .collect();
+            handles.into_iter()
+                .flat_map(|handle| handle.join().expect("distance matrix thread panicked"))
+                .collect()
+        })
+    }
+
+    fn row(&self, row: usize) -> Vec<f64> {
+        let origin = &self.origins[row];
+        let destinations = if self.upper_triangle { &self.destinations[row + 1..] } else { &self.destinations[..] };
+        destinations.iter().map(|destination| self.model.distance(origin, destination).0).collect()
+    }
+
+    /// Writes the matrix to `writer` in the given `MatrixFormat`, the rows
+    /// labelled with `origin_labels` and the columns with
+    /// `destination_labels`.
+    ///
+    /// The upper triangle is written as ragged rows on `Csv` and `Json`,
+    /// the row of each location starting at the location after it, and as
+    /// the one dimensional condensed matrix of SciPy's `squareform` on
+    /// `Npy`.
+    ///
+    /// # Errors
+    ///
+    /// If writing to `writer` fails.
+    pub fn write<W: Write>(&self,
+                           mut writer: W,
+                           format: MatrixFormat,
+                           origin_labels: &[String],
+                           destination_labels: &[String])
+                           -> Result<(), CustomerOutputError> {
+        match format {
+            MatrixFormat::Csv => {
+                let mut csv_writer = csv::WriterBuilder::new().flexible(self.upper_triangle).from_writer(writer);
+                let mut header = vec!["origin"];
+                header.extend(destination_labels.iter().map(|label| label.as_str()));
+                csv_writer.write_record(&header)?;
+                self.for_each_block(|first, rows| {
+                    for (row, label) in rows.iter().zip(&origin_labels[first..]) {
+                        let mut record = vec![label.clone()];
+                        record.extend(row.iter().map(|distance| distance.to_string()));
+                        csv_writer.write_record(&record)?;
+                    }
+                    Ok::<(), CustomerOutputError>(())
+                })?;
+                csv_writer.flush()?;
+            }
+            MatrixFormat::Npy => {
+                let shape = if self.upper_triangle {
+                    format!("({},)", self.len())
+                } else {
+                    format!("({}, {})", self.origins.len(), self.destinations.len())
+                };
+                let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}", shape);
+                // the magic string, the header length and the header end
+                // with a new line on the alignment
+                let unpadded = NPY_MAGIC.len() + 2 + header.len() + 1;
+                header.push_str(&" ".repeat((NPY_ALIGNMENT - unpadded % NPY_ALIGNMENT) % NPY_ALIGNMENT));
+                header.push('\n');
+
+                writer.write_all(NPY_MAGIC)?;
+                writer.write_all(&(header.len() as u16).to_le_bytes())?;
+                writer.write_all(header.as_bytes())?;
+                self.for_each_block(|_, rows| {
+                    let mut bytes = Vec::with_capacity(rows.iter().map(|row| row.len() * 8).sum());
+                    for distance in rows.iter().flatten() {
+                        bytes.extend_from_slice(&distance.to_le_bytes());
+                    }
+                    writer.write_all(&bytes)
+                })?;
+                writer.flush()?;
+            }
+            MatrixFormat::Json => {
+                write!(writer, "{{\"origins\":")?;
+                serde_json::to_writer(&mut writer, origin_labels)?;
+                write!(writer, ",\"destinations\":")?;
+                serde_json::to_writer(&mut writer, destination_labels)?;
+                write!(writer, ",\"upper_triangle\":{},\"distances\":[", self.upper_triangle)?;
+                self.for_each_block(|first, rows| {
+                    for (row, index) in rows.iter().zip(first..) {
+                        if index > 0 {
+                            write!(writer, ",")?;
+                        }
+                        serde_json::to_writer(&mut writer, row)?;
+                    }
+                    Ok::<(), CustomerOutputError>(())
+                })?;
+                writeln!(writer, "]}}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+    const CORK: Location = Location { latitude: 51.8985, longitude: -8.4756 };
+
+    fn generate_locations() -> Vec<Location> {
+        vec![
+            Location::new(53.2451022, -6.238335),
+            Location::new(53.1302756, -6.2397222),
+            Location::new(51.92893, -10.27699),
+            Location::new(52.3191841, -8.5072391),
+            Location::new(53.74452, -7.11167),
+        ]
+    }
+
+    fn labels(count: usize) -> Vec<String> {
+        (1..=count).map(|label| label.to_string()).collect()
+    }
+
+    fn collect(matrix: &DistanceMatrix) -> Vec<Vec<f64>> {
+        let mut collected = Vec::new();
+        matrix.for_each_block(|first, rows| {
+            assert_eq!(first, collected.len());
+            collected.extend(rows);
+            Ok::<(), ()>(())
+        }).unwrap();
+        collected
+    }
+
+    #[test]
+    fn matrix_format_from_str_parses_known_formats() {
+        assert_eq!(MatrixFormat::from_str(" NPY "), Ok(MatrixFormat::Npy));
+        assert_eq!(MatrixFormat::from_str("xml"), Err(ParseMatrixFormatError(String::from("unknown matrix format 'xml'"))));
+        assert!(MatrixFormat::NAMES.iter().all(|name| MatrixFormat::from_str(name).unwrap().to_string() == *name));
+    }
+
+    #[test]
+    fn rows_hold_the_distance_from_each_origin_to_each_destination() {
+        let matrix = DistanceMatrix::new(vec![DUBLIN, CORK], generate_locations());
+        let rows = collect(&matrix);
+
+        assert_eq!(rows.len(), 2);
+        for (row, origin) in rows.iter().zip(&[DUBLIN, CORK]) {
+            let expected: Vec<f64> = generate_locations().iter().map(|location| origin.distance_from(location).0).collect();
+            assert_eq!(*row, expected);
+        }
+        assert_eq!(matrix.len(), 10);
+    }
+
+    #[test]
+    fn rows_are_the_same_on_any_threads_and_blocks() {
+        let locations: Vec<Location> = (0..40).map(|index| Location::new(51.5 + index as f64 * 0.1, -10.0 + index as f64 * 0.13)).collect();
+        let expected = collect(&DistanceMatrix::new(locations.clone(), locations.clone()).with_threads(1));
+
+        for threads in 2..5 {
+            for block_cells in &[1, 39, 40, 41, 500, DEFAULT_BLOCK_CELLS] {
+                let matrix = DistanceMatrix::new(locations.clone(), locations.clone())
+                    .with_threads(threads)
+                    .with_block_cells(*block_cells);
+                assert_eq!(collect(&matrix), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn upper_triangle_only_keeps_the_distances_to_the_locations_after_each() {
+        let locations = generate_locations();
+        let full = collect(&DistanceMatrix::new(locations.clone(), locations.clone()));
+        let matrix = DistanceMatrix::upper_triangle(locations).with_threads(3).with_block_cells(4);
+        let upper = collect(&matrix);
+
+        assert_eq!(upper.len(), 5);
+        for (row, distances) in upper.iter().enumerate() {
+            assert_eq!(*distances, full[row][row + 1..].to_vec());
+        }
+        assert_eq!(matrix.len(), 10);
+        assert!(upper[4].is_empty());
+    }
+
+    #[test]
+    fn write_csv_writes_a_row_per_origin() {
+        let matrix = DistanceMatrix::new(vec![DUBLIN, DUBLIN], vec![DUBLIN, CORK]);
+        let mut output = Vec::new();
+        matrix.write(&mut output, MatrixFormat::Csv, &["a".to_string(), "b".to_string()], &labels(2)).unwrap();
+        let distance = DUBLIN.distance_from(&CORK).0;
+
+        assert_eq!(String::from_utf8(output).unwrap(), format!("origin,1,2\na,0,{}\nb,0,{}\n", distance, distance));
+    }
+
+    #[test]
+    fn write_csv_writes_ragged_rows_for_the_upper_triangle() {
+        let matrix = DistanceMatrix::upper_triangle(vec![DUBLIN, DUBLIN, DUBLIN]);
+        let mut output = Vec::new();
+        matrix.write(&mut output, MatrixFormat::Csv, &labels(3), &labels(3)).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "origin,1,2,3\n1,0,0\n2,0\n3\n");
+    }
+
+    #[test]
+    fn write_json_writes_the_labels_and_the_rows() {
+        let matrix = DistanceMatrix::upper_triangle(generate_locations()).with_block_cells(2);
+        let mut output = Vec::new();
+        matrix.write(&mut output, MatrixFormat::Json, &labels(5), &labels(5)).unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(written["origins"], serde_json::json!(["1", "2", "3", "4", "5"]));
+        assert_eq!(written["upper_triangle"], true);
+        let distances: Vec<Vec<f64>> = serde_json::from_value(written["distances"].clone()).unwrap();
+        let expected = collect(&matrix);
+        assert_eq!(distances.iter().map(Vec::len).collect::<Vec<_>>(), vec![4, 3, 2, 1, 0]);
+        for (row, expected) in distances.iter().zip(&expected) {
+            assert!(row.iter().zip(expected).all(|(distance, expected)| (distance - expected).abs() < 1e-9));
+        }
+    }
+
+    #[test]
+    fn write_npy_writes_an_aligned_header_and_the_distances() {
+        let matrix = DistanceMatrix::new(vec![DUBLIN, CORK], generate_locations()).with_block_cells(3);
+        let mut output = Vec::new();
+        matrix.write(&mut output, MatrixFormat::Npy, &labels(2), &labels(5)).unwrap();
+
+        assert_eq!(&output[..8], NPY_MAGIC);
+        let header_len = u16::from_le_bytes([output[8], output[9]]) as usize;
+        assert_eq!((10 + header_len) % NPY_ALIGNMENT, 0);
+        let header = String::from_utf8(output[10..10 + header_len].to_vec()).unwrap();
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (2, 5), }"));
+        assert!(header.ends_with(" \n"));
+
+        let distances: Vec<f64> = output[10 + header_len..]
+            .chunks(8)
+            .map(|bytes| f64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]))
+            .collect();
+        assert_eq!(distances, collect(&matrix).concat());
+    }
+
+    #[test]
+    fn write_npy_writes_the_upper_triangle_condensed() {
+        let matrix = DistanceMatrix::upper_triangle(generate_locations());
+        let mut output = Vec::new();
+        matrix.write(&mut output, MatrixFormat::Npy, &labels(5), &labels(5)).unwrap();
+        let header_len = u16::from_le_bytes([output[8], output[9]]) as usize;
+
+        assert!(String::from_utf8_lossy(&output[10..10 + header_len]).contains("'shape': (10,)"));
+        assert_eq!(output.len() - 10 - header_len, 10 * 8);
+    }
+}
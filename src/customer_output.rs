@@ -0,0 +1,1959 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::From;
+use std::error;
+use std::fmt;
+use std::io::Error as IoError;
+use std::io::Write;
+use std::str::FromStr;
+
+use csv;
+use csv::Error as CsvError;
+use serde_json;
+use serde_json::Error as JsonError;
+
+use customer::Customer;
+use customer::CustomerList;
+use customer_attribute::AttributeValue;
+use customer_cluster::Clustering;
+use customer_locator::{AttributedCustomer, LocatedCustomer};
+use customer_proximity::CustomerPair;
+use customer_route::Route;
+use customer_stats::CustomerStats;
+use customer_territory::Territories;
+use customer_venue::Venue;
+use location::{DistanceModel, Location, Ring};
+use units::{DistanceUnit, Kilometers};
+
+// How many straight segments the circles of a search are drawn with.
+const CIRCLE_SEGMENTS: usize = 72;
+
+/// The formats in which a `CustomerList` can be written out.
+///
+/// `Text` is the human friendly format the CLI has always printed,
+/// `Json` writes one JSON object per line (the same format
+/// `CustomerJsonFile` reads) and `Csv` writes a header row followed
+/// by a row per customer. `Kml` and `Gpx` write maps for Google Earth
+/// and GPS devices, only for searches and routes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Kml,
+    Gpx,
+}
+
+impl OutputFormat {
+    /// The names accepted by `OutputFormat::from_str`.
+    pub const NAMES: &'static [&'static str] = &["text", "json", "csv", "kml", "gpx"];
+}
+
+/// An error when trying to build an `OutputFormat` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseOutputFormatError(String);
+
+impl FromStr for OutputFormat {
+    type Err = ParseOutputFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "kml" => Ok(OutputFormat::Kml),
+            "gpx" => Ok(OutputFormat::Gpx),
+            other => Err(ParseOutputFormatError(format!("unknown output format '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Csv => write!(f, "csv"),
+            OutputFormat::Kml => write!(f, "kml"),
+            OutputFormat::Gpx => write!(f, "gpx"),
+        }
+    }
+}
+
+impl fmt::Display for ParseOutputFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Output format parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseOutputFormatError {}
+
+/// Which of the extra customer attributes get written out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeSelection {
+    All,
+    Only(Vec<String>),
+}
+
+impl AttributeSelection {
+    /// Builds an `AttributeSelection` from a comma separated list of names.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let selection = AttributeSelection::from_list("email, tier");
+    /// assert_eq!(selection, AttributeSelection::Only(vec![String::from("email"), String::from("tier")]));
+    /// ```
+    pub fn from_list(list: &str) -> AttributeSelection {
+        let names = list.split(',')
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect();
+
+        AttributeSelection::Only(names)
+    }
+
+    // The attribute names to write for `customers`. `All` takes the union of
+    // the attributes of every customer so CSV rows share the same columns.
+    fn names<'c, I>(&self, customers: I) -> Vec<String>
+        where I: IntoIterator<Item = &'c Customer>
+    {
+        match *self {
+            AttributeSelection::All => {
+                let names: BTreeSet<&String> = customers.into_iter()
+                    .flat_map(|customer| customer.attributes.keys())
+                    .collect();
+                names.into_iter().cloned().collect()
+            }
+            AttributeSelection::Only(ref names) => names.clone(),
+        }
+    }
+}
+
+/// An error encapsulating the things that can go wrong when writing
+/// a `CustomerList` out.
+#[derive(Debug)]
+pub enum CustomerOutputError {
+    Io(IoError),
+    Json(JsonError),
+    Csv(CsvError),
+    /// The output can't be written in the format, like clusters as KML.
+    Unsupported(&'static str, OutputFormat),
+}
+
+impl fmt::Display for CustomerOutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CustomerOutputError::Io(ref err) => write!(f, "Customer output IO error: {}", err),
+            CustomerOutputError::Json(ref err) => write!(f, "Customer output JSON error: {}", err),
+            CustomerOutputError::Csv(ref err) => write!(f, "Customer output CSV error: {}", err),
+            CustomerOutputError::Unsupported(output, format) => write!(f, "Customer output error: {} can't be written as {}", output, format),
+        }
+    }
+}
+
+impl error::Error for CustomerOutputError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CustomerOutputError::Io(ref err) => Some(err),
+            CustomerOutputError::Json(ref err) => Some(err),
+            CustomerOutputError::Csv(ref err) => Some(err),
+            CustomerOutputError::Unsupported(..) => None,
+        }
+    }
+}
+
+impl From<IoError> for CustomerOutputError {
+    fn from(err: IoError) -> Self {
+        CustomerOutputError::Io(err)
+    }
+}
+
+impl From<JsonError> for CustomerOutputError {
+    fn from(err: JsonError) -> Self {
+        CustomerOutputError::Json(err)
+    }
+}
+
+impl From<CsvError> for CustomerOutputError {
+    fn from(err: CsvError) -> Self {
+        CustomerOutputError::Csv(err)
+    }
+}
+
+/// Writes `customers` to `writer` in the given `OutputFormat`.
+///
+/// `location` is the center of the search the customers come from, the
+/// `Text` format prints it along with the distance of each customer to it
+/// under `model` in `unit`. `selection` picks which extra attributes are
+/// written.
+///
+/// # Examples
+///
+/// ```
+/// let stdout = io::stdout();
+/// write_customers(stdout.lock(), &customers, &Location::new(53.3393, -6.2576841), DistanceModel::Haversine, OutputFormat::Csv, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+/// ```
+pub fn write_customers<W: Write>(writer: W,
+                                 customers: &CustomerList,
+                                 location: &Location,
+                                 model: DistanceModel,
+                                 format: OutputFormat,
+                                 selection: &AttributeSelection,
+                                 unit: DistanceUnit)
+                                 -> Result<(), CustomerOutputError> {
+    let located: Vec<LocatedCustomer> = customers.iter()
+        .map(|customer| LocatedCustomer { customer: customer.clone(), distance: model.distance(&customer.location(), location) })
+        .collect();
+    write_search(writer, &located, location, None, format, selection, unit)
+}
+
+/// Writes the `customers` found by a search around `location` to `writer`
+/// like `write_customers`, with the distances the search measured, the
+/// `Kml` and `Gpx` maps also drawing the edges of `ring`, the area
+/// searched, when there's one.
+///
+/// `Kml` writes a placemark for the search centre, a polygon for the ring
+/// and a placemark per customer with its name, id, distance to the centre
+/// and selected attributes. `Gpx` writes a waypoint for the search centre
+/// and for each customer and a track along each edge of the ring.
+///
+/// # Examples
+///
+/// ```
+/// let ring = Ring::within(Kilometers(100f64));
+/// let customers = locator.locate_in_ring(&ring, &dublin);
+/// write_search(io::stdout(), &customers, &dublin, Some(&ring), OutputFormat::Kml, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+/// ```
+pub fn write_search<W: Write>(mut writer: W,
+                              customers: &[LocatedCustomer],
+                              location: &Location,
+                              ring: Option<&Ring>,
+                              format: OutputFormat,
+                              selection: &AttributeSelection,
+                              unit: DistanceUnit)
+                              -> Result<(), CustomerOutputError> {
+    let names = selection.names(customers.iter().map(|located| &located.customer));
+    match format {
+        OutputFormat::Text => write_text(&mut writer, customers, location, &names, unit),
+        OutputFormat::Json => write_json(&mut writer, customers, &names),
+        OutputFormat::Csv => write_csv(&mut writer, customers, &names),
+        OutputFormat::Kml => write_kml(&mut writer, customers, location, ring, &names, unit),
+        OutputFormat::Gpx => write_gpx(&mut writer, customers, location, ring, &names, unit),
+    }
+}
+
+fn write_text<W: Write>(writer: &mut W,
+                        customers: &[LocatedCustomer],
+                        location: &Location,
+                        names: &[String],
+                        unit: DistanceUnit)
+                        -> Result<(), CustomerOutputError> {
+    writeln!(writer, "Location is {}.", location)?;
+
+    for &LocatedCustomer { ref customer, distance } in customers {
+        write!(writer, "{} is {} from provided location.", customer, unit.format(distance))?;
+        write_attribute_suffix(writer, customer, names)?;
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+fn write_json<W: Write>(writer: &mut W, customers: &[LocatedCustomer], names: &[String]) -> Result<(), CustomerOutputError> {
+    for LocatedCustomer { customer, .. } in customers {
+        serde_json::to_writer(&mut *writer, &with_selected_attributes(customer, names))?;
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+fn write_csv<W: Write>(writer: &mut W, customers: &[LocatedCustomer], names: &[String]) -> Result<(), CustomerOutputError> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+
+    let mut header = vec!["user_id", "name", "latitude", "longitude"];
+    header.extend(names.iter().map(|name| name.as_str()));
+    csv_writer.write_record(&header)?;
+
+    for LocatedCustomer { customer, .. } in customers {
+        let mut record = vec![customer.user_id.to_string(),
+                              customer.name.clone(),
+                              customer.latitude.to_string(),
+                              customer.longitude.to_string()];
+        record.extend(attribute_cells(customer, names));
+        csv_writer.write_record(&record)?;
+    }
+
+    csv_writer.flush()?;
+    Ok(())
+}
+
+fn write_kml<W: Write>(writer: &mut W,
+                       customers: &[LocatedCustomer],
+                       location: &Location,
+                       ring: Option<&Ring>,
+                       names: &[String],
+                       unit: DistanceUnit)
+                       -> Result<(), CustomerOutputError> {
+    let coordinates = |locations: &[Location]| {
+        locations.iter()
+            .map(|location| format!("{},{},0", location.longitude, location.latitude))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(writer, "  <Document>")?;
+    writeln!(writer, "    <name>{} customers around {}</name>", customers.len(), escape_xml(&location.to_string()))?;
+    writeln!(writer, "    <Placemark>")?;
+    writeln!(writer, "      <name>Search centre</name>")?;
+    writeln!(writer, "      <description>{}</description>", escape_xml(&location.to_string()))?;
+    writeln!(writer, "      <Point><coordinates>{}</coordinates></Point>", coordinates(&[*location]))?;
+    writeln!(writer, "    </Placemark>")?;
+    if let Some(ring) = ring {
+        let edges = ring_edges(location, ring);
+        if let Some((outer, _)) = edges.first() {
+            writeln!(writer, "    <Placemark>")?;
+            writeln!(writer, "      <name>{}</name>", escape_xml(&ring_name(ring, unit)))?;
+            writeln!(writer, "      <Polygon>")?;
+            writeln!(writer, "        <outerBoundaryIs><LinearRing><coordinates>{}</coordinates></LinearRing></outerBoundaryIs>", coordinates(outer))?;
+            for (inner, _) in &edges[1..] {
+                writeln!(writer, "        <innerBoundaryIs><LinearRing><coordinates>{}</coordinates></LinearRing></innerBoundaryIs>", coordinates(inner))?;
+            }
+            writeln!(writer, "      </Polygon>")?;
+            writeln!(writer, "    </Placemark>")?;
+        }
+    }
+    for &LocatedCustomer { ref customer, distance } in customers {
+        writeln!(writer, "    <Placemark>")?;
+        writeln!(writer, "      <name>{}</name>", escape_xml(&customer.name))?;
+        writeln!(writer, "      <description>Customer {}, {} from the search centre</description>", customer.user_id, unit.format(distance))?;
+        writeln!(writer, "      <ExtendedData>")?;
+        writeln!(writer, r#"        <Data name="user_id"><value>{}</value></Data>"#, customer.user_id)?;
+        writeln!(writer, r#"        <Data name="distance"><value>{}</value></Data>"#, distance.0)?;
+        for (name, value) in selected_attributes(customer, names) {
+            writeln!(writer, r#"        <Data name="{}"><value>{}</value></Data>"#, escape_xml(name), escape_xml(&value.to_string()))?;
+        }
+        writeln!(writer, "      </ExtendedData>")?;
+        writeln!(writer, "      <Point><coordinates>{}</coordinates></Point>", coordinates(&[customer.location()]))?;
+        writeln!(writer, "    </Placemark>")?;
+    }
+    writeln!(writer, "  </Document>")?;
+    writeln!(writer, "</kml>")?;
+
+    Ok(())
+}
+
+fn write_gpx<W: Write>(writer: &mut W,
+                       customers: &[LocatedCustomer],
+                       location: &Location,
+                       ring: Option<&Ring>,
+                       names: &[String],
+                       unit: DistanceUnit)
+                       -> Result<(), CustomerOutputError> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<gpx version="1.1" creator="customer_locator" xmlns="http://www.topografix.com/GPX/1/1">"#)?;
+    writeln!(writer, r#"  <wpt lat="{}" lon="{}"><name>Search centre</name><desc>{}</desc><sym>Flag, Blue</sym></wpt>"#,
+             location.latitude,
+             location.longitude,
+             escape_xml(&location.to_string()))?;
+    for &LocatedCustomer { ref customer, distance } in customers {
+        let description = format!("Customer {}, {} from the search centre{}",
+                                  customer.user_id,
+                                  unit.format(distance),
+                                  attribute_suffix(customer, names));
+        writeln!(writer, r#"  <wpt lat="{}" lon="{}"><name>{}</name><desc>{}</desc></wpt>"#,
+                 customer.latitude,
+                 customer.longitude,
+                 escape_xml(&customer.name),
+                 escape_xml(&description))?;
+    }
+    if let Some(ring) = ring {
+        for (edge, radius) in ring_edges(location, ring) {
+            writeln!(writer, "  <trk>")?;
+            writeln!(writer, "    <name>{} radius</name>", escape_xml(&unit.format(radius)))?;
+            writeln!(writer, "    <trkseg>")?;
+            for point in edge {
+                writeln!(writer, r#"      <trkpt lat="{}" lon="{}"/>"#, point.latitude, point.longitude)?;
+            }
+            writeln!(writer, "    </trkseg>")?;
+            writeln!(writer, "  </trk>")?;
+        }
+    }
+    writeln!(writer, "</gpx>")?;
+
+    Ok(())
+}
+
+// The points drawing each edge of `ring` around `location` as a closed
+// circle, along with its radius, the outer edge first. Edges of a zero or
+// infinite radius aren't drawn.
+fn ring_edges(location: &Location, ring: &Ring) -> Vec<(Vec<Location>, Kilometers)> {
+    [ring.max_radius, ring.min_radius]
+        .iter()
+        .filter(|radius| radius.0 > 0f64 && radius.0.is_finite())
+        .map(|&radius| {
+            let circle = (0..=CIRCLE_SEGMENTS)
+                .map(|segment| location.destination(360f64 * (segment % CIRCLE_SEGMENTS) as f64 / CIRCLE_SEGMENTS as f64, radius))
+                .collect();
+            (circle, radius)
+        })
+        .collect()
+}
+
+// Names the area searched by `ring` in `unit`.
+fn ring_name(ring: &Ring, unit: DistanceUnit) -> String {
+    if ring.min_radius.0 > 0f64 {
+        format!("From {} to {}", unit.format(ring.min_radius), unit.format(ring.max_radius))
+    } else {
+        format!("Within {}", unit.format(ring.max_radius))
+    }
+}
+
+/// Writes the clusters of `clustering`, and the customers left out of them,
+/// to `writer` in the given `OutputFormat`.
+///
+/// `Text` lists each cluster with its centroid and the distance of each of
+/// its customers to it in `unit`, followed by the noise. `Json` writes one
+/// object per cluster with its `id`, `centroid`, `size` and `customers`, the
+/// noise last with a `null` id and centroid. `Csv` writes a row per customer
+/// starting with the id and centroid of its cluster, empty for the noise.
+///
+/// # Examples
+///
+/// ```
+/// let clustering = Dbscan::new(Kilometers(5f64), 10).cluster(&customers);
+/// write_clusters(io::stdout(), &clustering, OutputFormat::Json, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+/// ```
+pub fn write_clusters<W: Write>(mut writer: W,
+                                clustering: &Clustering,
+                                format: OutputFormat,
+                                selection: &AttributeSelection,
+                                unit: DistanceUnit)
+                                -> Result<(), CustomerOutputError> {
+    let every_customer: CustomerList = clustering.clusters
+        .iter()
+        .flat_map(|cluster| cluster.customers.iter().map(|located| &located.customer))
+        .chain(clustering.noise.iter())
+        .cloned()
+        .collect();
+    let names = selection.names(&every_customer);
+
+    match format {
+        OutputFormat::Text => write_clusters_text(&mut writer, clustering, &names, unit),
+        OutputFormat::Json => write_clusters_json(&mut writer, clustering, &names),
+        OutputFormat::Csv => write_clusters_csv(&mut writer, clustering, &names),
+        OutputFormat::Kml | OutputFormat::Gpx => Err(CustomerOutputError::Unsupported("clusters", format)),
+    }
+}
+
+fn write_clusters_text<W: Write>(writer: &mut W,
+                                 clustering: &Clustering,
+                                 names: &[String],
+                                 unit: DistanceUnit)
+                                 -> Result<(), CustomerOutputError> {
+    for cluster in &clustering.clusters {
+        writeln!(writer, "Cluster {} of {} customers around {}.", cluster.id, cluster.customers.len(), cluster.centroid)?;
+        for located in &cluster.customers {
+            let dist_from_centroid = unit.format(located.distance);
+            write!(writer, "    {} is {} from the centroid.", located.customer, dist_from_centroid)?;
+            write_attribute_suffix(writer, &located.customer, names)?;
+            writeln!(writer)?;
+        }
+    }
+
+    if !clustering.noise.is_empty() {
+        writeln!(writer, "Noise of {} customers.", clustering.noise.len())?;
+        for customer in &clustering.noise {
+            write!(writer, "    {}.", customer)?;
+            write_attribute_suffix(writer, customer, names)?;
+            writeln!(writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A cluster as written by the `Json` format.
+#[derive(Serialize)]
+struct ClusterRecord {
+    id: Option<usize>,
+    centroid: Option<Location>,
+    size: usize,
+    customers: Vec<Customer>,
+}
+
+fn write_clusters_json<W: Write>(writer: &mut W, clustering: &Clustering, names: &[String]) -> Result<(), CustomerOutputError> {
+    let select = |customers: Vec<&Customer>| {
+        customers.into_iter().map(|customer| with_selected_attributes(customer, names)).collect::<Vec<_>>()
+    };
+
+    let records = clustering.clusters.iter().map(|cluster| ClusterRecord {
+        id: Some(cluster.id),
+        centroid: Some(cluster.centroid),
+        size: cluster.customers.len(),
+        customers: select(cluster.customers.iter().map(|located| &located.customer).collect()),
+    });
+    let noise = ClusterRecord {
+        id: None,
+        centroid: None,
+        size: clustering.noise.len(),
+        customers: select(clustering.noise.iter().collect()),
+    };
+
+    for record in records.chain(Some(noise)) {
+        serde_json::to_writer(&mut *writer, &record)?;
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+fn write_clusters_csv<W: Write>(writer: &mut W, clustering: &Clustering, names: &[String]) -> Result<(), CustomerOutputError> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+
+    let mut header = vec!["cluster", "centroid_latitude", "centroid_longitude", "user_id", "name", "latitude", "longitude"];
+    header.extend(names.iter().map(|name| name.as_str()));
+    csv_writer.write_record(&header)?;
+
+    let clustered = clustering.clusters.iter().flat_map(|cluster| {
+        let columns = vec![cluster.id.to_string(), cluster.centroid.latitude.to_string(), cluster.centroid.longitude.to_string()];
+        cluster.customers.iter().map(move |located| (columns.clone(), &located.customer))
+    });
+    let noise = clustering.noise.iter().map(|customer| (vec![String::new(); 3], customer));
+
+    for (mut record, customer) in clustered.chain(noise) {
+        record.extend(vec![customer.user_id.to_string(),
+                           customer.name.clone(),
+                           customer.latitude.to_string(),
+                           customer.longitude.to_string()]);
+        record.extend(attribute_cells(customer, names));
+        csv_writer.write_record(&record)?;
+    }
+
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Writes the customers of each band around `location`, as found by
+/// `CustomerLocator::locate_in_bands`, to `writer` in the given
+/// `OutputFormat`. `banded` holds the customers of each of `bands` along
+/// with their distance to `location`.
+///
+/// `Text` lists each band with its radiuses in `unit` and the distance of
+/// each of its customers to `location`. `Json` writes one object per band
+/// with its `band` number, `min_radius` and `max_radius` in Km, `size` and
+/// `customers`. `Csv` writes a row per customer starting with the number
+/// and radiuses of its band.
+///
+/// # Examples
+///
+/// ```
+/// let bands = Ring::bands(&edges, Boundary::Exclusive);
+/// let banded = locator.locate_in_bands(&bands, &dublin);
+/// write_bands(io::stdout(), &bands, &banded, OutputFormat::Csv, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+/// ```
+pub fn write_bands<W: Write>(mut writer: W,
+                             bands: &[Ring],
+                             banded: &[Vec<LocatedCustomer>],
+                             format: OutputFormat,
+                             selection: &AttributeSelection,
+                             unit: DistanceUnit)
+                             -> Result<(), CustomerOutputError> {
+    let names = selection.names(banded.iter().flatten().map(|located| &located.customer));
+
+    match format {
+        OutputFormat::Text => {
+            for (number, (band, customers)) in bands.iter().zip(banded).enumerate() {
+                writeln!(writer, "Band {} from {} to {} of {} customers.",
+                         number + 1, unit.format(band.min_radius), unit.format(band.max_radius), customers.len())?;
+                for &LocatedCustomer { ref customer, distance } in customers {
+                    write!(writer, "    {} is {} from provided location.", customer, unit.format(distance))?;
+                    write_attribute_suffix(&mut writer, customer, &names)?;
+                    writeln!(writer)?;
+                }
+            }
+        }
+        OutputFormat::Json => {
+            for (number, (band, customers)) in bands.iter().zip(banded).enumerate() {
+                let record = BandRecord {
+                    band: number + 1,
+                    min_radius: band.min_radius,
+                    max_radius: band.max_radius,
+                    size: customers.len(),
+                    customers: customers.iter().map(|located| with_selected_attributes(&located.customer, &names)).collect(),
+                };
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            let mut header = vec!["band", "min_radius", "max_radius", "user_id", "name", "latitude", "longitude"];
+            header.extend(names.iter().map(|name| name.as_str()));
+            csv_writer.write_record(&header)?;
+
+            for (number, (band, customers)) in bands.iter().zip(banded).enumerate() {
+                for LocatedCustomer { customer, .. } in customers {
+                    let mut record = vec![(number + 1).to_string(),
+                                          band.min_radius.0.to_string(),
+                                          band.max_radius.0.to_string(),
+                                          customer.user_id.to_string(),
+                                          customer.name.clone(),
+                                          customer.latitude.to_string(),
+                                          customer.longitude.to_string()];
+                    record.extend(attribute_cells(customer, &names));
+                    csv_writer.write_record(&record)?;
+                }
+            }
+            csv_writer.flush()?;
+        }
+        OutputFormat::Kml | OutputFormat::Gpx => return Err(CustomerOutputError::Unsupported("bands", format)),
+    }
+
+    Ok(())
+}
+
+/// A band as written by the `Json` format.
+#[derive(Serialize)]
+struct BandRecord {
+    band: usize,
+    min_radius: Kilometers,
+    max_radius: Kilometers,
+    size: usize,
+    customers: Vec<Customer>,
+}
+
+/// Writes the customers of a search around several centres to `writer` in
+/// the given `OutputFormat`, `centres` holding the names of the centres.
+///
+/// `Text` prints each customer with the centres it matched and its
+/// distance to every centre in `unit`. `Json` writes the customer fields
+/// plus `centres`, the names of the ones matched, and `distances`, an
+/// object with the distance in Km to each centre by name. `Csv` adds a
+/// `centres` column with the names of the ones matched separated by `;`
+/// and a `distance_<name>` column per centre in Km.
+pub fn write_attributed<W: Write>(mut writer: W,
+                                  customers: &[AttributedCustomer],
+                                  centres: &[String],
+                                  format: OutputFormat,
+                                  selection: &AttributeSelection,
+                                  unit: DistanceUnit)
+                                  -> Result<(), CustomerOutputError> {
+    let every_customer: CustomerList = customers.iter().map(|attributed| attributed.customer.clone()).collect();
+    let names = selection.names(&every_customer);
+    let matched = |attributed: &AttributedCustomer| {
+        attributed.centres.iter().map(|centre| centres[*centre].as_str()).collect::<Vec<&str>>()
+    };
+
+    match format {
+        OutputFormat::Text => {
+            for attributed in customers {
+                let distances = centres.iter()
+                    .zip(&attributed.distances)
+                    .map(|(centre, distance)| format!("{} from {}", unit.format(*distance), centre))
+                    .collect::<Vec<String>>();
+                write!(writer, "{} matches {}, {}.", attributed.customer, matched(attributed).join(" and "), distances.join(", "))?;
+
+                write_attribute_suffix(&mut writer, &attributed.customer, &names)?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Json => {
+            for attributed in customers {
+                let record = AttributedRecord {
+                    customer: with_selected_attributes(&attributed.customer, &names),
+                    centres: matched(attributed),
+                    distances: centres.iter().map(|centre| centre.as_str()).zip(attributed.distances.iter().cloned()).collect(),
+                };
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            let mut header = vec![String::from("user_id"), String::from("name"), String::from("latitude"), String::from("longitude"), String::from("centres")];
+            header.extend(centres.iter().map(|centre| format!("distance_{}", centre)));
+            header.extend(names.iter().cloned());
+            csv_writer.write_record(&header)?;
+
+            for attributed in customers {
+                let customer = &attributed.customer;
+                let mut record = vec![customer.user_id.to_string(),
+                                      customer.name.clone(),
+                                      customer.latitude.to_string(),
+                                      customer.longitude.to_string(),
+                                      matched(attributed).join(";")];
+                record.extend(attributed.distances.iter().map(|distance| distance.0.to_string()));
+                record.extend(attribute_cells(customer, &names));
+                csv_writer.write_record(&record)?;
+            }
+            csv_writer.flush()?;
+        }
+        OutputFormat::Kml | OutputFormat::Gpx => return Err(CustomerOutputError::Unsupported("customers matched to several centres", format)),
+    }
+
+    Ok(())
+}
+
+/// A customer of a search around several centres as written by the `Json`
+/// format.
+#[derive(Serialize)]
+struct AttributedRecord<'c> {
+    #[serde(flatten)]
+    customer: Customer,
+    centres: Vec<&'c str>,
+    distances: BTreeMap<&'c str, Kilometers>,
+}
+
+/// Writes the territories of `territories`, and the customers no office
+/// could take, to `writer` in the given `OutputFormat`.
+///
+/// `Text` lists each office with its load, capacity and average distance in
+/// `unit`, followed by the distance of each of its customers to it, and ends
+/// with the total distance of the assignment. `Json`
+/// writes one object per office with its `name`, coordinates, `capacity`,
+/// `load`, `total_distance` and `average_distance` in Km and `customers`,
+/// the unassigned customers last with a `null` name. `Csv` writes a row per
+/// customer starting with the name of its office and the distance to it,
+/// both empty for the unassigned ones.
+pub fn write_territories<W: Write>(mut writer: W,
+                                   territories: &Territories,
+                                   format: OutputFormat,
+                                   selection: &AttributeSelection,
+                                   unit: DistanceUnit)
+                                   -> Result<(), CustomerOutputError> {
+    let every_customer: CustomerList = territories.territories
+        .iter()
+        .flat_map(|territory| territory.customers.iter().map(|located| &located.customer))
+        .chain(territories.unassigned.iter())
+        .cloned()
+        .collect();
+    let names = selection.names(&every_customer);
+
+    match format {
+        OutputFormat::Text => {
+            let write_customer = |writer: &mut W, customer: &Customer, distance: Option<Kilometers>| -> Result<(), CustomerOutputError> {
+                match distance {
+                    Some(distance) => write!(writer, "    {} is {} from the office.", customer, unit.format(distance))?,
+                    None => write!(writer, "    {}.", customer)?,
+                }
+                write_attribute_suffix(writer, customer, &names)?;
+                writeln!(writer)?;
+                Ok(())
+            };
+
+            for territory in &territories.territories {
+                write!(writer, "{} at {}: {} of {} customers", territory.office.name, territory.office.location, territory.load(), territory.office.capacity)?;
+                match territory.average_distance() {
+                    Some(average_distance) => writeln!(writer, ", {} away on average.", unit.format(average_distance))?,
+                    None => writeln!(writer, ".")?,
+                }
+                for located in &territory.customers {
+                    write_customer(&mut writer, &located.customer, Some(located.distance))?;
+                }
+            }
+            if !territories.unassigned.is_empty() {
+                writeln!(writer, "{} customers couldn't be assigned.", territories.unassigned.len())?;
+                for customer in &territories.unassigned {
+                    write_customer(&mut writer, customer, None)?;
+                }
+            }
+            writeln!(writer, "Total distance: {}.", unit.format(territories.total_distance()))?;
+        }
+        OutputFormat::Json => {
+            let select = |customers: Vec<&Customer>| {
+                customers.into_iter().map(|customer| with_selected_attributes(customer, &names)).collect::<Vec<_>>()
+            };
+            let records = territories.territories.iter().map(|territory| TerritoryRecord {
+                name: Some(territory.office.name.clone()),
+                latitude: Some(territory.office.location.latitude),
+                longitude: Some(territory.office.location.longitude),
+                capacity: Some(territory.office.capacity),
+                load: territory.load(),
+                total_distance: Some(territory.total_distance()),
+                average_distance: territory.average_distance(),
+                customers: select(territory.customers.iter().map(|located| &located.customer).collect()),
+            });
+            let unassigned = TerritoryRecord {
+                name: None,
+                latitude: None,
+                longitude: None,
+                capacity: None,
+                load: territories.unassigned.len(),
+                total_distance: None,
+                average_distance: None,
+                customers: select(territories.unassigned.iter().collect()),
+            };
+
+            for record in records.chain(Some(unassigned).filter(|unassigned| unassigned.load > 0)) {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            let mut header = vec!["office", "distance", "user_id", "name", "latitude", "longitude"];
+            header.extend(names.iter().map(|name| name.as_str()));
+            csv_writer.write_record(&header)?;
+
+            let assigned = territories.territories.iter().flat_map(|territory| {
+                territory.customers.iter().map(move |located| {
+                    (territory.office.name.clone(), located.distance.0.to_string(), &located.customer)
+                })
+            });
+            let unassigned = territories.unassigned.iter().map(|customer| (String::new(), String::new(), customer));
+            for (office, distance, customer) in assigned.chain(unassigned) {
+                let mut record = vec![office,
+                                      distance,
+                                      customer.user_id.to_string(),
+                                      customer.name.clone(),
+                                      customer.latitude.to_string(),
+                                      customer.longitude.to_string()];
+                record.extend(attribute_cells(customer, &names));
+                csv_writer.write_record(&record)?;
+            }
+            csv_writer.flush()?;
+        }
+        OutputFormat::Kml | OutputFormat::Gpx => return Err(CustomerOutputError::Unsupported("territories", format)),
+    }
+
+    Ok(())
+}
+
+/// A territory as written by the `Json` format, distances in Km.
+#[derive(Serialize)]
+struct TerritoryRecord {
+    name: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    capacity: Option<usize>,
+    load: usize,
+    total_distance: Option<Kilometers>,
+    average_distance: Option<Kilometers>,
+    customers: Vec<Customer>,
+}
+
+/// Writes the days of `route` to `writer` in the given `OutputFormat`.
+///
+/// `Text` lists the stops of each day in order with the distance from the
+/// stop before and since the day started, in `unit`, and the way back to
+/// the office. `Json` writes one object per day with its `day` number,
+/// `distance`, `return_leg` and `stops`, each stop a customer with its
+/// `stop` number, `leg` and `cumulative` distances in Km, the customers too
+/// far for a day last with a `null` day. `Csv` writes a row per stop and
+/// one for the way back to the office ending each day. `Gpx` writes the
+/// route like `write_route_gpx`, routes can't be written as `Kml`.
+pub fn write_route<W: Write>(mut writer: W,
+                             route: &Route,
+                             format: OutputFormat,
+                             selection: &AttributeSelection,
+                             unit: DistanceUnit)
+                             -> Result<(), CustomerOutputError> {
+    let every_customer: CustomerList = route.days
+        .iter()
+        .flat_map(|day| day.stops.iter().map(|stop| stop.customer.clone()))
+        .chain(route.unreachable.iter().cloned())
+        .collect();
+    let names = selection.names(&every_customer);
+
+    match format {
+        OutputFormat::Text => {
+            for (day, number) in route.days.iter().zip(1..) {
+                writeln!(writer, "Day {}: {} stops, {} from and back to {}.", number, day.stops.len(), unit.format(day.distance()), route.office)?;
+                for (stop, number) in day.stops.iter().zip(1..) {
+                    write!(writer, "    {}. {} after {}, {} so far.", number, stop.customer, unit.format(stop.leg), unit.format(stop.cumulative))?;
+                    write_attribute_suffix(&mut writer, &stop.customer, &names)?;
+                    writeln!(writer)?;
+                }
+                writeln!(writer, "    Back to the office after {}.", unit.format(day.return_leg))?;
+            }
+            if !route.unreachable.is_empty() {
+                writeln!(writer, "{} customers are too far to visit and come back within a day.", route.unreachable.len())?;
+            }
+            writeln!(writer, "Total distance: {} over {} days.", unit.format(route.total_distance()), route.days.len())?;
+        }
+        OutputFormat::Json => {
+            let records = route.days.iter().zip(1..).map(|(day, number)| DayRecord {
+                day: Some(number),
+                distance: Some(day.distance()),
+                return_leg: Some(day.return_leg),
+                stops: day.stops.iter().zip(1..).map(|(stop, number)| StopRecord {
+                    stop: Some(number),
+                    leg: Some(stop.leg),
+                    cumulative: Some(stop.cumulative),
+                    customer: with_selected_attributes(&stop.customer, &names),
+                }).collect(),
+            });
+            let unreachable = DayRecord {
+                day: None,
+                distance: None,
+                return_leg: None,
+                stops: route.unreachable.iter().map(|customer| StopRecord {
+                    stop: None,
+                    leg: None,
+                    cumulative: None,
+                    customer: with_selected_attributes(customer, &names),
+                }).collect(),
+            };
+
+            for record in records.chain(Some(unreachable).filter(|unreachable| !unreachable.stops.is_empty())) {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            let mut header = vec!["day", "stop", "leg", "cumulative", "user_id", "name", "latitude", "longitude"];
+            header.extend(names.iter().map(|name| name.as_str()));
+            csv_writer.write_record(&header)?;
+
+            let customer_record = |day: String, stop: String, leg: String, cumulative: String, customer: &Customer| {
+                let mut record = vec![day,
+                                      stop,
+                                      leg,
+                                      cumulative,
+                                      customer.user_id.to_string(),
+                                      customer.name.clone(),
+                                      customer.latitude.to_string(),
+                                      customer.longitude.to_string()];
+                record.extend(attribute_cells(customer, &names));
+                record
+            };
+            for (day, number) in route.days.iter().zip(1..) {
+                for (stop, stop_number) in day.stops.iter().zip(1..) {
+                    csv_writer.write_record(customer_record(number.to_string(),
+                                                            stop_number.to_string(),
+                                                            stop.leg.0.to_string(),
+                                                            stop.cumulative.0.to_string(),
+                                                            &stop.customer))?;
+                }
+                let mut record = vec![number.to_string(),
+                                      String::new(),
+                                      day.return_leg.0.to_string(),
+                                      day.distance().0.to_string(),
+                                      String::new(),
+                                      String::new(),
+                                      route.office.latitude.to_string(),
+                                      route.office.longitude.to_string()];
+                record.extend(names.iter().map(|_| String::new()));
+                csv_writer.write_record(&record)?;
+            }
+            for customer in &route.unreachable {
+                csv_writer.write_record(customer_record(String::new(), String::new(), String::new(), String::new(), customer))?;
+            }
+            csv_writer.flush()?;
+        }
+        OutputFormat::Gpx => write_route_gpx(writer, route, unit)?,
+        OutputFormat::Kml => return Err(CustomerOutputError::Unsupported("routes", format)),
+    }
+
+    Ok(())
+}
+
+/// A day of a route as written by the `Json` format, distances in Km.
+#[derive(Serialize)]
+struct DayRecord {
+    day: Option<usize>,
+    distance: Option<Kilometers>,
+    return_leg: Option<Kilometers>,
+    stops: Vec<StopRecord>,
+}
+
+/// A stop of a route as written by the `Json` format, distances in Km.
+#[derive(Serialize)]
+struct StopRecord {
+    stop: Option<usize>,
+    leg: Option<Kilometers>,
+    cumulative: Option<Kilometers>,
+    #[serde(flatten)]
+    customer: Customer,
+}
+
+/// Writes the days of `route` to `writer` as a [GPX 1.1]
+/// (https://www.topografix.com/GPX/1/1/) document, the format GPS devices
+/// load routes from.
+///
+/// The office is a waypoint and every day a route from the office through
+/// its stops and back, each stop named after its customer and described
+/// with its id and distances in `unit`. The customers too far for a day are
+/// left out.
+pub fn write_route_gpx<W: Write>(mut writer: W, route: &Route, unit: DistanceUnit) -> Result<(), CustomerOutputError> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<gpx version="1.1" creator="customer_locator" xmlns="http://www.topografix.com/GPX/1/1">"#)?;
+    writeln!(writer, r#"  <wpt lat="{}" lon="{}"><name>Office</name></wpt>"#, route.office.latitude, route.office.longitude)?;
+    for (day, number) in route.days.iter().zip(1..) {
+        writeln!(writer, "  <rte>")?;
+        writeln!(writer, "    <name>Day {}</name>", number)?;
+        writeln!(writer, "    <desc>{} stops, {}</desc>", day.stops.len(), escape_xml(&unit.format(day.distance())))?;
+        writeln!(writer, r#"    <rtept lat="{}" lon="{}"><name>Office</name></rtept>"#, route.office.latitude, route.office.longitude)?;
+        for stop in &day.stops {
+            let description = format!("Customer {}, {} from the last stop, {} so far",
+                                      stop.customer.user_id,
+                                      unit.format(stop.leg),
+                                      unit.format(stop.cumulative));
+            writeln!(writer, r#"    <rtept lat="{}" lon="{}"><name>{}</name><desc>{}</desc></rtept>"#,
+                     stop.customer.latitude,
+                     stop.customer.longitude,
+                     escape_xml(&stop.customer.name),
+                     escape_xml(&description))?;
+        }
+        writeln!(writer, r#"    <rtept lat="{}" lon="{}"><name>Office</name></rtept>"#, route.office.latitude, route.office.longitude)?;
+        writeln!(writer, "  </rte>")?;
+    }
+    writeln!(writer, "</gpx>")?;
+
+    Ok(())
+}
+
+// Escapes the characters of `text` with a meaning in XML.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            other => escaped.push(other),
+        }
+    }
+
+    escaped
+}
+
+/// Writes the pairs of customers close to each other found by
+/// `ProximityJoin::pairs` to `writer` in the given `OutputFormat`.
+///
+/// `Text` writes a line per pair with its distance in `unit`. `Json` writes
+/// one object per pair and `Csv` a row per pair, both with the
+/// `first_user_id`, `first_name`, `second_user_id`, `second_name` and
+/// `distance` in Km.
+pub fn write_pairs<W: Write>(mut writer: W,
+                             pairs: &[CustomerPair],
+                             format: OutputFormat,
+                             unit: DistanceUnit)
+                             -> Result<(), CustomerOutputError> {
+    let records = pairs.iter().map(|pair| PairRecord {
+        first_user_id: pair.first.user_id,
+        first_name: &pair.first.name,
+        second_user_id: pair.second.user_id,
+        second_name: &pair.second.name,
+        distance: pair.distance,
+    });
+
+    match format {
+        OutputFormat::Text => {
+            for pair in pairs {
+                writeln!(writer, "{} and {} are {} apart.", pair.first, pair.second, unit.format(pair.distance))?;
+            }
+        }
+        OutputFormat::Json => {
+            for record in records {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            for record in records {
+                csv_writer.serialize(record)?;
+            }
+            csv_writer.flush()?;
+        }
+        OutputFormat::Kml | OutputFormat::Gpx => return Err(CustomerOutputError::Unsupported("pairs", format)),
+    }
+
+    Ok(())
+}
+
+/// A pair of customers as written by the `Json` and `Csv` formats, the
+/// distance in Km.
+#[derive(Serialize)]
+struct PairRecord<'p> {
+    first_user_id: i64,
+    first_name: &'p str,
+    second_user_id: i64,
+    second_name: &'p str,
+    distance: Kilometers,
+}
+
+/// Writes `venues` to `writer` in the given `OutputFormat`, along with how
+/// many customers are too far from every one of them when there's any.
+///
+/// `Text` prints the total distance from the customers of each venue to it
+/// and the one to the customer furthest away in `unit`. `Json` writes one
+/// object per venue with its `name`, coordinates, customer count, distances
+/// in Km and `user_ids`, the `uncovered` customers last with a `null` name
+/// and coordinates. `Csv` writes a row per venue with the same fields but
+/// the user ids.
+pub fn write_venues<W: Write>(mut writer: W,
+                              venues: &[Venue],
+                              uncovered: &CustomerList,
+                              format: OutputFormat,
+                              unit: DistanceUnit)
+                              -> Result<(), CustomerOutputError> {
+    let records = venues.iter()
+        .map(|venue| VenueRecord {
+            name: Some(venue.name.clone()),
+            latitude: Some(venue.location.latitude),
+            longitude: Some(venue.location.longitude),
+            customers: venue.customers.len(),
+            total_distance: Some(venue.customers.iter().map(|located| located.distance.0).sum()),
+            max_distance: Some(venue.customers.iter().map(|located| located.distance.0).fold(0f64, f64::max)),
+            user_ids: venue.customers.iter().map(|located| located.customer.user_id).collect(),
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        OutputFormat::Text => {
+            for (venue, record) in venues.iter().zip(&records) {
+                writeln!(writer, "{} at {}: {} customers, {} in total and {} to the furthest one.",
+                         venue.name,
+                         venue.location,
+                         record.customers,
+                         unit.format(Kilometers(record.total_distance.unwrap_or_default())),
+                         unit.format(Kilometers(record.max_distance.unwrap_or_default())))?;
+            }
+            if !uncovered.is_empty() {
+                writeln!(writer, "{} customers are too far from every venue.", uncovered.len())?;
+            }
+        }
+        OutputFormat::Json => {
+            let uncovered = VenueRecord {
+                name: None,
+                latitude: None,
+                longitude: None,
+                customers: uncovered.len(),
+                total_distance: None,
+                max_distance: None,
+                user_ids: uncovered.iter().map(|customer| customer.user_id).collect(),
+            };
+            for record in records.iter().chain(Some(&uncovered).filter(|uncovered| uncovered.customers > 0)) {
+                serde_json::to_writer(&mut writer, record)?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            csv_writer.write_record(["name", "latitude", "longitude", "customers", "total_distance", "max_distance"])?;
+            let optional = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+            for record in &records {
+                csv_writer.write_record(&[record.name.clone().unwrap_or_default(),
+                                          optional(record.latitude),
+                                          optional(record.longitude),
+                                          record.customers.to_string(),
+                                          optional(record.total_distance),
+                                          optional(record.max_distance)])?;
+            }
+            if !uncovered.is_empty() {
+                csv_writer.write_record(&[String::new(), String::new(), String::new(), uncovered.len().to_string(), String::new(), String::new()])?;
+            }
+            csv_writer.flush()?;
+        }
+        OutputFormat::Kml | OutputFormat::Gpx => return Err(CustomerOutputError::Unsupported("venues", format)),
+    }
+
+    Ok(())
+}
+
+/// A venue as written by the `Json` and `Csv` formats, distances in Km.
+#[derive(Serialize)]
+struct VenueRecord {
+    name: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    customers: usize,
+    total_distance: Option<f64>,
+    max_distance: Option<f64>,
+    user_ids: Vec<i64>,
+}
+
+/// Writes `stats` to `writer` in `format`.
+///
+/// `Text` prints a line per figure with distances in `unit`, `Json` writes
+/// the whole `CustomerStats` as a single object and `Csv` writes a
+/// `statistic,value` row per figure. Distances are in Km on both.
+///
+/// # Errors
+///
+/// Fails with a `CustomerOutputError` when writing or serializing fails.
+pub fn write_stats<W: Write>(mut writer: W,
+                             stats: &CustomerStats,
+                             format: OutputFormat,
+                             unit: DistanceUnit)
+                             -> Result<(), CustomerOutputError> {
+    match format {
+        OutputFormat::Text => write_stats_text(&mut writer, stats, unit),
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut writer, stats)?;
+            writeln!(writer)?;
+            Ok(())
+        }
+        OutputFormat::Csv => write_stats_csv(writer, stats),
+        OutputFormat::Kml | OutputFormat::Gpx => Err(CustomerOutputError::Unsupported("statistics", format)),
+    }
+}
+
+fn write_stats_text<W: Write>(writer: &mut W, stats: &CustomerStats, unit: DistanceUnit) -> Result<(), CustomerOutputError> {
+    writeln!(writer, "Loaded {} customers, rejected {} and matched {} around {}.",
+             stats.loaded, stats.rejected, stats.matches, stats.location)?;
+    if let Some(ref nearest) = stats.nearest {
+        writeln!(writer, "Nearest: {} ({}) at {}.", nearest.customer.name, nearest.customer.user_id, unit.format(nearest.distance))?;
+    }
+    if let Some(ref farthest) = stats.farthest {
+        writeln!(writer, "Farthest: {} ({}) at {}.", farthest.customer.name, farthest.customer.user_id, unit.format(farthest.distance))?;
+    }
+    if let Some(mean_distance) = stats.mean_distance {
+        writeln!(writer, "Mean distance: {}.", unit.format(mean_distance))?;
+    }
+    if let Some(median_distance) = stats.median_distance {
+        writeln!(writer, "Median distance: {}.", unit.format(median_distance))?;
+    }
+    for percentile in &stats.percentiles {
+        writeln!(writer, "Percentile {}: {}.", percentile.percentile, unit.format(percentile.distance))?;
+    }
+    if !stats.histogram.is_empty() {
+        writeln!(writer, "Distances in buckets of {}:", unit.format(stats.bucket_width))?;
+        for bucket in &stats.histogram {
+            writeln!(writer, "    {} to {}: {}", unit.format(bucket.from), unit.format(bucket.to), bucket.customers)?;
+        }
+    }
+    if let Some(ref bounding_box) = stats.bounding_box {
+        writeln!(writer, "Bounding box: {} to {}.", bounding_box.south_west.coordinates(), bounding_box.north_east.coordinates())?;
+    }
+    if let Some(ref centroid) = stats.centroid {
+        writeln!(writer, "Centroid: {}.", centroid)?;
+    }
+
+    Ok(())
+}
+
+fn write_stats_csv<W: Write>(writer: W, stats: &CustomerStats) -> Result<(), CustomerOutputError> {
+    let mut rows = vec![
+        (String::from("loaded"), stats.loaded.to_string()),
+        (String::from("rejected"), stats.rejected.to_string()),
+        (String::from("matches"), stats.matches.to_string()),
+    ];
+    for (name, located) in &[("nearest", &stats.nearest), ("farthest", &stats.farthest)] {
+        if let Some(ref located) = located {
+            rows.push((format!("{}_user_id", name), located.customer.user_id.to_string()));
+            rows.push((format!("{}_distance", name), located.distance.0.to_string()));
+        }
+    }
+    if let Some(mean_distance) = stats.mean_distance {
+        rows.push((String::from("mean_distance"), mean_distance.0.to_string()));
+    }
+    if let Some(median_distance) = stats.median_distance {
+        rows.push((String::from("median_distance"), median_distance.0.to_string()));
+    }
+    for percentile in &stats.percentiles {
+        rows.push((format!("p{}_distance", percentile.percentile), percentile.distance.0.to_string()));
+    }
+    for bucket in &stats.histogram {
+        rows.push((format!("histogram_{}_{}", bucket.from.0, bucket.to.0), bucket.customers.to_string()));
+    }
+    if let Some(ref bounding_box) = stats.bounding_box {
+        rows.push((String::from("south_west_latitude"), bounding_box.south_west.latitude.to_string()));
+        rows.push((String::from("south_west_longitude"), bounding_box.south_west.longitude.to_string()));
+        rows.push((String::from("north_east_latitude"), bounding_box.north_east.latitude.to_string()));
+        rows.push((String::from("north_east_longitude"), bounding_box.north_east.longitude.to_string()));
+    }
+    if let Some(ref centroid) = stats.centroid {
+        rows.push((String::from("centroid_latitude"), centroid.latitude.to_string()));
+        rows.push((String::from("centroid_longitude"), centroid.longitude.to_string()));
+    }
+
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(["statistic", "value"])?;
+    for (statistic, value) in rows {
+        csv_writer.write_record(&[statistic, value])?;
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
+
+// A copy of `customer` with only the attributes in `names`.
+fn with_selected_attributes(customer: &Customer, names: &[String]) -> Customer {
+    let mut selected = customer.clone();
+    selected.attributes = selected_attributes(customer, names)
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    selected
+}
+
+// The selected attributes of `customer` as the ` [name: value, ...]` the
+// `Text` format ends its lines with, empty when it has none of them.
+fn attribute_suffix(customer: &Customer, names: &[String]) -> String {
+    let attributes = selected_attributes(customer, names)
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect::<Vec<String>>();
+    if attributes.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", attributes.join(", "))
+    }
+}
+
+fn write_attribute_suffix<W: Write>(writer: &mut W, customer: &Customer, names: &[String]) -> Result<(), CustomerOutputError> {
+    writer.write_all(attribute_suffix(customer, names).as_bytes())?;
+    Ok(())
+}
+
+// A CSV cell per attribute in `names`, a missing attribute is written as an
+// empty cell to keep the columns aligned.
+fn attribute_cells(customer: &Customer, names: &[String]) -> Vec<String> {
+    names.iter()
+        .map(|name| customer.attribute(name).map(|value| value.to_string()).unwrap_or_default())
+        .collect()
+}
+
+fn selected_attributes<'c>(customer: &'c Customer,
+                           names: &'c [String])
+                           -> impl Iterator<Item = (&'c String, &'c AttributeValue)> + 'c {
+    names.iter().filter_map(move |name| customer.attributes.get_key_value(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use customer::Customer;
+    use customer_cluster::Cluster;
+    use customer_locator::CustomerLocator;
+    use customer_stats::DistanceReport;
+    use customer_route::RoutePlanner;
+    use customer_territory::{Office, TerritoryAssignment};
+    use location::Boundary;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+
+    fn generate_customer_list() -> CustomerList {
+        CustomerList::from_vec(vec![
+            Customer::new(1, "Jose Narvaez", &Location::new(52.986375, -6.043701))
+                .with_attribute("email", "jose@example.com")
+                .with_attribute("tier", 1i64),
+            Customer::new(2, "Narvaez, Carlos", &Location::new(51.92893, -10.27699))
+                .with_attribute("phone", "555-1234"),
+        ])
+    }
+
+    fn write_to_string(format: OutputFormat, selection: &AttributeSelection) -> String {
+        let mut output = Vec::new();
+        write_customers(&mut output, &generate_customer_list(), &DUBLIN, DistanceModel::Haversine, format, selection, DistanceUnit::Kilometers).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn output_format_from_str_parses_known_formats() {
+        assert_eq!(OutputFormat::from_str("JSON"), Ok(OutputFormat::Json));
+        assert_eq!(OutputFormat::from_str("csv"), Ok(OutputFormat::Csv));
+        assert_eq!(OutputFormat::from_str(" Kml"), Ok(OutputFormat::Kml));
+        assert_eq!(OutputFormat::from_str("gpx"), Ok(OutputFormat::Gpx));
+        assert_eq!(OutputFormat::from_str("xml"), Err(ParseOutputFormatError(String::from("unknown output format 'xml'"))));
+    }
+
+    #[test]
+    fn write_customers_json_round_trips_every_attribute() {
+        let output = write_to_string(OutputFormat::Json, &AttributeSelection::All);
+        let customers: CustomerList = output.lines()
+            .map(|line| serde_json::from_str::<Customer>(line).unwrap())
+            .collect();
+
+        assert_eq!(customers, generate_customer_list());
+    }
+
+    #[test]
+    fn write_customers_csv_writes_a_column_per_attribute() {
+        let output = write_to_string(OutputFormat::Csv, &AttributeSelection::All);
+        let expected_output = "user_id,name,latitude,longitude,email,phone,tier\n\
+                               1,Jose Narvaez,52.986375,-6.043701,jose@example.com,,1\n\
+                               2,\"Narvaez, Carlos\",51.92893,-10.27699,,555-1234,\n";
+
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn write_customers_csv_writes_nested_attributes_as_json() {
+        let tags = AttributeValue::Array(vec![AttributeValue::from("vip"), AttributeValue::from("early")]);
+        let customers = CustomerList::from_vec(vec![
+            Customer::new(1, "Jose Narvaez", &Location::new(52.986375, -6.043701)).with_attribute("tags", tags),
+        ]);
+        let mut output = Vec::new();
+        write_customers(&mut output, &customers, &DUBLIN, DistanceModel::Haversine, OutputFormat::Csv, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "user_id,name,latitude,longitude,tags\n\
+                                                        1,Jose Narvaez,52.986375,-6.043701,\"[\"\"vip\"\",\"\"early\"\"]\"\n");
+    }
+
+    #[test]
+    fn write_customers_only_writes_the_selected_attributes() {
+        let output = write_to_string(OutputFormat::Text, &AttributeSelection::from_list("tier"));
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[0], "Location is (Dublin, Ireland) Location(53.3393, -6.2576841).");
+        assert!(lines[1].ends_with("from provided location. [tier: 1]"));
+        assert!(lines[2].ends_with("from provided location."));
+    }
+
+    #[test]
+    fn write_customers_text_prints_distances_in_the_given_unit() {
+        let mut output = Vec::new();
+        write_customers(&mut output, &generate_customer_list(), &DUBLIN, DistanceModel::Haversine, OutputFormat::Text,
+                        &AttributeSelection::Only(Vec::new()), DistanceUnit::Miles).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.lines().nth(1).unwrap().ends_with(" mi from provided location."));
+    }
+
+    fn located_from(customers: CustomerList, location: &Location) -> Vec<LocatedCustomer> {
+        customers.iter()
+            .map(|customer| LocatedCustomer { customer: customer.clone(), distance: customer.distance_from(location) })
+            .collect()
+    }
+
+    fn generate_clustering() -> Clustering {
+        let customers = generate_customer_list();
+        let centroid = Location::new(52.986375, -6.043701);
+        Clustering {
+            clusters: vec![Cluster {
+                id: 1,
+                centroid,
+                customers: located_from(customers.filter(|customer| customer.user_id == 1), &centroid),
+            }],
+            noise: customers.filter(|customer| customer.user_id == 2),
+        }
+    }
+
+    #[test]
+    fn write_clusters_csv_writes_the_cluster_of_each_customer() {
+        let mut output = Vec::new();
+        write_clusters(&mut output, &generate_clustering(), OutputFormat::Csv, &AttributeSelection::from_list("tier"),
+                       DistanceUnit::Kilometers).unwrap();
+        let expected_output = "cluster,centroid_latitude,centroid_longitude,user_id,name,latitude,longitude,tier\n\
+                               1,52.986375,-6.043701,1,Jose Narvaez,52.986375,-6.043701,1\n\
+                               ,,,2,\"Narvaez, Carlos\",51.92893,-10.27699,\n";
+
+        assert_eq!(String::from_utf8(output).unwrap(), expected_output);
+    }
+
+    #[test]
+    fn write_clusters_json_writes_an_object_per_cluster_and_the_noise() {
+        let mut output = Vec::new();
+        write_clusters(&mut output, &generate_clustering(), OutputFormat::Json, &AttributeSelection::All,
+                       DistanceUnit::Kilometers).unwrap();
+        let records: Vec<serde_json::Value> = String::from_utf8(output).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["id"], 1);
+        assert_eq!(records[0]["centroid"]["latitude"], 52.986375);
+        assert_eq!(records[0]["customers"][0]["email"], "jose@example.com");
+        assert_eq!(records[1]["id"], serde_json::Value::Null);
+        assert_eq!(records[1]["size"], 1);
+    }
+
+    #[test]
+    fn write_clusters_text_prints_distances_to_the_centroid() {
+        let mut output = Vec::new();
+        write_clusters(&mut output, &generate_clustering(), OutputFormat::Text, &AttributeSelection::Only(Vec::new()),
+                       DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[0], "Cluster 1 of 1 customers around Location(52.986375, -6.043701).");
+        assert!(lines[1].ends_with("is 0.000 Km from the centroid."));
+        assert_eq!(lines[2], "Noise of 1 customers.");
+    }
+
+    fn generate_venues() -> Vec<Venue> {
+        let customers = generate_customer_list();
+        let location = Location::new(52.986375, -6.043701);
+        vec![Venue {
+            name: String::from("Venue 1"),
+            location,
+            candidate: None,
+            customers: located_from(customers.filter(|customer| customer.user_id == 1), &location),
+        }]
+    }
+
+    #[test]
+    fn write_venues_csv_writes_a_row_per_venue_and_the_uncovered_customers() {
+        let mut output = Vec::new();
+        let uncovered = generate_customer_list().filter(|customer| customer.user_id == 2);
+        write_venues(&mut output, &generate_venues(), &uncovered, OutputFormat::Csv, DistanceUnit::Kilometers).unwrap();
+        let expected_output = "name,latitude,longitude,customers,total_distance,max_distance\n\
+                               Venue 1,52.986375,-6.043701,1,0,0\n\
+                               ,,,1,,\n";
+
+        assert_eq!(String::from_utf8(output).unwrap(), expected_output);
+    }
+
+    #[test]
+    fn write_venues_text_prints_the_distances_to_each_venue() {
+        let mut output = Vec::new();
+        write_venues(&mut output, &generate_venues(), &CustomerList::default(), OutputFormat::Text, DistanceUnit::Miles).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "Venue 1 at Location(52.986375, -6.043701): 1 customers, 0.000 mi in total and 0.000 mi to the furthest one.\n");
+    }
+
+    fn generate_stats() -> CustomerStats {
+        DistanceReport::new(DUBLIN)
+            .with_bucket_width(Kilometers(50.0))
+            .with_percentiles(&[90.0])
+            .with_loaded(3, 1)
+            .report(&generate_customer_list().filter(|customer| customer.user_id == 1))
+    }
+
+    #[test]
+    fn write_stats_text_prints_a_line_per_figure() {
+        let mut output = Vec::new();
+        write_stats(&mut output, &generate_stats(), OutputFormat::Text, DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[0], "Loaded 3 customers, rejected 1 and matched 1 around (Dublin, Ireland) Location(53.3393, -6.2576841).");
+        assert!(lines[1].starts_with("Nearest: Jose Narvaez (1) at 41."));
+        assert!(lines[5].starts_with("Percentile 90: 41."));
+        assert_eq!(lines[6], "Distances in buckets of 50.000 Km:");
+        assert_eq!(lines[7], "    0.000 Km to 50.000 Km: 1");
+        assert_eq!(lines[8], "Bounding box: Location(52.986375, -6.043701) to Location(52.986375, -6.043701).");
+    }
+
+    #[test]
+    fn write_stats_csv_writes_a_row_per_figure() {
+        let mut output = Vec::new();
+        write_stats(&mut output, &generate_stats(), OutputFormat::Csv, DistanceUnit::Miles).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let statistics: Vec<&str> = output.lines().map(|line| line.split(',').next().unwrap()).collect();
+
+        assert_eq!(statistics, vec!["statistic", "loaded", "rejected", "matches",
+                                    "nearest_user_id", "nearest_distance", "farthest_user_id", "farthest_distance",
+                                    "mean_distance", "median_distance", "p90_distance", "histogram_0_50",
+                                    "south_west_latitude", "south_west_longitude", "north_east_latitude",
+                                    "north_east_longitude", "centroid_latitude", "centroid_longitude"]);
+        assert!(output.contains("\nrejected,1\n"));
+    }
+
+    #[test]
+    fn write_stats_json_writes_a_single_object() {
+        let mut output = Vec::new();
+        write_stats(&mut output, &generate_stats(), OutputFormat::Json, DistanceUnit::Kilometers).unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(stats["matches"], 1);
+        assert_eq!(stats["nearest"]["user_id"], 1);
+        assert_eq!(stats["histogram"][0]["customers"], 1);
+    }
+
+    fn generate_bands() -> (Vec<Ring>, Vec<Vec<LocatedCustomer>>) {
+        let bands = Ring::bands(&[Kilometers(0.0), Kilometers(50.0), Kilometers(300.0)], Boundary::Exclusive);
+        let banded = generate_customer_list()
+            .into_iter()
+            .map(|customer| vec![LocatedCustomer { distance: customer.distance_from(&DUBLIN), customer }])
+            .collect();
+        (bands, banded)
+    }
+
+    #[test]
+    fn write_bands_text_lists_the_customers_of_each_band() {
+        let (bands, banded) = generate_bands();
+        let mut output = Vec::new();
+        write_bands(&mut output, &bands, &banded, OutputFormat::Text, &AttributeSelection::from_list("tier"), DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[0], "Band 1 from 0.000 Km to 50.000 Km of 1 customers.");
+        assert!(lines[1].starts_with("    Customer(\"Jose Narvaez\": 1)"));
+        assert!(lines[1].ends_with("from provided location. [tier: 1]"));
+        assert_eq!(lines[2], "Band 2 from 50.000 Km to 300.000 Km of 1 customers.");
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn write_bands_csv_writes_a_row_per_customer_with_its_band() {
+        let (bands, banded) = generate_bands();
+        let mut output = Vec::new();
+        write_bands(&mut output, &bands, &banded, OutputFormat::Csv, &AttributeSelection::Only(Vec::new()), DistanceUnit::Miles).unwrap();
+        let expected_output = "band,min_radius,max_radius,user_id,name,latitude,longitude\n\
+                               1,0,50,1,Jose Narvaez,52.986375,-6.043701\n\
+                               2,50,300,2,\"Narvaez, Carlos\",51.92893,-10.27699\n";
+
+        assert_eq!(String::from_utf8(output).unwrap(), expected_output);
+    }
+
+    #[test]
+    fn write_bands_json_writes_an_object_per_band() {
+        let (bands, banded) = generate_bands();
+        let mut output = Vec::new();
+        write_bands(&mut output, &bands, &banded, OutputFormat::Json, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+        let records: Vec<serde_json::Value> = String::from_utf8(output).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["band"], 2);
+        assert_eq!(records[1]["min_radius"], 50.0);
+        assert_eq!(records[1]["customers"][0]["phone"], "555-1234");
+    }
+
+    fn generate_attributed() -> Vec<AttributedCustomer> {
+        let cork = Location::new(51.8985, -8.4756);
+        generate_customer_list()
+            .into_iter()
+            .map(|customer| {
+                let distances = vec![customer.distance_from(&DUBLIN), customer.distance_from(&cork)];
+                let centres = if customer.user_id == 1 { vec![0] } else { vec![0, 1] };
+                AttributedCustomer { customer, centres, distances }
+            })
+            .collect()
+    }
+
+    fn centre_names() -> Vec<String> {
+        vec![String::from("Dublin"), String::from("Cork")]
+    }
+
+    #[test]
+    fn write_attributed_text_prints_the_matched_centres_and_distances() {
+        let mut output = Vec::new();
+        write_attributed(&mut output, &generate_attributed(), &centre_names(), OutputFormat::Text,
+                         &AttributeSelection::from_list("tier"), DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert!(lines[0].contains(" matches Dublin, 41."));
+        assert!(lines[0].ends_with(" Km from Cork. [tier: 1]"));
+        assert!(lines[1].contains(" matches Dublin and Cork, "));
+    }
+
+    #[test]
+    fn write_attributed_csv_writes_a_distance_column_per_centre() {
+        let mut output = Vec::new();
+        write_attributed(&mut output, &generate_attributed(), &centre_names(), OutputFormat::Csv,
+                         &AttributeSelection::from_list("phone"), DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[0], "user_id,name,latitude,longitude,centres,distance_Dublin,distance_Cork,phone");
+        assert!(lines[2].starts_with("2,\"Narvaez, Carlos\",51.92893,-10.27699,Dublin;Cork,"));
+        assert!(lines[2].ends_with(",555-1234"));
+    }
+
+    #[test]
+    fn write_attributed_json_writes_the_distances_by_centre() {
+        let mut output = Vec::new();
+        write_attributed(&mut output, &generate_attributed(), &centre_names(), OutputFormat::Json,
+                         &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+        let records: Vec<serde_json::Value> = String::from_utf8(output).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records[0]["user_id"], 1);
+        assert_eq!(records[0]["centres"], serde_json::json!(["Dublin"]));
+        assert!(records[0]["distances"]["Cork"].as_f64().unwrap() > 100.0);
+        assert_eq!(records[1]["phone"], "555-1234");
+    }
+
+    fn generate_territories() -> Territories {
+        let offices = vec![Office::new("Dublin", DUBLIN, 1), Office::new("Cork", Location::new(51.8985, -8.4756), 0)];
+        TerritoryAssignment::new(offices).assign(&generate_customer_list())
+    }
+
+    #[test]
+    fn write_territories_text_lists_the_customers_of_each_office() {
+        let mut output = Vec::new();
+        write_territories(&mut output, &generate_territories(), OutputFormat::Text, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert!(lines[0].starts_with("Dublin at (Dublin, Ireland) Location(53.3393, -6.2576841): 1 of 1 customers, 41."));
+        assert!(lines[1].starts_with("    Customer(\"Jose Narvaez\": 1)"));
+        assert!(lines[1].ends_with(" Km from the office. [email: jose@example.com, tier: 1]"));
+        assert_eq!(lines[2], "Cork at (Cork, Ireland) Location(51.8985, -8.4756): 0 of 0 customers.");
+        assert_eq!(lines[3], "1 customers couldn't be assigned.");
+        assert_eq!(lines[4], "    Customer(\"Narvaez, Carlos\": 2) located at (51.92893, -10.27699). [phone: 555-1234]");
+        assert!(lines[5].starts_with("Total distance: 41."));
+    }
+
+    #[test]
+    fn write_territories_csv_writes_a_row_per_customer_with_its_office() {
+        let mut output = Vec::new();
+        write_territories(&mut output, &generate_territories(), OutputFormat::Csv, &AttributeSelection::Only(Vec::new()), DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[0], "office,distance,user_id,name,latitude,longitude");
+        assert!(lines[1].starts_with("Dublin,41."));
+        assert!(lines[1].ends_with(",1,Jose Narvaez,52.986375,-6.043701"));
+        assert_eq!(lines[2], ",,2,\"Narvaez, Carlos\",51.92893,-10.27699");
+    }
+
+    #[test]
+    fn write_territories_json_writes_an_object_per_office_and_the_unassigned() {
+        let mut output = Vec::new();
+        write_territories(&mut output, &generate_territories(), OutputFormat::Json, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+        let records: Vec<serde_json::Value> = String::from_utf8(output).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["load"], 1);
+        assert_eq!(records[0]["total_distance"], records[0]["average_distance"]);
+        assert_eq!(records[1]["average_distance"], serde_json::Value::Null);
+        assert_eq!(records[2]["name"], serde_json::Value::Null);
+        assert_eq!(records[2]["customers"][0]["user_id"], 2);
+    }
+
+    fn generate_route() -> Route {
+        let mut customers = generate_customer_list();
+        customers.extend(Some(Customer::new(3, "Alice & Bob <Cahill>", &Location::new(53.2451022, -6.238335))));
+        RoutePlanner::new(DUBLIN)
+            .with_max_daily_distance(Kilometers(200f64))
+            .plan(&customers)
+    }
+
+    #[test]
+    fn write_route_text_lists_the_stops_of_each_day() {
+        let mut output = Vec::new();
+        write_route(&mut output, &generate_route(), OutputFormat::Text, &AttributeSelection::Only(Vec::new()), DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("Day 1: 2 stops, "));
+        assert!(lines[0].ends_with(" from and back to (Dublin, Ireland) Location(53.3393, -6.2576841)."));
+        assert!(lines[1].starts_with("    1. Customer(\"Alice & Bob <Cahill>\": 3) located at (53.2451022, -6.238335) after 10.55"));
+        assert!(lines[2].starts_with("    2. Customer(\"Jose Narvaez\": 1) located at (52.986375, -6.043701) after "));
+        assert!(lines[3].starts_with("    Back to the office after 41."));
+        assert_eq!(lines[4], "1 customers are too far to visit and come back within a day.");
+        assert!(lines[5].starts_with("Total distance: ") && lines[5].ends_with(" over 1 days."));
+    }
+
+    #[test]
+    fn write_route_csv_writes_a_row_per_stop_and_the_way_back() {
+        let mut output = Vec::new();
+        write_route(&mut output, &generate_route(), OutputFormat::Csv, &AttributeSelection::Only(vec!["tier".to_string()]), DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[0], "day,stop,leg,cumulative,user_id,name,latitude,longitude,tier");
+        assert!(lines[1].starts_with("1,1,10.55"));
+        assert!(lines[2].starts_with("1,2,") && lines[2].ends_with(",1,Jose Narvaez,52.986375,-6.043701,1"));
+        assert!(lines[3].starts_with("1,,41.") && lines[3].ends_with(",,,53.3393,-6.2576841,"));
+        assert_eq!(lines[4], ",,,,2,\"Narvaez, Carlos\",51.92893,-10.27699,");
+    }
+
+    #[test]
+    fn write_route_json_writes_an_object_per_day_and_the_unreachable() {
+        let mut output = Vec::new();
+        write_route(&mut output, &generate_route(), OutputFormat::Json, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+        let records: Vec<serde_json::Value> = String::from_utf8(output).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["day"], 1);
+        assert_eq!(records[0]["stops"][1]["stop"], 2);
+        assert_eq!(records[0]["stops"][1]["user_id"], 1);
+        assert_eq!(records[0]["stops"][1]["email"], "jose@example.com");
+        assert_eq!(records[0]["stops"][1]["cumulative"].as_f64().unwrap() + records[0]["return_leg"].as_f64().unwrap(),
+                   records[0]["distance"].as_f64().unwrap());
+        assert_eq!(records[1]["day"], serde_json::Value::Null);
+        assert_eq!(records[1]["stops"][0]["user_id"], 2);
+    }
+
+    #[test]
+    fn write_route_gpx_writes_a_route_per_day_from_the_office_and_back() {
+        let mut output = Vec::new();
+        write_route_gpx(&mut output, &generate_route(), DistanceUnit::Miles).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[0], r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        assert_eq!(lines[2], r#"  <wpt lat="53.3393" lon="-6.2576841"><name>Office</name></wpt>"#);
+        assert_eq!(lines[4], "    <name>Day 1</name>");
+        assert_eq!(lines[6], r#"    <rtept lat="53.3393" lon="-6.2576841"><name>Office</name></rtept>"#);
+        assert!(lines[7].starts_with(r#"    <rtept lat="53.2451022" lon="-6.238335"><name>Alice &amp; Bob &lt;Cahill&gt;</name><desc>Customer 3, 6.55"#));
+        assert_eq!(lines[9], lines[6]);
+        assert_eq!(lines[10], "  </rte>");
+        assert_eq!(lines[11], "</gpx>");
+        assert!(!output.contains("Narvaez, Carlos"));
+    }
+
+    fn generate_pairs() -> Vec<CustomerPair> {
+        let customers = generate_customer_list();
+        vec![CustomerPair {
+            first: customers[0].clone(),
+            second: customers[1].clone(),
+            distance: Kilometers(2.5f64),
+        }]
+    }
+
+    #[test]
+    fn write_pairs_text_writes_a_line_per_pair() {
+        let mut output = Vec::new();
+        write_pairs(&mut output, &generate_pairs(), OutputFormat::Text, DistanceUnit::Kilometers).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "Customer(\"Jose Narvaez\": 1) located at (52.986375, -6.043701) and \
+                    Customer(\"Narvaez, Carlos\": 2) located at (51.92893, -10.27699) are 2.500 Km apart.\n");
+    }
+
+    #[test]
+    fn write_pairs_csv_writes_a_row_per_pair() {
+        let mut output = Vec::new();
+        write_pairs(&mut output, &generate_pairs(), OutputFormat::Csv, DistanceUnit::Miles).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "first_user_id,first_name,second_user_id,second_name,distance\n\
+                    1,Jose Narvaez,2,\"Narvaez, Carlos\",2.5\n");
+    }
+
+    #[test]
+    fn write_pairs_json_writes_an_object_per_pair() {
+        let mut output = Vec::new();
+        write_pairs(&mut output, &generate_pairs(), OutputFormat::Json, DistanceUnit::Kilometers).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "{\"first_user_id\":1,\"first_name\":\"Jose Narvaez\",\"second_user_id\":2,\"second_name\":\"Narvaez, Carlos\",\"distance\":2.5}\n");
+    }
+
+    fn write_search_to_string(format: OutputFormat, ring: Option<&Ring>) -> String {
+        let customers = CustomerLocator::new(generate_customer_list()).locate_within(&Kilometers(1000f64), &DUBLIN);
+        let mut output = Vec::new();
+        write_search(&mut output, &customers, &DUBLIN, ring, format, &AttributeSelection::from_list("tier"), DistanceUnit::Kilometers).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn write_search_writes_the_distances_of_the_search_model() {
+        let locator = CustomerLocator::new(generate_customer_list()).with_model(DistanceModel::Vincenty);
+        let customers = locator.locate_within(&Kilometers(1000f64), &DUBLIN);
+        let vincenty = DistanceModel::Vincenty.distance(&customers[1].customer.location(), &DUBLIN);
+        let haversine = customers[1].customer.distance_from(&DUBLIN);
+        let formatted = |distance| DistanceUnit::Kilometers.format(distance);
+        assert!(formatted(vincenty) != formatted(haversine));
+
+        for format in &[OutputFormat::Text, OutputFormat::Kml, OutputFormat::Gpx] {
+            let mut output = Vec::new();
+            write_search(&mut output, &customers, &DUBLIN, None, *format, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+            let output = String::from_utf8(output).unwrap();
+            assert!(output.contains(&formatted(vincenty)), "{:?} misses the Vincenty distance", format);
+            assert!(!output.contains(&formatted(haversine)), "{:?} has the Haversine distance", format);
+        }
+
+        let mut output = Vec::new();
+        write_customers(&mut output, &generate_customer_list(), &DUBLIN, DistanceModel::Vincenty, OutputFormat::Text,
+                        &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains(&formatted(vincenty)));
+    }
+
+    #[test]
+    fn write_search_kml_writes_the_centre_the_ring_and_a_placemark_per_customer() {
+        let ring = Ring::new(Kilometers(10f64), Kilometers(500f64));
+        let output = write_search_to_string(OutputFormat::Kml, Some(&ring));
+
+        assert!(output.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n"));
+        assert!(output.contains("<Point><coordinates>-6.2576841,53.3393,0</coordinates></Point>"));
+        assert!(output.contains("<name>From 10.000 Km to 500.000 Km</name>"));
+        assert_eq!(output.matches("<outerBoundaryIs>").count(), 1);
+        assert_eq!(output.matches("<innerBoundaryIs>").count(), 1);
+        assert_eq!(output.matches("<Placemark>").count(), 4);
+        assert!(output.contains("<name>Narvaez, Carlos</name>"));
+        assert!(output.contains("<description>Customer 1, 41.768 Km from the search centre</description>"));
+        assert!(output.contains(r#"<Data name="user_id"><value>2</value></Data>"#));
+        assert!(output.contains(r#"<Data name="tier"><value>1</value></Data>"#));
+        assert!(!output.contains("phone"));
+        assert!(output.ends_with("  </Document>\n</kml>\n"));
+    }
+
+    #[test]
+    fn write_search_kml_draws_the_ring_at_its_radius() {
+        let ring = Ring::within(Kilometers(50f64));
+        let output = write_search_to_string(OutputFormat::Kml, Some(&ring));
+        let start = output.find("<outerBoundaryIs><LinearRing><coordinates>").unwrap() + "<outerBoundaryIs><LinearRing><coordinates>".len();
+        let end = start + output[start..].find("</coordinates>").unwrap();
+        let points: Vec<Location> = output[start..end]
+            .split(' ')
+            .map(|point| {
+                let coordinates: Vec<f64> = point.split(',').map(|coordinate| coordinate.parse().unwrap()).collect();
+                Location::new(coordinates[1], coordinates[0])
+            })
+            .collect();
+
+        assert_eq!(points.len(), CIRCLE_SEGMENTS + 1);
+        assert_eq!(points[0], points[CIRCLE_SEGMENTS]);
+        assert!(points.iter().all(|point| (point.distance_from(&DUBLIN).0 - 50f64).abs() < 1e-6));
+        assert!(output.contains("<name>Within 50.000 Km</name>"));
+        assert!(!output.contains("<innerBoundaryIs>"));
+    }
+
+    #[test]
+    fn write_search_gpx_writes_waypoints_and_a_track_per_edge() {
+        let ring = Ring::new(Kilometers(10f64), Kilometers(500f64));
+        let output = write_search_to_string(OutputFormat::Gpx, Some(&ring));
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines[1], r#"<gpx version="1.1" creator="customer_locator" xmlns="http://www.topografix.com/GPX/1/1">"#);
+        assert_eq!(lines[2], r#"  <wpt lat="53.3393" lon="-6.2576841"><name>Search centre</name><desc>(Dublin, Ireland) Location(53.3393, -6.2576841)</desc><sym>Flag, Blue</sym></wpt>"#);
+        assert_eq!(lines[3], r#"  <wpt lat="52.986375" lon="-6.043701"><name>Jose Narvaez</name><desc>Customer 1, 41.768 Km from the search centre [tier: 1]</desc></wpt>"#);
+        assert!(lines[4].starts_with(r#"  <wpt lat="51.92893" lon="-10.27699"><name>Narvaez, Carlos</name><desc>Customer 2, "#));
+        assert_eq!(lines[5], "  <trk>");
+        assert_eq!(lines[6], "    <name>500.000 Km radius</name>");
+        assert_eq!(output.matches("<trk>").count(), 2);
+        assert_eq!(output.matches("<trkpt ").count(), 2 * (CIRCLE_SEGMENTS + 1));
+        assert_eq!(*lines.last().unwrap(), "</gpx>");
+    }
+
+    #[test]
+    fn write_customers_gpx_writes_no_track_without_a_ring() {
+        let mut output = Vec::new();
+        write_customers(&mut output, &generate_customer_list(), &DUBLIN, DistanceModel::Haversine, OutputFormat::Gpx, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(output.matches("<wpt ").count(), 3);
+        assert!(!output.contains("<trk>"));
+    }
+
+    #[test]
+    fn write_route_gpx_format_writes_the_gpx_route() {
+        let mut output = Vec::new();
+        write_route(&mut output, &generate_route(), OutputFormat::Gpx, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap();
+        let mut expected = Vec::new();
+        write_route_gpx(&mut expected, &generate_route(), DistanceUnit::Kilometers).unwrap();
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn writers_without_a_map_reject_kml_and_gpx() {
+        let mut output = Vec::new();
+        let err = write_clusters(&mut output, &generate_clustering(), OutputFormat::Kml, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap_err();
+        assert_eq!(err.to_string(), "Customer output error: clusters can't be written as kml");
+
+        let err = write_route(&mut output, &generate_route(), OutputFormat::Kml, &AttributeSelection::All, DistanceUnit::Kilometers).unwrap_err();
+        assert_eq!(err.to_string(), "Customer output error: routes can't be written as kml");
+
+        let err = write_pairs(&mut output, &generate_pairs(), OutputFormat::Gpx, DistanceUnit::Kilometers).unwrap_err();
+        assert_eq!(err.to_string(), "Customer output error: pairs can't be written as gpx");
+        assert!(output.is_empty());
+    }
+}
@@ -0,0 +1,536 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::convert::From;
+use std::error;
+use std::fmt;
+
+use postgres::types::{FromSql, ToSql, Type};
+use postgres::Error as PostgresError;
+use postgres::{Client, NoTls, Row};
+
+use customer::{Customer, CustomerList};
+use customer_attribute::AttributeValue;
+use customer_datasource::{ColumnMapping, CustomerDatasource, SqlSource};
+use location::{BoundingBox, Location, VINCENTY_HAVERSINE_MAX_RATIO};
+use units::Kilometers;
+
+const DEFAULT_TABLE: &str = "customers";
+
+
+/// Struct abstracting the idea of a PostgreSQL database containing customer
+/// data.
+///
+/// It's an implementation of the `CustomerDatasource` trait reading the
+/// customers from a table, `customers` by default, or from the rows of a
+/// query, with the columns of the customer fields given by a
+/// `ColumnMapping`. Any other column is read as an extra attribute, the
+/// ones whose type has no attribute counterpart as text.
+///
+/// A radius search set with `within` is pushed down to the database: as an
+/// `ST_DWithin` geography query when PostGIS is installed, and as a bounding
+/// box prefilter otherwise. Either way the customers outside the radius are
+/// dropped on the client before returning them.
+///
+/// # Examples
+///
+/// ```
+/// let database = CustomerPostgres::new("host=localhost user=locator dbname=crm")
+///     .with_source(SqlSource::Table(String::from("contacts")))
+///     .with_columns(ColumnMapping::from_str("user_id=id")?)
+///     .within(radius, location);
+///
+/// let locator = CustomerLocator::from_source(database)?;
+/// ```
+///
+/// An expression index spares the `ST_DWithin` query a full scan:
+///
+/// ```sql
+/// CREATE INDEX ON customers USING gist ((ST_MakePoint(longitude, latitude)::geography));
+/// ```
+///
+/// # Errors
+///
+/// Besides the errors Postgres itself reports, like failing to connect,
+/// reading fails when a mapped column is missing from the rows or holds a
+/// `NULL`.
+pub struct CustomerPostgres {
+    params: String,
+    source: SqlSource,
+    columns: ColumnMapping,
+    within: Option<(Kilometers, Location)>,
+    postgis: Option<bool>,
+}
+
+/// An error encapsulating the things that can go wrong when reading
+/// customers from a PostgreSQL database.
+#[derive(Debug)]
+pub enum CustomerPostgresError {
+    Postgres(PostgresError),
+    MissingColumn(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for CustomerPostgresError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CustomerPostgresError::Postgres(ref err) => write!(f, "Customer Postgres error: {}", err),
+            CustomerPostgresError::MissingColumn(ref column) => write!(f, "Customer Postgres error: no column '{}' in the rows", column),
+            CustomerPostgresError::InvalidValue(ref reason) => write!(f, "Customer Postgres invalid value: {}", reason),
+        }
+    }
+}
+
+impl error::Error for CustomerPostgresError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CustomerPostgresError::Postgres(ref err) => Some(err),
+            CustomerPostgresError::MissingColumn(_) | CustomerPostgresError::InvalidValue(_) => None,
+        }
+    }
+}
+
+impl From<PostgresError> for CustomerPostgresError {
+    fn from(err: PostgresError) -> Self {
+        CustomerPostgresError::Postgres(err)
+    }
+}
+
+impl CustomerPostgres {
+    /// Constructs a new `CustomerPostgres` reading the `customers` table of
+    /// the database `params` connects to, either a `postgresql://` URL or
+    /// `key=value` pairs like `host=localhost user=locator`.
+    pub fn new(params: &str) -> CustomerPostgres {
+        CustomerPostgres {
+            params: params.to_string(),
+            source: SqlSource::Table(String::from(DEFAULT_TABLE)),
+            columns: ColumnMapping::default(),
+            within: None,
+            postgis: None,
+        }
+    }
+
+    /// Sets the table or the query the customers are read from.
+    pub fn with_source(mut self, source: SqlSource) -> CustomerPostgres {
+        self.source = source;
+        self
+    }
+
+    /// Sets the columns holding each of the customer fields.
+    pub fn with_columns(mut self, columns: ColumnMapping) -> CustomerPostgres {
+        self.columns = columns;
+        self
+    }
+
+    /// Only reads the customers no further than `radius` from `location`,
+    /// the ones right on it included so inclusive searches find them.
+    pub fn within(mut self, radius: Kilometers, location: Location) -> CustomerPostgres {
+        self.within = Some((radius, location));
+        self
+    }
+
+    /// Sets whether the radius search uses PostGIS instead of checking if
+    /// the database has it installed.
+    pub fn with_postgis(mut self, postgis: bool) -> CustomerPostgres {
+        self.postgis = Some(postgis);
+        self
+    }
+
+    // The statement reading the customers out of the columns of the source,
+    // casting them to the types a `Customer` holds, along with its parameters.
+    fn select_statement(&self, columns: &[(String, Type)], postgis: bool) -> (String, Vec<f64>) {
+        let projection = columns.iter()
+            .map(|(name, type_)| format!("{} AS {}", self.cast(name, type_), quote(name)))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut statement = format!("SELECT {} FROM {}", projection, self.from());
+
+        let (radius, location) = match self.within {
+            Some(within) => within,
+            None => return (statement, Vec::new()),
+        };
+        let latitude = format!("{}::float8", quote(&self.columns.latitude));
+        let longitude = format!("{}::float8", quote(&self.columns.longitude));
+        if postgis {
+            statement.push_str(&format!(" WHERE ST_DWithin(ST_MakePoint({}, {})::geography, ST_MakePoint($1, $2)::geography, $3)",
+                                        longitude,
+                                        latitude));
+            // PostGIS measures geography distances on the WGS 84 spheroid, so
+            // the radius gets some slack and the customers are filtered again
+            // on the client so both agree on who is within it.
+            return (statement, vec![location.longitude, location.latitude, radius.0 * 1000f64 * VINCENTY_HAVERSINE_MAX_RATIO]);
+        }
+
+        let bounding_box = BoundingBox::around(&location, &radius);
+        // a box crossing the antimeridian has its west edge further east than its east one.
+        let longitude_condition = if bounding_box.south_west.longitude <= bounding_box.north_east.longitude {
+            format!("{} BETWEEN $3 AND $4", longitude)
+        } else {
+            format!("({} >= $3 OR {} <= $4)", longitude, longitude)
+        };
+        statement.push_str(&format!(" WHERE {} BETWEEN $1 AND $2 AND {}", latitude, longitude_condition));
+        let parameters = vec![bounding_box.south_west.latitude,
+                              bounding_box.north_east.latitude,
+                              bounding_box.south_west.longitude,
+                              bounding_box.north_east.longitude];
+
+        (statement, parameters)
+    }
+
+    fn from(&self) -> String {
+        match self.source {
+            SqlSource::Table(ref table) => quote(table),
+            SqlSource::Query(ref query) => format!("({}) AS source", query.trim().trim_end_matches(';')),
+        }
+    }
+
+    // The expression reading the column `name` of type `type_` as one of the
+    // types `decode` handles: bool, int8, float8 or text.
+    fn cast(&self, name: &str, type_: &Type) -> String {
+        let column = quote(name);
+        if name == self.columns.user_id {
+            return format!("{}::int8", column);
+        }
+        if name == self.columns.name {
+            return format!("{}::text", column);
+        }
+        if name == self.columns.latitude || name == self.columns.longitude {
+            return format!("{}::float8", column);
+        }
+
+        match *type_ {
+            Type::BOOL | Type::INT8 | Type::FLOAT8 | Type::TEXT => column,
+            Type::INT2 | Type::INT4 => format!("{}::int8", column),
+            Type::FLOAT4 | Type::NUMERIC => format!("{}::float8", column),
+            _ => format!("{}::text", column),
+        }
+    }
+
+    fn uses_postgis(&self, client: &mut Client) -> Result<bool, PostgresError> {
+        if let Some(postgis) = self.postgis {
+            return Ok(postgis);
+        }
+        let row = client.query_one("SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'postgis')", &[])?;
+        Ok(row.get(0))
+    }
+}
+
+impl CustomerDatasource for CustomerPostgres {
+    type Err = CustomerPostgresError;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let mut client = Client::connect(&self.params, NoTls)?;
+        let postgis = self.within.is_some() && self.uses_postgis(&mut client)?;
+
+        let described = client.prepare(&format!("SELECT * FROM {}", self.from()))?;
+        let columns: Vec<(String, Type)> = described.columns()
+            .iter()
+            .map(|column| (column.name().to_string(), column.type_().clone()))
+            .collect();
+        let names: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+        let index_of = |column: &str| {
+            names.iter()
+                .position(|name| *name == column)
+                .ok_or_else(|| CustomerPostgresError::MissingColumn(column.to_string()))
+        };
+        let mapped = [index_of(&self.columns.user_id)?,
+                      index_of(&self.columns.name)?,
+                      index_of(&self.columns.latitude)?,
+                      index_of(&self.columns.longitude)?];
+
+        let (statement, parameters) = self.select_statement(&columns, postgis);
+        let parameters: Vec<&(dyn ToSql + Sync)> = parameters.iter().map(|parameter| parameter as &(dyn ToSql + Sync)).collect();
+
+        let mut customers = Vec::new();
+        for row in client.query(statement.as_str(), &parameters)? {
+            let user_id: i64 = not_null(&row, mapped[0], &names)?;
+            let name: String = not_null(&row, mapped[1], &names)?;
+            let location = Location::new(not_null(&row, mapped[2], &names)?, not_null(&row, mapped[3], &names)?);
+            if let Some((radius, ref center)) = self.within {
+                if location.distance_from(center) > radius {
+                    continue;
+                }
+            }
+
+            let mut customer = Customer::new(user_id, &name, &location);
+            for (index, column) in row.columns().iter().enumerate().filter(|&(index, _)| !mapped.contains(&index)) {
+                customer.attributes.insert(column.name().to_string(), attribute_value(&row, index)?);
+            }
+            customers.push(customer);
+        }
+
+        Ok(CustomerList::from_vec(customers))
+    }
+}
+
+// Quotes `identifier` to be used as a table or column name.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn not_null<T>(row: &Row, index: usize, names: &[&str]) -> Result<T, CustomerPostgresError>
+    where T: for<'a> FromSql<'a>
+{
+    row.get::<_, Option<T>>(index)
+        .ok_or_else(|| CustomerPostgresError::InvalidValue(format!("column '{}' is null", names[index])))
+}
+
+fn attribute_value(row: &Row, index: usize) -> Result<AttributeValue, PostgresError> {
+    let value = match *row.columns()[index].type_() {
+        Type::BOOL => row.try_get::<_, Option<bool>>(index)?.map(AttributeValue::Bool),
+        Type::INT8 => row.try_get::<_, Option<i64>>(index)?.map(AttributeValue::Integer),
+        Type::FLOAT8 => row.try_get::<_, Option<f64>>(index)?.map(AttributeValue::Float),
+        _ => row.try_get::<_, Option<String>>(index)?.map(AttributeValue::Text),
+    };
+
+    Ok(value.unwrap_or(AttributeValue::Null))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use std::process::Command;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+
+    static CLUSTERS: AtomicUsize = AtomicUsize::new(0);
+
+    // The Postgres server the tests reading customers run against: the one
+    // `CUSTOMER_LOCATOR_POSTGRES_URL` connects to, or else a throwaway
+    // cluster in a temporary directory, which is stopped and removed on drop.
+    // There's no skipping: without a server the tests fail.
+    struct TestServer {
+        url: String,
+        cluster: Option<PathBuf>,
+    }
+
+    impl TestServer {
+        fn start() -> TestServer {
+            if let Ok(url) = env::var("CUSTOMER_LOCATOR_POSTGRES_URL") {
+                return TestServer { url, cluster: None };
+            }
+
+            // kept short, the socket path can't be longer than 107 bytes.
+            let directory = env::temp_dir().join(format!("customer_locator_pg_{}_{}", process::id(), CLUSTERS.fetch_add(1, Ordering::SeqCst)));
+            let _ = fs::remove_dir_all(&directory);
+            let data = directory.join("data");
+            run(postgres_command("mkdir").arg("-p").arg(&directory), "mkdir");
+            run(postgres_command("initdb").arg("-D").arg(&data).args(["-A", "trust", "-U", "postgres"]), "initdb");
+
+            // the server only listens on a socket in the directory so clusters never clash.
+            let server = TestServer {
+                url: format!("host={} user=postgres dbname=postgres", directory.display()),
+                cluster: Some(directory.clone()),
+            };
+            let started = postgres_command("pg_ctl")
+                .arg("-D").arg(&data)
+                .arg("-l").arg(directory.join("postgres.log"))
+                .arg("-o").arg(format!("-c listen_addresses='' -k '{}'", directory.display()))
+                .args(["-w", "start"])
+                .output()
+                .expect("can't run pg_ctl, set CUSTOMER_LOCATOR_POSTGRES_URL to test against a running server");
+            if !started.status.success() {
+                panic!("pg_ctl failed to start the test cluster: {}{}",
+                       String::from_utf8_lossy(&started.stderr),
+                       fs::read_to_string(directory.join("postgres.log")).unwrap_or_default());
+            }
+
+            server
+        }
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            if let Some(ref directory) = self.cluster {
+                let _ = postgres_command("pg_ctl").arg("-D").arg(directory.join("data")).args(["-m", "immediate", "-w", "stop"]).output();
+                let _ = fs::remove_dir_all(directory);
+            }
+        }
+    }
+
+    // `initdb` and `pg_ctl` refuse to run as root, so then they are run as
+    // the `postgres` user, or the one `CUSTOMER_LOCATOR_POSTGRES_USER` names.
+    fn postgres_command(program: &str) -> Command {
+        let root = Command::new("id").arg("-u").output().map(|output| output.stdout == b"0\n").unwrap_or(false);
+        if !root {
+            return Command::new(program);
+        }
+        let user = env::var("CUSTOMER_LOCATOR_POSTGRES_USER").unwrap_or_else(|_| String::from("postgres"));
+        let mut command = Command::new("runuser");
+        command.args(["-u", &user, "--", program]);
+        command
+    }
+
+    fn run(command: &mut Command, program: &str) {
+        let output = command.output()
+            .unwrap_or_else(|err| panic!("can't run {}, set CUSTOMER_LOCATOR_POSTGRES_URL to test against a running server: {}", program, err));
+        if !output.status.success() {
+            panic!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim());
+        }
+    }
+
+    // Creates a CRM like table with its own column names and types, which is
+    // dropped when the returned guard goes out of scope.
+    struct TestTable {
+        url: String,
+        name: String,
+    }
+
+    impl TestTable {
+        fn create(url: &str, name: &str) -> TestTable {
+            let name = format!("{}_{}", name, process::id());
+            let mut client = Client::connect(url, NoTls).unwrap();
+            client.batch_execute(&format!("DROP TABLE IF EXISTS {0};
+                                           CREATE TABLE {0} (id int4 PRIMARY KEY, full_name varchar(64), lat numeric(9, 6), lng float8,
+                                                             tier text, score real, vip boolean, joined date);
+                                           INSERT INTO {0} VALUES (1, 'Jose Narvaez', 52.986375, -6.043701, 'gold', 4.5, true, '2017-03-23'),
+                                                                  (2, 'Carlos Narvaez', 51.92893, -10.27699, NULL, NULL, false, NULL),
+                                                                  (3, 'Ian Kehoe', 53.245102, -6.238335, 'silver', 3, NULL, NULL);",
+                                          name))
+                .unwrap();
+
+            TestTable { url: url.to_string(), name }
+        }
+
+        fn database(&self) -> CustomerPostgres {
+            CustomerPostgres::new(&self.url)
+                .with_source(SqlSource::Table(self.name.clone()))
+                .with_columns(ColumnMapping::from_str("user_id=id, name=full_name, latitude=lat, longitude=lng").unwrap())
+        }
+    }
+
+    impl Drop for TestTable {
+        fn drop(&mut self) {
+            if let Ok(mut client) = Client::connect(&self.url, NoTls) {
+                let _ = client.batch_execute(&format!("DROP TABLE IF EXISTS {}", self.name));
+            }
+        }
+    }
+
+    fn user_ids(customers: &CustomerList) -> Vec<i64> {
+        let mut user_ids: Vec<i64> = customers.iter().map(|customer| customer.user_id).collect();
+        user_ids.sort();
+        user_ids
+    }
+
+    #[test]
+    fn select_statement_pushes_the_radius_down_to_postgis_or_a_bounding_box() {
+        let columns = vec![(String::from("user_id"), Type::INT4),
+                           (String::from("name"), Type::VARCHAR),
+                           (String::from("latitude"), Type::NUMERIC),
+                           (String::from("longitude"), Type::FLOAT8),
+                           (String::from("score"), Type::FLOAT4),
+                           (String::from("joined"), Type::DATE)];
+        let database = CustomerPostgres::new("host=localhost").within(Kilometers(100f64), DUBLIN);
+
+        let (statement, parameters) = database.select_statement(&columns, true);
+        assert_eq!(statement,
+                   "SELECT \"user_id\"::int8 AS \"user_id\", \"name\"::text AS \"name\", \"latitude\"::float8 AS \"latitude\", \
+                    \"longitude\"::float8 AS \"longitude\", \"score\"::float8 AS \"score\", \"joined\"::text AS \"joined\" \
+                    FROM \"customers\" \
+                    WHERE ST_DWithin(ST_MakePoint(\"longitude\"::float8, \"latitude\"::float8)::geography, ST_MakePoint($1, $2)::geography, $3)");
+        assert_eq!(parameters, vec![DUBLIN.longitude, DUBLIN.latitude, 101000f64]);
+
+        let (statement, parameters) = database.select_statement(&columns, false);
+        assert!(statement.ends_with("WHERE \"latitude\"::float8 BETWEEN $1 AND $2 AND \"longitude\"::float8 BETWEEN $3 AND $4"));
+        assert_eq!(parameters.len(), 4);
+    }
+
+    #[test]
+    fn customer_postgres_fails_when_it_cannot_connect() {
+        match CustomerPostgres::new("host=127.0.0.1 port=1 user=nobody connect_timeout=2").customers() {
+            Err(CustomerPostgresError::Postgres(_)) => (),
+            other => panic!("expected a Postgres error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn customer_postgres_reads_a_table_with_mapped_columns_and_attributes() {
+        let server = TestServer::start();
+        let url = &server.url;
+        let table = TestTable::create(url, "customers_table");
+
+        let customers = table.database().customers().unwrap();
+
+        let jose = customers.get(1).unwrap();
+        assert_eq!(user_ids(&customers), vec![1, 2, 3]);
+        assert_eq!(jose.name, "Jose Narvaez");
+        assert_eq!(jose.location(), Location::new(52.986375, -6.043701));
+        assert_eq!(jose.attribute("tier"), Some(&AttributeValue::from("gold")));
+        assert_eq!(jose.attribute("score"), Some(&AttributeValue::Float(4.5)));
+        assert_eq!(jose.attribute("vip"), Some(&AttributeValue::Bool(true)));
+        assert_eq!(jose.attribute("joined"), Some(&AttributeValue::from("2017-03-23")));
+        assert_eq!(customers.get(2).unwrap().attribute("tier"), Some(&AttributeValue::Null));
+    }
+
+    #[test]
+    fn customer_postgres_reads_the_rows_of_a_query() {
+        let server = TestServer::start();
+        let url = &server.url;
+        let table = TestTable::create(url, "customers_query");
+        let query = format!("SELECT id, full_name, lat, lng FROM {} WHERE tier IS NOT NULL;", table.name);
+
+        let customers = table.database().with_source(SqlSource::Query(query)).customers().unwrap();
+
+        assert_eq!(user_ids(&customers), vec![1, 3]);
+        assert!(customers.get(1).unwrap().attributes.is_empty());
+    }
+
+    #[test]
+    fn customer_postgres_falls_back_to_a_bounding_box_without_postgis() {
+        let server = TestServer::start();
+        let url = &server.url;
+        let table = TestTable::create(url, "customers_bounding_box");
+
+        // Jose is inside the bounding box of the search but 39 Km away from Dublin.
+        let customers = table.database().with_postgis(false).within(Kilometers(30f64), DUBLIN).customers().unwrap();
+
+        assert_eq!(user_ids(&customers), vec![3]);
+    }
+
+    #[test]
+    #[ignore = "needs PostGIS, run with --ignored against a server that has it"]
+    fn customer_postgres_pushes_the_radius_down_to_postgis() {
+        let server = TestServer::start();
+        let url = &server.url;
+        let mut client = Client::connect(url, NoTls).unwrap();
+        if let Err(err) = client.batch_execute("CREATE EXTENSION IF NOT EXISTS postgis") {
+            let reason = err.as_db_error().map_or_else(|| err.to_string(), |err| err.message().to_string());
+            panic!("PostGIS isn't installed: {}", reason);
+        }
+        let table = TestTable::create(url, "customers_postgis");
+
+        let customers = table.database().within(Kilometers(50f64), DUBLIN).customers().unwrap();
+
+        assert_eq!(user_ids(&customers), vec![1, 3]);
+    }
+
+    #[test]
+    fn customer_postgres_fails_on_missing_columns_and_nulls() {
+        let server = TestServer::start();
+        let url = &server.url;
+        let table = TestTable::create(url, "customers_errors");
+
+        match CustomerPostgres::new(url).with_source(SqlSource::Table(table.name.clone())).customers() {
+            Err(CustomerPostgresError::MissingColumn(ref column)) if column == "user_id" => (),
+            other => panic!("expected a missing column error, got {:?}", other),
+        }
+        let columns = ColumnMapping::from_str("user_id=id, name=tier, latitude=lat, longitude=lng").unwrap();
+        match table.database().with_columns(columns).customers() {
+            Err(CustomerPostgresError::InvalidValue(ref reason)) => assert_eq!(reason, "column 'tier' is null"),
+            other => panic!("expected an invalid value error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module joining a `CustomerList` with itself to find the customers closer
+//! than some distance to each other: the same household, likely duplicates
+//! or people who could share a car.
+//!
+//! Candidates are looked up on a `NeighbourGrid` so only the customers on
+//! nearby cells are compared, instead of every pair of customers. The
+//! customers linked by pairs, directly or through others, are grouped into
+//! connected components with [union-find]
+//! (https://en.wikipedia.org/wiki/Disjoint-set_data_structure).
+//!
+//! # Examples
+//!
+//! ```
+//! let join = ProximityJoin::new(Kilometers(0.05f64));
+//! for pair in join.pairs(&customers) {
+//!     println!("{} and {} are {} apart", pair.first, pair.second, pair.distance);
+//! }
+//! let components = join.components(&customers);
+//! ```
+//!
+
+use customer::{Customer, CustomerList};
+use customer_cluster::{self, Clustering, NeighbourGrid};
+use location::{DistanceModel, Location};
+use units::Kilometers;
+
+/// Two customers closer than the distance of a `ProximityJoin`, `first`
+/// being the one coming first on the joined list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomerPair {
+    pub first: Customer,
+    pub second: Customer,
+    pub distance: Kilometers,
+}
+
+/// Struct joining the customers closer than `max_distance` to each other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProximityJoin {
+    max_distance: Kilometers,
+    model: DistanceModel,
+}
+
+impl ProximityJoin {
+    /// Constructs a join of the customers closer than `max_distance`.
+    pub fn new(max_distance: Kilometers) -> ProximityJoin {
+        ProximityJoin { max_distance, model: DistanceModel::Haversine }
+    }
+
+    /// Calculates the distances with `model` instead of the haversine one.
+    pub fn with_model(mut self, model: DistanceModel) -> ProximityJoin {
+        self.model = model;
+        self
+    }
+
+    /// Returns every pair of `customers` closer than the distance of the
+    /// join, the closest first and ties in the order of the list.
+    pub fn pairs(&self, customers: &CustomerList) -> Vec<CustomerPair> {
+        let list = customers.iter().collect::<Vec<_>>();
+        let mut pairs = Vec::new();
+        self.for_each_pair(customers, |first, second, distance| pairs.push((first, second, distance)));
+        pairs.sort_by(|first, second| first.2.0.total_cmp(&second.2.0).then((first.0, first.1).cmp(&(second.0, second.1))));
+
+        pairs.into_iter()
+            .map(|(first, second, distance)| CustomerPair {
+                first: list[first].clone(),
+                second: list[second].clone(),
+                distance,
+            })
+            .collect()
+    }
+
+    /// Groups `customers` into the components the pairs closer than the
+    /// distance of the join connect, transitively: customers share a
+    /// component when there's a chain of pairs between them. The customers
+    /// on no pair are left as the noise of the `Clustering`.
+    pub fn components(&self, customers: &CustomerList) -> Clustering {
+        let mut parents = (0..customers.len()).collect::<Vec<_>>();
+        let mut paired = vec![false; customers.len()];
+        self.for_each_pair(customers, |first, second, _| {
+            let (first_root, second_root) = (customer_cluster::root_of(&mut parents, first), customer_cluster::root_of(&mut parents, second));
+            parents[first_root.max(second_root)] = first_root.min(second_root);
+            paired[first] = true;
+            paired[second] = true;
+        });
+
+        let labels = (0..customers.len())
+            .map(|index| if paired[index] { Some(customer_cluster::root_of(&mut parents, index)) } else { None })
+            .collect::<Vec<_>>();
+
+        Clustering::from_labels(customers, &labels, self.model)
+    }
+
+    // Calls `pair` with the indexes of every pair of `customers` closer than
+    // the distance of the join, the first index the lowest, and their
+    // distance.
+    fn for_each_pair<F: FnMut(usize, usize, Kilometers)>(&self, customers: &CustomerList, mut pair: F) {
+        let locations = customers.iter().map(|customer| customer.location()).collect::<Vec<Location>>();
+        let grid = NeighbourGrid::new(&locations, self.max_distance, self.model);
+        for (first, location) in locations.iter().enumerate() {
+            for second in grid.neighbours(first).into_iter().filter(|&second| second > first) {
+                let distance = self.model.distance(location, &locations[second]);
+                if distance < self.max_distance {
+                    pair(first, second, distance);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Random customers around a few towns, and a few exactly on top of
+    // others, with a xorshift so the tests don't need a random crate.
+    fn generate_customer_list() -> CustomerList {
+        let towns = [Location::new(53.3393, -6.2576841), Location::new(51.8985, -8.4756), Location::new(-16.5, 179.99)];
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % 1_000_000) as f64 / 1_000_000f64 - 0.5
+        };
+
+        let mut customers: Vec<Customer> = (0..300)
+            .map(|index| {
+                let town = towns[index % towns.len()];
+                let location = Location::new(town.latitude + random() * 0.2, town.longitude + random() * 0.3);
+                let longitude = if location.longitude > 180f64 { location.longitude - 360f64 } else { location.longitude };
+                Customer::new(index as i64, "Customer", &Location::new(location.latitude, longitude))
+            })
+            .collect();
+        for index in 0..5 {
+            let twin = customers[index * 7].location();
+            customers.push(Customer::new(1000 + index as i64, "Twin", &twin));
+        }
+
+        CustomerList::from_vec(customers)
+    }
+
+    fn scanned_pairs(customers: &CustomerList, max_distance: Kilometers) -> Vec<(i64, i64)> {
+        let list = customers.iter().collect::<Vec<_>>();
+        let mut pairs = Vec::new();
+        for first in 0..list.len() {
+            for second in first + 1..list.len() {
+                if list[first].distance_from(&list[second].location()) < max_distance {
+                    pairs.push((list[first].user_id, list[second].user_id));
+                }
+            }
+        }
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn pairs_finds_the_same_pairs_as_a_scan() {
+        let customers = generate_customer_list();
+        for &max_distance in &[0.001f64, 0.5, 1.0, 3.0, 10.0] {
+            let mut pairs: Vec<(i64, i64)> = ProximityJoin::new(Kilometers(max_distance))
+                .pairs(&customers)
+                .iter()
+                .map(|pair| (pair.first.user_id, pair.second.user_id))
+                .collect();
+            pairs.sort();
+            assert_eq!(pairs, scanned_pairs(&customers, Kilometers(max_distance)), "max distance {}", max_distance);
+        }
+    }
+
+    #[test]
+    fn pairs_come_closest_first_with_their_distance() {
+        let pairs = ProximityJoin::new(Kilometers(2f64)).pairs(&generate_customer_list());
+
+        assert!(pairs.len() > 5);
+        assert!(pairs[..5].iter().all(|pair| pair.distance == Kilometers(0f64) && pair.second.name == "Twin"));
+        assert!(pairs.windows(2).all(|window| window[0].distance <= window[1].distance));
+        assert!(pairs.iter().all(|pair| pair.distance == pair.first.distance_from(&pair.second.location())));
+    }
+
+    #[test]
+    fn pairs_are_joined_by_the_distance_of_the_model() {
+        let customers = CustomerList::from_vec(vec![
+            Customer::new(1, "First", &Location::new(53.0, -6.0)),
+            Customer::new(2, "Second", &Location::new(53.0, -6.01)),
+        ]);
+        let vincenty = DistanceModel::Vincenty.distance(&customers[0].location(), &customers[1].location());
+        let join = ProximityJoin::new(Kilometers(vincenty.0 * 1.001)).with_model(DistanceModel::Vincenty);
+
+        let pairs = join.pairs(&customers);
+
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].distance, vincenty);
+        assert!(ProximityJoin::new(vincenty).with_model(DistanceModel::Vincenty).pairs(&customers).is_empty());
+    }
+
+    #[test]
+    fn pairs_leave_out_customers_exactly_at_the_distance() {
+        let customers = CustomerList::from_vec(vec![
+            Customer::new(1, "First", &Location::new(53.0, -6.0)),
+            Customer::new(2, "Second", &Location::new(53.0, -6.01)),
+        ]);
+        let distance = customers[0].distance_from(&customers[1].location());
+
+        assert!(ProximityJoin::new(distance).pairs(&customers).is_empty());
+        assert_eq!(ProximityJoin::new(Kilometers(distance.0 * 1.001)).pairs(&customers).len(), 1);
+    }
+
+    #[test]
+    fn components_group_the_customers_connected_by_pairs() {
+        let customers = generate_customer_list();
+        let max_distance = Kilometers(1.5f64);
+        let components = ProximityJoin::new(max_distance).components(&customers);
+
+        // every pair is within a component and no pair crosses components
+        for (first, second) in scanned_pairs(&customers, max_distance) {
+            assert!(components.cluster_of(first).is_some());
+            assert_eq!(components.cluster_of(first), components.cluster_of(second));
+        }
+        for customer in &components.noise {
+            assert!(scanned_pairs(&customers, max_distance).iter().all(|&(first, second)| first != customer.user_id && second != customer.user_id));
+        }
+        let grouped: usize = components.clusters.iter().map(|cluster| cluster.customers.len()).sum();
+        assert_eq!(grouped + components.noise.len(), customers.len());
+    }
+
+    #[test]
+    fn components_link_customers_transitively() {
+        let customers = CustomerList::from_vec(vec![
+            Customer::new(1, "West", &Location::new(53.0, -6.02)),
+            Customer::new(2, "Alone", &Location::new(54.0, -6.0)),
+            Customer::new(3, "East", &Location::new(53.0, -6.0)),
+            Customer::new(4, "Middle", &Location::new(53.0, -6.01)),
+        ]);
+        let components = ProximityJoin::new(Kilometers(1f64)).components(&customers);
+
+        assert_eq!(components.clusters.len(), 1);
+        let user_ids: Vec<i64> = components.clusters[0].customers.iter().map(|located| located.customer.user_id).collect();
+        assert_eq!(user_ids, vec![1, 3, 4]);
+        assert_eq!(components.noise.iter().map(|customer| customer.user_id).collect::<Vec<_>>(), vec![2]);
+    }
+}
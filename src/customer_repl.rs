@@ -0,0 +1,530 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module containing an interactive shell to run one search after another
+//! on customers loaded only once.
+//!
+//! The shell understands these commands:
+//!
+//! - `within 50km of 53.3,-6.2`: the customers within a radius, in `km` or
+//!   `mi`, or in the current unit when the number comes alone.
+//! - `nearest 5 to dublin`: the closest customers, nearest first.
+//! - `show 12`: a single customer.
+//! - `stats`: numbers about the loaded customers and the last search.
+//! - `set unit mi`, `set output json` and `set attributes email,tier`.
+//! - `help` and `quit`.
+//!
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use rustyline::{Context, Editor, Helper};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+
+use customer::CustomerList;
+use customer_locator::{CustomerLocator, LocatedCustomer};
+use customer_output::{self, AttributeSelection, CustomerOutputError, OutputFormat};
+use customer_stats::{CustomerStats, DistanceReport};
+use customer_store::CustomerStore;
+use gazetteer::Gazetteer;
+use location::{BoundingBox, Location, Ring};
+use units::DistanceUnit;
+
+const PROMPT: &str = "customers> ";
+const COMMANDS: &[&str] = &["within", "nearest", "show", "stats", "set", "help", "quit"];
+const SETTINGS: &[&str] = &["unit", "output", "attributes"];
+const HELP: &str = "Commands:
+  within <radius>[km|mi] of <place|latitude,longitude>
+  nearest <count> to <place|latitude,longitude>
+  show <user_id>
+  stats
+  set unit <km|mi>
+  set output <text|json|csv|kml|gpx>
+  set attributes <all|name,...>
+  help
+  quit";
+
+/// A radius as typed on the shell, without a unit it's taken in the
+/// unit currently set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Radius {
+    pub value: f64,
+    pub unit: Option<DistanceUnit>,
+}
+
+/// The settings that can be changed with `set`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    Unit(DistanceUnit),
+    Output(OutputFormat),
+    Attributes(AttributeSelection),
+}
+
+/// A command read on the shell.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Within(Radius, Location),
+    Nearest(usize, Location),
+    Show(i64),
+    Stats,
+    Set(Setting),
+    Help,
+    Quit,
+}
+
+/// An error when trying to build a `Command` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseCommandError(String);
+
+impl fmt::Display for ParseCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Command parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseCommandError {}
+
+impl FromStr for Command {
+    type Err = ParseCommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.split_first() {
+            Some((&"within", arguments)) => {
+                let (radius, location) = split_on(arguments, "of", "within <radius> of <location>")?;
+                Ok(Command::Within(parse_radius(&radius)?, parse_location(&location)?))
+            }
+            Some((&"nearest", arguments)) => {
+                let (count, location) = split_on(arguments, "to", "nearest <count> to <location>")?;
+                let count = usize::from_str(&count)
+                    .map_err(|err| ParseCommandError(format!("invalid count '{}': {}", count, err)))?;
+                Ok(Command::Nearest(count, parse_location(&location)?))
+            }
+            Some((&"show", [user_id])) => {
+                i64::from_str(user_id)
+                    .map(Command::Show)
+                    .map_err(|err| ParseCommandError(format!("invalid user_id '{}': {}", user_id, err)))
+            }
+            Some((&"set", [name, value @ ..])) if !value.is_empty() => parse_setting(name, &value.join(" ")).map(Command::Set),
+            Some((&"stats", [])) => Ok(Command::Stats),
+            Some((&"help", [])) => Ok(Command::Help),
+            Some((&"quit", [])) | Some((&"exit", [])) => Ok(Command::Quit),
+            Some((command, _)) if COMMANDS.contains(command) => {
+                Err(ParseCommandError(format!("wrong arguments for {}, try help", command)))
+            }
+            Some((command, _)) => Err(ParseCommandError(format!("unknown command '{}', try help", command))),
+            None => Err(ParseCommandError(String::from("empty command"))),
+        }
+    }
+}
+
+// Splits `words` on `separator` joining each side back.
+fn split_on(words: &[&str], separator: &str, usage: &str) -> Result<(String, String), ParseCommandError> {
+    match words.iter().position(|word| *word == separator) {
+        Some(index) if index > 0 && index + 1 < words.len() => {
+            Ok((words[..index].join(""), words[index + 1..].join(" ")))
+        }
+        _ => Err(ParseCommandError(format!("usage: {}", usage))),
+    }
+}
+
+fn parse_radius(radius: &str) -> Result<Radius, ParseCommandError> {
+    let number_length = radius.find(|c: char| c.is_alphabetic()).unwrap_or(radius.len());
+    let (value, unit) = radius.split_at(number_length);
+
+    let value = f64::from_str(value).map_err(|err| ParseCommandError(format!("invalid radius '{}': {}", radius, err)))?;
+    let unit = if unit.is_empty() {
+        None
+    } else {
+        Some(DistanceUnit::from_str(unit).map_err(|err| ParseCommandError(err.to_string()))?)
+    };
+
+    Ok(Radius { value, unit })
+}
+
+// Takes either `latitude,longitude` or the name of a place on the gazetteer.
+fn parse_location(location: &str) -> Result<Location, ParseCommandError> {
+    Gazetteer::global().locate(location).map_err(|err| ParseCommandError(err.to_string()))
+}
+
+fn parse_setting(name: &str, value: &str) -> Result<Setting, ParseCommandError> {
+    match name {
+        "unit" => DistanceUnit::from_str(value).map(Setting::Unit).map_err(|err| ParseCommandError(err.to_string())),
+        "output" => OutputFormat::from_str(value).map(Setting::Output).map_err(|err| ParseCommandError(err.to_string())),
+        "attributes" if value == "all" => Ok(Setting::Attributes(AttributeSelection::All)),
+        "attributes" => Ok(Setting::Attributes(AttributeSelection::from_list(value))),
+        other => Err(ParseCommandError(format!("unknown setting '{}'", other))),
+    }
+}
+
+/// Returns where the word under the cursor starts and the words it can be
+/// completed to, `line` being what was typed up to the cursor.
+pub fn complete(line: &str) -> (usize, Vec<String>) {
+    let start = line.rfind(char::is_whitespace).map(|index| index + 1).unwrap_or(0);
+    let (previous, word) = line.split_at(start);
+    let previous: Vec<&str> = previous.split_whitespace().collect();
+
+    let candidates: Vec<&str> = match previous.as_slice() {
+        [] => COMMANDS.to_vec(),
+        ["within", _] => vec!["of"],
+        ["nearest", _] => vec!["to"],
+        // places with spaces in their names only complete their first word.
+        ["within", _, "of"] | ["nearest", _, "to"] => {
+            let mut places: Vec<&str> = Gazetteer::global().names().filter(|name| !name.contains(' ')).collect();
+            places.sort();
+            places
+        }
+        ["set"] => SETTINGS.to_vec(),
+        ["set", "unit"] => DistanceUnit::NAMES.to_vec(),
+        ["set", "output"] => OutputFormat::NAMES.to_vec(),
+        ["set", "attributes"] => vec!["all"],
+        _ => Vec::new(),
+    };
+
+    let completions = candidates.into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(String::from)
+        .collect();
+    (start, completions)
+}
+
+struct CommandHelper;
+
+impl Completer for CommandHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context) -> Result<(usize, Vec<String>), ReadlineError> {
+        Ok(complete(&line[..pos]))
+    }
+}
+
+impl Hinter for CommandHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CommandHelper {}
+
+impl Validator for CommandHelper {}
+
+impl Helper for CommandHelper {}
+
+/// An error encapsulating the things that can make the shell stop.
+#[derive(Debug)]
+pub enum CustomerReplError {
+    Readline(ReadlineError),
+    Output(CustomerOutputError),
+}
+
+impl fmt::Display for CustomerReplError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CustomerReplError::Readline(ref err) => write!(f, "Customer shell input error: {}", err),
+            CustomerReplError::Output(ref err) => write!(f, "Customer shell output error: {}", err),
+        }
+    }
+}
+
+impl error::Error for CustomerReplError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CustomerReplError::Readline(ref err) => Some(err),
+            CustomerReplError::Output(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<ReadlineError> for CustomerReplError {
+    fn from(err: ReadlineError) -> Self {
+        CustomerReplError::Readline(err)
+    }
+}
+
+impl From<CustomerOutputError> for CustomerReplError {
+    fn from(err: CustomerOutputError) -> Self {
+        CustomerReplError::Output(err)
+    }
+}
+
+/// Struct running commands on the customers of a `CustomerLocator`.
+///
+/// # Examples
+///
+/// ```
+/// let locator = CustomerLocator::from_source(customers_json_file).unwrap();
+/// let mut repl = CustomerRepl::new(locator, gazetteer.locate("cork")?, OutputFormat::Text, AttributeSelection::All);
+///
+/// // reads commands from the terminal until `quit` or Ctrl-D.
+/// repl.run(Some(Path::new(".customer_locator_history"))).unwrap();
+/// ```
+pub struct CustomerRepl<S = CustomerList> {
+    locator: CustomerLocator<S>,
+    // the location of the last search, `show` prints distances from it.
+    location: Location,
+    unit: DistanceUnit,
+    output_format: OutputFormat,
+    attributes: AttributeSelection,
+    last_search: Option<CustomerStats>,
+}
+
+impl<S: CustomerStore> CustomerRepl<S> {
+    /// Constructs a new shell on the customers of `locator` printing them
+    /// as the CLI would with `output_format` and `attributes`, and their
+    /// distances from `location` until a search is made.
+    pub fn new(locator: CustomerLocator<S>,
+               location: Location,
+               output_format: OutputFormat,
+               attributes: AttributeSelection)
+               -> CustomerRepl<S> {
+        CustomerRepl { locator, location, unit: DistanceUnit::Kilometers, output_format, attributes, last_search: None }
+    }
+
+    /// Sets the unit radiuses are read in and distances printed in, the
+    /// same as `set unit` does.
+    pub fn with_unit(mut self, unit: DistanceUnit) -> CustomerRepl<S> {
+        self.unit = unit;
+        self
+    }
+
+    /// Reads commands from the terminal and runs them until `quit` or the
+    /// end of the input, keeping the history of commands at `history_path`.
+    ///
+    /// # Errors
+    ///
+    /// When the terminal can't be read or the output can't be written.
+    /// Mistyped commands just print an error.
+    pub fn run(&mut self, history_path: Option<&Path>) -> Result<(), CustomerReplError> {
+        let mut editor = Editor::<CommandHelper, DefaultHistory>::new()?;
+        editor.set_helper(Some(CommandHelper));
+        if let Some(history_path) = history_path {
+            // there's no history the first time around.
+            let _ = editor.load_history(history_path);
+        }
+
+        let stdout = io::stdout();
+        loop {
+            let line = match editor.readline(PROMPT) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(err.into()),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line.as_str())?;
+
+            match Command::from_str(&line) {
+                Ok(Command::Quit) => break,
+                Ok(command) => self.execute(&command, stdout.lock())?,
+                Err(err) => println!("{}", err),
+            }
+        }
+
+        if let Some(history_path) = history_path {
+            editor.save_history(history_path)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `command` writing its output to `writer`.
+    pub fn execute<W: Write>(&mut self, command: &Command, mut writer: W) -> Result<(), CustomerOutputError> {
+        match *command {
+            Command::Within(radius, location) => {
+                let radius = radius.unit.unwrap_or(self.unit).to_kilometers(radius.value);
+                let mut customers = self.locator.locate_within(&radius, &location);
+                customers.sort_by_key(|located| located.customer.user_id);
+                self.write_search(writer, &customers, &location, Some(&Ring::within(radius)))
+            }
+            Command::Nearest(count, location) => {
+                let customers = self.locator.nearest(count, &location);
+                self.write_search(writer, &customers, &location, None)
+            }
+            Command::Show(user_id) => {
+                match self.locator.find(user_id) {
+                    Some(customer) => self.write_customers(writer, &CustomerList::from_vec(vec![customer]), &self.location),
+                    None => Ok(writeln!(writer, "No customer with user_id {}.", user_id)?),
+                }
+            }
+            Command::Stats => self.write_stats(writer),
+            Command::Set(ref setting) => {
+                match *setting {
+                    Setting::Unit(unit) => self.unit = unit,
+                    Setting::Output(output_format) => self.output_format = output_format,
+                    Setting::Attributes(ref attributes) => self.attributes = attributes.clone(),
+                }
+                Ok(())
+            }
+            Command::Help => Ok(writeln!(writer, "{}", HELP)?),
+            Command::Quit => Ok(()),
+        }
+    }
+
+    fn write_search<W: Write>(&mut self,
+                              writer: W,
+                              customers: &[LocatedCustomer],
+                              location: &Location,
+                              ring: Option<&Ring>)
+                              -> Result<(), CustomerOutputError> {
+        self.location = *location;
+        self.last_search = Some(DistanceReport::new(*location).report_located(customers));
+
+        customer_output::write_search(writer, customers, location, ring, self.output_format, &self.attributes, self.unit)
+    }
+
+    fn write_customers<W: Write>(&self, writer: W, customers: &CustomerList, location: &Location) -> Result<(), CustomerOutputError> {
+        customer_output::write_customers(writer, customers, location, self.locator.model(), self.output_format, &self.attributes, self.unit)
+    }
+
+    fn write_stats<W: Write>(&self, mut writer: W) -> Result<(), CustomerOutputError> {
+        let customers = self.locator.customers();
+        writeln!(writer, "Customers loaded: {}", customers.len())?;
+
+        let locations: Vec<Location> = (0..customers.len()).map(|index| customers.location_at(index)).collect();
+        if let Some(bounding_box) = BoundingBox::enclosing(&locations) {
+            let (south_west, north_east) = (bounding_box.south_west, bounding_box.north_east);
+            writeln!(writer, "Latitudes: {} to {}", south_west.latitude, north_east.latitude)?;
+            if south_west.longitude > north_east.longitude {
+                writeln!(writer, "Longitudes: {} to {} across the antimeridian", south_west.longitude, north_east.longitude)?;
+            } else {
+                writeln!(writer, "Longitudes: {} to {}", south_west.longitude, north_east.longitude)?;
+            }
+        }
+
+        match self.last_search {
+            Some(CustomerStats { matches, nearest: Some(ref nearest), farthest: Some(ref farthest), .. }) => {
+                writeln!(writer, "Last search: {} customers, nearest at {}, farthest at {}",
+                         matches, self.unit.format(nearest.distance), self.unit.format(farthest.distance))?;
+            }
+            Some(_) => writeln!(writer, "Last search: no customers")?,
+            None => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use customer::Customer;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+
+    fn generate_repl() -> CustomerRepl {
+        let locator = CustomerLocator::new(CustomerList::from_vec(vec![
+            Customer::new(1, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
+            Customer::new(2, "Nora Dempsey", &Location::new(53.1302756, -6.2397222)),
+            Customer::new(3, "Eoin Ahearn", &Location::new(54.0894797, -6.18671))
+                .with_attribute("email", "eoin@example.com"),
+        ]));
+        CustomerRepl::new(locator, DUBLIN, OutputFormat::Text, AttributeSelection::All)
+    }
+
+    fn execute(repl: &mut CustomerRepl, line: &str) -> String {
+        let mut output = Vec::new();
+        repl.execute(&Command::from_str(line).unwrap(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn command_from_str_parses_every_command() {
+        assert_eq!(Command::from_str("within 50km of 53.3,-6.2"),
+                   Ok(Command::Within(Radius { value: 50f64, unit: Some(DistanceUnit::Kilometers) }, Location::new(53.3, -6.2))));
+        assert_eq!(Command::from_str("within 30 mi of 53.3, -6.2"),
+                   Ok(Command::Within(Radius { value: 30f64, unit: Some(DistanceUnit::Miles) }, Location::new(53.3, -6.2))));
+        assert_eq!(Command::from_str("within 12.5 of Dublin"),
+                   Ok(Command::Within(Radius { value: 12.5f64, unit: None }, DUBLIN)));
+        assert_eq!(Command::from_str("nearest 5 to dublin"), Ok(Command::Nearest(5, DUBLIN)));
+        assert_eq!(Command::from_str("show 12"), Ok(Command::Show(12)));
+        assert_eq!(Command::from_str("  stats "), Ok(Command::Stats));
+        assert_eq!(Command::from_str("set unit mi"), Ok(Command::Set(Setting::Unit(DistanceUnit::Miles))));
+        assert_eq!(Command::from_str("set attributes email, tier"),
+                   Ok(Command::Set(Setting::Attributes(AttributeSelection::from_list("email,tier")))));
+    }
+
+    #[test]
+    fn command_from_str_fails_on_mistyped_commands() {
+        assert_eq!(Command::from_str("within 50km 53.3,-6.2"), Err(ParseCommandError(String::from("usage: within <radius> of <location>"))));
+        assert_eq!(Command::from_str("within 50ly of dublin"), Err(ParseCommandError(String::from("Distance unit parse error: unknown distance unit 'ly'"))));
+        assert_eq!(Command::from_str("nearest 5 to atlantis"), Err(ParseCommandError(String::from("unknown place 'atlantis'"))));
+        assert_eq!(Command::from_str("nearest 5 to corck"), Ok(Command::Nearest(5, Location::new(51.8985, -8.4756))));
+        assert_eq!(Command::from_str("show"), Err(ParseCommandError(String::from("wrong arguments for show, try help"))));
+        assert_eq!(Command::from_str("locate"), Err(ParseCommandError(String::from("unknown command 'locate', try help"))));
+    }
+
+    #[test]
+    fn execute_runs_searches_with_the_current_settings() {
+        let mut repl = generate_repl();
+
+        let output = execute(&mut repl, "within 25 of dublin");
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("Km from provided location."));
+
+        execute(&mut repl, "set unit mi");
+        execute(&mut repl, "set output json");
+        let output = execute(&mut repl, "nearest 1 to 54.0,-6.2");
+        assert_eq!(output, "{\"user_id\":3,\"name\":\"Eoin Ahearn\",\"latitude\":54.0894797,\"longitude\":-6.18671,\"email\":\"eoin@example.com\"}\n");
+
+        execute(&mut repl, "set output text");
+        let output = execute(&mut repl, "within 10 of dublin");
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.contains("mi from provided location."));
+    }
+
+    #[test]
+    fn execute_reports_stats_and_missing_customers() {
+        let mut repl = generate_repl();
+
+        assert_eq!(execute(&mut repl, "show 42"), "No customer with user_id 42.\n");
+
+        execute(&mut repl, "within 100km of dublin");
+        let stats = execute(&mut repl, "stats");
+        assert!(stats.starts_with("Customers loaded: 3\nLatitudes: 53.1302756 to 54.0894797\n"));
+        assert!(stats.contains("Last search: 3 customers, nearest at 10.556 Km"));
+    }
+
+    #[test]
+    fn execute_reports_the_longitudes_across_the_antimeridian() {
+        let locator = CustomerLocator::new(CustomerList::from_vec(vec![
+            Customer::new(1, "Suva", &Location::new(-18.1416, 178.4419)),
+            Customer::new(2, "Taveuni", &Location::new(-16.8, -179.9)),
+        ]));
+        let mut repl = CustomerRepl::new(locator, DUBLIN, OutputFormat::Text, AttributeSelection::All);
+
+        let stats = execute(&mut repl, "stats");
+        assert!(stats.contains("Longitudes: 178.4419 to -179.9 across the antimeridian\n"), "{}", stats);
+    }
+
+    #[test]
+    fn help_lists_every_output_format() {
+        let help = execute(&mut generate_repl(), "help");
+        for format in &["text", "json", "csv", "kml", "gpx"] {
+            assert!(Command::from_str(&format!("set output {}", format)).is_ok());
+            assert!(help.contains(format), "{} is missing from the help", format);
+        }
+    }
+
+    #[test]
+    fn complete_completes_commands_settings_and_places() {
+        assert_eq!(complete("ne"), (0, vec![String::from("nearest")]));
+        assert_eq!(complete("s"), (0, vec![String::from("show"), String::from("stats"), String::from("set")]));
+        assert_eq!(complete("set u"), (4, vec![String::from("unit")]));
+        assert_eq!(complete("set unit "), (9, vec![String::from("km"), String::from("mi")]));
+        assert_eq!(complete("within 50km of dub"), (15, vec![String::from("dubai"), String::from("dublin")]));
+        assert_eq!(complete("show 1"), (5, Vec::new()));
+    }
+}
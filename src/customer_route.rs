@@ -0,0 +1,508 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module planning the visits to the customers, starting and ending every
+//! day at the office, over great-circle distances.
+//!
+//! `RoutePlanner` builds a single tour through every customer by going to
+//! the nearest customer not visited yet, then shortens it with [2-opt]
+//! (https://en.wikipedia.org/wiki/2-opt), reversing stretches of the tour,
+//! and [Or-opt], moving runs of up to three stops elsewhere, until neither
+//! finds anything shorter. Both look at every pair of stops, so planning
+//! takes the square of the customers on each pass: fine for the customers
+//! of a search, not for a whole country.
+//!
+//! With a maximum distance per day the tour is split into days, going back
+//! to the office in between, with the fewest days and then the shortest
+//! distance the order of the tour allows. Each day is shortened again on
+//! its own. Customers too far to go and come back within a day are left
+//! out.
+//!
+//! # Examples
+//!
+//! ```
+//! let route = RoutePlanner::new(office)
+//!     .with_max_daily_distance(Kilometers(300f64))
+//!     .plan(&customers);
+//! for (day, number) in route.days.iter().zip(1..) {
+//!     println!("Day {}: {} stops, {}", number, day.stops.len(), day.distance());
+//! }
+//! ```
+//!
+
+use customer::{Customer, CustomerList};
+use location::{DistanceModel, Location};
+use units::Kilometers;
+
+// Improvements under this, in Km, aren't worth changing the tour for.
+const DISTANCE_TOLERANCE: f64 = 1e-9;
+
+// The longest run of stops Or-opt moves at once.
+const MAX_OR_OPT_SEGMENT: usize = 3;
+
+/// A visit to a customer, with the distance from the stop before it and
+/// the distance since the day started at the office.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stop {
+    pub customer: Customer,
+    pub leg: Kilometers,
+    pub cumulative: Kilometers,
+}
+
+/// A day of visits, from the office through `stops` and back to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Day {
+    pub stops: Vec<Stop>,
+    /// The distance from the last stop back to the office.
+    pub return_leg: Kilometers,
+}
+
+impl Day {
+    /// Returns the distance travelled on the day, back to the office.
+    pub fn distance(&self) -> Kilometers {
+        let travelled = self.stops.last().map_or(0f64, |stop| stop.cumulative.0);
+        Kilometers(travelled + self.return_leg.0)
+    }
+}
+
+/// The route found by `RoutePlanner`: the days of visits in order and the
+/// customers too far to visit within a day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub office: Location,
+    pub days: Vec<Day>,
+    pub unreachable: CustomerList,
+}
+
+impl Route {
+    /// Returns the distance travelled over every day.
+    pub fn total_distance(&self) -> Kilometers {
+        Kilometers(self.days.iter().map(|day| day.distance().0).sum())
+    }
+}
+
+/// Struct planning the visits to customers from an office.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutePlanner {
+    office: Location,
+    max_daily_distance: Option<Kilometers>,
+    model: DistanceModel,
+}
+
+impl RoutePlanner {
+    /// Constructs a planner of routes starting and ending at `office`,
+    /// visiting every customer on a single day.
+    pub fn new(office: Location) -> RoutePlanner {
+        RoutePlanner { office, max_daily_distance: None, model: DistanceModel::Haversine }
+    }
+
+    /// Calculates the distances with `model` instead of the haversine one.
+    pub fn with_model(mut self, model: DistanceModel) -> RoutePlanner {
+        self.model = model;
+        self
+    }
+
+    /// Splits the route into days of up to `max_daily_distance` each.
+    pub fn with_max_daily_distance(mut self, max_daily_distance: Kilometers) -> RoutePlanner {
+        self.max_daily_distance = Some(max_daily_distance);
+        self
+    }
+
+    /// Plans the visits to `customers`.
+    pub fn plan(&self, customers: &CustomerList) -> Route {
+        let max_daily_distance = self.max_daily_distance.map_or(f64::INFINITY, |max_daily_distance| max_daily_distance.0);
+        let (reachable, unreachable) = customers.clone()
+            .partition(|customer| 2f64 * self.model.distance(&customer.location(), &self.office).0 <= max_daily_distance);
+
+        let mut locations = vec![self.office];
+        locations.extend(reachable.iter().map(|customer| customer.location()));
+        let network = Network::new(locations, self.model);
+
+        let mut tour = network.nearest_neighbour_tour();
+        network.improve(&mut tour);
+
+        let days = network.split(&tour, max_daily_distance)
+            .into_iter()
+            .map(|mut day| {
+                network.improve(&mut day);
+                self.day(&reachable, &day)
+            })
+            .collect();
+
+        Route { office: self.office, days, unreachable }
+    }
+
+    // Builds the `Day` visiting the stops of `tour`, its first stop being
+    // the office and the rest customers of `reachable` one after theirs.
+    fn day(&self, reachable: &CustomerList, tour: &[usize]) -> Day {
+        let mut stops = Vec::with_capacity(tour.len() - 1);
+        let mut cumulative = 0f64;
+        let mut last = self.office;
+        for &stop in &tour[1..] {
+            let customer = &reachable[stop - 1];
+            let leg = self.model.distance(&customer.location(), &last);
+            cumulative += leg.0;
+            last = customer.location();
+            stops.push(Stop { customer: customer.clone(), leg, cumulative: Kilometers(cumulative) });
+        }
+
+        Day { stops, return_leg: self.model.distance(&last, &self.office) }
+    }
+}
+
+// The office, at 0, and the customers to visit, with the distances between
+// them. Tours are closed, they go back from their last stop to their first
+// one, and always start at the office.
+struct Network {
+    locations: Vec<Location>,
+    model: DistanceModel,
+}
+
+impl Network {
+    fn new(locations: Vec<Location>, model: DistanceModel) -> Network {
+        Network { locations, model }
+    }
+
+    fn distance(&self, from: usize, to: usize) -> f64 {
+        self.model.distance(&self.locations[from], &self.locations[to]).0
+    }
+
+    // Goes from the office to the nearest customer not visited yet, ties
+    // going to the customer given first, until every customer is visited.
+    fn nearest_neighbour_tour(&self) -> Vec<usize> {
+        let mut tour = vec![0];
+        let mut unvisited: Vec<usize> = (1..self.locations.len()).collect();
+        while !unvisited.is_empty() {
+            let last = tour[tour.len() - 1];
+            let (nearest, _) = unvisited.iter()
+                .map(|&stop| self.distance(last, stop))
+                .enumerate()
+                .fold((0, f64::INFINITY), |best, (index, distance)| if distance < best.1 { (index, distance) } else { best });
+            tour.push(unvisited.remove(nearest));
+        }
+
+        tour
+    }
+
+    // Applies 2-opt and Or-opt moves to `tour` until neither shortens it.
+    fn improve(&self, tour: &mut Vec<usize>) {
+        loop {
+            let reversed = self.two_opt(tour);
+            let moved = self.or_opt(tour);
+            if !reversed && !moved {
+                break;
+            }
+        }
+    }
+
+    // Reverses the stretches of `tour` that shorten it, until none does.
+    // Returns whether the tour changed.
+    fn two_opt(&self, tour: &mut [usize]) -> bool {
+        let stops = tour.len();
+        let mut changed = false;
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for first in 0..stops.saturating_sub(2) {
+                for second in first + 2..stops {
+                    if first == 0 && second == stops - 1 {
+                        // both edges meet at the office
+                        continue;
+                    }
+                    let (a, b) = (tour[first], tour[first + 1]);
+                    let (c, d) = (tour[second], tour[(second + 1) % stops]);
+                    let saving = self.distance(a, b) + self.distance(c, d) - self.distance(a, c) - self.distance(b, d);
+                    if saving > DISTANCE_TOLERANCE {
+                        tour[first + 1..=second].reverse();
+                        improved = true;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    // Moves the runs of up to `MAX_OR_OPT_SEGMENT` stops of `tour` between
+    // two other stops, reversed if that's shorter, while that shortens it.
+    // Returns whether the tour changed.
+    fn or_opt(&self, tour: &mut Vec<usize>) -> bool {
+        let mut changed = false;
+        let mut improved = true;
+        while improved {
+            improved = false;
+            'segments: for length in 1..=MAX_OR_OPT_SEGMENT {
+                let stops = tour.len();
+                if length + 2 > stops {
+                    // nowhere else to move the run to
+                    break;
+                }
+                // the office stays at the start, so runs start after it
+                for start in 1..=stops - length {
+                    let end = start + length - 1;
+                    let (previous, next) = (tour[start - 1], tour[(end + 1) % stops]);
+                    let (head, tail) = (tour[start], tour[end]);
+                    let saving = self.distance(previous, head) + self.distance(tail, next) - self.distance(previous, next);
+                    if saving <= DISTANCE_TOLERANCE {
+                        continue;
+                    }
+
+                    let mut best: Option<(f64, usize, bool)> = None;
+                    for after in (0..stops).filter(|&after| after + 1 < start || after > end) {
+                        let (u, v) = (tour[after], tour[(after + 1) % stops]);
+                        let forward = self.distance(u, head) + self.distance(tail, v) - self.distance(u, v);
+                        let backward = self.distance(u, tail) + self.distance(head, v) - self.distance(u, v);
+                        let (cost, reversed) = if backward < forward { (backward, true) } else { (forward, false) };
+                        if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                            best = Some((cost, after, reversed));
+                        }
+                    }
+
+                    if let Some((cost, after, reversed)) = best {
+                        if saving - cost > DISTANCE_TOLERANCE {
+                            let mut segment: Vec<usize> = tour.drain(start..=end).collect();
+                            if reversed {
+                                segment.reverse();
+                            }
+                            let at = if after > end { after - length + 1 } else { after + 1 };
+                            tour.splice(at..at, segment);
+                            improved = true;
+                            changed = true;
+                            continue 'segments;
+                        }
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    // Splits `tour` into tours of up to `max_distance` each going back to
+    // the office, keeping the order of its stops, with the fewest tours and
+    // then the shortest total distance.
+    fn split(&self, tour: &[usize], max_distance: f64) -> Vec<Vec<usize>> {
+        let stops = &tour[1..];
+        // the fewest days and shortest distance visiting the first `i`
+        // stops, and where the last of those days starts.
+        let mut best: Vec<(usize, f64, usize)> = vec![(usize::MAX, f64::INFINITY, 0); stops.len() + 1];
+        best[0] = (0, 0f64, 0);
+        for start in 0..stops.len() {
+            if best[start].0 == usize::MAX {
+                continue;
+            }
+            let mut travelled = self.distance(0, stops[start]);
+            for end in start..stops.len() {
+                if end > start {
+                    travelled += self.distance(stops[end - 1], stops[end]);
+                }
+                if travelled > max_distance {
+                    break;
+                }
+                let day = travelled + self.distance(stops[end], 0);
+                if day > max_distance {
+                    continue;
+                }
+                let candidate = (best[start].0 + 1, best[start].1 + day, start);
+                if candidate.0 < best[end + 1].0 || (candidate.0 == best[end + 1].0 && candidate.1 < best[end + 1].1) {
+                    best[end + 1] = candidate;
+                }
+            }
+        }
+
+        let mut days = Vec::new();
+        let mut end = stops.len();
+        while end > 0 {
+            let start = best[end].2;
+            let mut day = vec![0];
+            day.extend_from_slice(&stops[start..end]);
+            days.push(day);
+            end = start;
+        }
+        days.reverse();
+
+        days
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use location::EARTH_RADIUS_IN_KM;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+
+    fn generate_customer_list() -> CustomerList {
+        CustomerList::from_vec(vec![
+            Customer::new(1, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
+            Customer::new(2, "Nora Dempsey", &Location::new(53.1302756, -6.2397222)),
+            Customer::new(3, "Theresa Enright", &Location::new(53.1229599, -6.2705202)),
+            Customer::new(4, "Eoin Ahearn", &Location::new(54.0894797, -6.18671)),
+            Customer::new(5, "Olive Ahearn", &Location::new(53.00, -7.00)),
+            Customer::new(6, "Stephen McArdle", &Location::new(53.038056, -7.653889)),
+            Customer::new(7, "Alice Cahill", &Location::new(51.92893, -10.27699)),
+            Customer::new(8, "Jack Enright", &Location::new(52.3191841, -8.5072391)),
+            Customer::new(9, "Oliver Ahearn", &Location::new(53.74452, -7.11167)),
+            Customer::new(10, "Nick Enright", &Location::new(53.761389, -7.2875)),
+        ])
+    }
+
+    fn user_ids(day: &Day) -> Vec<i64> {
+        day.stops.iter().map(|stop| stop.customer.user_id).collect()
+    }
+
+    // A point `km` east of the office along its parallel, close enough to
+    // the great circle at these distances.
+    fn east_of_office(km: f64) -> Location {
+        let degrees = (km / (EARTH_RADIUS_IN_KM * DUBLIN.latitude.to_radians().cos())).to_degrees();
+        Location::new(DUBLIN.latitude, DUBLIN.longitude + degrees)
+    }
+
+    // Tries every order of `customers`, returning the shortest tour from
+    // the office and back.
+    fn brute_force(customers: &CustomerList) -> f64 {
+        fn search(locations: &[Location], last: Location, visited: &mut Vec<bool>, travelled: f64, best: &mut f64) {
+            if visited.iter().all(|&visited| visited) {
+                *best = best.min(travelled + last.distance_from(&DUBLIN).0);
+                return;
+            }
+            for index in 0..locations.len() {
+                if !visited[index] {
+                    visited[index] = true;
+                    search(locations, locations[index], visited, travelled + last.distance_from(&locations[index]).0, best);
+                    visited[index] = false;
+                }
+            }
+        }
+
+        let locations: Vec<Location> = customers.iter().map(|customer| customer.location()).collect();
+        let mut best = f64::INFINITY;
+        search(&locations, DUBLIN, &mut vec![false; locations.len()], 0f64, &mut best);
+        best
+    }
+
+    #[test]
+    fn plan_visits_every_customer_once_on_a_single_day() {
+        let customers = generate_customer_list();
+        let route = RoutePlanner::new(DUBLIN).plan(&customers);
+
+        assert_eq!(route.days.len(), 1);
+        let mut visited = user_ids(&route.days[0]);
+        visited.sort();
+        assert_eq!(visited, (1..=10).collect::<Vec<i64>>());
+        assert!(route.unreachable.is_empty());
+    }
+
+    #[test]
+    fn plan_reports_the_legs_and_the_cumulative_distances() {
+        let route = RoutePlanner::new(DUBLIN).plan(&generate_customer_list());
+        let day = &route.days[0];
+
+        let mut last = DUBLIN;
+        let mut cumulative = 0f64;
+        for stop in &day.stops {
+            assert_eq!(stop.leg, stop.customer.distance_from(&last));
+            cumulative += stop.leg.0;
+            assert_eq!(stop.cumulative, Kilometers(cumulative));
+            last = stop.customer.location();
+        }
+        assert_eq!(day.return_leg, last.distance_from(&DUBLIN));
+        assert_eq!(route.total_distance(), Kilometers(cumulative + day.return_leg.0));
+    }
+
+    #[test]
+    fn plan_measures_the_legs_with_the_model() {
+        let route = RoutePlanner::new(DUBLIN).with_model(DistanceModel::Vincenty).plan(&generate_customer_list());
+        let day = &route.days[0];
+
+        let mut last = DUBLIN;
+        for stop in &day.stops {
+            assert_eq!(stop.leg, DistanceModel::Vincenty.distance(&stop.customer.location(), &last));
+            last = stop.customer.location();
+        }
+        assert_eq!(day.return_leg, DistanceModel::Vincenty.distance(&last, &DUBLIN));
+    }
+
+    #[test]
+    fn plan_finds_the_shortest_tour_of_a_few_customers() {
+        let customers = generate_customer_list();
+        let route = RoutePlanner::new(DUBLIN).plan(&customers);
+
+        assert!((route.total_distance().0 - brute_force(&customers)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn plan_untangles_a_line_of_customers() {
+        let customers: CustomerList = [30f64, 10f64, 50f64, 20f64, 40f64].iter()
+            .zip(1..)
+            .map(|(&km, user_id)| Customer::new(user_id, "Customer", &east_of_office(km)))
+            .collect();
+        let route = RoutePlanner::new(DUBLIN).plan(&customers);
+        let visited = user_ids(&route.days[0]);
+
+        assert!(visited == vec![2, 4, 1, 5, 3] || visited == vec![3, 5, 1, 4, 2]);
+        assert!((route.total_distance().0 - 100f64).abs() < 0.1);
+    }
+
+    #[test]
+    fn plan_splits_the_route_into_days_within_the_max_distance() {
+        let customers = generate_customer_list();
+        let route = RoutePlanner::new(DUBLIN)
+            .with_max_daily_distance(Kilometers(400f64))
+            .plan(&customers);
+
+        assert!(route.days.len() > 1);
+        assert!(route.days.iter().all(|day| day.distance() <= Kilometers(400f64)));
+        let mut visited: Vec<i64> = route.days.iter().flat_map(user_ids).collect();
+        visited.extend(route.unreachable.iter().map(|customer| customer.user_id));
+        visited.sort();
+        assert_eq!(visited, (1..=10).collect::<Vec<i64>>());
+    }
+
+    #[test]
+    fn plan_leaves_out_the_customers_too_far_for_a_day() {
+        let customers = generate_customer_list();
+        let route = RoutePlanner::new(DUBLIN)
+            .with_max_daily_distance(Kilometers(400f64))
+            .plan(&customers);
+
+        let unreachable: Vec<i64> = route.unreachable.iter().map(|customer| customer.user_id).collect();
+        assert_eq!(unreachable, vec![7]);
+    }
+
+    #[test]
+    fn plan_uses_the_fewest_days() {
+        let customers: CustomerList = [-30f64, -20f64, 20f64, 30f64].iter()
+            .zip(1..)
+            .map(|(&km, user_id)| Customer::new(user_id, "Customer", &east_of_office(km)))
+            .collect();
+        let route = RoutePlanner::new(DUBLIN)
+            .with_max_daily_distance(Kilometers(120.1f64))
+            .plan(&customers);
+
+        assert_eq!(route.days.len(), 1);
+
+        let route = RoutePlanner::new(DUBLIN)
+            .with_max_daily_distance(Kilometers(119.9f64))
+            .plan(&customers);
+
+        assert_eq!(route.days.len(), 2);
+        assert!(route.days.iter().all(|day| day.distance() <= Kilometers(60.1f64)));
+        assert!(route.unreachable.is_empty());
+    }
+
+    #[test]
+    fn plan_without_customers_has_no_days() {
+        let route = RoutePlanner::new(DUBLIN).plan(&CustomerList::default());
+
+        assert!(route.days.is_empty());
+        assert_eq!(route.total_distance(), Kilometers(0f64));
+    }
+}
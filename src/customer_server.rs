@@ -0,0 +1,895 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module exposing a `CustomerLocator` through a small HTTP API so other
+//! services can look customers up without shelling out to the CLI.
+//!
+//! All the endpoints answer `GET` requests with JSON bodies:
+//!
+//! - `/customers/within?location=53.3393,-6.2576841&radius=50`: the
+//!   customers within `radius` kilometers of `location`.
+//! - `/customers/nearest?location=53.3393,-6.2576841&k=5`: the `k`
+//!   customers closest to `location`, nearest first.
+//! - `/customers/bbox?south_west=51.4,-10.7&north_east=55.4,-5.4`: the
+//!   customers inside the bounding box.
+//! - `/customers/stats?location=53.3393,-6.2576841&radius=100&bucket_width=10`:
+//!   the `CustomerStats` of the customers within `radius` kilometers of
+//!   `location`, or of all of them without a `radius`. `bucket_width` is
+//!   optional too.
+//! - `/customers/{user_id}`: a single customer.
+//! - `/status`: how many customers are served and how the last reload went.
+//!
+//! Plus `POST /reload` which asks for the customers to be rebuilt from their
+//! source when the server was given a reloader, answering right away.
+//!
+//! Locations are written `latitude,longitude` or given by a place name as on
+//! the CLI. Errors are answered with the matching status code and a
+//! `{"error": "..."}` body.
+//!
+//! Reloads build the new customers on a background thread while the old ones
+//! keep being served and then swap them in at once. Requests already running
+//! keep the customers they started with, and a reload that fails leaves the
+//! last good customers in place.
+//!
+//! A server can be shut down by a SIGINT or a SIGTERM, on Unix, which lets
+//! the requests being answered finish before `run` returns.
+//!
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::mem;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use customer::{Customer, CustomerList};
+use customer_locator::{CustomerLocator, LocatedCustomer};
+use customer_stats::DistanceReport;
+use customer_store::CustomerStore;
+use gazetteer::Gazetteer;
+use location::{BoundingBox, Location};
+use units::Kilometers;
+
+// How often the workers check whether the server was shut down.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Serialize)]
+struct WithinResponse {
+    location: Location,
+    radius: Kilometers,
+    customers: Vec<LocatedCustomer>,
+}
+
+#[derive(Debug, Serialize)]
+struct NearestResponse {
+    location: Location,
+    k: usize,
+    customers: Vec<LocatedCustomer>,
+}
+
+#[derive(Debug, Serialize)]
+struct BoundingBoxResponse {
+    bounding_box: BoundingBox,
+    customers: CustomerList,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// How many customers are being served and how the last reload went.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReloadStatus {
+    pub customers: usize,
+    pub reloads: u64,
+    pub last_reload_at: Option<u64>,
+    pub last_error: Option<String>,
+    pub reloading: bool,
+}
+
+// The size and modification time of a watched file.
+type FileFingerprint = (u64, SystemTime);
+
+// Builds a fresh locator from the source of the customers.
+type Reloader<S> = Box<dyn Fn() -> Result<CustomerLocator<S>, String> + Send + Sync>;
+
+/// The ways a request to the API can fail, each one maps to a status code.
+#[derive(Debug, PartialEq)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    MethodNotAllowed,
+    Conflict(String),
+    ReloadFailed(String),
+}
+
+impl ApiError {
+    fn status_code(&self) -> u16 {
+        match *self {
+            ApiError::BadRequest(_) => 400,
+            ApiError::NotFound(_) => 404,
+            ApiError::MethodNotAllowed => 405,
+            ApiError::Conflict(_) => 409,
+            ApiError::ReloadFailed(_) => 500,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApiError::BadRequest(ref reason) => write!(f, "{}", reason),
+            ApiError::NotFound(ref reason) => write!(f, "{}", reason),
+            ApiError::MethodNotAllowed => write!(f, "method not allowed"),
+            ApiError::Conflict(ref reason) => write!(f, "{}", reason),
+            ApiError::ReloadFailed(ref reason) => write!(f, "reload failed, still serving the last good customers: {}", reason),
+        }
+    }
+}
+
+/// An error when the server can't be started.
+#[derive(Debug)]
+pub struct CustomerServerError(Box<dyn error::Error + Send + Sync>);
+
+impl fmt::Display for CustomerServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Customer server error: {}", self.0)
+    }
+}
+
+impl error::Error for CustomerServerError {}
+
+/// Struct serving the customers of a `CustomerLocator` over HTTP.
+///
+/// # Examples
+///
+/// ```
+/// let locator = CustomerLocator::from_source(CustomerJsonFile::new(path)).unwrap();
+/// let server = CustomerServer::bind("127.0.0.1:8080", locator)
+///     .unwrap()
+///     .with_reloader(move || CustomerLocator::from_source(CustomerJsonFile::new(path)))
+///     .with_watch(path, Duration::from_secs(2));
+///
+/// // blocks answering requests on 4 threads until `shutdown` is called.
+/// server.run(4);
+/// ```
+pub struct CustomerServer<S = CustomerList> {
+    server: Server,
+    locator: RwLock<Arc<CustomerLocator<S>>>,
+    reloader: Option<Reloader<S>>,
+    watch: Option<(PathBuf, Duration, Option<FileFingerprint>)>,
+    reload_status: Mutex<ReloadStatus>,
+    // set while the reloader runs so reloads never overlap.
+    reloading: AtomicBool,
+    // set by `POST /reload` and taken by the background reload thread.
+    reload_requested: (Mutex<bool>, Condvar),
+    shutdown: AtomicBool,
+    shutdown_on_signals: bool,
+}
+
+impl<S: CustomerStore + Send + Sync> CustomerServer<S> {
+    /// Binds a server to `address` serving the customers of `locator`.
+    pub fn bind(address: &str, locator: CustomerLocator<S>) -> Result<CustomerServer<S>, CustomerServerError> {
+        let server = Server::http(address).map_err(CustomerServerError)?;
+        let reload_status = ReloadStatus { customers: locator.customers().len(), ..ReloadStatus::default() };
+
+        Ok(CustomerServer {
+            server,
+            locator: RwLock::new(Arc::new(locator)),
+            reloader: None,
+            watch: None,
+            reload_status: Mutex::new(reload_status),
+            reloading: AtomicBool::new(false),
+            reload_requested: (Mutex::new(false), Condvar::new()),
+            shutdown: AtomicBool::new(false),
+            shutdown_on_signals: false,
+        })
+    }
+
+    /// Sets the function `reload` uses to build the customers again, usually
+    /// by reading them from the same source they were first read from.
+    pub fn with_reloader<F, E>(mut self, reloader: F) -> CustomerServer<S>
+        where F: Fn() -> Result<CustomerLocator<S>, E> + Send + Sync + 'static,
+              E: fmt::Display
+    {
+        self.reloader = Some(Box::new(move || reloader().map_err(|err| err.to_string())));
+        self
+    }
+
+    /// Makes `run` reload the customers whenever the file at `path` changes,
+    /// checking it every `interval`.
+    pub fn with_watch<P: Into<PathBuf>>(mut self, path: P, interval: Duration) -> CustomerServer<S> {
+        let path = path.into();
+        let fingerprint = file_fingerprint(&path);
+        self.watch = Some((path, interval, fingerprint));
+        self
+    }
+
+    /// Makes `run` shut the server down when the process gets a SIGINT or
+    /// a SIGTERM, like `shutdown` does. Only on Unix.
+    pub fn with_shutdown_on_signals(mut self) -> CustomerServer<S> {
+        self.shutdown_on_signals = true;
+        self
+    }
+
+    /// Returns the locator currently being served.
+    ///
+    /// The returned locator is not affected by later reloads.
+    pub fn locator(&self) -> Arc<CustomerLocator<S>> {
+        self.locator.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Returns how many customers are served and how the last reload went.
+    pub fn reload_status(&self) -> ReloadStatus {
+        let mut reload_status = self.reload_status.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+        reload_status.reloading = self.reloading.load(Ordering::SeqCst);
+        reload_status
+    }
+
+    /// Builds the customers again with the reloader and swaps them in. The
+    /// customers keep being served, and the status answered, meanwhile.
+    ///
+    /// # Errors
+    ///
+    /// When there is no reloader, another reload is running or the reloader
+    /// fails, in which case the customers served so far are kept.
+    pub fn reload(&self) -> Result<ReloadStatus, ApiError> {
+        let reloader = self.reloader
+            .as_ref()
+            .ok_or_else(|| ApiError::NotFound(String::from("reloading is not enabled")))?;
+        if self.reloading.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            return Err(ApiError::Conflict(String::from("a reload is already running")));
+        }
+
+        let reloaded = reloader();
+        let mut reload_status = self.reload_status.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let reloaded = match reloaded {
+            Ok(locator) => {
+                let customers = locator.customers().len();
+                *self.locator.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(locator);
+
+                reload_status.customers = customers;
+                reload_status.reloads += 1;
+                reload_status.last_reload_at = Some(seconds_since_epoch(SystemTime::now()));
+                reload_status.last_error = None;
+                Ok(reload_status.clone())
+            }
+            Err(err) => {
+                reload_status.last_error = Some(err.clone());
+                Err(ApiError::ReloadFailed(err))
+            }
+        };
+        self.reloading.store(false, Ordering::SeqCst);
+
+        reloaded
+    }
+
+    /// Asks `run` to reload the customers in the background, returning the
+    /// status they are being served with until then.
+    ///
+    /// # Errors
+    ///
+    /// When there is no reloader.
+    pub fn request_reload(&self) -> Result<ReloadStatus, ApiError> {
+        if self.reloader.is_none() {
+            return Err(ApiError::NotFound(String::from("reloading is not enabled")));
+        }
+
+        // the status is taken before waking the reloader, which may be done
+        // with a small source before the answer would be built otherwise.
+        let status = self.reload_status();
+        let (ref requested, ref wakeup) = self.reload_requested;
+        *requested.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = true;
+        wakeup.notify_one();
+
+        Ok(ReloadStatus { reloading: true, ..status })
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Answers requests on `workers` threads until `shutdown` is called.
+    pub fn run(&self, workers: usize) {
+        thread::scope(|scope| {
+            if self.reloader.is_some() || self.watch.is_some() {
+                scope.spawn(|| self.reload_in_background());
+            }
+            if self.shutdown_on_signals {
+                signals::install();
+                scope.spawn(|| self.shut_down_on_signal());
+            }
+            for _ in 0..workers.max(1) {
+                scope.spawn(|| self.handle_requests());
+            }
+        });
+    }
+
+    /// Makes `run` return once the requests being answered are done.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+    }
+
+    // Shuts the server down once the process gets a SIGINT or a SIGTERM.
+    fn shut_down_on_signal(&self) {
+        while !self.shutdown.load(Ordering::SeqCst) {
+            if signals::received() {
+                self.shutdown();
+            }
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+    }
+
+    // Reloads whenever `request_reload` asks for it or the size or
+    // modification time of the watched file change. A file caught half
+    // written fails to reload and gets picked up on a later check.
+    fn reload_in_background(&self) {
+        let mut watch = self.watch.clone().map(|(path, interval, fingerprint)| (path, interval, fingerprint, Instant::now()));
+        let poll_interval = watch.as_ref().map_or(SHUTDOWN_POLL_INTERVAL, |watch| watch.1.min(SHUTDOWN_POLL_INTERVAL));
+        while !self.shutdown.load(Ordering::SeqCst) {
+            let mut reload = self.take_reload_request(poll_interval);
+            if let Some((ref path, interval, ref mut last_fingerprint, ref mut last_check)) = watch {
+                if last_check.elapsed() >= interval {
+                    *last_check = Instant::now();
+                    let current_fingerprint = file_fingerprint(path);
+                    if current_fingerprint.is_some() && current_fingerprint != *last_fingerprint {
+                        *last_fingerprint = current_fingerprint;
+                        reload = true;
+                    }
+                }
+            }
+
+            if reload {
+                match self.reload() {
+                    Ok(status) => eprintln!("Reloaded {} customers", status.customers),
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+        }
+    }
+
+    // Waits up to `timeout` for a reload request, returning whether there
+    // was one.
+    fn take_reload_request(&self, timeout: Duration) -> bool {
+        let (ref requested, ref wakeup) = self.reload_requested;
+        let requested = requested.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (mut requested, _) = wakeup.wait_timeout_while(requested, timeout, |requested| !*requested)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        mem::replace(&mut *requested, false)
+    }
+
+    fn handle_requests(&self) {
+        while !self.shutdown.load(Ordering::SeqCst) {
+            match self.server.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+                Ok(Some(request)) => self.handle(request),
+                Ok(None) => continue,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn handle(&self, request: Request) {
+        let (status_code, body) = match self.route(request.method(), request.url()) {
+            Ok(body) => (200, body),
+            Err(err) => (err.status_code(), to_json(&ErrorResponse { error: err.to_string() })),
+        };
+
+        let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+            .expect("static header is valid");
+        let response = Response::from_string(body)
+            .with_status_code(status_code)
+            .with_header(content_type);
+        if let Err(err) = request.respond(response) {
+            eprintln!("{}", err);
+        }
+    }
+
+    /// Answers a request given its method and URL returning the JSON body.
+    pub fn route(&self, method: &Method, url: &str) -> Result<String, ApiError> {
+        let (path, query) = parse_url(url);
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        // requests keep the locator they start with even if a reload swaps it meanwhile.
+        let locator = self.locator();
+        match (method, segments.as_slice()) {
+            (&Method::Post, ["reload"]) => self.request_reload().map(|status| to_json(&status)),
+            (&Method::Get, ["status"]) => Ok(to_json(&self.reload_status())),
+            (&Method::Get, ["customers", "within"]) => within(&locator, &query),
+            (&Method::Get, ["customers", "nearest"]) => nearest(&locator, &query),
+            (&Method::Get, ["customers", "bbox"]) => bounding_box(&locator, &query),
+            (&Method::Get, ["customers", "stats"]) => stats(&locator, &query),
+            (&Method::Get, ["customers", user_id]) => customer(&locator, user_id),
+            (_, ["reload"]) | (_, ["status"]) | (_, ["customers", _]) => Err(ApiError::MethodNotAllowed),
+            _ => Err(ApiError::NotFound(format!("no such endpoint {}", path))),
+        }
+    }
+}
+
+fn within<S: CustomerStore>(locator: &CustomerLocator<S>, query: &HashMap<String, String>) -> Result<String, ApiError> {
+    let location = location_parameter(query, "location")?;
+    let radius = Kilometers(parameter(query, "radius")?);
+
+    let mut customers = locator.locate_within(&radius, &location);
+    customers.sort_by_key(|located| located.customer.user_id);
+
+    Ok(to_json(&WithinResponse { location, radius, customers }))
+}
+
+fn nearest<S: CustomerStore>(locator: &CustomerLocator<S>, query: &HashMap<String, String>) -> Result<String, ApiError> {
+    let location = location_parameter(query, "location")?;
+    let k = parameter(query, "k")?;
+
+    Ok(to_json(&NearestResponse { location, k, customers: locator.nearest(k, &location) }))
+}
+
+fn bounding_box<S: CustomerStore>(locator: &CustomerLocator<S>, query: &HashMap<String, String>) -> Result<String, ApiError> {
+    let bounding_box = BoundingBox::new(parameter(query, "south_west")?, parameter(query, "north_east")?);
+    if bounding_box.south_west.latitude > bounding_box.north_east.latitude {
+        return Err(ApiError::BadRequest(String::from("south_west must be south of north_east")));
+    }
+
+    Ok(to_json(&BoundingBoxResponse { bounding_box, customers: locator.locate_in(&bounding_box) }))
+}
+
+fn stats<S: CustomerStore>(locator: &CustomerLocator<S>, query: &HashMap<String, String>) -> Result<String, ApiError> {
+    let location = location_parameter(query, "location")?;
+    let mut report = DistanceReport::new(location)
+        .with_model(locator.model())
+        .with_loaded(locator.customers().len(), locator.rejected());
+    if query.contains_key("bucket_width") {
+        let bucket_width: f64 = parameter(query, "bucket_width")?;
+        if bucket_width <= 0f64 || !bucket_width.is_finite() {
+            return Err(ApiError::BadRequest(String::from("bucket_width must be positive")));
+        }
+        report = report.with_bucket_width(Kilometers(bucket_width));
+    }
+
+    if query.contains_key("radius") {
+        let customers = locator.locate_within(&Kilometers(parameter(query, "radius")?), &location);
+        Ok(to_json(&report.report_located(&customers)))
+    } else {
+        Ok(to_json(&report.report(locator.customers())))
+    }
+}
+
+fn customer<S: CustomerStore>(locator: &CustomerLocator<S>, user_id: &str) -> Result<String, ApiError> {
+    let user_id = i64::from_str(user_id)
+        .map_err(|_| ApiError::NotFound(format!("no such endpoint /customers/{}", user_id)))?;
+
+    locator.find(user_id)
+        .map(|customer: Customer| to_json(&customer))
+        .ok_or_else(|| ApiError::NotFound(format!("no customer with user_id {}", user_id)))
+}
+
+fn file_fingerprint(path: &Path) -> Option<FileFingerprint> {
+    fs::metadata(path).and_then(|metadata| Ok((metadata.len(), metadata.modified()?))).ok()
+}
+
+fn seconds_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+// Returns the query parameter `name` as either coordinates or a place name.
+fn location_parameter(query: &HashMap<String, String>, name: &str) -> Result<Location, ApiError> {
+    let value = query.get(name)
+        .ok_or_else(|| ApiError::BadRequest(format!("missing parameter {}", name)))?;
+
+    Gazetteer::global()
+        .locate(value)
+        .map_err(|err| ApiError::BadRequest(format!("invalid parameter {}: {}", name, err)))
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("responses are always serializable")
+}
+
+// Returns the query parameter `name` parsed, failing with a `BadRequest`
+// when it's missing or can't be parsed.
+fn parameter<T>(query: &HashMap<String, String>, name: &str) -> Result<T, ApiError>
+    where T: FromStr,
+          T::Err: fmt::Display
+{
+    let value = query.get(name)
+        .ok_or_else(|| ApiError::BadRequest(format!("missing parameter {}", name)))?;
+
+    T::from_str(value).map_err(|err| ApiError::BadRequest(format!("invalid parameter {}: {}", name, err)))
+}
+
+// Splits `url` on its path and its decoded query parameters.
+fn parse_url(url: &str) -> (String, HashMap<String, String>) {
+    let mut parts = url.splitn(2, '?');
+    let path = percent_decode(parts.next().unwrap_or(""));
+    let query = parts.next()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            let name = percent_decode(&pair.next().unwrap_or("").replace('+', " "));
+            let value = percent_decode(&pair.next().unwrap_or("").replace('+', " "));
+            (name, value)
+        })
+        .collect();
+
+    (path, query)
+}
+
+fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = if bytes[index] == b'%' { encoded.get(index + 1..index + 3) } else { None };
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// There's no signal handling in std, so the handler is installed through
+// the C library, only recording that a signal came. A second signal after
+// it kills the process as usual, should the requests never finish.
+#[cfg(unix)]
+mod signals {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    const SIG_DFL: usize = 0;
+
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+    }
+
+    extern "C" fn on_signal(signum: i32) {
+        RECEIVED.store(true, Ordering::SeqCst);
+        unsafe {
+            signal(signum, SIG_DFL);
+        }
+    }
+
+    pub fn install() {
+        let handler = on_signal as extern "C" fn(i32) as usize;
+        unsafe {
+            signal(SIGINT, handler);
+            signal(SIGTERM, handler);
+        }
+    }
+
+    pub fn received() -> bool {
+        RECEIVED.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub fn install() {}
+
+    pub fn received() -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::prelude::*;
+    use std::net::TcpStream;
+    use std::process;
+    use std::sync::mpsc;
+    use serde_json::Value;
+    use customer_json_file::CustomerJsonFile;
+
+    const CUSTOMERS_JSON_LINE: &str = r#"{"user_id": 1, "name": "Nora Dempsey", "latitude": "53.1302756", "longitude": "-6.2397222"}"#;
+    const OTHER_CUSTOMERS_JSON_LINE: &str = r#"{"user_id": 2, "name": "Eoin Ahearn", "latitude": "54.0894797", "longitude": "-6.18671"}"#;
+
+    fn generate_locator() -> CustomerLocator {
+        CustomerLocator::new(CustomerList::from_vec(vec![
+            Customer::new(3, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
+            Customer::new(1, "Nora Dempsey", &Location::new(53.1302756, -6.2397222)),
+            Customer::new(2, "Eoin Ahearn", &Location::new(54.0894797, -6.18671))
+                .with_attribute("email", "eoin@example.com"),
+            Customer::new(4, "Stephen McArdle", &Location::new(53.038056, -7.653889)),
+        ]))
+    }
+
+    fn get(address: SocketAddr, path: &str) -> (u16, Value) {
+        request(address, "GET", path)
+    }
+
+    fn request(address: SocketAddr, method: &str, path: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", method, path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status_code = response[9..12].parse().unwrap();
+        let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+        (status_code, serde_json::from_str(body).unwrap())
+    }
+
+    fn temporary_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("customer_locator_server_{}_{}", process::id(), name))
+    }
+
+    fn reloading_server(path: &Path) -> CustomerServer {
+        let locator = CustomerLocator::from_source(CustomerJsonFile::new(path)).unwrap();
+        let reload_path = path.to_path_buf();
+
+        CustomerServer::bind("127.0.0.1:0", locator)
+            .unwrap()
+            .with_reloader(move || CustomerLocator::from_source(CustomerJsonFile::new(&reload_path)))
+    }
+
+    fn user_ids(body: &Value) -> Vec<i64> {
+        body["customers"].as_array().unwrap().iter().map(|customer| customer["user_id"].as_i64().unwrap()).collect()
+    }
+
+    #[test]
+    fn server_answers_every_endpoint_over_http() {
+        let server = Arc::new(CustomerServer::bind("127.0.0.1:0", generate_locator()).unwrap());
+        let address = server.local_addr().unwrap();
+        let running_server = server.clone();
+        let handle = thread::spawn(move || running_server.run(2));
+
+        let (status_code, body) = get(address, "/customers/within?location=53.3393,-6.2576841&radius=25");
+        assert_eq!(status_code, 200);
+        assert_eq!(user_ids(&body), vec![1, 3]);
+        assert_eq!(body["radius"], 25.0);
+        assert!(body["customers"][0]["distance"].as_f64().unwrap() < 25.0);
+
+        let (status_code, body) = get(address, "/customers/nearest?location=53.3393%2C-6.2576841&k=3");
+        assert_eq!(status_code, 200);
+        assert_eq!(user_ids(&body), vec![3, 1, 2]);
+
+        let (status_code, body) = get(address, "/customers/nearest?location=Dublin&k=1");
+        assert_eq!(status_code, 200);
+        assert_eq!(body["location"]["latitude"], 53.3393);
+
+        let (status_code, body) = get(address, "/customers/bbox?south_west=53.0,-7.0&north_east=54.5,-6.0");
+        assert_eq!(status_code, 200);
+        assert_eq!(user_ids(&body), vec![3, 1, 2]);
+
+        let (status_code, body) = get(address, "/customers/stats?location=dublin&radius=25&bucket_width=5");
+        assert_eq!(status_code, 200);
+        assert_eq!((body["loaded"].as_i64(), body["matches"].as_i64()), (Some(4), Some(2)));
+        assert_eq!(body["nearest"]["user_id"], 3);
+        assert_eq!(body["bucket_width"], 5.0);
+
+        let (status_code, body) = get(address, "/customers/stats?location=dublin");
+        assert_eq!(status_code, 200);
+        assert_eq!(body["matches"], 4);
+        assert_eq!(body["farthest"]["user_id"], 4);
+
+        let (status_code, body) = get(address, "/customers/2");
+        assert_eq!(status_code, 200);
+        assert_eq!(body["name"], "Eoin Ahearn");
+        assert_eq!(body["email"], "eoin@example.com");
+
+        let (status_code, body) = get(address, "/customers/42");
+        assert_eq!(status_code, 404);
+        assert_eq!(body["error"], "no customer with user_id 42");
+
+        server.shutdown();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn route_fails_with_bad_request_on_missing_or_invalid_parameters() {
+        let server = CustomerServer::bind("127.0.0.1:0", generate_locator()).unwrap();
+
+        assert_eq!(server.route(&Method::Get, "/customers/within?location=53.3393,-6.2576841"),
+                   Err(ApiError::BadRequest(String::from("missing parameter radius"))));
+        assert_eq!(server.route(&Method::Get, "/customers/nearest?location=53.3393&k=3"),
+                   Err(ApiError::BadRequest(String::from("invalid parameter location: Location parse error: missing element latitude,longitude on tuple"))));
+        assert_eq!(server.route(&Method::Get, "/customers/nearest?location=53.3393,-6.2&k=-1"),
+                   Err(ApiError::BadRequest(String::from("invalid parameter k: invalid digit found in string"))));
+        assert_eq!(server.route(&Method::Get, "/customers/stats?location=dublin&bucket_width=0"),
+                   Err(ApiError::BadRequest(String::from("bucket_width must be positive"))));
+        assert_eq!(server.route(&Method::Get, "/customers/bbox?south_west=55.0,-7.0&north_east=54.5,-6.0"),
+                   Err(ApiError::BadRequest(String::from("south_west must be south of north_east"))));
+    }
+
+    #[test]
+    fn route_fails_with_not_found_and_method_not_allowed() {
+        let server = CustomerServer::bind("127.0.0.1:0", generate_locator()).unwrap();
+
+        assert_eq!(server.route(&Method::Get, "/orders"), Err(ApiError::NotFound(String::from("no such endpoint /orders"))));
+        assert_eq!(server.route(&Method::Post, "/customers/1"), Err(ApiError::MethodNotAllowed));
+    }
+
+    #[test]
+    fn reload_swaps_the_customers_leaving_running_requests_with_the_old_ones() {
+        let path = temporary_path("reload.json");
+        fs::write(&path, CUSTOMERS_JSON_LINE).unwrap();
+        let server = reloading_server(&path);
+        let running_request_locator = server.locator();
+
+        fs::write(&path, format!("{}\n{}", CUSTOMERS_JSON_LINE, OTHER_CUSTOMERS_JSON_LINE)).unwrap();
+        let status = server.reload().unwrap();
+        assert_eq!(status.customers, 2);
+        assert_eq!(status.reloads, 1);
+        assert_eq!(status.last_error, None);
+
+        assert!(server.route(&Method::Get, "/customers/2").is_ok());
+        assert_eq!(running_request_locator.find(2), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reload_failing_keeps_serving_the_last_good_customers() {
+        let path = temporary_path("reload_malformed.json");
+        fs::write(&path, CUSTOMERS_JSON_LINE).unwrap();
+        let server = reloading_server(&path);
+
+        fs::write(&path, format!("{}\n{{\"user_id\": 2, \"name\"", CUSTOMERS_JSON_LINE)).unwrap();
+        match server.reload() {
+            Err(ApiError::ReloadFailed(_)) => (),
+            other => panic!("expected the reload to fail, got {:?}", other),
+        }
+
+        assert!(server.route(&Method::Get, "/customers/1").is_ok());
+        let status = server.reload_status();
+        assert_eq!(status.customers, 1);
+        assert_eq!(status.reloads, 0);
+        assert!(status.last_error.unwrap().starts_with("Customer Json file"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reload_fails_with_not_found_without_a_reloader() {
+        let server = CustomerServer::bind("127.0.0.1:0", generate_locator()).unwrap();
+
+        assert_eq!(server.reload(), Err(ApiError::NotFound(String::from("reloading is not enabled"))));
+        assert_eq!(server.route(&Method::Post, "/reload"), Err(ApiError::NotFound(String::from("reloading is not enabled"))));
+        assert_eq!(server.route(&Method::Get, "/reload"), Err(ApiError::MethodNotAllowed));
+    }
+
+    #[test]
+    fn reload_keeps_answering_the_status_while_the_reloader_runs() {
+        let path = temporary_path("reload_slow.json");
+        fs::write(&path, CUSTOMERS_JSON_LINE).unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let reload_path = path.clone();
+        let server = CustomerServer::bind("127.0.0.1:0", CustomerLocator::from_source(CustomerJsonFile::new(&path)).unwrap())
+            .unwrap()
+            .with_reloader(move || {
+                released.lock().unwrap().recv().unwrap();
+                CustomerLocator::from_source(CustomerJsonFile::new(&reload_path))
+            });
+
+        thread::scope(|scope| {
+            let reload = scope.spawn(|| server.reload());
+            while !server.reload_status().reloading {
+                thread::yield_now();
+            }
+
+            let status: Value = serde_json::from_str(&server.route(&Method::Get, "/status").unwrap()).unwrap();
+            assert_eq!((status["reloading"].as_bool(), status["reloads"].as_u64()), (Some(true), Some(0)));
+            assert_eq!(server.reload(), Err(ApiError::Conflict(String::from("a reload is already running"))));
+
+            release.send(()).unwrap();
+            assert_eq!(reload.join().unwrap().map(|status| status.reloads), Ok(1));
+        });
+        assert!(!server.reload_status().reloading);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reload_request_is_answered_at_once_and_reloads_in_the_background() {
+        let path = temporary_path("reload_request.json");
+        fs::write(&path, CUSTOMERS_JSON_LINE).unwrap();
+        let server = Arc::new(reloading_server(&path));
+        let address = server.local_addr().unwrap();
+        let running_server = server.clone();
+        let handle = thread::spawn(move || running_server.run(1));
+
+        fs::write(&path, format!("{}\n{}\n", CUSTOMERS_JSON_LINE, OTHER_CUSTOMERS_JSON_LINE)).unwrap();
+        let (status_code, body) = request(address, "POST", "/reload");
+        assert_eq!(status_code, 200);
+        assert_eq!(body["reloading"], true);
+        assert_eq!(body["customers"], 1);
+
+        let started = Instant::now();
+        while server.reload_status().reloads == 0 && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let (_, body) = get(address, "/status");
+        assert_eq!((body["customers"].as_u64(), body["reloads"].as_u64()), (Some(2), Some(1)));
+
+        server.shutdown();
+        handle.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn server_reloads_the_customers_when_the_watched_file_changes() {
+        let path = temporary_path("watch.json");
+        fs::write(&path, CUSTOMERS_JSON_LINE).unwrap();
+        let server = Arc::new(reloading_server(&path).with_watch(&path, Duration::from_millis(10)));
+        let address = server.local_addr().unwrap();
+        let running_server = server.clone();
+        let handle = thread::spawn(move || running_server.run(1));
+
+        fs::write(&path, format!("{}\n{}\n", CUSTOMERS_JSON_LINE, OTHER_CUSTOMERS_JSON_LINE)).unwrap();
+        let started = Instant::now();
+        while server.reload_status().reloads == 0 && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let (status_code, body) = get(address, "/customers/2");
+        assert_eq!(status_code, 200);
+        assert_eq!(body["name"], "Eoin Ahearn");
+        let (_, body) = get(address, "/status");
+        assert_eq!(body["customers"], 2);
+
+        server.shutdown();
+        handle.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn server_shuts_down_on_sigterm() {
+        let server = Arc::new(CustomerServer::bind("127.0.0.1:0", generate_locator()).unwrap().with_shutdown_on_signals());
+        let address = server.local_addr().unwrap();
+        let running_server = server.clone();
+        let (stopped, on_stop) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            running_server.run(1);
+            stopped.send(()).unwrap();
+        });
+
+        // the handler is installed before the workers start, so it is once one answers.
+        assert_eq!(get(address, "/status").0, 200);
+        let killed = process::Command::new("kill").arg("-TERM").arg(process::id().to_string()).status().unwrap();
+        assert!(killed.success());
+
+        on_stop.recv_timeout(Duration::from_secs(5)).expect("the server kept running after a SIGTERM");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn parse_url_decodes_the_path_and_query_parameters() {
+        let (path, query) = parse_url("/customers/within?location=53.3%2C-6.2&name=Jose+Narvaez&empty=");
+        assert_eq!(path, "/customers/within");
+        assert_eq!(query["location"], "53.3,-6.2");
+        assert_eq!(query["name"], "Jose Narvaez");
+        assert_eq!(query["empty"], "");
+    }
+}
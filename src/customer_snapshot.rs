@@ -0,0 +1,660 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module implementing a compact binary snapshot of a `CustomerList` so
+//! big customer files don't have to be parsed on every run.
+//!
+//! A snapshot is laid out as follows, all integers and floats being
+//! little endian and every section starting at an 8 bytes boundary so
+//! the records can be read straight from a memory mapped file:
+//!
+//! ```text
+//! +-----------------------------------------------------------------+
+//! | header (64 bytes)                                               |
+//! |   magic "CLSNAP\0\0" | version u32 | flags u32 | count u64      |
+//! |   source size u64 | source mtime secs i64 | mtime nanos u32     |
+//! |   padding u32 | source hash u64 | payload checksum u64          |
+//! +-----------------------------------------------------------------+
+//! | records (count * 40 bytes)                                      |
+//! |   user_id i64 | latitude f64 | longitude f64 | name offset u64  |
+//! |   name length u64                                               |
+//! +-----------------------------------------------------------------+
+//! | attributes (count * 16 bytes)                                   |
+//! |   offset u64 | length u64 (a JSON object, 0 length when empty)  |
+//! +-----------------------------------------------------------------+
+//! | strings length u64 | strings (UTF-8, padded to 8 bytes)         |
+//! +-----------------------------------------------------------------+
+//! ```
+//!
+//! The payload checksum covers everything after the header, the source
+//! fields identify the file the snapshot was built from so a stale
+//! snapshot is detected and rebuilt. Offsets into the strings section are
+//! 64 bits wide so names and attributes can take more than 4 GiB.
+//!
+
+use std::collections::hash_map::RandomState;
+use std::convert::{From, TryFrom};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::prelude::*;
+use std::io::{BufWriter, SeekFrom};
+use std::io::Error as IoError;
+use std::path::Path;
+use std::process;
+use std::str;
+use std::time::UNIX_EPOCH;
+
+use memmap2::Mmap;
+use serde_json;
+
+use customer::Customer;
+use customer::CustomerList;
+use customer_attribute::Attributes;
+use customer_datasource::CustomerDatasource;
+
+const MAGIC: &[u8; 8] = b"CLSNAP\0\0";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 64;
+const RECORD_LEN: usize = 40;
+const ATTRIBUTES_LEN: usize = 16;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// An incremental 64 bits FNV-1a hasher used for the snapshot checksum
+/// and the hash of the source file.
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub fn new() -> Fnv1a {
+        Fnv1a(FNV_OFFSET_BASIS)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Identifies the contents of the source file a snapshot was built from.
+///
+/// A snapshot is only reused when the size, modification time and hash of
+/// the source file are the same ones recorded on it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SourceFingerprint {
+    pub size: u64,
+    pub mtime_secs: i64,
+    pub mtime_nanos: u32,
+    pub hash: u64,
+}
+
+impl SourceFingerprint {
+    /// Builds the `SourceFingerprint` of the file at `path`. The whole file is
+    /// read to hash it, which is still way cheaper than parsing it.
+    pub fn of(path: &Path) -> Result<SourceFingerprint, IoError> {
+        let metadata = fs::metadata(path)?;
+        let (mtime_secs, mtime_nanos) = match metadata.modified()?.duration_since(UNIX_EPOCH) {
+            Ok(since_epoch) => (since_epoch.as_secs() as i64, since_epoch.subsec_nanos()),
+            Err(before_epoch) => (-(before_epoch.duration().as_secs() as i64), before_epoch.duration().subsec_nanos()),
+        };
+
+        let mut file = File::open(path)?;
+        let mut hasher = Fnv1a::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+
+        Ok(SourceFingerprint {
+            size: metadata.len(),
+            mtime_secs,
+            mtime_nanos,
+            hash: hasher.finish(),
+        })
+    }
+}
+
+/// An error encapsulating the things that can go wrong when writing or
+/// reading a snapshot.
+#[derive(Debug)]
+pub enum CustomerSnapshotError {
+    Io(IoError),
+    Corrupt(String),
+}
+
+impl fmt::Display for CustomerSnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CustomerSnapshotError::Io(ref err) => write!(f, "Customer snapshot IO error: {}", err),
+            CustomerSnapshotError::Corrupt(ref reason) => write!(f, "Customer snapshot is corrupt: {}", reason),
+        }
+    }
+}
+
+impl error::Error for CustomerSnapshotError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CustomerSnapshotError::Io(ref err) => Some(err),
+            CustomerSnapshotError::Corrupt(_) => None,
+        }
+    }
+}
+
+impl From<IoError> for CustomerSnapshotError {
+    fn from(err: IoError) -> Self {
+        CustomerSnapshotError::Io(err)
+    }
+}
+
+fn padding(len: usize) -> usize {
+    (8 - len % 8) % 8
+}
+
+// A writer that keeps the checksum of everything written through it.
+struct ChecksumWriter<W> {
+    inner: W,
+    checksum: Fnv1a,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        let written = self.inner.write(buf)?;
+        self.checksum.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.inner.flush()
+    }
+}
+
+// A writer that only counts the bytes written to it, used to know the
+// length of the JSON encoded attributes without keeping them around.
+struct CountingWriter(u64);
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        Ok(())
+    }
+}
+
+fn write_attributes<W: Write>(writer: &mut W, attributes: &Attributes) -> Result<(), IoError> {
+    if !attributes.is_empty() {
+        serde_json::to_writer(writer, attributes)?;
+    }
+    Ok(())
+}
+
+/// Writes `customers` to `writer` as a snapshot of a source identified by
+/// `fingerprint`.
+///
+/// The sections are streamed to `writer` as they are encoded, the payload
+/// checksum is computed on the way and written on the header at the end,
+/// which is why `writer` has to be seekable. The customers are walked
+/// twice, first to lay out the strings section and then to write it.
+///
+/// # Examples
+///
+/// ```
+/// let mut snapshot = Cursor::new(Vec::new());
+/// write_snapshot(&mut snapshot, &customers, &SourceFingerprint::of(Path::new("customers.json")).unwrap()).unwrap();
+/// let (decoded, _) = decode_snapshot(snapshot.get_ref()).unwrap();
+/// assert_eq!(decoded, customers);
+/// ```
+pub fn write_snapshot<W: Write + Seek>(writer: &mut W,
+                                       customers: &CustomerList,
+                                       fingerprint: &SourceFingerprint)
+                                       -> Result<(), IoError> {
+    let start = writer.stream_position()?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?; // flags, reserved
+    writer.write_all(&(customers.len() as u64).to_le_bytes())?;
+    writer.write_all(&fingerprint.size.to_le_bytes())?;
+    writer.write_all(&fingerprint.mtime_secs.to_le_bytes())?;
+    writer.write_all(&fingerprint.mtime_nanos.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?; // padding
+    writer.write_all(&fingerprint.hash.to_le_bytes())?;
+    writer.write_all(&0u64.to_le_bytes())?; // payload checksum, written last
+
+    let mut payload = ChecksumWriter { inner: &mut *writer, checksum: Fnv1a::new() };
+    let mut attributes_index = Vec::with_capacity(customers.len());
+    let mut strings_len = 0u64;
+    for customer in customers {
+        payload.write_all(&customer.user_id.to_le_bytes())?;
+        payload.write_all(&customer.latitude.to_le_bytes())?;
+        payload.write_all(&customer.longitude.to_le_bytes())?;
+        payload.write_all(&strings_len.to_le_bytes())?;
+        payload.write_all(&(customer.name.len() as u64).to_le_bytes())?;
+        strings_len += customer.name.len() as u64;
+
+        let mut attributes_len = CountingWriter(0);
+        write_attributes(&mut attributes_len, &customer.attributes)?;
+        attributes_index.push((strings_len, attributes_len.0));
+        strings_len += attributes_len.0;
+    }
+
+    for (offset, len) in attributes_index {
+        payload.write_all(&offset.to_le_bytes())?;
+        payload.write_all(&len.to_le_bytes())?;
+    }
+
+    payload.write_all(&strings_len.to_le_bytes())?;
+    for customer in customers {
+        payload.write_all(customer.name.as_bytes())?;
+        write_attributes(&mut payload, &customer.attributes)?;
+    }
+    payload.write_all(&[0u8; 8][..padding(strings_len as usize)])?;
+
+    let checksum = payload.checksum.finish();
+    let end = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(start + HEADER_LEN as u64 - 8))?;
+    writer.write_all(&checksum.to_le_bytes())?;
+    writer.seek(SeekFrom::Start(end))?;
+
+    Ok(())
+}
+
+// A cursor over the snapshot bytes that fails instead of panicking when
+// the snapshot is truncated.
+struct Reader<'s> {
+    bytes: &'s [u8],
+    position: usize,
+}
+
+impl<'s> Reader<'s> {
+    fn take(&mut self, len: usize) -> Result<&'s [u8], CustomerSnapshotError> {
+        let end = self.position.checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| CustomerSnapshotError::Corrupt(String::from("unexpected end of snapshot")))?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, CustomerSnapshotError> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, CustomerSnapshotError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn i64(&mut self) -> Result<i64, CustomerSnapshotError> {
+        Ok(self.u64()? as i64)
+    }
+
+    fn f64(&mut self) -> Result<f64, CustomerSnapshotError> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+}
+
+/// Decodes only the header of a snapshot returning the number of customers
+/// and the `SourceFingerprint` it was built from.
+pub fn decode_snapshot_header(snapshot: &[u8]) -> Result<(usize, SourceFingerprint), CustomerSnapshotError> {
+    let mut reader = Reader { bytes: snapshot, position: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(CustomerSnapshotError::Corrupt(String::from("not a customer snapshot")));
+    }
+
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(CustomerSnapshotError::Corrupt(format!("unsupported snapshot version {}", version)));
+    }
+
+    let _flags = reader.u32()?;
+    let count = reader.u64()? as usize;
+    let size = reader.u64()?;
+    let mtime_secs = reader.i64()?;
+    let mtime_nanos = reader.u32()?;
+    let _padding = reader.u32()?;
+    let hash = reader.u64()?;
+
+    Ok((count, SourceFingerprint { size, mtime_secs, mtime_nanos, hash }))
+}
+
+/// Decodes a snapshot built with `encode_snapshot` returning the customers
+/// along with the `SourceFingerprint` of the file they were loaded from.
+///
+/// # Errors
+///
+/// Returns `CustomerSnapshotError::Corrupt` when the magic, version or
+/// checksum don't match or the snapshot is truncated.
+pub fn decode_snapshot(snapshot: &[u8]) -> Result<(CustomerList, SourceFingerprint), CustomerSnapshotError> {
+    let (count, fingerprint) = decode_snapshot_header(snapshot)?;
+
+    let mut reader = Reader { bytes: snapshot, position: HEADER_LEN - 8 };
+    let expected_checksum = reader.u64()?;
+    let mut checksum = Fnv1a::new();
+    checksum.update(&snapshot[HEADER_LEN..]);
+    if checksum.finish() != expected_checksum {
+        return Err(CustomerSnapshotError::Corrupt(String::from("checksum mismatch")));
+    }
+
+    let invalid_count = || CustomerSnapshotError::Corrupt(String::from("invalid customer count"));
+    let records_len = count.checked_mul(RECORD_LEN).ok_or_else(invalid_count)?;
+    let attributes_len = count.checked_mul(ATTRIBUTES_LEN).ok_or_else(invalid_count)?;
+    let mut records = Reader { bytes: reader.take(records_len)?, position: 0 };
+    let mut attributes = Reader { bytes: reader.take(attributes_len)?, position: 0 };
+    let strings_len = reader.u64()? as usize;
+    let strings = reader.take(strings_len)?;
+
+    let string_at = |offset: u64, len: u64| -> Result<&[u8], CustomerSnapshotError> {
+        let out_of_bounds = |_| CustomerSnapshotError::Corrupt(String::from("string out of bounds"));
+        let mut strings = Reader { bytes: strings, position: usize::try_from(offset).map_err(out_of_bounds)? };
+        strings.take(usize::try_from(len).map_err(out_of_bounds)?)
+    };
+
+    let mut customers = Vec::with_capacity(count);
+    for _ in 0..count {
+        let user_id = records.i64()?;
+        let latitude = records.f64()?;
+        let longitude = records.f64()?;
+        let name = string_at(records.u64()?, records.u64()?)?;
+        let name = str::from_utf8(name)
+            .map_err(|err| CustomerSnapshotError::Corrupt(format!("invalid customer name: {}", err)))?
+            .to_owned();
+
+        let encoded_attributes = string_at(attributes.u64()?, attributes.u64()?)?;
+        let attributes: Attributes = if encoded_attributes.is_empty() {
+            Attributes::new()
+        } else {
+            serde_json::from_slice(encoded_attributes)
+                .map_err(|err| CustomerSnapshotError::Corrupt(format!("invalid customer attributes: {}", err)))?
+        };
+
+        customers.push(Customer { user_id, name, latitude, longitude, attributes });
+    }
+
+    Ok((CustomerList::from_vec(customers), fingerprint))
+}
+
+/// Writes a snapshot of `customers` to the file at `path`.
+///
+/// The snapshot is written to a temporary file next to `path` and then
+/// renamed so readers never see a half written snapshot. The temporary file
+/// name is unique to the process and the call so concurrent writers of the
+/// same snapshot don't step on each other.
+pub fn write_snapshot_file(path: &Path,
+                           customers: &CustomerList,
+                           fingerprint: &SourceFingerprint)
+                           -> Result<(), CustomerSnapshotError> {
+    let random = RandomState::new().build_hasher().finish();
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(format!(".{}.{:016x}.tmp", process::id(), random));
+
+    let written = File::create(&temporary_path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write_snapshot(&mut writer, customers, fingerprint)?;
+        writer.flush()
+    });
+    if let Err(err) = written.and_then(|_| fs::rename(&temporary_path, path)) {
+        let _ = fs::remove_file(&temporary_path);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// A `CustomerDatasource` that caches the customers of another file based
+/// datasource on a snapshot.
+///
+/// The first time the customers are loaded from `source` and written to
+/// the snapshot at `cache_path`. From then on they are loaded from the
+/// snapshot as long as the `SourceFingerprint` of `source_path` is the one
+/// recorded on it, otherwise they are loaded from `source` again and the
+/// snapshot is rebuilt. A corrupt snapshot is treated as a stale one.
+///
+/// # Examples
+///
+/// ```
+/// let path = Path::new("customers.json");
+/// let cached = CustomerSnapshotCache::new(CustomerJsonFile::new(path), path, Path::new("customers.snap"));
+///
+/// // Errors handling omitted for brevity
+/// let locator = CustomerLocator::from_source(cached).unwrap();
+/// ```
+pub struct CustomerSnapshotCache<'f, S> {
+    source: S,
+    source_path: &'f Path,
+    cache_path: &'f Path,
+}
+
+impl<'f, S: CustomerDatasource> CustomerSnapshotCache<'f, S> {
+    pub fn new(source: S, source_path: &'f Path, cache_path: &'f Path) -> CustomerSnapshotCache<'f, S> {
+        CustomerSnapshotCache { source, source_path, cache_path }
+    }
+
+    // Returns the customers on the snapshot if it's fresh, `None` if it's
+    // missing, stale or corrupt.
+    fn cached_customers(&self, fingerprint: &SourceFingerprint) -> Option<CustomerList> {
+        let file = File::open(self.cache_path).ok()?;
+        // Safe as long as the snapshot is not modified while mapped, it's only
+        // ever replaced by renaming a new one over it.
+        let snapshot = unsafe { Mmap::map(&file).ok()? };
+        match decode_snapshot_header(&snapshot) {
+            Ok((_, ref cached_fingerprint)) if cached_fingerprint == fingerprint => (),
+            _ => return None,
+        }
+
+        decode_snapshot(&snapshot).ok().map(|(customers, _)| customers)
+    }
+}
+
+/// An error encapsulating the things that can go wrong when loading the
+/// customers through a `CustomerSnapshotCache`. The errors of the cached
+/// datasource are kept apart from the snapshot ones.
+#[derive(Debug)]
+pub enum CustomerSnapshotCacheError<E> {
+    Source(E),
+    Snapshot(CustomerSnapshotError),
+}
+
+impl<E: fmt::Display> fmt::Display for CustomerSnapshotCacheError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CustomerSnapshotCacheError::Source(ref err) => write!(f, "{}", err),
+            CustomerSnapshotCacheError::Snapshot(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for CustomerSnapshotCacheError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CustomerSnapshotCacheError::Source(ref err) => Some(err),
+            CustomerSnapshotCacheError::Snapshot(ref err) => Some(err),
+        }
+    }
+}
+
+impl<'f, S> CustomerDatasource for CustomerSnapshotCache<'f, S>
+    where S: CustomerDatasource,
+          S::Err: 'static
+{
+    type Err = CustomerSnapshotCacheError<S::Err>;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let fingerprint = SourceFingerprint::of(self.source_path)
+            .map_err(|err| CustomerSnapshotCacheError::Snapshot(CustomerSnapshotError::Io(err)))?;
+
+        if let Some(customers) = self.cached_customers(&fingerprint) {
+            return Ok(customers);
+        }
+
+        let customers = self.source.customers().map_err(CustomerSnapshotCacheError::Source)?;
+        // The customers are good even if they can't be cached, they'll just be
+        // loaded from the source again next time.
+        if let Err(err) = write_snapshot_file(self.cache_path, &customers, &fingerprint) {
+            eprintln!("Can't write the customer snapshot {}: {}", self.cache_path.display(), err);
+        }
+
+        Ok(customers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Cursor;
+    use std::process;
+    use customer_json_file::CustomerJsonFile;
+    use location::Location;
+
+    const CUSTOMERS_ATTRIBUTES_JSON_FILE: &str = "tests/fixtures/customers_attributes.json";
+
+    fn generate_customer_list() -> CustomerList {
+        CustomerList::from_vec(vec![
+            Customer::new(1, "Jose Narvaez", &Location::new(52.986375, -6.043701))
+                .with_attribute("email", "jose@example.com"),
+            Customer::new(2, "Carlos Narváez", &Location::new(51.92893, -10.27699)),
+        ])
+    }
+
+    fn encode_snapshot(customers: &CustomerList, fingerprint: &SourceFingerprint) -> Vec<u8> {
+        let mut snapshot = Cursor::new(Vec::new());
+        write_snapshot(&mut snapshot, customers, fingerprint).unwrap();
+        snapshot.into_inner()
+    }
+
+    fn temporary_path(name: &str) -> ::std::path::PathBuf {
+        env::temp_dir().join(format!("customer_locator_{}_{}", process::id(), name))
+    }
+
+    #[test]
+    fn decode_snapshot_returns_what_encode_snapshot_was_given() {
+        let fingerprint = SourceFingerprint { size: 10, mtime_secs: 20, mtime_nanos: 30, hash: 40 };
+        let snapshot = encode_snapshot(&generate_customer_list(), &fingerprint);
+
+        assert_eq!(snapshot.len() % 8, 0);
+        assert_eq!(decode_snapshot(&snapshot).unwrap(), (generate_customer_list(), fingerprint));
+    }
+
+    #[test]
+    fn decode_snapshot_detects_corruption() {
+        let mut snapshot = encode_snapshot(&generate_customer_list(), &SourceFingerprint::default());
+        let last = snapshot.len() - 1;
+        snapshot[HEADER_LEN + 1] ^= 0xff;
+
+        match decode_snapshot(&snapshot) {
+            Err(CustomerSnapshotError::Corrupt(reason)) => assert_eq!(reason, "checksum mismatch"),
+            other => panic!("expected a corrupt snapshot error, got {:?}", other),
+        }
+
+        match decode_snapshot(&snapshot[..last]) {
+            Err(CustomerSnapshotError::Corrupt(_)) => (),
+            other => panic!("expected a corrupt snapshot error, got {:?}", other),
+        }
+
+        match decode_snapshot(b"not a snapshot at all") {
+            Err(CustomerSnapshotError::Corrupt(_)) => (),
+            other => panic!("expected a corrupt snapshot error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encode_snapshot_writes_64_bits_string_offsets() {
+        let snapshot = encode_snapshot(&generate_customer_list(), &SourceFingerprint::default());
+        let u64_at = |position: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&snapshot[position..position + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        let (first_record, second_record) = (HEADER_LEN, HEADER_LEN + RECORD_LEN);
+        let first_attributes = HEADER_LEN + 2 * RECORD_LEN;
+
+        assert_eq!((u64_at(first_record + 24), u64_at(first_record + 32)), (0, 12));
+        assert_eq!(u64_at(first_attributes), 12);
+        assert_eq!(u64_at(second_record + 24), 12 + u64_at(first_attributes + 8));
+        assert_eq!(u64_at(second_record + 32), "Carlos Narváez".len() as u64);
+    }
+
+    #[test]
+    fn decode_snapshot_rejects_other_versions() {
+        let mut snapshot = encode_snapshot(&generate_customer_list(), &SourceFingerprint::default());
+        snapshot[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&1u32.to_le_bytes());
+
+        match decode_snapshot(&snapshot) {
+            Err(CustomerSnapshotError::Corrupt(reason)) => assert_eq!(reason, "unsupported snapshot version 1"),
+            other => panic!("expected a corrupt snapshot error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn snapshot_cache_writes_the_snapshot_and_reuses_it_while_the_source_is_unchanged() {
+        let source_path = Path::new(CUSTOMERS_ATTRIBUTES_JSON_FILE);
+        let cache_path = temporary_path("cache_reuse.snap");
+        let _ = fs::remove_file(&cache_path);
+
+        let cache = CustomerSnapshotCache::new(CustomerJsonFile::new(source_path), source_path, &cache_path);
+        let loaded_customers = cache.customers().unwrap();
+        let (cached_customers, fingerprint) = decode_snapshot(&fs::read(&cache_path).unwrap()).unwrap();
+        assert_eq!(cached_customers, loaded_customers);
+        assert_eq!(fingerprint, SourceFingerprint::of(source_path).unwrap());
+
+        // a snapshot with different customers but the same fingerprint is reused as is.
+        write_snapshot_file(&cache_path, &generate_customer_list(), &fingerprint).unwrap();
+        assert_eq!(cache.customers().unwrap(), generate_customer_list());
+
+        // while a stale one is rebuilt from the source.
+        let stale_fingerprint = SourceFingerprint { hash: fingerprint.hash + 1, ..fingerprint };
+        write_snapshot_file(&cache_path, &generate_customer_list(), &stale_fingerprint).unwrap();
+        assert_eq!(cache.customers().unwrap(), loaded_customers);
+
+        fs::remove_file(&cache_path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_cache_returns_the_customers_even_if_the_snapshot_cant_be_written() {
+        let source_path = Path::new(CUSTOMERS_ATTRIBUTES_JSON_FILE);
+        let cache_path = temporary_path("missing_directory").join("cache.snap");
+
+        let cache = CustomerSnapshotCache::new(CustomerJsonFile::new(source_path), source_path, &cache_path);
+        assert_eq!(cache.customers().unwrap(), CustomerJsonFile::new(source_path).customers().unwrap());
+        assert!(!cache_path.exists());
+    }
+
+    #[test]
+    fn write_snapshot_file_leaves_no_temporary_files_behind() {
+        let directory = temporary_path("no_leftovers");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir(&directory).unwrap();
+        let cache_path = directory.join("cache.snap");
+
+        write_snapshot_file(&cache_path, &generate_customer_list(), &SourceFingerprint::default()).unwrap();
+        write_snapshot_file(&cache_path, &generate_customer_list(), &SourceFingerprint::default()).unwrap();
+
+        let files: Vec<_> = fs::read_dir(&directory).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(files, vec![::std::ffi::OsString::from("cache.snap")]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
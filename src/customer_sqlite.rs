@@ -0,0 +1,532 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::From;
+use std::error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::Error as SqliteError;
+use rusqlite::{params_from_iter, Connection, OpenFlags, Row};
+
+use customer::{Customer, CustomerList};
+use customer_attribute::AttributeValue;
+use customer_datasource::{ColumnMapping, CustomerDatasource, SqlSource};
+use location::{BoundingBox, Location};
+
+const DEFAULT_TABLE: &str = "customers";
+
+/// Struct abstracting the idea of a SQLite database containing customer data.
+///
+/// It's an implementation of the `CustomerDatasource` trait reading the
+/// customers from a table, `customers` by default, or from the rows of a
+/// query. The columns holding each customer field are set with a
+/// `ColumnMapping`, the rest of them are read as extra attributes (`BLOB`s
+/// are skipped).
+///
+/// A `BoundingBox` given with `within` is pushed down into the `WHERE`
+/// clause so an index on the coordinates, like the one `write_customers`
+/// creates, spares a full scan of big databases. Coordinates on columns
+/// SQLite may keep as text, and on the rows of queries, are cast to numbers
+/// first, which can't use the index.
+///
+/// # Examples
+///
+/// ```
+/// let database = CustomerSqlite::new(Path::new("customers.db"))
+///     .with_source(SqlSource::Table(String::from("crm_customers")))
+///     .with_columns(ColumnMapping::from_str("user_id=id")?)
+///     .within(BoundingBox::around(&location, &radius));
+///
+/// let locator = CustomerLocator::from_source(database)?;
+/// let customers = locator.locate_within(&radius, &location);
+///
+/// // storing the located customers on another table.
+/// CustomerSqlite::new(Path::new("reports.db"))
+///     .with_source(SqlSource::Table(String::from("near_dublin")))
+///     .write_customers(&customers)?;
+/// ```
+///
+/// # Errors
+///
+/// Besides the errors SQLite itself reports, reading fails when a mapped
+/// column is missing from the rows or holds a value of the wrong type, and
+/// writing fails when the source is a query instead of a table.
+pub struct CustomerSqlite<'f> {
+    database_path: &'f Path,
+    source: SqlSource,
+    columns: ColumnMapping,
+    bounding_box: Option<BoundingBox>,
+}
+
+/// An error encapsulating the things that can go wrong when reading
+/// customers from or writing them to a SQLite database.
+#[derive(Debug)]
+pub enum CustomerSqliteError {
+    Sqlite(SqliteError),
+    MissingColumn(String),
+    InvalidValue(String),
+    NotATable,
+}
+
+impl fmt::Display for CustomerSqliteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CustomerSqliteError::Sqlite(ref err) => write!(f, "Customer SQLite error: {}", err),
+            CustomerSqliteError::MissingColumn(ref column) => write!(f, "Customer SQLite error: no column '{}' in the rows", column),
+            CustomerSqliteError::InvalidValue(ref reason) => write!(f, "Customer SQLite invalid value: {}", reason),
+            CustomerSqliteError::NotATable => write!(f, "Customer SQLite error: customers can only be written to a table, not a query"),
+        }
+    }
+}
+
+impl error::Error for CustomerSqliteError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CustomerSqliteError::Sqlite(ref err) => Some(err),
+            CustomerSqliteError::MissingColumn(_) |
+            CustomerSqliteError::InvalidValue(_) |
+            CustomerSqliteError::NotATable => None,
+        }
+    }
+}
+
+impl From<SqliteError> for CustomerSqliteError {
+    fn from(err: SqliteError) -> Self {
+        CustomerSqliteError::Sqlite(err)
+    }
+}
+
+impl<'f> CustomerSqlite<'f> {
+    /// Constructs a new `CustomerSqlite` reading the `customers` table of the
+    /// database at `database_path`.
+    pub fn new(database_path: &'f Path) -> CustomerSqlite<'f> {
+        CustomerSqlite {
+            database_path,
+            source: SqlSource::Table(String::from(DEFAULT_TABLE)),
+            columns: ColumnMapping::default(),
+            bounding_box: None,
+        }
+    }
+
+    /// Sets the table or the query the customers are read from.
+    pub fn with_source(mut self, source: SqlSource) -> CustomerSqlite<'f> {
+        self.source = source;
+        self
+    }
+
+    /// Sets the columns holding each of the customer fields.
+    pub fn with_columns(mut self, columns: ColumnMapping) -> CustomerSqlite<'f> {
+        self.columns = columns;
+        self
+    }
+
+    /// Only reads the customers inside `bounding_box`.
+    pub fn within(mut self, bounding_box: BoundingBox) -> CustomerSqlite<'f> {
+        self.bounding_box = Some(bounding_box);
+        self
+    }
+
+    /// Writes `customers` to the table of the source, creating it and adding
+    /// a column for each of their attributes when missing. Customers already
+    /// on the table are replaced. Returns the number of customers written.
+    ///
+    /// # Errors
+    ///
+    /// When the source is a query or the database can't be written.
+    pub fn write_customers(&self, customers: &CustomerList) -> Result<usize, CustomerSqliteError> {
+        let table = match self.source {
+            SqlSource::Table(ref table) => table,
+            SqlSource::Query(_) => return Err(CustomerSqliteError::NotATable),
+        };
+        let attributes: BTreeSet<&String> = customers.iter()
+            .flat_map(|customer| customer.attributes.keys())
+            .filter(|name| !self.columns.columns().contains(&name.as_str()))
+            .collect();
+
+        let mut connection = Connection::open(self.database_path)?;
+        let transaction = connection.transaction()?;
+        transaction.execute(&format!("CREATE TABLE IF NOT EXISTS {} ({} INTEGER PRIMARY KEY, {} TEXT NOT NULL, {} REAL NOT NULL, {} REAL NOT NULL)",
+                                     quote(table),
+                                     quote(&self.columns.user_id),
+                                     quote(&self.columns.name),
+                                     quote(&self.columns.latitude),
+                                     quote(&self.columns.longitude)),
+                             [])?;
+        transaction.execute(&format!("CREATE INDEX IF NOT EXISTS {} ON {} ({}, {})",
+                                     quote(&format!("{}_location", table)),
+                                     quote(table),
+                                     quote(&self.columns.latitude),
+                                     quote(&self.columns.longitude)),
+                             [])?;
+
+        let existing_columns = table_columns(&transaction, table)?;
+        for attribute in attributes.iter().filter(|attribute| !existing_columns.contains(attribute.as_str())) {
+            transaction.execute(&format!("ALTER TABLE {} ADD COLUMN {}", quote(table), quote(attribute)), [])?;
+        }
+
+        let mut columns: Vec<&str> = self.columns.columns().to_vec();
+        columns.extend(attributes.iter().map(|attribute| attribute.as_str()));
+        let insert = format!("INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+                             quote(table),
+                             columns.iter().map(|column| quote(column)).collect::<Vec<String>>().join(", "),
+                             vec!["?"; columns.len()].join(", "));
+        {
+            let mut statement = transaction.prepare(&insert)?;
+            for customer in customers {
+                let mut values = vec![Value::Integer(customer.user_id),
+                                      Value::Text(customer.name.clone()),
+                                      Value::Real(customer.latitude),
+                                      Value::Real(customer.longitude)];
+                values.extend(attributes.iter().map(|attribute| sqlite_value(customer.attribute(attribute))));
+                statement.execute(params_from_iter(values))?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(customers.len())
+    }
+
+    // The statement reading the customers along with its parameters, the
+    // bounding box prefilter if there's one.
+    fn select_statement(&self, connection: &Connection) -> Result<(String, Vec<f64>), SqliteError> {
+        let from = match self.source {
+            SqlSource::Table(ref table) => quote(table),
+            SqlSource::Query(ref query) if self.bounding_box.is_none() => return Ok((query.clone(), Vec::new())),
+            SqlSource::Query(ref query) => format!("({})", query.trim().trim_end_matches(';')),
+        };
+        let bounding_box = match self.bounding_box {
+            Some(bounding_box) => bounding_box,
+            None => return Ok((format!("SELECT * FROM {}", from), Vec::new())),
+        };
+
+        // text never compares as a number, so columns that may hold the
+        // coordinates as text are compared cast.
+        let numeric = match self.source {
+            SqlSource::Table(ref table) => {
+                let types = column_types(connection, table)?;
+                let is_numeric = |column: &str| types.get(column).is_some_and(|declared| has_numeric_affinity(declared));
+                is_numeric(&self.columns.latitude) && is_numeric(&self.columns.longitude)
+            }
+            SqlSource::Query(_) => false,
+        };
+        let as_number = |column: &str| if numeric { quote(column) } else { format!("CAST({} AS REAL)", quote(column)) };
+        let latitude = as_number(&self.columns.latitude);
+        let longitude = as_number(&self.columns.longitude);
+        // a box crossing the antimeridian has its west edge further east than its east one.
+        let longitude_condition = if bounding_box.south_west.longitude <= bounding_box.north_east.longitude {
+            format!("{} BETWEEN ?3 AND ?4", longitude)
+        } else {
+            format!("({} >= ?3 OR {} <= ?4)", longitude, longitude)
+        };
+        let statement = format!("SELECT * FROM {} WHERE {} BETWEEN ?1 AND ?2 AND {}", from, latitude, longitude_condition);
+        let parameters = vec![bounding_box.south_west.latitude,
+                              bounding_box.north_east.latitude,
+                              bounding_box.south_west.longitude,
+                              bounding_box.north_east.longitude];
+
+        Ok((statement, parameters))
+    }
+}
+
+impl<'f> CustomerDatasource for CustomerSqlite<'f> {
+    type Err = CustomerSqliteError;
+
+    fn customers(&self) -> Result<CustomerList, Self::Err> {
+        let connection = Connection::open_with_flags(self.database_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let (sql, parameters) = self.select_statement(&connection)?;
+        let mut statement = connection.prepare(&sql)?;
+        let names: Vec<String> = statement.column_names().into_iter().map(String::from).collect();
+
+        let index_of = |column: &str| {
+            names.iter()
+                .position(|name| name == column)
+                .ok_or_else(|| CustomerSqliteError::MissingColumn(column.to_string()))
+        };
+        let user_id = index_of(&self.columns.user_id)?;
+        let name = index_of(&self.columns.name)?;
+        let latitude = index_of(&self.columns.latitude)?;
+        let longitude = index_of(&self.columns.longitude)?;
+
+        let mut customers = Vec::new();
+        let mut rows = statement.query(params_from_iter(parameters))?;
+        while let Some(row) = rows.next()? {
+            let location = Location::new(coordinate(row, latitude, &names)?, coordinate(row, longitude, &names)?);
+            let mut customer = Customer::new(row.get(user_id)?, &row.get::<_, String>(name)?, &location);
+            for (index, column) in names.iter().enumerate() {
+                if index == user_id || index == name || index == latitude || index == longitude {
+                    continue;
+                }
+                if let Some(value) = attribute_value(row.get_ref(index)?) {
+                    customer.attributes.insert(column.clone(), value);
+                }
+            }
+            customers.push(customer);
+        }
+
+        Ok(CustomerList::from_vec(customers))
+    }
+}
+
+// Quotes `identifier` to be used as a table or column name.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn table_columns(connection: &Connection, table: &str) -> Result<HashSet<String>, SqliteError> {
+    let mut statement = connection.prepare(&format!("PRAGMA table_info({})", quote(table)))?;
+    let columns = statement.query_map([], |row| row.get::<_, String>("name"))?;
+    columns.collect()
+}
+
+// The declared types of the columns of `table` keyed by their name.
+fn column_types(connection: &Connection, table: &str) -> Result<HashMap<String, String>, SqliteError> {
+    let mut statement = connection.prepare(&format!("PRAGMA table_info({})", quote(table)))?;
+    let columns = statement.query_map([], |row| Ok((row.get::<_, String>("name")?, row.get::<_, String>("type")?)))?;
+    columns.collect()
+}
+
+// Whether a column declared `declared_type` stores the numbers it's given as
+// text as numbers, following the type affinity rules of SQLite.
+fn has_numeric_affinity(declared_type: &str) -> bool {
+    let declared_type = declared_type.to_uppercase();
+    if declared_type.contains("INT") {
+        return true;
+    }
+
+    let text = ["CHAR", "CLOB", "TEXT"].iter().any(|text| declared_type.contains(text));
+    !text && !declared_type.contains("BLOB") && !declared_type.is_empty()
+}
+
+// Customer exports encode the coordinates as strings too, like the JSON ones.
+fn coordinate(row: &Row, index: usize, names: &[String]) -> Result<f64, CustomerSqliteError> {
+    let invalid = || CustomerSqliteError::InvalidValue(format!("column '{}' is not a coordinate", names[index]));
+    match row.get_ref(index)? {
+        ValueRef::Real(value) => Ok(value),
+        ValueRef::Integer(value) => Ok(value as f64),
+        ValueRef::Text(text) => {
+            let text = String::from_utf8_lossy(text);
+            f64::from_str(text.trim()).map_err(|_| invalid())
+        }
+        ValueRef::Null | ValueRef::Blob(_) => Err(invalid()),
+    }
+}
+
+fn attribute_value(value: ValueRef) -> Option<AttributeValue> {
+    match value {
+        ValueRef::Null => Some(AttributeValue::Null),
+        ValueRef::Integer(value) => Some(AttributeValue::Integer(value)),
+        ValueRef::Real(value) => Some(AttributeValue::Float(value)),
+        ValueRef::Text(text) => Some(AttributeValue::Text(String::from_utf8_lossy(text).into_owned())),
+        ValueRef::Blob(_) => None,
+    }
+}
+
+fn sqlite_value(value: Option<&AttributeValue>) -> Value {
+    match value {
+        None | Some(&AttributeValue::Null) => Value::Null,
+        Some(&AttributeValue::Bool(value)) => Value::Integer(value as i64),
+        Some(&AttributeValue::Integer(value)) => Value::Integer(value),
+        Some(&AttributeValue::Float(value)) => Value::Real(value),
+        Some(AttributeValue::Text(value)) => Value::Text(value.clone()),
+        Some(nested) => Value::Text(nested.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use customer_locator::CustomerLocator;
+    use units::Kilometers;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+
+    fn temporary_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("customer_locator_sqlite_{}_{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    // A CRM like table, with its own column names and a couple of extra ones.
+    fn generate_database(name: &str) -> PathBuf {
+        let path = temporary_path(name);
+        let connection = Connection::open(&path).unwrap();
+        connection.execute_batch("CREATE TABLE crm (id INTEGER PRIMARY KEY, full_name TEXT, lat REAL, lng REAL, tier TEXT, score REAL, photo BLOB);
+                                  INSERT INTO crm VALUES (1, 'Jose Narvaez', 52.986375, -6.043701, 'gold', 4.5, x'00');
+                                  INSERT INTO crm VALUES (2, 'Carlos Narvaez', 51.92893, -10.27699, NULL, 3.0, NULL);
+                                  INSERT INTO crm VALUES (3, 'Ian Kehoe', 53.2451022, -6.238335, 'silver', NULL, NULL);
+                                  CREATE TABLE customers (user_id, name, latitude, longitude);
+                                  INSERT INTO customers VALUES (4, 'Nora Dempsey', '53.1302756', '-6.2397222');")
+            .unwrap();
+        path
+    }
+
+    fn crm_columns() -> ColumnMapping {
+        ColumnMapping::from_str("user_id=id, name=full_name, latitude=lat, longitude=lng").unwrap()
+    }
+
+    #[test]
+    fn customer_sqlite_reads_a_table_with_mapped_columns_and_attributes() {
+        let path = generate_database("table.db");
+        let database = CustomerSqlite::new(&path)
+            .with_source(SqlSource::Table(String::from("crm")))
+            .with_columns(crm_columns());
+
+        let customers = database.customers().unwrap();
+
+        let jose = customers.get(1).unwrap();
+        assert_eq!(customers.len(), 3);
+        assert_eq!(jose.name, "Jose Narvaez");
+        assert_eq!(jose.location(), Location::new(52.986375, -6.043701));
+        assert_eq!(jose.attribute("tier"), Some(&AttributeValue::from("gold")));
+        assert_eq!(jose.attribute("score"), Some(&AttributeValue::Float(4.5)));
+        assert_eq!(jose.attribute("photo"), None);
+        assert_eq!(customers.get(2).unwrap().attribute("tier"), Some(&AttributeValue::Null));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn customer_sqlite_reads_coordinates_stored_as_text() {
+        let path = generate_database("text.db");
+
+        let customers = CustomerSqlite::new(&path).customers().unwrap();
+
+        assert_eq!(customers.get(4).unwrap().location(), Location::new(53.1302756, -6.2397222));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn customer_sqlite_reads_the_rows_of_a_query() {
+        let path = generate_database("query.db");
+        let database = CustomerSqlite::new(&path)
+            .with_source(SqlSource::Query(String::from("SELECT id, full_name, lat, lng FROM crm WHERE tier IS NOT NULL;")))
+            .with_columns(crm_columns());
+
+        let customers = database.customers().unwrap();
+        let within = database.within(BoundingBox::around(&DUBLIN, &Kilometers(30f64))).customers().unwrap();
+
+        assert_eq!(customers.iter().map(|customer| customer.user_id).collect::<Vec<i64>>(), vec![1, 3]);
+        assert_eq!(within.iter().map(|customer| customer.user_id).collect::<Vec<i64>>(), vec![3]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn customer_sqlite_pushes_the_bounding_box_down_to_sql() {
+        let path = generate_database("bounding_box.db");
+        let database = CustomerSqlite::new(&path)
+            .with_source(SqlSource::Table(String::from("crm")))
+            .with_columns(crm_columns())
+            .within(BoundingBox::around(&DUBLIN, &Kilometers(50f64)));
+
+        let (statement, parameters) = database.select_statement(&Connection::open(&path).unwrap()).unwrap();
+        let locator = CustomerLocator::from_source(database).unwrap();
+
+        assert!(statement.starts_with("SELECT * FROM \"crm\" WHERE \"lat\" BETWEEN ?1 AND ?2"));
+        assert_eq!(parameters.len(), 4);
+        // Carlos, in Kerry, never gets out of the database.
+        assert_eq!(locator.locate_in(&BoundingBox::new(Location::new(-90.0, -180.0), Location::new(90.0, 180.0))).len(), 2);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn customer_sqlite_pushes_the_bounding_box_down_on_coordinates_stored_as_text() {
+        let path = generate_database("bounding_box_text.db");
+        Connection::open(&path).unwrap()
+            .execute_batch("CREATE TABLE exports (user_id INTEGER, name TEXT, latitude TEXT, longitude VARCHAR(16));
+                            INSERT INTO exports VALUES (4, 'Nora Dempsey', '53.1302756', '-6.2397222');
+                            INSERT INTO exports VALUES (5, 'Eoin Ahearn', '54.0894797', '-6.18671');
+                            INSERT INTO exports VALUES (6, 'Theresa Enright', ' 53.1229599', '-6.2705202');")
+            .unwrap();
+        let radius = Kilometers(30f64);
+
+        // as the CLI does with `--format sqlite`.
+        for source in &["customers", "exports"] {
+            let database = CustomerSqlite::new(&path)
+                .with_source(SqlSource::Table(source.to_string()))
+                .within(BoundingBox::around(&DUBLIN, &radius));
+            let (statement, _) = database.select_statement(&Connection::open(&path).unwrap()).unwrap();
+            let locator = CustomerLocator::from_source(database).unwrap();
+
+            assert!(statement.contains("CAST(\"latitude\" AS REAL) BETWEEN ?1 AND ?2"), "{}", statement);
+            let user_ids: Vec<i64> = locator.locate_within(&radius, &DUBLIN).iter().map(|located| located.customer.user_id).collect();
+            assert_eq!(user_ids, if *source == "customers" { vec![4] } else { vec![4, 6] });
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn has_numeric_affinity_follows_the_sqlite_rules() {
+        for declared_type in &["REAL", "double precision", "FLOAT", "INTEGER", "NUMERIC", "DECIMAL(10,6)", "POINT"] {
+            assert!(has_numeric_affinity(declared_type), "{}", declared_type);
+        }
+        for declared_type in &["", "TEXT", "VARCHAR(16)", "CLOB", "BLOB"] {
+            assert!(!has_numeric_affinity(declared_type), "{}", declared_type);
+        }
+    }
+
+    #[test]
+    fn customer_sqlite_fails_on_missing_columns_and_invalid_coordinates() {
+        let path = generate_database("errors.db");
+
+        match CustomerSqlite::new(&path).with_source(SqlSource::Table(String::from("crm"))).customers() {
+            Err(CustomerSqliteError::MissingColumn(ref column)) if column == "user_id" => (),
+            other => panic!("expected a missing column error, got {:?}", other),
+        }
+        let columns = ColumnMapping { longitude: String::from("tier"), ..crm_columns() };
+        match CustomerSqlite::new(&path).with_source(SqlSource::Table(String::from("crm"))).with_columns(columns).customers() {
+            Err(CustomerSqliteError::InvalidValue(ref reason)) => assert_eq!(reason, "column 'tier' is not a coordinate"),
+            other => panic!("expected an invalid value error, got {:?}", other),
+        }
+        match CustomerSqlite::new(&path).with_source(SqlSource::Table(String::from("nope"))).customers() {
+            Err(CustomerSqliteError::Sqlite(_)) => (),
+            other => panic!("expected a SQLite error, got {:?}", other),
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn write_customers_creates_the_table_and_replaces_existing_rows() {
+        let path = temporary_path("write.db");
+        let database = CustomerSqlite::new(&path).with_source(SqlSource::Table(String::from("located")));
+        let jose = Customer::new(1, "Jose Narvaez", &Location::new(52.986375, -6.043701));
+
+        database.write_customers(&CustomerList::from_vec(vec![jose.clone()])).unwrap();
+        let written = database.write_customers(&CustomerList::from_vec(vec![
+            jose.with_attribute("tier", "gold"),
+            Customer::new(2, "Carlos Narvaez", &Location::new(51.92893, -10.27699)).with_attribute("vip", true),
+        ])).unwrap();
+
+        let customers = database.customers().unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(customers.len(), 2);
+        assert_eq!(customers.get(1).unwrap().attribute("tier"), Some(&AttributeValue::from("gold")));
+        assert_eq!(customers.get(2).unwrap().attribute("vip"), Some(&AttributeValue::Integer(1)));
+        assert_eq!(customers.get(2).unwrap().attribute("tier"), Some(&AttributeValue::Null));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn write_customers_fails_on_queries() {
+        let path = temporary_path("write_query.db");
+        let database = CustomerSqlite::new(&path).with_source(SqlSource::Query(String::from("SELECT 1")));
+
+        match database.write_customers(&CustomerList::from_vec(Vec::new())) {
+            Err(CustomerSqliteError::NotATable) => (),
+            other => panic!("expected a not a table error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module summarising how a `CustomerList` is spread around a `Location`:
+//! how many customers there are, the nearest and farthest ones, the
+//! distribution of their distances and the area they cover.
+//!
+//! A `DistanceReport` works the same on all the customers of a locator and
+//! on the ones a search returned. The search doesn't know how many
+//! customers were loaded or rejected, so those are given to the report.
+//!
+//! # Examples
+//!
+//! ```
+//! let customers = locator.locate_within(&Kilometers(100f64), &dublin);
+//! let stats = DistanceReport::new(dublin)
+//!     .with_bucket_width(Kilometers(25f64))
+//!     .with_loaded(locator.customers().len(), locator.rejected())
+//!     .report_located(&customers);
+//! println!("{} customers {} away on average", stats.matches, stats.mean_distance.unwrap());
+//! ```
+//!
+
+use customer::Customer;
+use customer_locator::LocatedCustomer;
+use customer_store::CustomerStore;
+use location::{BoundingBox, DistanceModel, Location};
+use units::Kilometers;
+
+/// The percentiles reported unless others are asked for.
+pub const DEFAULT_PERCENTILES: &[f64] = &[90f64, 95f64, 99f64];
+
+/// The width of the histogram buckets unless another is asked for.
+pub const DEFAULT_BUCKET_WIDTH: Kilometers = Kilometers(10f64);
+
+/// The distance under which `percentile` percent of the customers are.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Percentile {
+    pub percentile: f64,
+    pub distance: Kilometers,
+}
+
+/// How many customers are between `from`, included, and `to` away.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HistogramBucket {
+    pub from: Kilometers,
+    pub to: Kilometers,
+    pub customers: usize,
+}
+
+/// The summary of a `CustomerList` built by a `DistanceReport`.
+///
+/// Everything about distances is `None`, or empty, when there are no
+/// matches.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CustomerStats {
+    /// The location distances are measured from.
+    pub location: Location,
+    /// The customers read from the source, rejected ones excluded.
+    pub loaded: usize,
+    /// The customers read from the source that couldn't be located.
+    pub rejected: usize,
+    /// The customers summarised.
+    pub matches: usize,
+    pub nearest: Option<LocatedCustomer>,
+    pub farthest: Option<LocatedCustomer>,
+    pub mean_distance: Option<Kilometers>,
+    pub median_distance: Option<Kilometers>,
+    pub percentiles: Vec<Percentile>,
+    pub bucket_width: Kilometers,
+    /// Consecutive buckets from zero to the farthest customer, empty ones
+    /// included.
+    pub histogram: Vec<HistogramBucket>,
+    pub bounding_box: Option<BoundingBox>,
+    /// The spherical centroid of the customers. See `Location::centroid`.
+    pub centroid: Option<Location>,
+}
+
+/// Builds the `CustomerStats` of customers around a `Location`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceReport {
+    location: Location,
+    bucket_width: Kilometers,
+    percentiles: Vec<f64>,
+    loaded: Option<(usize, usize)>,
+    model: DistanceModel,
+}
+
+impl DistanceReport {
+    /// Constructs a report measuring distances from `location`, with
+    /// `DEFAULT_BUCKET_WIDTH` buckets and the `DEFAULT_PERCENTILES`.
+    pub fn new(location: Location) -> DistanceReport {
+        DistanceReport {
+            location,
+            bucket_width: DEFAULT_BUCKET_WIDTH,
+            percentiles: DEFAULT_PERCENTILES.to_vec(),
+            loaded: None,
+            model: DistanceModel::Haversine,
+        }
+    }
+
+    /// Sets the `DistanceModel` the distances of the customers are
+    /// calculated with, the Haversine one unless another is given.
+    /// `report_located` takes the distances it's given as they are.
+    pub fn with_model(mut self, model: DistanceModel) -> DistanceReport {
+        self.model = model;
+        self
+    }
+
+    /// Sets the width of the histogram buckets. A width that isn't
+    /// positive leaves the histogram empty.
+    pub fn with_bucket_width(mut self, bucket_width: Kilometers) -> DistanceReport {
+        self.bucket_width = bucket_width;
+        self
+    }
+
+    /// Sets the percentiles to report, each one between 0 and 100.
+    pub fn with_percentiles(mut self, percentiles: &[f64]) -> DistanceReport {
+        self.percentiles = percentiles.to_vec();
+        self
+    }
+
+    /// Sets how many customers were loaded and rejected when reporting on
+    /// the results of a search. By default the reported customers are
+    /// taken as all the loaded ones.
+    pub fn with_loaded(mut self, loaded: usize, rejected: usize) -> DistanceReport {
+        self.loaded = Some((loaded, rejected));
+        self
+    }
+
+    /// Returns the `CustomerStats` of customers a search already measured
+    /// the distance to `location` of, like the ones
+    /// `CustomerLocator::locate_within` returns. See `report`.
+    pub fn report_located(&self, customers: &[LocatedCustomer]) -> CustomerStats {
+        let locations: Vec<Location> = customers.iter().map(|located| located.customer.location()).collect();
+        let distances = customers.iter().map(|located| located.distance).collect();
+        self.summarise(locations, distances, |index| customers[index].customer.clone())
+    }
+
+    /// Returns the `CustomerStats` of all the customers on `store`, like a
+    /// `CustomerList`. Only the nearest and farthest customers are built.
+    ///
+    /// Percentiles, the median included, are interpolated between the two
+    /// closest distances. Customers at the same distance as the nearest or
+    /// farthest one are reported in favour of the first of them.
+    pub fn report<S: CustomerStore>(&self, store: &S) -> CustomerStats {
+        let locations: Vec<Location> = (0..store.len()).map(|index| store.location_at(index)).collect();
+        let distances = self.distances(&locations);
+        self.summarise(locations, distances, |index| store.customer_at(index))
+    }
+
+    fn distances(&self, locations: &[Location]) -> Vec<Kilometers> {
+        locations.iter().map(|location| self.model.distance(location, &self.location)).collect()
+    }
+
+    // Summarises the customers at `locations`, `distances` away, building
+    // with `customer_at` only the ones that end up on the stats.
+    fn summarise<F>(&self, locations: Vec<Location>, distances: Vec<Kilometers>, customer_at: F) -> CustomerStats
+        where F: Fn(usize) -> Customer
+    {
+        let nearest = (0..distances.len()).reduce(|nearest, other| if distances[other] < distances[nearest] { other } else { nearest });
+        let farthest = (0..distances.len()).reduce(|farthest, other| if distances[other] > distances[farthest] { other } else { farthest });
+        let located = |index: usize| LocatedCustomer { customer: customer_at(index), distance: distances[index] };
+
+        let mut sorted_distances: Vec<f64> = distances.iter().map(|distance| distance.0).collect();
+        sorted_distances.sort_by(f64::total_cmp);
+        let mean_distance = if sorted_distances.is_empty() {
+            None
+        } else {
+            Some(Kilometers(sorted_distances.iter().sum::<f64>() / sorted_distances.len() as f64))
+        };
+        let (loaded, rejected) = self.loaded.unwrap_or((locations.len(), 0));
+
+        CustomerStats {
+            location: self.location,
+            loaded,
+            rejected,
+            matches: locations.len(),
+            nearest: nearest.map(located),
+            farthest: farthest.map(located),
+            mean_distance,
+            median_distance: percentile(&sorted_distances, 50f64),
+            percentiles: self.percentiles
+                .iter()
+                .filter_map(|&rank| percentile(&sorted_distances, rank).map(|distance| Percentile { percentile: rank, distance }))
+                .collect(),
+            bucket_width: self.bucket_width,
+            histogram: histogram(&sorted_distances, self.bucket_width),
+            bounding_box: BoundingBox::enclosing(&locations),
+            centroid: Location::centroid(&locations),
+        }
+    }
+}
+
+// Returns the `rank`th percentile of the sorted `distances`, interpolating
+// linearly between the closest two.
+fn percentile(distances: &[f64], rank: f64) -> Option<Kilometers> {
+    if distances.is_empty() {
+        return None;
+    }
+
+    let position = rank.clamp(0f64, 100f64) / 100f64 * (distances.len() - 1) as f64;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    let fraction = position - below as f64;
+
+    Some(Kilometers(distances[below] + (distances[above] - distances[below]) * fraction))
+}
+
+// Counts the sorted `distances` into buckets `width` wide.
+fn histogram(distances: &[f64], width: Kilometers) -> Vec<HistogramBucket> {
+    let farthest = match distances.last() {
+        Some(&farthest) if width.0 > 0f64 && width.0.is_finite() => farthest,
+        _ => return Vec::new(),
+    };
+
+    let mut counts = vec![0usize; (farthest / width.0) as usize + 1];
+    for distance in distances {
+        counts[(distance / width.0) as usize] += 1;
+    }
+
+    counts.into_iter()
+        .enumerate()
+        .map(|(index, customers)| HistogramBucket {
+            from: Kilometers(index as f64 * width.0),
+            to: Kilometers((index + 1) as f64 * width.0),
+            customers,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use customer::CustomerList;
+    use customer_store::CompactCustomerList;
+    use location::EARTH_RADIUS_IN_KM;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+
+    // customers due north of Dublin, 10, 20, 30 and 45 Km away.
+    fn generate_customer_list() -> CustomerList {
+        let north_of_dublin = |kilometers: f64| Location::new(DUBLIN.latitude + (kilometers / EARTH_RADIUS_IN_KM).to_degrees(), DUBLIN.longitude);
+        CustomerList::from_vec(vec![
+            Customer::new(1, "Ian Kehoe", &north_of_dublin(20.0)),
+            Customer::new(2, "Nora Dempsey", &north_of_dublin(10.0)),
+            Customer::new(3, "Theresa Enright", &north_of_dublin(45.0)),
+            Customer::new(4, "Eoin Ahearn", &north_of_dublin(30.0)),
+        ])
+    }
+
+    fn assert_close(actual: Option<Kilometers>, expected: f64) {
+        let actual = actual.unwrap().0;
+        assert!((actual - expected).abs() < 1e-6, "{} isn't {}", actual, expected);
+    }
+
+    #[test]
+    fn report_finds_the_nearest_and_farthest_customers() {
+        let stats = DistanceReport::new(DUBLIN).report(&generate_customer_list());
+
+        assert_eq!(stats.matches, 4);
+        assert_eq!(stats.nearest.as_ref().map(|located| located.customer.user_id), Some(2));
+        assert_eq!(stats.farthest.as_ref().map(|located| located.customer.user_id), Some(3));
+        assert_close(stats.nearest.map(|located| located.distance), 10.0);
+        assert_close(stats.farthest.map(|located| located.distance), 45.0);
+    }
+
+    #[test]
+    fn report_calculates_the_mean_median_and_percentiles() {
+        let stats = DistanceReport::new(DUBLIN)
+            .with_percentiles(&[0.0, 50.0, 90.0, 100.0])
+            .report(&generate_customer_list());
+
+        assert_close(stats.mean_distance, 26.25);
+        assert_close(stats.median_distance, 25.0);
+        let percentiles: Vec<f64> = stats.percentiles.iter().map(|percentile| percentile.percentile).collect();
+        assert_eq!(percentiles, vec![0.0, 50.0, 90.0, 100.0]);
+        assert_close(Some(stats.percentiles[0].distance), 10.0);
+        assert_close(Some(stats.percentiles[1].distance), 25.0);
+        assert_close(Some(stats.percentiles[2].distance), 40.5);
+        assert_close(Some(stats.percentiles[3].distance), 45.0);
+    }
+
+    #[test]
+    fn report_counts_the_distances_into_buckets() {
+        let stats = DistanceReport::new(DUBLIN)
+            .with_bucket_width(Kilometers(25.0))
+            .report(&generate_customer_list());
+
+        let histogram: Vec<(f64, f64, usize)> = stats.histogram
+            .iter()
+            .map(|bucket| (bucket.from.0, bucket.to.0, bucket.customers))
+            .collect();
+        assert_eq!(histogram, vec![(0.0, 25.0, 2), (25.0, 50.0, 2)]);
+
+        let stats = DistanceReport::new(DUBLIN)
+            .with_bucket_width(Kilometers(12.5))
+            .report(&generate_customer_list());
+        let counts: Vec<usize> = stats.histogram.iter().map(|bucket| bucket.customers).collect();
+        assert_eq!(counts, vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn report_leaves_the_histogram_empty_without_a_positive_width() {
+        let stats = DistanceReport::new(DUBLIN)
+            .with_bucket_width(Kilometers(0.0))
+            .report(&generate_customer_list());
+
+        assert!(stats.histogram.is_empty());
+    }
+
+    #[test]
+    fn report_fits_the_area_of_the_customers() {
+        let customers = generate_customer_list();
+        let stats = DistanceReport::new(DUBLIN).report(&customers);
+        let bounding_box = stats.bounding_box.unwrap();
+
+        assert_eq!(bounding_box.south_west, customers[1].location());
+        assert_eq!(bounding_box.north_east, customers[2].location());
+        let centroid = stats.centroid.unwrap();
+        assert!((centroid.longitude - DUBLIN.longitude).abs() < 1e-9);
+        assert!(centroid.latitude > customers[1].latitude && centroid.latitude < customers[2].latitude);
+    }
+
+    #[test]
+    fn report_takes_the_loaded_and_rejected_customers_of_a_search() {
+        let customers = generate_customer_list();
+        assert_eq!(DistanceReport::new(DUBLIN).report(&customers).loaded, 4);
+
+        let stats = DistanceReport::new(DUBLIN).with_loaded(32, 2).report(&customers);
+        assert_eq!((stats.loaded, stats.rejected, stats.matches), (32, 2, 4));
+    }
+
+    #[test]
+    fn report_on_no_customers_has_no_distances() {
+        let stats = DistanceReport::new(DUBLIN).with_loaded(32, 0).report(&CustomerList::default());
+
+        assert_eq!(stats.matches, 0);
+        assert_eq!(stats.nearest, None);
+        assert_eq!(stats.mean_distance, None);
+        assert_eq!(stats.median_distance, None);
+        assert!(stats.percentiles.is_empty());
+        assert!(stats.histogram.is_empty());
+        assert_eq!(stats.bounding_box, None);
+        assert_eq!(stats.centroid, None);
+    }
+
+    #[test]
+    fn report_on_a_compact_store_matches_the_report_of_the_same_customers() {
+        let customers = generate_customer_list();
+        let compact: CompactCustomerList = customers.iter().cloned().collect();
+
+        assert_eq!(DistanceReport::new(DUBLIN).report(&compact), DistanceReport::new(DUBLIN).report(&customers));
+    }
+
+    #[test]
+    fn report_measures_the_distances_with_the_model() {
+        let customers = generate_customer_list();
+        let stats = DistanceReport::new(DUBLIN).with_model(DistanceModel::Vincenty).report(&customers);
+        let farthest = stats.farthest.unwrap();
+
+        assert_eq!(farthest.distance, DistanceModel::Vincenty.distance(&farthest.customer.location(), &DUBLIN));
+        assert!(farthest.distance != farthest.customer.distance_from(&DUBLIN));
+    }
+
+    #[test]
+    fn report_located_takes_the_distances_it_is_given() {
+        let located: Vec<LocatedCustomer> = generate_customer_list()
+            .into_iter()
+            .map(|customer| LocatedCustomer { distance: Kilometers(customer.user_id as f64), customer })
+            .collect();
+        let stats = DistanceReport::new(DUBLIN).report_located(&located);
+
+        assert_close(stats.mean_distance, 2.5);
+        assert_eq!(stats.farthest.map(|located| located.customer.user_id), Some(4));
+    }
+}
@@ -0,0 +1,423 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, HashMap};
+use std::iter::FromIterator;
+
+use customer::Customer;
+use customer::CustomerList;
+use customer_attribute::Attributes;
+use location::{Location, Ring, EARTH_RADIUS_IN_KM};
+use units::Kilometers;
+
+///
+/// Trait abstracting the way a `CustomerLocator` keeps its customers
+/// in memory.
+///
+/// Customers are addressed by their position on the store, from `0` to
+/// `len() - 1`, so the locator can run its calculations over the
+/// coordinates and only build `Customer` instances for the ones that
+/// end up on a result.
+///
+/// `CustomerList` is the default store, `CompactCustomerList` trades the
+/// flexibility of a `Vec<Customer>` for a much smaller memory footprint.
+pub trait CustomerStore {
+    /// Returns the number of customers on the store.
+    fn len(&self) -> usize;
+
+    /// Returns the `user_id` of the customer at `index`.
+    fn user_id_at(&self, index: usize) -> i64;
+
+    /// Returns the `Location` of the customer at `index`.
+    fn location_at(&self, index: usize) -> Location;
+
+    /// Returns the distance in `Kilometers` between the customer at `index`
+    /// and `location`, the same as `Customer::distance_from` would.
+    fn distance_at(&self, index: usize, location: &Location) -> Kilometers {
+        self.location_at(index).distance_from(location)
+    }
+
+    /// Builds the `Customer` at `index`.
+    fn customer_at(&self, index: usize) -> Customer;
+
+    /// Returns the indexes of the customers in `ring` around `location` in
+    /// the order they are stored.
+    fn indexes_in_ring(&self, ring: &Ring, location: &Location) -> Vec<usize> {
+        (0..self.len())
+            .filter(|index| ring.contains(self.distance_at(*index, location)))
+            .collect()
+    }
+}
+
+impl CustomerStore for CustomerList {
+    fn len(&self) -> usize {
+        CustomerList::len(self)
+    }
+
+    fn user_id_at(&self, index: usize) -> i64 {
+        self[index].user_id
+    }
+
+    fn location_at(&self, index: usize) -> Location {
+        self[index].location()
+    }
+
+    fn distance_at(&self, index: usize, location: &Location) -> Kilometers {
+        self[index].distance_from(location)
+    }
+
+    fn customer_at(&self, index: usize) -> Customer {
+        self[index].clone()
+    }
+}
+
+/// How the coordinates of a `CompactCustomerList` are stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoordinatePrecision {
+    /// As 64 bits floats, exactly as they were loaded.
+    Exact,
+    /// As 32 bits integers holding micro-degrees, which is around 11cm of
+    /// precision at the equator and takes half the memory.
+    MicroDegrees,
+}
+
+const MICRO_DEGREES: f64 = 1_000_000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Coordinates {
+    Exact { latitudes: Vec<f64>, longitudes: Vec<f64> },
+    MicroDegrees { latitudes: Vec<i32>, longitudes: Vec<i32> },
+}
+
+/// A `CustomerStore` laid out as a struct of arrays.
+///
+/// Instead of a `Vec<Customer>` with a heap allocated name per customer it
+/// keeps every field on its own contiguous array so the distance
+/// calculations only walk over the coordinates they need:
+///
+/// - coordinates are kept as `f64` or, with `CoordinatePrecision::MicroDegrees`,
+///   quantised to `i32` micro-degrees.
+/// - the cosine of every latitude, the most expensive part of the Haversine
+///   formula that only depends on the customer, is precomputed. The one of
+///   the location being searched is computed once per search, and customers
+///   whose latitude alone is too far away are discarded before doing any
+///   trigonometry.
+/// - names are interned on a single buffer so repeated names are stored once.
+/// - extra attributes are kept on a sparse map as most customers have none.
+///
+/// With `CoordinatePrecision::Exact` the distances and so the search results
+/// are exactly the same ones as with a `CustomerList`.
+///
+/// # Examples
+///
+/// ```
+/// let compact = CompactCustomerList::new(customer_list, CoordinatePrecision::MicroDegrees);
+///
+/// let locator = CustomerLocator::new(compact);
+/// let customers = locator.locate_within(&Kilometers(100.0), &Location::new(53.3393, -6.2576841));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompactCustomerList {
+    user_ids: Vec<i64>,
+    coordinates: Coordinates,
+    latitude_cosines: Vec<f64>,
+    name_ids: Vec<u32>,
+    name_spans: Vec<(u32, u32)>,
+    names: String,
+    name_index: HashMap<String, u32>,
+    attributes: BTreeMap<u32, Attributes>,
+}
+
+impl CompactCustomerList {
+    /// Constructs an empty `CompactCustomerList` storing coordinates with the
+    /// given precision.
+    pub fn with_precision(precision: CoordinatePrecision) -> CompactCustomerList {
+        let coordinates = match precision {
+            CoordinatePrecision::Exact => Coordinates::Exact { latitudes: Vec::new(), longitudes: Vec::new() },
+            CoordinatePrecision::MicroDegrees => {
+                Coordinates::MicroDegrees { latitudes: Vec::new(), longitudes: Vec::new() }
+            }
+        };
+
+        CompactCustomerList {
+            user_ids: Vec::new(),
+            coordinates,
+            latitude_cosines: Vec::new(),
+            name_ids: Vec::new(),
+            name_spans: Vec::new(),
+            names: String::new(),
+            name_index: HashMap::new(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Constructs a `CompactCustomerList` out of a `CustomerList`.
+    pub fn new(customers: CustomerList, precision: CoordinatePrecision) -> CompactCustomerList {
+        let mut compact = CompactCustomerList::with_precision(precision);
+        compact.reserve(customers.len());
+        compact.extend(customers);
+        compact.shrink_to_fit();
+        compact
+    }
+
+    /// Reserves capacity for at least `additional` more customers.
+    pub fn reserve(&mut self, additional: usize) {
+        self.user_ids.reserve(additional);
+        self.latitude_cosines.reserve(additional);
+        self.name_ids.reserve(additional);
+        match self.coordinates {
+            Coordinates::Exact { ref mut latitudes, ref mut longitudes } => {
+                latitudes.reserve(additional);
+                longitudes.reserve(additional);
+            }
+            Coordinates::MicroDegrees { ref mut latitudes, ref mut longitudes } => {
+                latitudes.reserve(additional);
+                longitudes.reserve(additional);
+            }
+        }
+    }
+
+    /// Releases the spare capacity of the arrays and drops the lookup table
+    /// used to intern the names. Names pushed afterwards are still interned
+    /// against the ones pushed from then on.
+    pub fn shrink_to_fit(&mut self) {
+        self.user_ids.shrink_to_fit();
+        self.latitude_cosines.shrink_to_fit();
+        self.name_ids.shrink_to_fit();
+        self.name_spans.shrink_to_fit();
+        self.names.shrink_to_fit();
+        self.name_index = HashMap::new();
+        match self.coordinates {
+            Coordinates::Exact { ref mut latitudes, ref mut longitudes } => {
+                latitudes.shrink_to_fit();
+                longitudes.shrink_to_fit();
+            }
+            Coordinates::MicroDegrees { ref mut latitudes, ref mut longitudes } => {
+                latitudes.shrink_to_fit();
+                longitudes.shrink_to_fit();
+            }
+        }
+    }
+
+    /// Appends a customer given its fields. The name is only copied if it was
+    /// not seen before.
+    pub fn push(&mut self, user_id: i64, name: &str, location: &Location, attributes: Attributes) {
+        let index = self.user_ids.len() as u32;
+        self.user_ids.push(user_id);
+
+        let latitude = match self.coordinates {
+            Coordinates::Exact { ref mut latitudes, ref mut longitudes } => {
+                latitudes.push(location.latitude);
+                longitudes.push(location.longitude);
+                location.latitude
+            }
+            Coordinates::MicroDegrees { ref mut latitudes, ref mut longitudes } => {
+                let latitude = (location.latitude * MICRO_DEGREES).round() as i32;
+                latitudes.push(latitude);
+                longitudes.push((location.longitude * MICRO_DEGREES).round() as i32);
+                f64::from(latitude) / MICRO_DEGREES
+            }
+        };
+        self.latitude_cosines.push(latitude.to_radians().cos());
+
+        let name_id = self.intern(name);
+        self.name_ids.push(name_id);
+
+        if !attributes.is_empty() {
+            self.attributes.insert(index, attributes);
+        }
+    }
+
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(name_id) = self.name_index.get(name) {
+            return *name_id;
+        }
+
+        let name_id = self.name_spans.len() as u32;
+        self.name_spans.push((self.names.len() as u32, name.len() as u32));
+        self.names.push_str(name);
+        self.name_index.insert(name.into(), name_id);
+        name_id
+    }
+
+    /// Returns the name of the customer at `index`.
+    pub fn name_at(&self, index: usize) -> &str {
+        let (start, len) = self.name_spans[self.name_ids[index] as usize];
+        &self.names[start as usize..(start + len) as usize]
+    }
+
+    // The same calculation as `Location::distance_from` with the customer as
+    // `self`, only reusing the precomputed cosines of both latitudes.
+    fn distance_with_cosine_at(&self, index: usize, location: &Location, location_latitude_cosine: f64) -> Kilometers {
+        let (latitude, longitude) = self.coordinates_at(index);
+        let delta_latitude = (location.latitude - latitude).to_radians();
+        let delta_longitude = (location.longitude - longitude).to_radians();
+
+        let a = ((delta_latitude / 2.0f64).sin().powf(2.0f64)) +
+                self.latitude_cosines[index] *
+                location_latitude_cosine *
+                ((delta_longitude / 2.0f64).sin().powf(2.0f64));
+
+        let c = 2.0f64 * (a.sqrt().asin());
+
+        Kilometers(EARTH_RADIUS_IN_KM * c)
+    }
+
+    fn coordinates_at(&self, index: usize) -> (f64, f64) {
+        match self.coordinates {
+            Coordinates::Exact { ref latitudes, ref longitudes } => (latitudes[index], longitudes[index]),
+            Coordinates::MicroDegrees { ref latitudes, ref longitudes } => {
+                (f64::from(latitudes[index]) / MICRO_DEGREES, f64::from(longitudes[index]) / MICRO_DEGREES)
+            }
+        }
+    }
+}
+
+impl CustomerStore for CompactCustomerList {
+    fn len(&self) -> usize {
+        self.user_ids.len()
+    }
+
+    fn user_id_at(&self, index: usize) -> i64 {
+        self.user_ids[index]
+    }
+
+    fn location_at(&self, index: usize) -> Location {
+        let (latitude, longitude) = self.coordinates_at(index);
+        Location::new(latitude, longitude)
+    }
+
+    fn distance_at(&self, index: usize, location: &Location) -> Kilometers {
+        self.distance_with_cosine_at(index, location, location.latitude.to_radians().cos())
+    }
+
+    fn indexes_in_ring(&self, ring: &Ring, location: &Location) -> Vec<usize> {
+        let location_latitude_cosine = location.latitude.to_radians().cos();
+        // the distance between two points is never shorter than the distance
+        // between their latitudes, so most customers can be discarded by
+        // looking at the latitude alone.
+        // A tiny margin is added so rounding never discards a customer.
+        let max_delta_latitude = (ring.max_radius.0 / EARTH_RADIUS_IN_KM).to_degrees() * (1.0 + 1e-9);
+
+        (0..self.len())
+            .filter(|index| {
+                let (latitude, _) = self.coordinates_at(*index);
+                (location.latitude - latitude).abs() <= max_delta_latitude &&
+                ring.contains(self.distance_with_cosine_at(*index, location, location_latitude_cosine))
+            })
+            .collect()
+    }
+
+    fn customer_at(&self, index: usize) -> Customer {
+        let (latitude, longitude) = self.coordinates_at(index);
+        Customer {
+            user_id: self.user_ids[index],
+            name: self.name_at(index).into(),
+            latitude,
+            longitude,
+            attributes: self.attributes.get(&(index as u32)).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl Extend<Customer> for CompactCustomerList {
+    fn extend<I: IntoIterator<Item = Customer>>(&mut self, iter: I) {
+        for customer in iter {
+            let location = customer.location();
+            self.push(customer.user_id, &customer.name, &location, customer.attributes);
+        }
+    }
+}
+
+impl FromIterator<Customer> for CompactCustomerList {
+    fn from_iter<I: IntoIterator<Item = Customer>>(iter: I) -> Self {
+        let mut compact = CompactCustomerList::with_precision(CoordinatePrecision::Exact);
+        compact.extend(iter);
+        compact
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use location::Boundary;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+
+    fn generate_customer_list() -> CustomerList {
+        CustomerList::from_vec(vec![
+            Customer::new(1, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
+            Customer::new(2, "Nora Dempsey", &Location::new(53.1302756, -6.2397222))
+                .with_attribute("email", "nora@example.com"),
+            Customer::new(3, "Ian Kehoe", &Location::new(54.0894797, -6.18671)),
+            Customer::new(4, "Stephen McArdle", &Location::new(-33.4489, -70.6693)),
+        ])
+    }
+
+    #[test]
+    fn compact_customer_list_gives_back_the_same_customers() {
+        let customer_list = generate_customer_list();
+        let compact = CompactCustomerList::new(customer_list.clone(), CoordinatePrecision::Exact);
+
+        assert_eq!(compact.len(), customer_list.len());
+        for (index, customer) in customer_list.iter().enumerate() {
+            assert_eq!(compact.customer_at(index), *customer);
+            assert_eq!(compact.user_id_at(index), customer.user_id);
+        }
+    }
+
+    #[test]
+    fn compact_customer_list_interns_repeated_names() {
+        let compact = CompactCustomerList::new(generate_customer_list(), CoordinatePrecision::Exact);
+
+        assert_eq!(compact.name_spans.len(), 3);
+        assert_eq!(compact.name_at(0), "Ian Kehoe");
+        assert_eq!(compact.name_at(2), "Ian Kehoe");
+    }
+
+    #[test]
+    fn compact_customer_list_distances_are_the_same_as_customer_distances() {
+        let customer_list = generate_customer_list();
+        let compact = CompactCustomerList::new(customer_list.clone(), CoordinatePrecision::Exact);
+        let dublin = DUBLIN;
+
+        for (index, customer) in customer_list.iter().enumerate() {
+            assert_eq!(compact.distance_at(index, &dublin), customer.distance_from(&dublin));
+        }
+    }
+
+    #[test]
+    fn compact_customer_list_quantises_coordinates_to_micro_degrees() {
+        let customer_list = generate_customer_list();
+        let compact = CompactCustomerList::new(customer_list.clone(), CoordinatePrecision::MicroDegrees);
+        let dublin = DUBLIN;
+
+        assert!(matches!(compact.coordinates, Coordinates::MicroDegrees { .. }));
+        assert_eq!(compact.location_at(0), Location::new(53.245102, -6.238335));
+        for (index, customer) in customer_list.iter().enumerate() {
+            let difference = compact.distance_at(index, &dublin).0 - customer.distance_from(&dublin).0;
+            assert!(difference.abs() < 0.001, "difference of {} km for customer {}", difference, index);
+        }
+    }
+
+    #[test]
+    fn compact_customer_list_finds_the_same_customers_in_rings() {
+        let customer_list = generate_customer_list();
+        let compact = CompactCustomerList::new(customer_list.clone(), CoordinatePrecision::Exact);
+        let on_the_edge = customer_list[1].distance_from(&DUBLIN);
+
+        let rings = [Ring::new(Kilometers(5.0), on_the_edge),
+                     Ring::new(Kilometers(5.0), on_the_edge).with_max_boundary(Boundary::Inclusive),
+                     Ring::new(on_the_edge, Kilometers(1000.0)).with_min_boundary(Boundary::Exclusive)];
+        for ring in &rings {
+            assert_eq!(compact.indexes_in_ring(ring, &DUBLIN), customer_list.indexes_in_ring(ring, &DUBLIN));
+        }
+        assert!(!customer_list.indexes_in_ring(&rings[0], &DUBLIN).contains(&1));
+        assert!(customer_list.indexes_in_ring(&rings[1], &DUBLIN).contains(&1));
+    }
+}
@@ -0,0 +1,473 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module assigning customers to offices that can only take so many of
+//! them, minimising the total distance from the customers to their office.
+//!
+//! Sending every customer to its nearest office overloads the busy ones.
+//! `TerritoryAssignment` solves the assignment as a minimum cost flow from
+//! the customers to the offices, with [successive shortest paths]
+//! (https://en.wikipedia.org/wiki/Minimum-cost_flow_problem). Customers are
+//! added one at a time along the cheapest chain of moves of the customers
+//! already assigned, which is searched on a graph of the offices alone so
+//! it scales with the customers times the offices rather than with the
+//! square of the customers.
+//!
+//! When the offices can't take every customer, as many as possible are
+//! assigned and the ones left out are the ones that make the total
+//! distance of the rest the shortest.
+//!
+//! # Examples
+//!
+//! ```
+//! let offices = vec![Office::new("Dublin", dublin, 100), Office::new("Cork", cork, 60)];
+//! let territories = TerritoryAssignment::new(offices)
+//!     .with_max_distance(Kilometers(150f64))
+//!     .assign(&customers);
+//! for territory in &territories.territories {
+//!     println!("{}: {} customers", territory.office.name, territory.customers.len());
+//! }
+//! ```
+//!
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use customer::CustomerList;
+use customer_locator::LocatedCustomer;
+use location::{DistanceModel, Location};
+use units::Kilometers;
+
+// Savings under this, in Km, aren't worth moving customers around for.
+const DISTANCE_TOLERANCE: f64 = 1e-9;
+
+/// An office customers can be assigned to, taking up to `capacity` of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Office {
+    pub name: String,
+    pub location: Location,
+    pub capacity: usize,
+}
+
+impl Office {
+    /// Constructs a new `Office` given its name, location and capacity.
+    pub fn new(name: &str, location: Location, capacity: usize) -> Office {
+        Office { name: name.to_string(), location, capacity }
+    }
+}
+
+/// An office along with the customers assigned to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Territory {
+    pub office: Office,
+    /// The customers of the office, in the order they were given, with
+    /// their distance to it.
+    pub customers: Vec<LocatedCustomer>,
+}
+
+impl Territory {
+    /// Returns how many customers the office takes.
+    pub fn load(&self) -> usize {
+        self.customers.len()
+    }
+
+    /// Returns the sum of the distances from the customers to the office.
+    pub fn total_distance(&self) -> Kilometers {
+        Kilometers(self.customers.iter().map(|located| located.distance.0).sum())
+    }
+
+    /// Returns the average distance from the customers to the office,
+    /// `None` when it has none.
+    pub fn average_distance(&self) -> Option<Kilometers> {
+        if self.customers.is_empty() {
+            return None;
+        }
+
+        Some(Kilometers(self.total_distance().0 / self.load() as f64))
+    }
+}
+
+/// The territories found by `TerritoryAssignment`, one per office in the
+/// order they were given, and the customers no office could take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Territories {
+    pub territories: Vec<Territory>,
+    pub unassigned: CustomerList,
+}
+
+impl Territories {
+    /// Returns the sum of the distances from every assigned customer to its
+    /// office.
+    pub fn total_distance(&self) -> Kilometers {
+        Kilometers(self.territories.iter().map(|territory| territory.total_distance().0).sum())
+    }
+}
+
+/// Struct assigning customers to offices with limited capacities.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerritoryAssignment {
+    offices: Vec<Office>,
+    max_distance: Option<Kilometers>,
+    model: DistanceModel,
+}
+
+impl TerritoryAssignment {
+    /// Constructs an assignment to `offices`, customers going to any office
+    /// however far.
+    pub fn new(offices: Vec<Office>) -> TerritoryAssignment {
+        TerritoryAssignment { offices, max_distance: None, model: DistanceModel::Haversine }
+    }
+
+    /// Calculates the distances with `model` instead of the haversine one.
+    pub fn with_model(mut self, model: DistanceModel) -> TerritoryAssignment {
+        self.model = model;
+        self
+    }
+
+    /// Only assigns customers to offices up to `max_distance` from them.
+    pub fn with_max_distance(mut self, max_distance: Kilometers) -> TerritoryAssignment {
+        self.max_distance = Some(max_distance);
+        self
+    }
+
+    /// Assigns `customers` to the offices so the most customers are
+    /// assigned within the capacities and the maximum distance, with the
+    /// shortest total distance to their offices.
+    pub fn assign(&self, customers: &CustomerList) -> Territories {
+        let distances: Vec<Vec<f64>> = customers.iter()
+            .map(|customer| self.offices.iter().map(|office| self.model.distance(&customer.location(), &office.location).0).collect())
+            .collect();
+        let max_distance = self.max_distance.map_or(f64::INFINITY, |max_distance| max_distance.0);
+        let capacities = self.offices.iter().map(|office| office.capacity).collect();
+
+        let mut flow = AssignmentFlow::new(distances, max_distance, capacities);
+        // a customer left out can get in later through the ones assigned
+        // after it, so the ones left out are retried until none gets in.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for customer in 0..customers.len() {
+                if flow.assigned[customer].is_none() && flow.insert(customer) {
+                    changed = true;
+                }
+            }
+        }
+
+        let mut territories: Vec<Territory> = self.offices.iter()
+            .map(|office| Territory { office: office.clone(), customers: Vec::new() })
+            .collect();
+        let mut unassigned = CustomerList::default();
+        for (index, (customer, office)) in customers.iter().zip(&flow.assigned).enumerate() {
+            match *office {
+                Some(office) => {
+                    let distance = Kilometers(flow.distances[index][office]);
+                    territories[office].customers.push(LocatedCustomer { customer: customer.clone(), distance });
+                }
+                None => unassigned.extend(Some(customer.clone())),
+            }
+        }
+
+        Territories { territories, unassigned }
+    }
+}
+
+// A distance paired with the customer it belongs to, ordered by distance
+// so it can be kept on a heap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate(f64, usize);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+// The state of the minimum cost flow from the customers to the offices.
+//
+// Heaps hold the customers of each office and go stale as customers move,
+// entries of customers no longer at the office are dropped when they reach
+// the top.
+struct AssignmentFlow {
+    distances: Vec<Vec<f64>>,
+    max_distance: f64,
+    capacities: Vec<usize>,
+    assigned: Vec<Option<usize>>,
+    loads: Vec<usize>,
+    // what moving each customer of office `a` to office `b` adds to the
+    // total distance, cheapest first, on `moves[a][b]`.
+    moves: Vec<Vec<BinaryHeap<Reverse<Candidate>>>>,
+    // the distance from each customer of an office to it, furthest first.
+    furthest: Vec<BinaryHeap<Candidate>>,
+}
+
+impl AssignmentFlow {
+    fn new(distances: Vec<Vec<f64>>, max_distance: f64, capacities: Vec<usize>) -> AssignmentFlow {
+        let offices = capacities.len();
+        AssignmentFlow {
+            assigned: vec![None; distances.len()],
+            distances,
+            max_distance,
+            loads: vec![0; offices],
+            capacities,
+            moves: (0..offices).map(|_| (0..offices).map(|_| BinaryHeap::new()).collect()).collect(),
+            furthest: (0..offices).map(|_| BinaryHeap::new()).collect(),
+        }
+    }
+
+    // Adds `customer` along the cheapest chain of moves ending on an office
+    // with room for one more customer, or in place of the customer whose
+    // leaving saves the most when every office it can reach is full.
+    // Returns whether the customer got in.
+    fn insert(&mut self, customer: usize) -> bool {
+        let offices = self.capacities.len();
+        for from in 0..offices {
+            self.drop_stale(from);
+        }
+
+        // Bellman-Ford from the customer over the offices, moves can save
+        // distance so they can be negative.
+        let mut costs: Vec<f64> = (0..offices)
+            .map(|office| if self.reaches(customer, office) { self.distances[customer][office] } else { f64::INFINITY })
+            .collect();
+        let mut previous: Vec<Option<(usize, usize)>> = vec![None; offices];
+        for _ in 0..offices {
+            let mut relaxed = false;
+            for from in 0..offices {
+                if costs[from].is_infinite() {
+                    continue;
+                }
+                for to in 0..offices {
+                    if let Some(&Reverse(Candidate(cost, moved))) = self.moves[from][to].peek() {
+                        if costs[from] + cost < costs[to] - DISTANCE_TOLERANCE {
+                            costs[to] = costs[from] + cost;
+                            previous[to] = Some((from, moved));
+                            relaxed = true;
+                        }
+                    }
+                }
+            }
+            if !relaxed {
+                break;
+            }
+        }
+
+        let with_room = (0..offices)
+            .filter(|&office| self.loads[office] < self.capacities[office] && costs[office].is_finite())
+            .min_by(|&first, &second| costs[first].total_cmp(&costs[second]));
+        if let Some(office) = with_room {
+            self.move_along(customer, office, &previous);
+            self.loads[office] += 1;
+            return true;
+        }
+
+        let replaced = (0..offices)
+            .filter(|&office| costs[office].is_finite())
+            .filter_map(|office| self.furthest[office].peek().map(|&Candidate(distance, replaced)| (costs[office] - distance, office, replaced)))
+            .min_by(|first, second| first.0.total_cmp(&second.0));
+        match replaced {
+            Some((saving, office, replaced)) if saving < -DISTANCE_TOLERANCE => {
+                self.assigned[replaced] = None;
+                self.move_along(customer, office, &previous);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Moves the customers on the chain `previous` leads to `office` through
+    // and assigns `customer` to the first office of the chain.
+    fn move_along(&mut self, customer: usize, office: usize, previous: &[Option<(usize, usize)>]) {
+        let mut to = office;
+        while let Some((from, moved)) = previous[to] {
+            self.place(moved, to);
+            to = from;
+        }
+        self.place(customer, to);
+    }
+
+    fn place(&mut self, customer: usize, office: usize) {
+        self.assigned[customer] = Some(office);
+        let distance = self.distances[customer][office];
+        self.furthest[office].push(Candidate(distance, customer));
+        for other in (0..self.capacities.len()).filter(|&other| other != office) {
+            if self.reaches(customer, other) {
+                self.moves[office][other].push(Reverse(Candidate(self.distances[customer][other] - distance, customer)));
+            }
+        }
+    }
+
+    fn reaches(&self, customer: usize, office: usize) -> bool {
+        self.distances[customer][office] <= self.max_distance
+    }
+
+    fn drop_stale(&mut self, office: usize) {
+        let assigned = &self.assigned;
+        while self.furthest[office].peek().is_some_and(|&Candidate(_, customer)| assigned[customer] != Some(office)) {
+            self.furthest[office].pop();
+        }
+        for moves in &mut self.moves[office] {
+            while moves.peek().is_some_and(|&Reverse(Candidate(_, customer))| assigned[customer] != Some(office)) {
+                moves.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use customer::Customer;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+    const CORK: Location = Location { latitude: 51.8985, longitude: -8.4756 };
+    const GALWAY: Location = Location { latitude: 53.2707, longitude: -9.0568 };
+
+    fn generate_customer_list() -> CustomerList {
+        CustomerList::from_vec(vec![
+            Customer::new(1, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
+            Customer::new(2, "Nora Dempsey", &Location::new(53.1302756, -6.2397222)),
+            Customer::new(3, "Theresa Enright", &Location::new(53.1229599, -6.2705202)),
+            Customer::new(4, "Eoin Ahearn", &Location::new(54.0894797, -6.18671)),
+            Customer::new(5, "Olive Ahearn", &Location::new(53.00, -7.00)),
+            Customer::new(6, "Stephen McArdle", &Location::new(53.038056, -7.653889)),
+            Customer::new(7, "Alice Cahill", &Location::new(51.92893, -10.27699)),
+            Customer::new(8, "Jack Enright", &Location::new(52.3191841, -8.5072391)),
+            Customer::new(9, "Oliver Ahearn", &Location::new(53.74452, -7.11167)),
+            Customer::new(10, "Nick Enright", &Location::new(53.761389, -7.2875)),
+        ])
+    }
+
+    fn user_ids(customers: &[LocatedCustomer]) -> Vec<i64> {
+        customers.iter().map(|located| located.customer.user_id).collect()
+    }
+
+    // Tries every assignment of `customers` within the capacities, returning
+    // the most customers assigned and the shortest total distance for them.
+    fn brute_force(customers: &CustomerList, offices: &[Office], max_distance: f64) -> (usize, f64) {
+        fn search(customers: &CustomerList, offices: &[Office], max_distance: f64, index: usize, loads: &mut Vec<usize>, (assigned, total): (usize, f64), best: &mut (usize, f64)) {
+            if index == customers.len() {
+                if assigned > best.0 || (assigned == best.0 && total < best.1) {
+                    *best = (assigned, total);
+                }
+                return;
+            }
+            search(customers, offices, max_distance, index + 1, loads, (assigned, total), best);
+            for (office, load) in offices.iter().zip(0..) {
+                let distance = customers[index].distance_from(&office.location).0;
+                if loads[load] < office.capacity && distance <= max_distance {
+                    loads[load] += 1;
+                    search(customers, offices, max_distance, index + 1, loads, (assigned + 1, total + distance), best);
+                    loads[load] -= 1;
+                }
+            }
+        }
+
+        let mut best = (0, f64::INFINITY);
+        search(customers, offices, max_distance, 0, &mut vec![0; offices.len()], (0, 0f64), &mut best);
+        best
+    }
+
+    #[test]
+    fn assign_sends_customers_to_their_nearest_office_when_there_is_room() {
+        let offices = vec![Office::new("Dublin", DUBLIN, 10), Office::new("Cork", CORK, 10), Office::new("Galway", GALWAY, 10)];
+        let customers = generate_customer_list();
+        let territories = TerritoryAssignment::new(offices.clone()).assign(&customers);
+
+        for territory in &territories.territories {
+            for LocatedCustomer { customer, distance } in &territory.customers {
+                assert_eq!(*distance, customer.distance_from(&territory.office.location));
+                assert!(offices.iter().all(|office| *distance <= customer.distance_from(&office.location)));
+            }
+        }
+        assert_eq!(user_ids(&territories.territories[1].customers), vec![7, 8]);
+        assert!(territories.unassigned.is_empty());
+    }
+
+    #[test]
+    fn assign_respects_the_capacities_with_the_shortest_total_distance() {
+        let offices = vec![Office::new("Dublin", DUBLIN, 3), Office::new("Cork", CORK, 3), Office::new("Galway", GALWAY, 4)];
+        let customers = generate_customer_list();
+        let territories = TerritoryAssignment::new(offices.clone()).assign(&customers);
+
+        let loads: Vec<usize> = territories.territories.iter().map(|territory| territory.load()).collect();
+        assert!(loads.iter().zip(&offices).all(|(load, office)| *load <= office.capacity));
+        assert_eq!(loads.iter().sum::<usize>(), 10);
+
+        let (assigned, total) = brute_force(&customers, &offices, f64::INFINITY);
+        assert_eq!(assigned, 10);
+        assert!((territories.total_distance().0 - total).abs() < 1e-6);
+    }
+
+    #[test]
+    fn assign_leaves_out_the_customers_that_cost_the_most_when_short_of_room() {
+        let offices = vec![Office::new("Dublin", DUBLIN, 2), Office::new("Cork", CORK, 1), Office::new("Galway", GALWAY, 3)];
+        let customers = generate_customer_list();
+        let territories = TerritoryAssignment::new(offices.clone())
+            .with_max_distance(Kilometers(150.0))
+            .assign(&customers);
+
+        let (assigned, total) = brute_force(&customers, &offices, 150.0);
+        assert_eq!(customers.len() - territories.unassigned.len(), assigned);
+        assert!((territories.total_distance().0 - total).abs() < 1e-6);
+        for territory in &territories.territories {
+            assert!(territory.customers.iter().all(|located| located.distance.0 <= 150.0));
+        }
+    }
+
+    #[test]
+    fn assign_moves_customers_along_to_make_room_for_later_ones() {
+        // the first customer is closer to Dublin but can go to Galway, the
+        // second one can only go to Dublin.
+        let customers = CustomerList::from_vec(vec![
+            Customer::new(1, "Olive Ahearn", &Location::new(53.3, -7.6)),
+            Customer::new(2, "Ian Kehoe", &Location::new(53.2451022, -6.238335)),
+        ]);
+        let offices = vec![Office::new("Dublin", DUBLIN, 1), Office::new("Galway", GALWAY, 1)];
+        let territories = TerritoryAssignment::new(offices)
+            .with_max_distance(Kilometers(120.0))
+            .assign(&customers);
+
+        assert_eq!(user_ids(&territories.territories[0].customers), vec![2]);
+        assert_eq!(user_ids(&territories.territories[1].customers), vec![1]);
+    }
+
+    #[test]
+    fn assign_measures_the_customers_with_the_model() {
+        let offices = vec![Office::new("Dublin", DUBLIN, 10), Office::new("Cork", CORK, 10)];
+        let territories = TerritoryAssignment::new(offices)
+            .with_model(DistanceModel::Vincenty)
+            .assign(&generate_customer_list());
+
+        for territory in &territories.territories {
+            for located in &territory.customers {
+                assert_eq!(located.distance, DistanceModel::Vincenty.distance(&located.customer.location(), &territory.office.location));
+            }
+        }
+    }
+
+    #[test]
+    fn territory_reports_its_load_and_average_distance() {
+        let territories = TerritoryAssignment::new(vec![Office::new("Dublin", DUBLIN, 2)]).assign(&generate_customer_list());
+        let dublin = &territories.territories[0];
+
+        assert_eq!(dublin.load(), 2);
+        assert_eq!(territories.unassigned.len(), 8);
+        let expected = dublin.customers.iter().map(|located| located.customer.distance_from(&DUBLIN).0).sum::<f64>() / 2.0;
+        assert_eq!(dublin.average_distance(), Some(Kilometers(expected)));
+        assert_eq!(user_ids(&dublin.customers), vec![1, 2]);
+    }
+}
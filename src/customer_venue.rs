@@ -0,0 +1,661 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module implementing the search for the best places to meet customers
+//! at, all of them calculated on the sphere so they hold across the
+//! antimeridian and near the poles.
+//!
+//! * The spherical centroid, `Location::centroid`, is the quickest to
+//!   calculate but gets dragged by far away customers.
+//! * The geometric median minimises the total distance travelled by the
+//!   customers.
+//! * The minimax centre minimises the distance travelled by the customer
+//!   furthest away.
+//! * `FacilityPlacement` chooses several venues to have as many customers
+//!   as possible within a radius of one of them.
+//!
+//! The centroid is a method of `CustomerList`, the median and the minimax
+//! centre are the `geometric_median` and `minimax_centre` functions.
+//!
+//! # Examples
+//!
+//! ```
+//! let party = customer_venue::geometric_median(&customers).unwrap();
+//! println!("{} in total", customers.total_distance_from(&party));
+//!
+//! let placement = FacilityPlacement::new(3, Kilometers(25f64)).place(&customers);
+//! for venue in &placement.venues {
+//!     println!("{}: {} customers", venue.location, venue.customers.len());
+//! }
+//! ```
+//!
+
+use customer::CustomerList;
+use customer_cluster::NeighbourGrid;
+use customer_locator::LocatedCustomer;
+use location::{DistanceModel, Location};
+use units::Kilometers;
+
+const MAX_MEDIAN_ITERATIONS: usize = 1000;
+// Below this angle, in radians (about a millimeter), two points are the same one.
+const ANGLE_TOLERANCE: f64 = 1e-10;
+const MAX_SWAP_PASSES: usize = 10;
+
+/// Returns the point minimising the sum of the great-circle distances from
+/// `customers` to it, with the spherical version of [Weiszfeld's algorithm]
+/// (https://en.wikipedia.org/wiki/Geometric_median#Computation) started from
+/// their centroid. `None` when there are no customers.
+///
+/// # Examples
+///
+/// ```
+/// let party = customer_venue::geometric_median(&customers).unwrap();
+/// println!("{} travelled in total", customers.total_distance_from(&party));
+/// ```
+pub fn geometric_median(customers: &CustomerList) -> Option<Location> {
+    let locations = customers.iter().map(|customer| customer.location()).collect::<Vec<_>>();
+    let first = *locations.first()?;
+    let vectors = locations.iter().map(|location| location.to_vector()).collect::<Vec<_>>();
+
+    let mut median = Location::centroid(&locations).unwrap_or(first).to_vector();
+    for _ in 0..MAX_MEDIAN_ITERATIONS {
+        // each location pulls the median in proportion to the inverse of the
+        // sine of its distance, which leaves it still where the pulls along
+        // the surface cancel out.
+        let mut pull = [0f64; 3];
+        for vector in &vectors {
+            let sine = norm(cross(median, *vector));
+            if sine < ANGLE_TOLERANCE {
+                continue;
+            }
+            pull = add(pull, scale(*vector, 1f64 / sine));
+        }
+
+        let next = match Location::from_vector(pull) {
+            Some(next) => next.to_vector(),
+            None => break,
+        };
+        let moved = norm(cross(median, next));
+        median = next;
+        if moved < ANGLE_TOLERANCE {
+            break;
+        }
+    }
+
+    Location::from_vector(median)
+}
+
+/// Returns the centre of the smallest circle on the surface holding every
+/// one of `customers`, the point minimising the distance to the customer
+/// furthest away from it.
+///
+/// It's the direction of the centre of the smallest ball around their unit
+/// vectors, found with [Welzl's algorithm]
+/// (https://en.wikipedia.org/wiki/Smallest-circle_problem). `None` when
+/// there are no customers, or they don't fit in a hemisphere and every
+/// point has one of them at least a quarter of the earth away.
+///
+/// # Examples
+///
+/// ```
+/// let party = customer_venue::minimax_centre(&customers).unwrap();
+/// println!("nobody travels more than {}", customers.max_distance_from(&party));
+/// ```
+pub fn minimax_centre(customers: &CustomerList) -> Option<Location> {
+    let mut vectors = customers.iter().map(|customer| customer.location().to_vector()).collect::<Vec<_>>();
+    if vectors.is_empty() {
+        return None;
+    }
+    shuffle(&mut vectors);
+
+    let mut ball = Ball::around(&[vectors[0]]);
+    for i in 1..vectors.len() {
+        if ball.contains(vectors[i]) {
+            continue;
+        }
+        ball = Ball::around(&[vectors[i]]);
+        for j in 0..i {
+            if ball.contains(vectors[j]) {
+                continue;
+            }
+            ball = Ball::around(&[vectors[i], vectors[j]]);
+            for k in 0..j {
+                if ball.contains(vectors[k]) {
+                    continue;
+                }
+                ball = Ball::around(&[vectors[i], vectors[j], vectors[k]]);
+                for l in 0..k {
+                    if !ball.contains(vectors[l]) {
+                        ball = Ball::around(&[vectors[i], vectors[j], vectors[k], vectors[l]]);
+                    }
+                }
+            }
+        }
+    }
+
+    // a ball centred on the center of the earth is as far from every point.
+    if norm(ball.center) < 1e-9 {
+        return None;
+    }
+    Location::from_vector(ball.center)
+}
+
+/// A ball in space, squared radius included.
+struct Ball {
+    center: [f64; 3],
+    radius_squared: f64,
+}
+
+impl Ball {
+    // The smallest ball with `points`, at most 4 of them, on its surface.
+    fn around(points: &[[f64; 3]]) -> Ball {
+        let center = match *points {
+            [a] => a,
+            [a, b] => scale(add(a, b), 0.5f64),
+            [a, b, c] => {
+                let (ab, ac) = (sub(b, a), sub(c, a));
+                let normal = cross(ab, ac);
+                let normal_squared = dot(normal, normal);
+                if normal_squared < 1e-24 {
+                    return Ball::widest(points);
+                }
+                let offset = add(scale(cross(normal, ab), dot(ac, ac)), scale(cross(ac, normal), dot(ab, ab)));
+                add(a, scale(offset, 1f64 / (2f64 * normal_squared)))
+            }
+            [a, b, c, d] => {
+                // the center is as far from the 4 points: 2 (p - a) . x = |p|^2 - |a|^2
+                let rows = [scale(sub(b, a), 2f64), scale(sub(c, a), 2f64), scale(sub(d, a), 2f64)];
+                let rhs = [dot(b, b) - dot(a, a), dot(c, c) - dot(a, a), dot(d, d) - dot(a, a)];
+                let determinant = dot(rows[0], cross(rows[1], rows[2]));
+                if determinant.abs() < 1e-18 {
+                    return Ball::widest(points);
+                }
+                // Cramer's rule over the columns of the system.
+                let columns = transpose(rows);
+                [dot(rhs, cross(columns[1], columns[2])) / determinant,
+                 dot(columns[0], cross(rhs, columns[2])) / determinant,
+                 dot(columns[0], cross(columns[1], rhs)) / determinant]
+            }
+            _ => unreachable!("a ball is defined by 1 to 4 points"),
+        };
+
+        let radius_squared = points.iter().map(|&point| squared_distance(center, point)).fold(0f64, f64::max);
+        Ball { center, radius_squared }
+    }
+
+    // The widest of the balls around each pair of `points`, used when they
+    // are degenerate and all of them lie on the same circle or line.
+    fn widest(points: &[[f64; 3]]) -> Ball {
+        let mut widest = Ball::around(&points[..1]);
+        for i in 0..points.len() {
+            for j in i + 1..points.len() {
+                let ball = Ball::around(&[points[i], points[j]]);
+                if ball.radius_squared > widest.radius_squared {
+                    widest = ball;
+                }
+            }
+        }
+
+        widest
+    }
+
+    fn contains(&self, point: [f64; 3]) -> bool {
+        squared_distance(self.center, point) <= self.radius_squared + 1e-12
+    }
+}
+
+/// A venue chosen by `FacilityPlacement` and the customers going to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Venue {
+    /// "Venue 1", "Venue 2" and so on, in the order they were chosen.
+    pub name: String,
+    pub location: Location,
+    /// The index of the venue on the candidates it was chosen from, `None`
+    /// when it was found anywhere.
+    pub candidate: Option<usize>,
+    /// The customers having this venue as the closest one within the
+    /// radius of the placement, with their distance to it.
+    pub customers: Vec<LocatedCustomer>,
+}
+
+/// The venues chosen by `FacilityPlacement` and the customers too far
+/// away from every one of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub venues: Vec<Venue>,
+    pub uncovered: CustomerList,
+}
+
+/// A solver choosing `venues` locations to have as many customers as
+/// possible within `radius` of one of them, the [maximum coverage problem]
+/// (https://en.wikipedia.org/wiki/Maximum_coverage_problem).
+///
+/// Venues are chosen one at a time, each one covering as many customers
+/// not covered yet as possible, and then swapped for other candidates
+/// while that covers more customers. Choosing them one at a time is
+/// guaranteed to cover at least 63% of what the best placement does, the
+/// swaps usually get it much closer.
+///
+/// Venues are chosen among the candidates given, or anywhere when there
+/// are none: then the candidates are the locations of the customers and
+/// the centroids of the customers within `radius` of each one.
+///
+/// # Examples
+///
+/// ```
+/// let hotels = vec![Location::new(53.3438, -6.2546), Location::new(51.8985, -8.4756)];
+/// let placement = FacilityPlacement::new(1, Kilometers(50f64))
+///     .with_candidates(hotels)
+///     .place(&customers);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FacilityPlacement {
+    venues: usize,
+    radius: Kilometers,
+    candidates: Option<Vec<Location>>,
+    model: DistanceModel,
+}
+
+impl FacilityPlacement {
+    pub fn new(venues: usize, radius: Kilometers) -> FacilityPlacement {
+        FacilityPlacement { venues, radius, candidates: None, model: DistanceModel::Haversine }
+    }
+
+    /// Calculates the distances with `model` instead of the haversine one.
+    pub fn with_model(mut self, model: DistanceModel) -> FacilityPlacement {
+        self.model = model;
+        self
+    }
+
+    /// Chooses the venues among `candidates` instead of anywhere.
+    pub fn with_candidates(mut self, candidates: Vec<Location>) -> FacilityPlacement {
+        self.candidates = Some(candidates);
+        self
+    }
+
+    /// Places the venues for `customers`. Fewer venues than asked for are
+    /// placed when the rest wouldn't cover anyone else.
+    pub fn place(&self, customers: &CustomerList) -> Placement {
+        let locations = customers.iter().map(|customer| customer.location()).collect::<Vec<_>>();
+        let grid = NeighbourGrid::new(&locations, self.radius, self.model);
+        let candidates = match self.candidates {
+            Some(ref candidates) => candidates.clone(),
+            None => free_space_candidates(&locations, &grid),
+        };
+        let coverage = candidates.iter().map(|candidate| grid.within(candidate)).collect::<Vec<_>>();
+
+        let mut counts = CoverCounts::new(locations.len());
+        let mut chosen: Vec<usize> = Vec::new();
+        while chosen.len() < self.venues {
+            let mut best: Option<(usize, usize)> = None;
+            for (candidate, covers) in coverage.iter().enumerate() {
+                if chosen.contains(&candidate) {
+                    continue;
+                }
+                let gain = counts.gain(covers);
+                if gain > best.map_or(0, |(_, best_gain)| best_gain) {
+                    best = Some((candidate, gain));
+                }
+            }
+            match best {
+                Some((candidate, _)) => {
+                    counts.add(&coverage[candidate]);
+                    chosen.push(candidate);
+                }
+                None => break,
+            }
+        }
+
+        // swapping a venue for another candidate while that covers more
+        // customers. Without the venue of the slot, each candidate would
+        // cover the customers it gains over the other venues.
+        for _ in 0..MAX_SWAP_PASSES {
+            let mut improved = false;
+            for slot in 0..chosen.len() {
+                counts.remove(&coverage[chosen[slot]]);
+                let mut best_gain = counts.gain(&coverage[chosen[slot]]);
+                for (candidate, covers) in coverage.iter().enumerate() {
+                    if chosen.contains(&candidate) {
+                        continue;
+                    }
+                    let gain = counts.gain(covers);
+                    if gain > best_gain {
+                        best_gain = gain;
+                        chosen[slot] = candidate;
+                        improved = true;
+                    }
+                }
+                counts.add(&coverage[chosen[slot]]);
+            }
+            if !improved {
+                break;
+            }
+        }
+
+        self.assign(customers, &locations, &candidates, &coverage, &chosen)
+    }
+
+    // Sends each covered customer to the closest chosen venue covering it.
+    fn assign(&self,
+              customers: &CustomerList,
+              locations: &[Location],
+              candidates: &[Location],
+              coverage: &[Vec<usize>],
+              chosen: &[usize])
+              -> Placement {
+        let mut venue_of: Vec<Option<(usize, Kilometers)>> = vec![None; locations.len()];
+        for (venue, &candidate) in chosen.iter().enumerate() {
+            for &customer in &coverage[candidate] {
+                let distance = self.model.distance(&locations[customer], &candidates[candidate]);
+                if venue_of[customer].is_none_or(|(_, closest)| distance < closest) {
+                    venue_of[customer] = Some((venue, distance));
+                }
+            }
+        }
+
+        let mut members = vec![Vec::new(); chosen.len()];
+        let mut uncovered = Vec::new();
+        for (customer, venue) in customers.iter().zip(venue_of) {
+            match venue {
+                Some((venue, distance)) => members[venue].push(LocatedCustomer { customer: customer.clone(), distance }),
+                None => uncovered.push(customer.clone()),
+            }
+        }
+        let venues = chosen.iter()
+            .zip(members)
+            .enumerate()
+            .map(|(index, (&candidate, members))| Venue {
+                name: format!("Venue {}", index + 1),
+                location: candidates[candidate],
+                candidate: self.candidates.as_ref().map(|_| candidate),
+                customers: members,
+            })
+            .collect();
+
+        Placement { venues, uncovered: CustomerList::from_vec(uncovered) }
+    }
+}
+
+// The customer locations, and the centroid of the customers within the
+// radius of each one, which is often a better spot than any customer.
+fn free_space_candidates(locations: &[Location], grid: &NeighbourGrid) -> Vec<Location> {
+    let mut candidates = locations.to_vec();
+    for index in 0..locations.len() {
+        let neighbours = grid.neighbours(index);
+        if neighbours.len() > 1 {
+            let around = neighbours.iter().map(|&neighbour| locations[neighbour]).collect::<Vec<_>>();
+            candidates.extend(Location::centroid(&around));
+        }
+    }
+
+    candidates
+}
+
+/// How many of the chosen venues cover each customer, to tell what a
+/// candidate would add without counting the whole coverage again.
+struct CoverCounts {
+    counts: Vec<usize>,
+}
+
+impl CoverCounts {
+    fn new(customers: usize) -> CoverCounts {
+        CoverCounts { counts: vec![0; customers] }
+    }
+
+    fn add(&mut self, customers: &[usize]) {
+        for &customer in customers {
+            self.counts[customer] += 1;
+        }
+    }
+
+    fn remove(&mut self, customers: &[usize]) {
+        for &customer in customers {
+            self.counts[customer] -= 1;
+        }
+    }
+
+    // The customers among `customers` not covered by any venue yet.
+    fn gain(&self, customers: &[usize]) -> usize {
+        customers.iter().filter(|&&customer| self.counts[customer] == 0).count()
+    }
+}
+
+// A Fisher-Yates shuffle with a fixed seed: Welzl's algorithm needs the
+// points in random order to run in linear time, and the same input should
+// give the same output.
+fn shuffle<T>(items: &mut [T]) {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    for index in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        items.swap(index, (state % (index as u64 + 1)) as usize);
+    }
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f64; 3], factor: f64) -> [f64; 3] {
+    [a[0] * factor, a[1] * factor, a[2] * factor]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn squared_distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    let difference = sub(a, b);
+    dot(difference, difference)
+}
+
+fn transpose(rows: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    [[rows[0][0], rows[1][0], rows[2][0]],
+     [rows[0][1], rows[1][1], rows[2][1]],
+     [rows[0][2], rows[1][2], rows[2][2]]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use customer::Customer;
+
+    const DUBLIN: Location = Location { latitude: 53.3393, longitude: -6.2576841 };
+    const CORK: Location = Location { latitude: 51.8969, longitude: -8.4863 };
+
+    fn customers_at(locations: &[Location]) -> CustomerList {
+        locations.iter()
+            .enumerate()
+            .map(|(index, location)| Customer::new(index as i64 + 1, "Customer", location))
+            .collect()
+    }
+
+    // `count` locations around `center`, within roughly `spread` degrees.
+    fn around(center: &Location, spread: f64, count: usize) -> Vec<Location> {
+        (0..count)
+            .map(|index| {
+                let angle = index as f64 * 2.4;
+                let distance = spread * ((index % 5) as f64 + 1f64) / 5f64;
+                Location::new(center.latitude + distance * angle.sin(), center.longitude + distance * angle.cos())
+            })
+            .collect()
+    }
+
+    fn covered(placement: &Placement) -> usize {
+        placement.venues.iter().map(|venue| venue.customers.len()).sum()
+    }
+
+    fn assert_close(location: &Location, expected: &Location, kilometers: f64) {
+        let distance = location.distance_from(expected).0;
+        assert!(distance < kilometers, "{} is {} Km away from {}", location, distance, expected);
+    }
+
+    #[test]
+    fn geometric_median_resists_far_away_customers() {
+        let mut locations = around(&DUBLIN, 0.05, 9);
+        locations.push(Location::new(40.7128, -74.0059));
+        let customers = customers_at(&locations);
+
+        let median = geometric_median(&customers).unwrap();
+        let centroid = customers.centroid().unwrap();
+
+        assert_close(&median, &DUBLIN, 10f64);
+        assert!(centroid.distance_from(&DUBLIN).0 > 300f64);
+        assert!(customers.total_distance_from(&median) < customers.total_distance_from(&centroid));
+    }
+
+    #[test]
+    fn geometric_median_is_a_local_minimum_of_the_total_distance() {
+        let customers = customers_at(&[DUBLIN, CORK, Location::new(53.2707, -9.0568), Location::new(54.5973, -5.9301)]);
+        let median = geometric_median(&customers).unwrap();
+        let total = customers.total_distance_from(&median).0;
+
+        for (delta_latitude, delta_longitude) in [(0.01, 0.0), (-0.01, 0.0), (0.0, 0.01), (0.0, -0.01)] {
+            let nearby = Location::new(median.latitude + delta_latitude, median.longitude + delta_longitude);
+            assert!(customers.total_distance_from(&nearby).0 > total - 1e-6);
+        }
+    }
+
+    #[test]
+    fn minimax_centre_is_the_middle_of_the_furthest_customers() {
+        let customers = customers_at(&[DUBLIN, CORK, Location::new(52.6, -7.3)]);
+
+        let centre = minimax_centre(&customers).unwrap();
+
+        let half = DUBLIN.distance_from(&CORK).0 / 2f64;
+        assert!((centre.distance_from(&DUBLIN).0 - half).abs() < 1e-6);
+        assert!((centre.distance_from(&CORK).0 - half).abs() < 1e-6);
+        assert!((customers.max_distance_from(&centre).0 - half).abs() < 1e-6);
+    }
+
+    #[test]
+    fn minimax_centre_minimises_the_furthest_distance() {
+        let customers = customers_at(&around(&Location::new(-17.5, 179.8), 2f64, 25));
+        let centre = minimax_centre(&customers).unwrap();
+        let furthest = customers.max_distance_from(&centre).0;
+
+        for customer in &customers {
+            assert!(customer.distance_from(&centre).0 <= furthest + 1e-6);
+        }
+        for (delta_latitude, delta_longitude) in [(0.01, 0.0), (-0.01, 0.0), (0.0, 0.01), (0.0, -0.01)] {
+            let nearby = Location::new(centre.latitude + delta_latitude, centre.longitude + delta_longitude);
+            assert!(customers.max_distance_from(&nearby).0 > furthest - 1e-6);
+        }
+    }
+
+    #[test]
+    fn centres_of_no_customers_are_none() {
+        let customers = CustomerList::default();
+        assert_eq!(customers.centroid(), None);
+        assert_eq!(geometric_median(&customers), None);
+        assert_eq!(minimax_centre(&customers), None);
+
+        // a point and its antipode are as far from every location.
+        let antipodes = customers_at(&[Location::new(0.0, 0.0), Location::new(0.0, 180.0)]);
+        assert_eq!(minimax_centre(&antipodes), None);
+    }
+
+    #[test]
+    fn facility_placement_covers_the_biggest_groups_first() {
+        let mut locations = around(&DUBLIN, 0.1, 10);
+        locations.extend(around(&CORK, 0.1, 6));
+        locations.push(Location::new(53.2707, -9.0568));
+        let customers = customers_at(&locations);
+
+        let placement = FacilityPlacement::new(2, Kilometers(20f64)).place(&customers);
+
+        assert_eq!(placement.venues.len(), 2);
+        assert_eq!(placement.venues[0].customers.len(), 10);
+        assert_close(&placement.venues[0].location, &DUBLIN, 20f64);
+        assert_eq!(placement.venues[1].customers.len(), 6);
+        assert_close(&placement.venues[1].location, &CORK, 20f64);
+        assert_eq!(covered(&placement), 16);
+        assert_eq!(placement.uncovered.len(), 1);
+        assert_eq!(placement.venues[0].candidate, None);
+    }
+
+    #[test]
+    fn facility_placement_chooses_among_the_candidates() {
+        let mut locations = around(&DUBLIN, 0.1, 10);
+        locations.extend(around(&CORK, 0.1, 6));
+        let customers = customers_at(&locations);
+        let candidates = vec![Location::new(53.2707, -9.0568), CORK, DUBLIN];
+
+        let placement = FacilityPlacement::new(1, Kilometers(20f64))
+            .with_candidates(candidates)
+            .place(&customers);
+
+        assert_eq!(placement.venues.len(), 1);
+        assert_eq!(placement.venues[0].candidate, Some(2));
+        assert_eq!(placement.venues[0].location, DUBLIN);
+        assert_eq!(placement.uncovered.len(), 6);
+    }
+
+    #[test]
+    fn facility_placement_swaps_venues_for_better_ones() {
+        // the first venue chosen covers the most customers, but two others
+        // split the same customers and more between them.
+        let west = Location::new(53.0, -7.0);
+        let east = Location::new(53.0, -6.0);
+        let middle = Location::new(53.0, -6.5);
+        let mut locations = Vec::new();
+        locations.extend(around(&Location::new(53.0, -6.75), 0.01, 4));
+        locations.extend(around(&Location::new(53.0, -6.25), 0.01, 4));
+        locations.extend(around(&Location::new(53.0, -7.2), 0.01, 3));
+        locations.extend(around(&Location::new(53.0, -5.8), 0.01, 3));
+        let customers = customers_at(&locations);
+
+        let placement = FacilityPlacement::new(2, Kilometers(20f64))
+            .with_candidates(vec![middle, west, east])
+            .place(&customers);
+
+        assert_eq!(covered(&placement), 14);
+        let mut chosen = placement.venues.iter().map(|venue| venue.candidate.unwrap()).collect::<Vec<_>>();
+        chosen.sort();
+        assert_eq!(chosen, vec![1, 2]);
+    }
+
+    #[test]
+    fn facility_placement_stops_when_nobody_else_is_covered() {
+        let customers = customers_at(&around(&DUBLIN, 0.05, 5));
+
+        let placement = FacilityPlacement::new(3, Kilometers(50f64)).place(&customers);
+
+        assert_eq!(placement.venues.len(), 1);
+        assert_eq!(covered(&placement), 5);
+    }
+
+    #[test]
+    fn facility_placement_measures_the_customers_with_the_model() {
+        let customers = customers_at(&around(&DUBLIN, 0.05, 5));
+
+        let placement = FacilityPlacement::new(1, Kilometers(50f64))
+            .with_model(DistanceModel::Vincenty)
+            .place(&customers);
+
+        let venue = &placement.venues[0];
+        for located in &venue.customers {
+            assert_eq!(located.distance, DistanceModel::Vincenty.distance(&located.customer.location(), &venue.location));
+        }
+    }
+}
@@ -0,0 +1,391 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module containing an offline gazetteer: the named places a `Location`
+//! can be given by, like `cork` or `office:galway`, instead of its
+//! latitude and longitude.
+//!
+//! The world capitals and some major cities come bundled from
+//! `data/places.csv`. User defined places, like offices, are read from a
+//! CSV file with `name,latitude,longitude` columns and take precedence over
+//! the bundled ones.
+//!
+//! # Examples
+//!
+//! ```
+//! let gazetteer = Gazetteer::bundled().with_places(Gazetteer::read_places(Path::new("places.csv"))?);
+//! let cork = gazetteer.locate("cork")?;
+//! let galway_office = gazetteer.locate("office:galway")?;
+//! let somewhere = gazetteer.locate("53.3393,-6.2576841")?;
+//! ```
+//!
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::Error as IoError;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use csv;
+use csv::Error as CsvError;
+
+use location::{Location, ParseLocationError};
+
+const BUNDLED_PLACES: &str = include_str!("../data/places.csv");
+
+// The gazetteer `Location`s are displayed with, see `Gazetteer::install`.
+static GLOBAL_GAZETTEER: OnceLock<Gazetteer> = OnceLock::new();
+
+/// The kinds of places, in the order they are preferred when several share
+/// a name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaceKind {
+    #[default]
+    User,
+    Capital,
+    City,
+}
+
+/// A named place on earth surface.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Place {
+    pub name: String,
+    #[serde(default)]
+    pub country_code: String,
+    #[serde(default)]
+    pub country: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default)]
+    pub kind: PlaceKind,
+}
+
+impl Place {
+    /// Returns the `Location` of the place.
+    pub fn location(&self) -> Location {
+        Location::new(self.latitude, self.longitude)
+    }
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.country.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{}, {}", self.name, self.country)
+        }
+    }
+}
+
+/// An error encapsulating the things that can go wrong when reading places
+/// or looking them up.
+#[derive(Debug)]
+pub enum GazetteerError {
+    Io(IoError),
+    Csv(CsvError),
+    Location(ParseLocationError),
+    UnknownPlace(String, Vec<String>),
+}
+
+impl fmt::Display for GazetteerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GazetteerError::Io(ref err) => write!(f, "Gazetteer IO error: {}", err),
+            GazetteerError::Csv(ref err) => write!(f, "Gazetteer CSV error: {}", err),
+            GazetteerError::Location(ref err) => write!(f, "{}", err),
+            GazetteerError::UnknownPlace(ref name, ref suggestions) if suggestions.is_empty() => {
+                write!(f, "unknown place '{}'", name)
+            }
+            GazetteerError::UnknownPlace(ref name, ref suggestions) => {
+                write!(f, "unknown place '{}', did you mean '{}'?", name, suggestions.join("' or '"))
+            }
+        }
+    }
+}
+
+impl error::Error for GazetteerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            GazetteerError::Io(ref err) => Some(err),
+            GazetteerError::Csv(ref err) => Some(err),
+            GazetteerError::Location(ref err) => Some(err),
+            GazetteerError::UnknownPlace(..) => None,
+        }
+    }
+}
+
+impl From<IoError> for GazetteerError {
+    fn from(err: IoError) -> Self {
+        GazetteerError::Io(err)
+    }
+}
+
+impl From<CsvError> for GazetteerError {
+    fn from(err: CsvError) -> Self {
+        GazetteerError::Csv(err)
+    }
+}
+
+impl From<ParseLocationError> for GazetteerError {
+    fn from(err: ParseLocationError) -> Self {
+        GazetteerError::Location(err)
+    }
+}
+
+/// Struct looking places up by name, allowing for typos, and by location.
+#[derive(Debug, Clone, Default)]
+pub struct Gazetteer {
+    places: Vec<Place>,
+    // normalized name -> indexes of the places with that name, best first.
+    names: HashMap<String, Vec<usize>>,
+    // coordinates bits -> index of the best place at them.
+    locations: HashMap<(u64, u64), usize>,
+}
+
+impl Gazetteer {
+    /// Constructs a new `Gazetteer` with the bundled world capitals and cities.
+    pub fn bundled() -> Gazetteer {
+        let places = read_places_from(BUNDLED_PLACES.as_bytes()).expect("the bundled places are valid");
+        Gazetteer::default().with_places(places)
+    }
+
+    /// Reads user defined places from the CSV file at `path`.
+    ///
+    /// The file has a header and at least `name`, `latitude` and `longitude`
+    /// columns. `country_code` and `country` can be given too.
+    ///
+    /// # Errors
+    ///
+    /// When the file can't be read or a row is malformed.
+    pub fn read_places(path: &Path) -> Result<Vec<Place>, GazetteerError> {
+        read_places_from(File::open(path)?)
+    }
+
+    /// Adds `places` to the gazetteer.
+    pub fn with_places(mut self, places: Vec<Place>) -> Gazetteer {
+        for place in places {
+            let index = self.places.len();
+            let name = normalize(&place.name);
+            let coordinates = (place.latitude.to_bits(), place.longitude.to_bits());
+            let kind = place.kind;
+            self.places.push(place);
+
+            // the sort is stable so places of the same kind keep the order they were added in.
+            let places = &self.places;
+            let same_name = self.names.entry(name).or_default();
+            same_name.push(index);
+            same_name.sort_by_key(|index| places[*index].kind);
+
+            match self.locations.get(&coordinates) {
+                Some(other) if places[*other].kind <= kind => (),
+                _ => {
+                    self.locations.insert(coordinates, index);
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Makes `gazetteer` the one `global` returns, for `Location`s to be
+    /// displayed with the user defined places. Only the first call has an
+    /// effect, returning the gazetteer back on the next ones.
+    pub fn install(gazetteer: Gazetteer) -> Result<(), Gazetteer> {
+        GLOBAL_GAZETTEER.set(gazetteer)
+    }
+
+    /// Returns the installed gazetteer, or the bundled one if none was.
+    pub fn global() -> &'static Gazetteer {
+        GLOBAL_GAZETTEER.get_or_init(Gazetteer::bundled)
+    }
+
+    /// Returns the location `query` refers to, either `latitude,longitude`
+    /// or the name of a place as `find` takes it.
+    ///
+    /// # Errors
+    ///
+    /// When the coordinates are malformed or there's no such place.
+    pub fn locate(&self, query: &str) -> Result<Location, GazetteerError> {
+        let query = query.trim();
+        let looks_like_coordinates = query.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.');
+        if looks_like_coordinates {
+            return Ok(Location::from_str(&query.replace(' ', ""))?);
+        }
+
+        self.find(query).map(Place::location)
+    }
+
+    /// Returns the place named `name`, ignoring case and accents.
+    ///
+    /// When no place has that exact name, a place whose name is a typo away
+    /// is returned as long as there's a single one.
+    ///
+    /// # Errors
+    ///
+    /// When there's no such place, with the closest names as suggestions.
+    pub fn find(&self, name: &str) -> Result<&Place, GazetteerError> {
+        let normalized = normalize(name);
+        if let Some(indexes) = self.names.get(&normalized) {
+            return Ok(&self.places[indexes[0]]);
+        }
+
+        let allowed_typos = match normalized.chars().count() {
+            0..=4 => 1,
+            5..=8 => 2,
+            _ => 3,
+        };
+        let mut candidates: Vec<(usize, &String)> = self.names
+            .keys()
+            .map(|candidate| (edit_distance(&normalized, candidate), candidate))
+            .filter(|&(distance, _)| distance <= allowed_typos + 1)
+            .collect();
+        candidates.sort();
+
+        let closest_distance = candidates.first().map(|&(distance, _)| distance);
+        let closest_count = candidates.iter().filter(|&&(distance, _)| Some(distance) == closest_distance).count();
+        match candidates.first() {
+            Some(&(distance, candidate)) if distance <= allowed_typos && closest_count == 1 => {
+                Ok(&self.places[self.names[candidate][0]])
+            }
+            _ => {
+                let suggestions = candidates.iter()
+                    .take(3)
+                    .map(|&(_, candidate)| self.places[self.names[candidate][0]].name.clone())
+                    .collect();
+                Err(GazetteerError::UnknownPlace(name.to_string(), suggestions))
+            }
+        }
+    }
+
+    /// Returns the place at exactly `location`, if any.
+    pub fn place_at(&self, location: &Location) -> Option<&Place> {
+        self.locations
+            .get(&(location.latitude.to_bits(), location.longitude.to_bits()))
+            .map(|index| &self.places[*index])
+    }
+
+    /// Returns the names of the places, lowercased.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.keys().map(String::as_str)
+    }
+}
+
+fn read_places_from<R: ::std::io::Read>(reader: R) -> Result<Vec<Place>, GazetteerError> {
+    let mut csv_reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    let places = csv_reader.deserialize().collect::<Result<Vec<Place>, CsvError>>()?;
+    Ok(places)
+}
+
+// Lowercases `name`, strips the most common accents and collapses spaces.
+fn normalize(name: &str) -> String {
+    let folded: String = name.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'á' | 'à' | 'â' | 'ä' | 'ã' | 'å' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'í' | 'ì' | 'î' | 'ï' => 'i',
+            'ó' | 'ò' | 'ô' | 'ö' | 'õ' | 'ø' => 'o',
+            'ú' | 'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            'ñ' => 'n',
+            other => other,
+        })
+        .collect();
+
+    folded.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Levenshtein distance between `first` and `second`.
+fn edit_distance(first: &str, second: &str) -> usize {
+    let second: Vec<char> = second.chars().collect();
+    let mut previous: Vec<usize> = (0..=second.len()).collect();
+    let mut current = vec![0; second.len() + 1];
+
+    for (i, first_char) in first.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, second_char) in second.iter().enumerate() {
+            let substitution = previous[j] + if first_char == *second_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[second.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLACES_CSV_FILE: &str = "tests/fixtures/places.csv";
+
+    fn generate_gazetteer() -> Gazetteer {
+        Gazetteer::bundled().with_places(Gazetteer::read_places(Path::new(PLACES_CSV_FILE)).unwrap())
+    }
+
+    #[test]
+    fn locate_resolves_coordinates_and_places() {
+        let gazetteer = generate_gazetteer();
+
+        assert_eq!(gazetteer.locate("53.3393, -6.2576841").unwrap(), Location::new(53.3393, -6.2576841));
+        assert_eq!(gazetteer.locate("Cork").unwrap(), Location::new(51.8985, -8.4756));
+        assert_eq!(gazetteer.locate("office:galway").unwrap(), Location::new(53.2743, -9.0514));
+        assert_eq!(gazetteer.locate("  são paulo ").unwrap(), Location::new(-23.5505, -46.6333));
+    }
+
+    #[test]
+    fn find_prefers_user_places_over_bundled_ones() {
+        let gazetteer = generate_gazetteer();
+        let place = gazetteer.find("limerick").unwrap();
+
+        assert_eq!(place.kind, PlaceKind::User);
+        assert_eq!(place.to_string(), "Limerick");
+    }
+
+    #[test]
+    fn find_forgives_a_typo_when_there_is_a_single_match() {
+        let gazetteer = generate_gazetteer();
+
+        assert_eq!(gazetteer.find("corck").unwrap().name, "Cork");
+        assert_eq!(gazetteer.find("Galwey").unwrap().name, "Galway");
+        assert_eq!(gazetteer.find("ofice:galway").unwrap().name, "office:galway");
+    }
+
+    #[test]
+    fn find_fails_with_suggestions_on_unknown_places() {
+        match generate_gazetteer().find("atlantis") {
+            Err(GazetteerError::UnknownPlace(ref name, ref suggestions)) if name == "atlantis" && suggestions.is_empty() => (),
+            other => panic!("expected an unknown place error, got {:?}", other),
+        }
+
+        let error = generate_gazetteer().locate("Xome").unwrap_err();
+        assert_eq!(error.to_string(), "unknown place 'Xome', did you mean 'Lome' or 'Rome'?");
+    }
+
+    #[test]
+    fn place_at_finds_the_place_at_a_location() {
+        let gazetteer = generate_gazetteer();
+
+        assert_eq!(gazetteer.place_at(&Location::new(53.3393, -6.2576841)).unwrap().to_string(), "Dublin, Ireland");
+        assert_eq!(gazetteer.place_at(&Location::new(53.2743, -9.0514)).unwrap().to_string(), "office:galway");
+        assert!(gazetteer.place_at(&Location::new(53.3393, -6.25)).is_none());
+    }
+
+    #[test]
+    fn read_places_fails_on_malformed_rows() {
+        match read_places_from("name,latitude,longitude\nCork,north,-8.4756\n".as_bytes()) {
+            Err(GazetteerError::Csv(_)) => (),
+            other => panic!("expected a CSV error, got {:?}", other),
+        }
+    }
+}
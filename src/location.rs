@@ -12,11 +12,20 @@ use std::num::ParseFloatError;
 use std::error;
 use std::fmt;
 
+use gazetteer::Gazetteer;
 use units::Kilometers;
 
-const EARTH_RADIUS_IN_KM: f64 = 6372.8f64;
-const DUBLIN_LAT: f64 = 53.3393;
-const DUBLIN_LONG: f64 = -6.2576841;
+pub const EARTH_RADIUS_IN_KM: f64 = 6372.8f64;
+
+const WGS84_SEMI_MAJOR_AXIS_IN_KM: f64 = 6378.137f64;
+const WGS84_FLATTENING: f64 = 1f64 / 298.257223563f64;
+const VINCENTY_MAX_ITERATIONS: usize = 200;
+const VINCENTY_TOLERANCE: f64 = 1e-12;
+/// How much longer or shorter a distance on the WGS 84 spheroid, like the
+/// Vincenty ones or the PostGIS geography ones, can be than the Haversine
+/// distance between the same locations. They never differ by more than
+/// 0.6%, the ratio rounds that up to 1% to leave some margin.
+pub const VINCENTY_HAVERSINE_MAX_RATIO: f64 = 1.01f64;
 
 /// Struct representing a location on earth surface.
 ///
@@ -36,7 +45,7 @@ const DUBLIN_LONG: f64 = -6.2576841;
 /// let location: Location::new(latitude, longitude);
 /// assert_eq!(location, Location { latitude: 53.3393, longitude: -6.2576841});
 /// ```
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub struct Location {
     pub latitude: f64,
     pub longitude: f64,
@@ -58,11 +67,25 @@ impl Location {
     /// ```
     pub fn new(latitude: f64, longitude: f64) -> Location {
         Location {
-            latitude: latitude,
-            longitude: longitude,
+            latitude,
+            longitude,
         }
     }
 
+    /// Returns something to display only the coordinates of the location
+    /// with, for machine output and for loops formatting many locations.
+    /// `Display` also names the place at the location.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let dublin = Location::new(53.3393, -6.2576841);
+    /// assert_eq!(dublin.coordinates().to_string(), "Location(53.3393, -6.2576841)");
+    /// ```
+    pub fn coordinates(&self) -> LocationCoordinates<'_> {
+        LocationCoordinates(self)
+    }
+
     /// Returns a the distance in `Kilometers` between the `self` and other
     /// `Location` and the provided one.
     ///
@@ -97,38 +120,407 @@ impl Location {
         Kilometers(EARTH_RADIUS_IN_KM * c)
     }
 
-    /// Returns a whether the location is Dublin, Ireland.
+    /// Returns whether `self` is somewhere on earth: finite coordinates with
+    /// the latitude within ±90 and the longitude within ±180 degrees.
+    pub fn is_valid(&self) -> bool {
+        self.latitude.abs() <= 90f64 && self.longitude.abs() <= 180f64
+    }
+
+    /// Returns the unit vector pointing from the center of the earth to
+    /// `self`, the form spherical averages are calculated in.
+    pub fn to_vector(self) -> [f64; 3] {
+        let (latitude, longitude) = (self.latitude.to_radians(), self.longitude.to_radians());
+        [latitude.cos() * longitude.cos(), latitude.cos() * longitude.sin(), latitude.sin()]
+    }
+
+    /// Returns the `Location` `vector` points to from the center of the
+    /// earth, whatever its length. `None` for the zero vector.
+    pub fn from_vector(vector: [f64; 3]) -> Option<Location> {
+        let [x, y, z] = vector;
+        let length = (x * x + y * y + z * z).sqrt();
+        if length < 1e-12 || !length.is_finite() {
+            return None;
+        }
+
+        Some(Location::new((z / length).asin().to_degrees(), y.atan2(x).to_degrees()))
+    }
+
+    /// Returns the `Location` reached travelling `distance` from `self`
+    /// along the great circle starting at `bearing` degrees clockwise from
+    /// north, its longitude kept within ±180 degrees.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let north = Location::new(53.3393, -6.2576841).destination(0f64, Kilometers(10f64));
+    /// assert!(north.latitude > 53.3393);
+    /// ```
+    pub fn destination(&self, bearing: f64, distance: Kilometers) -> Location {
+        let angle = distance.0 / EARTH_RADIUS_IN_KM;
+        let (latitude, longitude, bearing) = (self.latitude.to_radians(), self.longitude.to_radians(), bearing.to_radians());
+
+        let destination_latitude = (latitude.sin() * angle.cos() + latitude.cos() * angle.sin() * bearing.cos()).asin();
+        let destination_longitude = longitude +
+                                    (bearing.sin() * angle.sin() * latitude.cos())
+                                        .atan2(angle.cos() - latitude.sin() * destination_latitude.sin());
+        let destination_longitude = (destination_longitude.to_degrees() + 540f64).rem_euclid(360f64) - 180f64;
+
+        Location::new(destination_latitude.to_degrees(), destination_longitude)
+    }
+
+    /// Returns the spherical centroid of `locations`: the point on the
+    /// surface under the average of their unit vectors. Unlike averaging the
+    /// coordinates it's right across the antimeridian and near the poles.
+    ///
+    /// `None` when there are no locations, or they cancel out like two
+    /// antipodes do.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let fiji = Location::centroid(&[Location::new(-18.0, 179.0), Location::new(-18.0, -179.0)]).unwrap();
+    /// assert!((fiji.longitude.abs() - 180.0).abs() < 1e-9);
+    /// ```
+    pub fn centroid(locations: &[Location]) -> Option<Location> {
+        let mut sum = [0f64; 3];
+        for location in locations {
+            for (total, component) in sum.iter_mut().zip(location.to_vector().iter()) {
+                *total += component;
+            }
+        }
+
+        Location::from_vector(sum)
+    }
+}
+
+/// Struct representing a rectangular area on earth surface delimited by
+/// its south west and north east corners.
+///
+/// When the west edge is further east than the east one the box is taken
+/// to cross the antimeridian.
+///
+/// # Examples
+///
+/// ```
+/// let ireland = BoundingBox::new(Location::new(51.4, -10.7), Location::new(55.4, -5.4));
+/// assert!(ireland.contains(&Location::new(53.3393, -6.2576841)));
+/// ```
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct BoundingBox {
+    pub south_west: Location,
+    pub north_east: Location,
+}
+
+impl BoundingBox {
+    /// Constructs a new `BoundingBox` given its south west and north east corners.
+    pub fn new(south_west: Location, north_east: Location) -> BoundingBox {
+        BoundingBox { south_west, north_east }
+    }
+
+    /// Returns the smallest box containing every `Location` within `radius`
+    /// of `center`, so candidates can be prefiltered with plain comparisons
+    /// before calculating any distance.
     ///
-    /// Just a convenience function used for the purposes of the exercise.
+    /// When a pole is within `radius` the box spans every longitude.
     ///
     /// # Examples
     ///
     /// ```
-    /// let dublin: Location::new(53.3393, -6.2576841); // Dublin
-    /// assert_eq!(dublin.is_dublin(), true);
-    /// 
-    /// let new_york = Location(40.7128, 74.0059);
-    /// assert_eq!(new_york.is_dublin(), false);
+    /// let around_dublin = BoundingBox::around(&Location::new(53.3393, -6.2576841), &Kilometers(100f64));
+    /// assert!(around_dublin.contains(&Location::new(53.2451022, -6.238335)));
     /// ```
-    pub fn is_dublin(&self) -> bool {
-        *self == Self::dublin()
+    pub fn around(center: &Location, radius: &Kilometers) -> BoundingBox {
+        let angular_radius = radius.0 / EARTH_RADIUS_IN_KM;
+        let south = center.latitude - angular_radius.to_degrees();
+        let north = center.latitude + angular_radius.to_degrees();
+        if south <= -90f64 || north >= 90f64 {
+            return BoundingBox::new(Location::new(south.max(-90f64), -180f64), Location::new(north.min(90f64), 180f64));
+        }
+
+        // the circle is widest where its edge runs along a meridian, which is
+        // off the latitude of the center.
+        let delta_longitude = (angular_radius.sin() / center.latitude.to_radians().cos()).asin().to_degrees();
+        let wrap = |longitude: f64| (longitude + 180f64).rem_euclid(360f64) - 180f64;
+
+        BoundingBox::new(Location::new(south, wrap(center.longitude - delta_longitude)),
+                         Location::new(north, wrap(center.longitude + delta_longitude)))
     }
 
-    /// Constructs a new `Location` object with the coordinates of Dublin, Ireland.
+    /// Returns the smallest box containing every one of `locations`, `None`
+    /// when there are none.
+    ///
+    /// The box crosses the antimeridian when that makes it narrower, so
+    /// customers around Fiji get a box a few degrees wide rather than one
+    /// spanning the whole earth.
+    ///
+    /// # Examples
     ///
-    /// Just a convenience function used for the purposes of the exercise.
+    /// ```
+    /// let fiji = BoundingBox::enclosing(&[Location::new(-17.7, 178.4), Location::new(-16.8, -179.9)]).unwrap();
+    /// assert_eq!(fiji.south_west, Location::new(-17.7, 178.4));
+    /// ```
+    pub fn enclosing(locations: &[Location]) -> Option<BoundingBox> {
+        let south = locations.iter().map(|location| location.latitude).reduce(f64::min)?;
+        let north = locations.iter().map(|location| location.latitude).reduce(f64::max)?;
+
+        // the box leaves out the widest gap between consecutive longitudes,
+        // which is the one across the antimeridian unless another is wider.
+        let mut longitudes: Vec<f64> = locations.iter().map(|location| location.longitude).collect();
+        longitudes.sort_by(f64::total_cmp);
+        let (mut west, mut east) = (longitudes[0], longitudes[longitudes.len() - 1]);
+        let mut widest_gap = west + 360f64 - east;
+        for pair in longitudes.windows(2) {
+            if pair[1] - pair[0] > widest_gap {
+                widest_gap = pair[1] - pair[0];
+                west = pair[1];
+                east = pair[0];
+            }
+        }
+
+        Some(BoundingBox::new(Location::new(south, west), Location::new(north, east)))
+    }
+
+    /// Returns whether `location` lies inside the box, edges included.
+    pub fn contains(&self, location: &Location) -> bool {
+        let within_latitudes = location.latitude >= self.south_west.latitude &&
+                               location.latitude <= self.north_east.latitude;
+        let within_longitudes = if self.south_west.longitude <= self.north_east.longitude {
+            location.longitude >= self.south_west.longitude && location.longitude <= self.north_east.longitude
+        } else {
+            location.longitude >= self.south_west.longitude || location.longitude <= self.north_east.longitude
+        };
+
+        within_latitudes && within_longitudes
+    }
+}
+
+/// Whether customers exactly on the edge of a `Ring` are part of it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Boundary {
+    Inclusive,
+    Exclusive,
+}
+
+/// The area between two distances from a location, `min_radius` and
+/// `max_radius`, each edge either part of it or not.
+///
+/// `Ring::within` is the disc of a search, leaving out its edge as
+/// `CustomerLocator::locate_within` always has. `Ring::new` starts rings
+/// on their `min_radius` and ends them before their `max_radius`, so
+/// consecutive rings don't overlap.
+///
+/// # Examples
+///
+/// ```
+/// // customers from 50 to 150 km away, both included.
+/// let regional = Ring::new(Kilometers(50f64), Kilometers(150f64)).with_max_boundary(Boundary::Inclusive);
+/// assert!(regional.contains(Kilometers(150f64)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Ring {
+    pub min_radius: Kilometers,
+    pub max_radius: Kilometers,
+    pub min_boundary: Boundary,
+    pub max_boundary: Boundary,
+}
+
+impl Ring {
+    /// Constructs the ring from `min_radius`, included, to `max_radius`,
+    /// excluded.
+    pub fn new(min_radius: Kilometers, max_radius: Kilometers) -> Ring {
+        Ring { min_radius, max_radius, min_boundary: Boundary::Inclusive, max_boundary: Boundary::Exclusive }
+    }
+
+    /// Constructs the disc of every distance under `radius`.
+    pub fn within(radius: Kilometers) -> Ring {
+        Ring::new(Kilometers(0f64), radius)
+    }
+
+    /// Returns the consecutive rings between `edges`, which must be sorted,
+    /// each one starting where the previous one ends. `max_boundary` tells
+    /// whether distances on an edge go to the ring inside it or to the one
+    /// outside it, distances of exactly the first edge are always in the
+    /// first ring.
     ///
     /// # Examples
     ///
     /// ```
-    /// let dublin: Location::dublin(); // Dublin
-    /// assert_eq!(dublin.is_dublin(), true);
+    /// let edges = [Kilometers(0f64), Kilometers(10f64), Kilometers(50f64), Kilometers(100f64)];
+    /// let bands = Ring::bands(&edges, Boundary::Exclusive);
+    /// assert_eq!(bands[1], Ring::new(Kilometers(10f64), Kilometers(50f64)));
     /// ```
-    pub fn dublin() -> Location {
-        Location::new(DUBLIN_LAT, DUBLIN_LONG)
+    pub fn bands(edges: &[Kilometers], max_boundary: Boundary) -> Vec<Ring> {
+        let min_boundary = match max_boundary {
+            Boundary::Inclusive => Boundary::Exclusive,
+            Boundary::Exclusive => Boundary::Inclusive,
+        };
+
+        edges.windows(2)
+            .enumerate()
+            .map(|(index, edges)| Ring {
+                min_radius: edges[0],
+                max_radius: edges[1],
+                min_boundary: if index == 0 { Boundary::Inclusive } else { min_boundary },
+                max_boundary,
+            })
+            .collect()
+    }
+
+    /// Sets whether distances of exactly `min_radius` are in the ring.
+    pub fn with_min_boundary(mut self, boundary: Boundary) -> Ring {
+        self.min_boundary = boundary;
+        self
+    }
+
+    /// Sets whether distances of exactly `max_radius` are in the ring.
+    pub fn with_max_boundary(mut self, boundary: Boundary) -> Ring {
+        self.max_boundary = boundary;
+        self
+    }
+
+    /// Returns whether something `distance` away from the center of the
+    /// ring is in it.
+    pub fn contains(&self, distance: Kilometers) -> bool {
+        let above_min = match self.min_boundary {
+            Boundary::Inclusive => distance >= self.min_radius,
+            Boundary::Exclusive => distance > self.min_radius,
+        };
+        let below_max = match self.max_boundary {
+            Boundary::Inclusive => distance <= self.max_radius,
+            Boundary::Exclusive => distance < self.max_radius,
+        };
+
+        above_min && below_max
+    }
+}
+
+/// The models distances between `Location`s can be calculated with.
+///
+/// `Haversine` is the spherical [Haversine formula]
+/// (https://en.wikipedia.org/wiki/Haversine_formula) used by
+/// `Location::distance_from`. `Vincenty` solves [Vincenty's inverse formula]
+/// (https://en.wikipedia.org/wiki/Vincenty%27s_formulae) on the WGS-84
+/// ellipsoid, which is accurate to the millimetre but some ten times slower
+/// and off by up to 0.6% from the spherical distances.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceModel {
+    Haversine,
+    Vincenty,
+}
+
+impl DistanceModel {
+    /// The names accepted by `DistanceModel::from_str`.
+    pub const NAMES: &'static [&'static str] = &["haversine", "vincenty"];
+
+    /// Returns the distance between `from` and `to` under the model.
+    ///
+    /// Vincenty's formula doesn't converge for some nearly antipodal points,
+    /// those fall back to the Haversine distance.
+    pub fn distance(&self, from: &Location, to: &Location) -> Kilometers {
+        match *self {
+            DistanceModel::Haversine => from.distance_from(to),
+            DistanceModel::Vincenty => vincenty_distance(from, to).unwrap_or_else(|| from.distance_from(to)),
+        }
+    }
+
+    /// Returns a radius that covers, with Haversine distances, every location
+    /// within `radius` under the model. Databases filtering with spherical
+    /// distances are given this one so no customer is left out.
+    pub fn haversine_radius_covering(&self, radius: Kilometers) -> Kilometers {
+        match *self {
+            DistanceModel::Haversine => radius,
+            DistanceModel::Vincenty => Kilometers(radius.0 * VINCENTY_HAVERSINE_MAX_RATIO),
+        }
     }
 }
 
+// Solves Vincenty's inverse problem on the WGS-84 ellipsoid, `None` when the
+// iteration doesn't converge.
+fn vincenty_distance(from: &Location, to: &Location) -> Option<Kilometers> {
+    let semi_major_axis = WGS84_SEMI_MAJOR_AXIS_IN_KM;
+    let flattening = WGS84_FLATTENING;
+    let semi_minor_axis = (1f64 - flattening) * semi_major_axis;
+
+    let longitude_difference = (to.longitude - from.longitude).to_radians();
+    let reduced_latitude_1 = ((1f64 - flattening) * from.latitude.to_radians().tan()).atan();
+    let reduced_latitude_2 = ((1f64 - flattening) * to.latitude.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = reduced_latitude_1.sin_cos();
+    let (sin_u2, cos_u2) = reduced_latitude_2.sin_cos();
+
+    let mut lambda = longitude_difference;
+    for _ in 0..VINCENTY_MAX_ITERATIONS {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2) +
+                         (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2)).sqrt();
+        if sin_sigma == 0f64 {
+            return Some(Kilometers(0f64));
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos_squared_alpha = 1f64 - sin_alpha * sin_alpha;
+        // on the equator cos²α is 0 and so is the term using cos 2σm.
+        let cos_2_sigma_m = if cos_squared_alpha != 0f64 {
+            cos_sigma - 2f64 * sin_u1 * sin_u2 / cos_squared_alpha
+        } else {
+            0f64
+        };
+        let c = flattening / 16f64 * cos_squared_alpha * (4f64 + flattening * (4f64 - 3f64 * cos_squared_alpha));
+
+        let previous_lambda = lambda;
+        lambda = longitude_difference + (1f64 - c) * flattening * sin_alpha *
+                 (sigma + c * sin_sigma * (cos_2_sigma_m + c * cos_sigma * (-1f64 + 2f64 * cos_2_sigma_m.powi(2))));
+        if (lambda - previous_lambda).abs() > VINCENTY_TOLERANCE {
+            continue;
+        }
+
+        let u_squared = cos_squared_alpha * (semi_major_axis.powi(2) - semi_minor_axis.powi(2)) / semi_minor_axis.powi(2);
+        let a = 1f64 + u_squared / 16384f64 * (4096f64 + u_squared * (-768f64 + u_squared * (320f64 - 175f64 * u_squared)));
+        let b = u_squared / 1024f64 * (256f64 + u_squared * (-128f64 + u_squared * (74f64 - 47f64 * u_squared)));
+        let delta_sigma = b * sin_sigma * (cos_2_sigma_m + b / 4f64 *
+            (cos_sigma * (-1f64 + 2f64 * cos_2_sigma_m.powi(2)) -
+             b / 6f64 * cos_2_sigma_m * (-3f64 + 4f64 * sin_sigma.powi(2)) * (-3f64 + 4f64 * cos_2_sigma_m.powi(2))));
+
+        return Some(Kilometers(semi_minor_axis * a * (sigma - delta_sigma)));
+    }
+
+    None
+}
+
+/// An error when trying to build a `DistanceModel` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseDistanceModelError(String);
+
+impl FromStr for DistanceModel {
+    type Err = ParseDistanceModelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "haversine" => Ok(DistanceModel::Haversine),
+            "vincenty" => Ok(DistanceModel::Vincenty),
+            other => Err(ParseDistanceModelError(format!("unknown distance model '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for DistanceModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DistanceModel::Haversine => write!(f, "haversine"),
+            DistanceModel::Vincenty => write!(f, "vincenty"),
+        }
+    }
+}
+
+impl fmt::Display for ParseDistanceModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Distance model parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseDistanceModelError {}
+
 /// An error when trying to build a `Location` instance from a `&str`.
 ///
 /// Akin to Parse*Error from Rust standard library. Used on the
@@ -168,24 +560,94 @@ impl error::Error for ParseLocationError {
         &self.0
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn cause(&self) -> Option<&dyn error::Error> {
         Some(self)
     }
 }
 
+/// Prints the coordinates, preceded by the name of the place at them when
+/// there's one on the installed `Gazetteer`.
 impl fmt::Display for Location {
      fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-         write!(f, "Location({}, {})", self.latitude, self.longitude)
+         if let Some(place) = Gazetteer::global().place_at(self) {
+             write!(f, "({}) ", place)?;
+         }
+         write!(f, "{}", self.coordinates())
+     }
+}
+
+/// Displays only the coordinates of a `Location`, without looking up the
+/// place at them. See `Location::coordinates`.
+pub struct LocationCoordinates<'l>(&'l Location);
+
+impl<'l> fmt::Display for LocationCoordinates<'l> {
+     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+         write!(f, "Location({}, {})", self.0.latitude, self.0.longitude)
      }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    const DUBLIN_LAT: f64 = 53.3393;
+    const DUBLIN_LONG: f64 = -6.2576841;
+    const DUBLIN: Location = Location { latitude: DUBLIN_LAT, longitude: DUBLIN_LONG };
     const NY_LAT: f64 = 40.7128f64;
     const NY_LONG: f64 = -74.0059f64;
     const DUB_NY_DIST_IN_KM: Kilometers = Kilometers(5116.751541958293);
 
+    #[test]
+    fn destination_is_the_distance_away_on_the_bearing() {
+        let dublin = Location::new(53.3393, -6.2576841);
+        for &bearing in &[0f64, 45f64, 90f64, 200f64, 315f64] {
+            let destination = dublin.destination(bearing, Kilometers(100f64));
+            assert!((dublin.distance_from(&destination).0 - 100f64).abs() < 1e-6, "bearing {}", bearing);
+        }
+
+        let north = dublin.destination(0f64, Kilometers(100f64));
+        assert!((north.longitude - dublin.longitude).abs() < 1e-9 && north.latitude > dublin.latitude);
+        let east = Location::new(0f64, 179.5f64).destination(90f64, Kilometers(111.3f64));
+        assert!(east.longitude < -179f64);
+    }
+
+    #[test]
+    fn vincenty_distance_matches_the_reference_geodesic() {
+        // Flinders Peak to Buninyong, the example on Vincenty's 1975 paper.
+        let flinders_peak = Location::new(-(37f64 + 57f64 / 60f64 + 3.72030 / 3600f64), 144f64 + 25f64 / 60f64 + 29.52440 / 3600f64);
+        let buninyong = Location::new(-(37f64 + 39f64 / 60f64 + 10.15610 / 3600f64), 143f64 + 55f64 / 60f64 + 35.38390 / 3600f64);
+
+        let distance = DistanceModel::Vincenty.distance(&flinders_peak, &buninyong);
+        assert!((distance.0 - 54.972271).abs() < 1e-6, "{}", distance);
+        assert_eq!(DistanceModel::Vincenty.distance(&DUBLIN, &DUBLIN), Kilometers(0f64));
+    }
+
+    #[test]
+    fn vincenty_distance_stays_close_to_the_haversine_one() {
+        let new_york = Location::new(NY_LAT, NY_LONG);
+        let equator = (Location::new(0f64, 10f64), Location::new(0f64, 20f64));
+        for &(from, to) in &[(DUBLIN, new_york), equator, (Location::new(89f64, 0f64), Location::new(-89f64, 1f64))] {
+            let ratio = DistanceModel::Vincenty.distance(&from, &to).0 / DistanceModel::Haversine.distance(&from, &to).0;
+            assert!((ratio - 1f64).abs() < 0.006, "{} to {}: {}", from, to, ratio);
+        }
+
+        let radius = DistanceModel::Vincenty.haversine_radius_covering(Kilometers(100f64));
+        let north = Location::new(0f64, 0f64).destination(0f64, Kilometers(100f64));
+        assert!(DistanceModel::Vincenty.distance(&Location::new(0f64, 0f64), &north) < Kilometers(100f64));
+        assert!(radius > Kilometers(100f64 / 0.994));
+
+        // nearly antipodal points Vincenty can't solve fall back to Haversine
+        let (from, to) = (Location::new(0f64, 0f64), Location::new(0.5f64, 179.7f64));
+        assert!(DistanceModel::Vincenty.distance(&from, &to).0.is_finite());
+    }
+
+    #[test]
+    fn distance_model_from_str_parses_known_models() {
+        assert_eq!(DistanceModel::from_str(" Vincenty"), Ok(DistanceModel::Vincenty));
+        assert_eq!(DistanceModel::from_str("haversine"), Ok(DistanceModel::Haversine));
+        assert_eq!(DistanceModel::Vincenty.to_string(), "vincenty");
+        assert!(DistanceModel::from_str("flat").is_err());
+    }
+
     #[test]
     fn new_builds_correct_instance() {
         let location = Location::new(NY_LAT, NY_LONG);
@@ -195,7 +657,7 @@ mod tests {
 
     #[test]
     fn distance_from_calculates_distance_between_diff_points_long() {
-        let dublin = Location::dublin();
+        let dublin = DUBLIN;
         let new_york = Location::new(NY_LAT, NY_LONG);
         assert_eq!(dublin.distance_from(&new_york), DUB_NY_DIST_IN_KM);
         assert_eq!(new_york.distance_from(&dublin), DUB_NY_DIST_IN_KM);
@@ -203,33 +665,53 @@ mod tests {
 
     #[test]
     fn distance_from_calculates_distance_same_point() {
-        let dublin = Location::dublin();
+        let dublin = DUBLIN;
         assert_eq!(dublin.distance_from(&dublin), Kilometers(0f64));
     }
 
     #[test]
-    fn is_dublin_is_true_for_dublin() {
-        let dublin = Location::dublin();
-        assert_eq!(dublin.is_dublin(), true);
+    fn vectors_round_trip_to_locations() {
+        let location = Location::from_vector(DUBLIN.to_vector()).unwrap();
+        assert!((location.latitude - DUBLIN_LAT).abs() < 1e-9);
+        assert!((location.longitude - DUBLIN_LONG).abs() < 1e-9);
+        assert_eq!(Location::from_vector([0f64; 3]), None);
     }
 
     #[test]
-    fn is_dublin_is_false_for_something_else_than_dublin() {
-        let new_york = Location::new(NY_LAT, NY_LONG);
-        assert_eq!(new_york.is_dublin(), false);
+    fn centroid_averages_on_the_sphere() {
+        let fiji = Location::centroid(&[Location::new(-18.0, 179.0), Location::new(-18.0, -179.0)]).unwrap();
+        assert!((fiji.longitude.abs() - 180.0).abs() < 1e-9);
+        assert!((fiji.latitude + 18.0).abs() < 0.01);
+
+        let pole = Location::centroid(&[Location::new(80.0, 0.0), Location::new(80.0, 120.0), Location::new(80.0, -120.0)]).unwrap();
+        assert!((pole.latitude - 90.0).abs() < 1e-6);
+
+        assert_eq!(Location::centroid(&[]), None);
+        assert_eq!(Location::centroid(&[Location::new(0.0, 0.0), Location::new(0.0, 180.0)]), None);
+    }
+
+    #[test]
+    fn display_prints_the_name_of_the_place_at_the_location() {
+        assert_eq!(DUBLIN.to_string(), "(Dublin, Ireland) Location(53.3393, -6.2576841)");
+        assert_eq!(Location::new(51.8985, -8.4756).to_string(), "(Cork, Ireland) Location(51.8985, -8.4756)");
+    }
+
+    #[test]
+    fn display_prints_only_the_coordinates_when_there_is_no_place_at_the_location() {
+        let somewhere_in_the_atlantic = Location::new(NY_LAT, -40.0);
+        assert_eq!(somewhere_in_the_atlantic.to_string(), "Location(40.7128, -40)");
     }
 
     #[test]
-    fn dublin_is_returns_an_instance_centered_at_dublin() {
-        let expected_dublin = Location::new(DUBLIN_LAT, DUBLIN_LONG);
-        assert_eq!(expected_dublin, Location::dublin());
+    fn coordinates_prints_only_the_coordinates() {
+        assert_eq!(DUBLIN.coordinates().to_string(), "Location(53.3393, -6.2576841)");
     }
 
     #[test]
     fn from_str_returns_an_instance_centered_at_given_coordinates() {
         let location_str = format!("{},{}", DUBLIN_LAT, DUBLIN_LONG);
         let location_instance = Location::from_str(&location_str).expect("this location str was supposed to parse succesfully.");
-        assert_eq!(location_instance, Location::dublin());
+        assert_eq!(location_instance, DUBLIN);
     }
 
     #[test]
@@ -260,7 +742,7 @@ mod tests {
     fn from_str_fails_with_float_point_parse_err_when_lat_is_not_a_float() {
         let location_str = "40.7128xxx,-74.0059";
         let expected_error = ParseLocationError(String::from("error parsing location invalid float literal"));
-        let actual_error = Location::from_str(&location_str).unwrap_err();
+        let actual_error = Location::from_str(location_str).unwrap_err();
         assert_eq!(expected_error, actual_error);
     }
 
@@ -268,7 +750,7 @@ mod tests {
     fn from_str_fails_with_float_point_parse_err_when_long_is_not_a_float() {
         let location_str = "40.7128,-74.0059asdf";
         let expected_error = ParseLocationError(String::from("error parsing location invalid float literal"));
-        let actual_error = Location::from_str(&location_str).unwrap_err();
+        let actual_error = Location::from_str(location_str).unwrap_err();
         assert_eq!(expected_error, actual_error);
     }
 
@@ -276,7 +758,119 @@ mod tests {
     fn from_str_fails_with_missing_coord_err_invalid_sep() {
         let location_str = "40.7128/-74.0059";
         let expected_error = ParseLocationError(String::from("missing element latitude,longitude on tuple"));
-        let actual_error = Location::from_str(&location_str).unwrap_err();
+        let actual_error = Location::from_str(location_str).unwrap_err();
         assert_eq!(expected_error, actual_error);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn bounding_box_contains_the_locations_inside_it_edges_included() {
+        let ireland = BoundingBox::new(Location::new(51.4, -10.7), Location::new(55.4, -5.4));
+
+        assert!(ireland.contains(&DUBLIN));
+        assert!(ireland.contains(&Location::new(51.4, -5.4)));
+        assert!(!ireland.contains(&Location::new(40.7128, -74.0059)));
+        assert!(!ireland.contains(&Location::new(56.0, -6.0)));
+    }
+
+    #[test]
+    fn bounding_box_contains_handles_boxes_crossing_the_antimeridian() {
+        let fiji = BoundingBox::new(Location::new(-21.0, 176.0), Location::new(-12.0, -178.0));
+
+        assert!(fiji.contains(&Location::new(-18.1, 178.4)));
+        assert!(fiji.contains(&Location::new(-16.0, -179.5)));
+        assert!(!fiji.contains(&Location::new(-18.0, 0.0)));
+    }
+
+    #[test]
+    fn is_valid_rejects_coordinates_off_the_earth() {
+        assert!(Location::new(90.0, -180.0).is_valid());
+        assert!(!Location::new(90.5, 0.0).is_valid());
+        assert!(!Location::new(0.0, 181.0).is_valid());
+        assert!(!Location::new(f64::NAN, 0.0).is_valid());
+        assert!(!Location::new(0.0, f64::INFINITY).is_valid());
+    }
+
+    #[test]
+    fn bounding_box_enclosing_fits_the_locations() {
+        let irish = [Location::new(53.3, -6.2), Location::new(51.9, -8.5), Location::new(54.6, -5.9)];
+        assert_eq!(BoundingBox::enclosing(&irish),
+                   Some(BoundingBox::new(Location::new(51.9, -8.5), Location::new(54.6, -5.9))));
+        assert_eq!(BoundingBox::enclosing(&[]), None);
+    }
+
+    #[test]
+    fn bounding_box_enclosing_crosses_the_antimeridian_when_narrower() {
+        let fiji = [Location::new(-17.7, 178.4), Location::new(-16.8, -179.9), Location::new(-18.1, 179.2)];
+        let bounding_box = BoundingBox::enclosing(&fiji).unwrap();
+
+        assert_eq!(bounding_box, BoundingBox::new(Location::new(-18.1, 178.4), Location::new(-16.8, -179.9)));
+        assert!(fiji.iter().all(|location| bounding_box.contains(location)));
+        assert!(!bounding_box.contains(&Location::new(-17.0, 0.0)));
+    }
+
+    #[test]
+    fn ring_contains_the_distances_between_its_radiuses() {
+        let ring = Ring::new(Kilometers(50.0), Kilometers(150.0));
+
+        assert!(ring.contains(Kilometers(50.0)));
+        assert!(ring.contains(Kilometers(100.0)));
+        assert!(!ring.contains(Kilometers(150.0)));
+        assert!(!ring.contains(Kilometers(49.9)));
+        assert!(!ring.contains(Kilometers(f64::NAN)));
+    }
+
+    #[test]
+    fn ring_boundaries_decide_about_the_edges() {
+        let ring = Ring::new(Kilometers(50.0), Kilometers(150.0))
+            .with_min_boundary(Boundary::Exclusive)
+            .with_max_boundary(Boundary::Inclusive);
+
+        assert!(!ring.contains(Kilometers(50.0)));
+        assert!(ring.contains(Kilometers(150.0)));
+        assert!(Ring::within(Kilometers(10.0)).contains(Kilometers(0.0)));
+        assert!(!Ring::within(Kilometers(10.0)).contains(Kilometers(10.0)));
+    }
+
+    #[test]
+    fn ring_bands_put_every_distance_on_a_single_band() {
+        let edges = [Kilometers(0.0), Kilometers(10.0), Kilometers(50.0), Kilometers(100.0)];
+        for &boundary in &[Boundary::Inclusive, Boundary::Exclusive] {
+            let bands = Ring::bands(&edges, boundary);
+            assert_eq!(bands.len(), 3);
+            for &distance in &[0.0, 5.0, 10.0, 49.9, 50.0, 99.0] {
+                assert_eq!(bands.iter().filter(|band| band.contains(Kilometers(distance))).count(), 1);
+            }
+        }
+
+        assert!(Ring::bands(&edges, Boundary::Exclusive)[1].contains(Kilometers(10.0)));
+        assert!(Ring::bands(&edges, Boundary::Inclusive)[0].contains(Kilometers(10.0)));
+        assert!(Ring::bands(&edges, Boundary::Inclusive)[2].contains(Kilometers(100.0)));
+        assert!(Ring::bands(&edges[..1], Boundary::Exclusive).is_empty());
+    }
+
+    #[test]
+    fn bounding_box_around_contains_the_locations_within_the_radius() {
+        let radius = Kilometers(100f64);
+        let around_dublin = BoundingBox::around(&DUBLIN, &radius);
+
+        for bearing in 0..36 {
+            let angle = (bearing as f64 * 10f64).to_radians();
+            let location = Location::new(DUBLIN.latitude + 0.89 * angle.cos(), DUBLIN.longitude + 1.49 * angle.sin());
+            if location.distance_from(&DUBLIN) < radius {
+                assert!(around_dublin.contains(&location), "{} is within the radius", location);
+            }
+        }
+        assert!(!around_dublin.contains(&Location::new(51.8985, -8.4756)));
+    }
+
+    #[test]
+    fn bounding_box_around_wraps_the_antimeridian_and_the_poles() {
+        let around_suva = BoundingBox::around(&Location::new(-18.1, 179.5), &Kilometers(200f64));
+        assert!(around_suva.contains(&Location::new(-18.1, -179.5)));
+        assert!(!around_suva.contains(&Location::new(-18.1, 0.0)));
+
+        let around_alert = BoundingBox::around(&Location::new(82.5, -62.3), &Kilometers(1000f64));
+        assert_eq!(around_alert.south_west.longitude, -180f64);
+        assert_eq!(around_alert.north_east, Location::new(90f64, 180f64));
+    }
+}
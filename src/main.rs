@@ -11,31 +11,100 @@
 /// Originally written to search for people in a radius of 100km of the
 /// center of Dublin, Ireland.
 ///
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
 extern crate clap;
+extern crate csv;
+extern crate memmap2;
+extern crate tiny_http;
+extern crate rustyline;
+extern crate toml;
+extern crate flate2;
+extern crate country_boundaries;
+extern crate rusqlite;
+extern crate postgres;
+extern crate ureq;
 
-use std::path::Path;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::str::FromStr;
 
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
 
+mod config;
 mod customer;
+mod customer_attribute;
+mod customer_cluster;
+mod customer_combinators;
+mod customer_csv_file;
 mod customer_locator;
+mod customer_matrix;
 mod customer_datasource;
+mod customer_http;
 mod customer_json_file;
+mod customer_json_mmap_file;
+mod customer_output;
+mod customer_postgres;
+mod customer_proximity;
+mod customer_repl;
+mod customer_route;
+mod customer_server;
+mod customer_snapshot;
+mod customer_sqlite;
+mod customer_stats;
+mod customer_store;
+mod customer_territory;
+mod customer_venue;
+mod gazetteer;
 mod location;
+mod reverse_geocoder;
 mod units;
 
-use location::Location;
-use units::Kilometers;
-use customer_locator::CustomerLocator;
+use config::{Config, ConfigLayer};
+use gazetteer::Gazetteer;
+use location::{Boundary, BoundingBox, DistanceModel, Location, Ring};
+use units::{DistanceUnit, Kilometers};
+use customer::CustomerList;
+use customer_locator::{CentreMatch, CustomerLocator, LocatedCustomer};
+use customer_matrix::{DistanceMatrix, MatrixFormat};
+use customer_cluster::{ClusterAlgorithm, Dbscan, Hdbscan};
+use customer_combinators::Boxed;
+use customer_datasource::{ColumnMapping, CustomerDatasource, InputFormat, SqlSource};
+use customer_csv_file::CustomerCsvFile;
+use customer_http::{BodyFormat, CustomerHttp};
 use customer_json_file::CustomerJsonFile;
+use customer_json_mmap_file::CustomerJsonMmapFile;
+use customer_output::{AttributeSelection, CustomerOutputError, OutputFormat};
+use customer_postgres::CustomerPostgres;
+use customer_proximity::ProximityJoin;
+use customer_repl::CustomerRepl;
+use customer_route::RoutePlanner;
+use customer_server::CustomerServer;
+use customer_snapshot::CustomerSnapshotCache;
+use customer_sqlite::CustomerSqlite;
+use customer_stats::DistanceReport;
+use customer_store::{CoordinatePrecision, CustomerStore};
+use customer_territory::{Office, TerritoryAssignment};
+use customer_venue::{FacilityPlacement, Venue};
+use reverse_geocoder::ReverseGeocoder;
 
-const DEFAULT_ARG_CUSTOMERS_FILE: &'static str = "data/customers.json";
-const DEFAULT_ARG_RADIUS_IN_KM: &'static str = "100";
-const DEFAULT_ARG_LOCATION: &'static str = "53.3393,-6.2576841"; // Dublin
+const DEFAULT_ARG_BIND: &str = "127.0.0.1:8080";
+const DEFAULT_ARG_WORKERS: &str = "4";
+const WATCH_INTERVAL_IN_SECS: u64 = 2;
+const REPL_HISTORY_FILE: &str = ".customer_locator_history";
+const DEFAULT_ARG_SAVE_TABLE: &str = "located_customers";
+const DEFAULT_ARG_ALGORITHM: &str = "dbscan";
+const DEFAULT_ARG_EPS: &str = "5";
+const DEFAULT_ARG_MIN_POINTS: &str = "5";
+const DEFAULT_ARG_BUCKET_WIDTH: &str = "10";
+const DEFAULT_ARG_MATCH_CENTRES: &str = "any";
+const DEFAULT_ARG_MATRIX_FORMAT: &str = "csv";
 
 fn main() {
     let matches = App::new("CustomerLocator")
@@ -46,45 +115,377 @@ fn main() {
             .short("f")
             .long("file")
             .value_name("FILE")
-            .help("The input file with the customers")
-            .default_value(DEFAULT_ARG_CUSTOMERS_FILE)
+            .help("The input file with the customers, an http(s) URL serving them, or the connection string of a postgres input [default: data/customers.json]")
             .takes_value(true))
+        .arg(Arg::with_name("input")
+            .long("input")
+            .value_name("INPUT")
+            .multiple(true)
+            .number_of_values(1)
+            .conflicts_with("file")
+            .help("An input with the customers, given like --file. Repeat it to read several inputs one after another, all in the --format.")
+            .takes_value(true))
+        .arg(Arg::with_name("merge")
+            .long("merge")
+            .help("Only keep the first customer read with each user_id across the inputs, instead of every one of them"))
+        .arg(Arg::with_name("exclude")
+            .long("exclude")
+            .value_name("USER_IDS")
+            .help("Leave out the customers with these comma separated user ids, like test accounts")
+            .takes_value(true))
+        .arg(Arg::with_name("round-coordinates")
+            .long("round-coordinates")
+            .value_name("DECIMALS")
+            .help("Round the coordinates of the customers read to this many decimals")
+            .takes_value(true))
+        .arg(Arg::with_name("limit")
+            .long("limit")
+            .value_name("COUNT")
+            .help("Only keep the first COUNT customers read")
+            .takes_value(true))
+        .arg(Arg::with_name("format")
+            .long("format")
+            .value_name("FORMAT")
+            .help("The format of the input file [default: json]")
+            .possible_values(InputFormat::NAMES)
+            .takes_value(true))
+        .arg(Arg::with_name("table")
+            .long("table")
+            .value_name("TABLE")
+            .help("The table of a SQLite or Postgres input the customers are read from [default: customers]")
+            .takes_value(true))
+        .arg(Arg::with_name("query")
+            .long("query")
+            .value_name("SQL")
+            .conflicts_with("table")
+            .help("A query returning the customers of a SQLite or Postgres input, instead of reading a whole table")
+            .takes_value(true))
+        .arg(Arg::with_name("columns")
+            .long("columns")
+            .value_name("MAPPING")
+            .help("The columns of a SQLite or Postgres input holding the customer fields, like user_id=id,latitude=lat,longitude=lng. Any other column is read as an attribute.")
+            .takes_value(true))
+        .arg(Arg::with_name("no-postgis")
+            .long("no-postgis")
+            .help("Search a Postgres input with a bounding box instead of PostGIS, for servers where it's detected but can't be used"))
+        .arg(Arg::with_name("save-sqlite")
+            .long("save-sqlite")
+            .value_name("DATABASE")
+            .help("Also store the located customers on a table of this SQLite database")
+            .takes_value(true))
+        .arg(Arg::with_name("save-table")
+            .long("save-table")
+            .value_name("TABLE")
+            .help("The table --save-sqlite stores the located customers on")
+            .default_value(DEFAULT_ARG_SAVE_TABLE)
+            .takes_value(true))
+        .arg(Arg::with_name("cache")
+            .long("cache")
+            .value_name("CACHE")
+            .help("A snapshot file in which the customers of a JSON file are cached. It's rebuilt whenever the input file changes.")
+            .takes_value(true))
+        .arg(Arg::with_name("http-cache")
+            .long("http-cache")
+            .value_name("DIRECTORY")
+            .help("A directory in which the customers downloaded from a URL are kept, only downloading them again when they change.")
+            .takes_value(true))
+        .arg(Arg::with_name("http-timeout")
+            .long("http-timeout")
+            .value_name("SECONDS")
+            .help("How long to wait on a URL input before giving up, each failure being retried a few times [default: 30]")
+            .takes_value(true))
+        .arg(Arg::with_name("http-retries")
+            .long("http-retries")
+            .value_name("RETRIES")
+            .help("How many times a URL input is downloaded again on transient failures [default: 3]")
+            .takes_value(true))
+        .arg(Arg::with_name("http-format")
+            .long("http-format")
+            .value_name("FORMAT")
+            .help("The format of the body of a URL input, guessed from its Content-Type and extension when not given")
+            .possible_values(BodyFormat::NAMES)
+            .takes_value(true))
+        .arg(Arg::with_name("mmap")
+            .long("mmap")
+            .conflicts_with("format")
+            .help("Read the input file through a memory map, which is faster on big files. Same as --format json-mmap."))
+        .arg(Arg::with_name("compact")
+            .long("compact")
+            .help("Keep the customers on a compact struct of arrays layout that takes a fraction of the memory."))
+        .arg(Arg::with_name("quantise")
+            .long("quantise")
+            .requires("compact")
+            .help("Store the coordinates of the compact layout as micro-degrees, halving their size."))
         .arg(Arg::with_name("radius")
             .short("r")
             .long("radius")
             .value_name("RADIUS")
-            .help("The radius of the search in Kilometers")
-            .default_value(DEFAULT_ARG_RADIUS_IN_KM)
+            .help("The radius of the search, in the unit given with --unit [default: 100]")
+            .takes_value(true))
+        .arg(Arg::with_name("min-radius")
+            .long("min-radius")
+            .value_name("DISTANCE")
+            .help("Leave out the customers closer than this, in the unit given with --unit, searching a ring instead of a disc")
+            .takes_value(true))
+        .arg(Arg::with_name("include-radius")
+            .long("include-radius")
+            .help("Also locate the customers exactly --radius away, which are left out by default. With --bands the customers on an edge go to the inner band."))
+        .arg(Arg::with_name("exclude-min-radius")
+            .long("exclude-min-radius")
+            .requires("min-radius")
+            .help("Leave out the customers exactly --min-radius away, which are located by default"))
+        .arg(Arg::with_name("bands")
+            .long("bands")
+            .value_name("DISTANCES")
+            .conflicts_with("stats")
+            .help("Group the located customers in bands ending at these comma separated distances, like 10,50,100, in the unit given with --unit. The first band starts at --min-radius and the last one replaces --radius.")
+            .takes_value(true))
+        .arg(Arg::with_name("unit")
+            .short("u")
+            .long("unit")
+            .value_name("UNIT")
+            .help("The unit of the radius and the distances printed [default: km]")
+            .possible_values(DistanceUnit::NAMES)
             .takes_value(true))
         .arg(Arg::with_name("location")
             .short("l")
             .long("location")
             .value_name("LOCATION")
-            .help("The location for what customers are gonna be located. In the format latitude,longitude.")
-            .default_value(DEFAULT_ARG_LOCATION)
+            .help("The location for what customers are gonna be located. Either latitude,longitude or the name of a place, like cork or office:galway. [default: dublin]")
+            .takes_value(true))
+        .arg(Arg::with_name("centre")
+            .long("centre")
+            .value_name("LOCATION")
+            .multiple(true)
+            .number_of_values(1)
+            .conflicts_with_all(&["bands", "stats"])
+            .help("Search around this location instead of --location, given like --location. Repeat it to search around several centres, matching them as --match-centres says.")
+            .takes_value(true))
+        .arg(Arg::with_name("match-centres")
+            .long("match-centres")
+            .value_name("MATCH")
+            .requires("centre")
+            .help("Whether to locate the customers near any --centre, the ones near all of them or every customer with its nearest centre [default: any]")
+            .possible_values(CentreMatch::NAMES)
+            .takes_value(true))
+        .arg(Arg::with_name("places")
+            .long("places")
+            .value_name("FILE")
+            .help("A CSV file of extra places with name, latitude and longitude columns, taking precedence over the bundled ones.")
+            .takes_value(true))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("FORMAT")
+            .help("The format in which the located customers are printed. [default: text]")
+            .possible_values(OutputFormat::NAMES)
+            .takes_value(true))
+        .arg(Arg::with_name("distance-model")
+            .long("distance-model")
+            .value_name("MODEL")
+            .help("The model distances are calculated with [default: haversine]")
+            .possible_values(DistanceModel::NAMES)
+            .takes_value(true))
+        .arg(Arg::with_name("print-config")
+            .long("print-config")
+            .help("Print the effective configuration, merged from the config files, the environment and the flags, and exit."))
+        .arg(Arg::with_name("attributes")
+            .short("a")
+            .long("attributes")
+            .value_name("ATTRIBUTES")
+            .help("Comma separated list of the extra customer attributes to print. All of them by default.")
+            .takes_value(true))
+        .arg(Arg::with_name("geocode")
+            .long("geocode")
+            .help("Add the nearest settlement, admin region and country code of each customer as the settlement, admin_region and country_code attributes."))
+        .arg(Arg::with_name("stats")
+            .long("stats")
+            .help("Print statistics of the located customers instead of them: how many were loaded, rejected and matched, the nearest and farthest ones, the distribution of their distances and the area they cover."))
+        .arg(Arg::with_name("bucket-width")
+            .long("bucket-width")
+            .value_name("DISTANCE")
+            .requires("stats")
+            .help("The width of the buckets of the --stats distance histogram, in the unit given with --unit [default: 10]")
+            .takes_value(true))
+        .arg(Arg::with_name("percentiles")
+            .long("percentiles")
+            .value_name("PERCENTILES")
+            .requires("stats")
+            .help("The comma separated percentiles of the distances --stats reports, each one between 0 and 100 [default: 90,95,99]")
             .takes_value(true))
         .arg(Arg::with_name("quiet")
             .short("q")
             .long("quiet")
             .help("Don't print anything to stdout. Used when benchmarking, so we don't wait on stdout flushing."))
+        .subcommand(SubCommand::with_name("serve")
+            .about("Serves the customers over an HTTP JSON API instead of printing a search")
+            .arg(Arg::with_name("bind")
+                .long("bind")
+                .value_name("ADDRESS")
+                .help("The address the server listens on")
+                .default_value(DEFAULT_ARG_BIND)
+                .takes_value(true))
+            .arg(Arg::with_name("workers")
+                .long("workers")
+                .value_name("WORKERS")
+                .help("The number of threads answering requests")
+                .default_value(DEFAULT_ARG_WORKERS)
+                .takes_value(true))
+            .arg(Arg::with_name("watch")
+                .long("watch")
+                .help("Reload the customers whenever the input file changes. POST /reload always reloads them.")))
+        .subcommand(SubCommand::with_name("cluster")
+            .about("Groups the customers into clusters of nearby ones instead of printing a search, printed in the --output format")
+            .arg(Arg::with_name("algorithm")
+                .long("algorithm")
+                .value_name("ALGORITHM")
+                .help("The clustering algorithm. HDBSCAN needs no --eps but compares every pair of customers.")
+                .possible_values(ClusterAlgorithm::NAMES)
+                .default_value(DEFAULT_ARG_ALGORITHM)
+                .takes_value(true))
+            .arg(Arg::with_name("eps")
+                .long("eps")
+                .value_name("DISTANCE")
+                .help("How close, in the unit given with --unit, DBSCAN neighbours are")
+                .default_value(DEFAULT_ARG_EPS)
+                .takes_value(true))
+            .arg(Arg::with_name("min-points")
+                .long("min-points")
+                .value_name("COUNT")
+                .help("The customers within --eps, itself included, a customer needs to start a DBSCAN cluster, and the smallest HDBSCAN cluster")
+                .default_value(DEFAULT_ARG_MIN_POINTS)
+                .takes_value(true))
+            .arg(Arg::with_name("min-samples")
+                .long("min-samples")
+                .value_name("COUNT")
+                .help("The neighbours HDBSCAN measures the density around a customer with, fewer finding more clusters and less noise [default: --min-points]")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("pairs")
+            .about("Finds the customers closer than --within to each other, printed in the --output format")
+            .arg(Arg::with_name("within")
+                .long("within")
+                .value_name("DISTANCE")
+                .help("How close the customers of a pair are, in --unit")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("components")
+                .long("components")
+                .help("Group the customers connected by pairs, directly or through others, instead of listing the pairs")))
+        .subcommand(SubCommand::with_name("venue")
+            .about("Finds where to meet the customers: their centroid, the point with the least total distance to them and the one with the least distance to the furthest one")
+            .arg(Arg::with_name("venues")
+                .long("venues")
+                .value_name("COUNT")
+                .help("Place this many venues instead, with as many customers as possible within --radius of one of them")
+                .takes_value(true))
+            .arg(Arg::with_name("candidates")
+                .long("candidates")
+                .value_name("FILE")
+                .requires("venues")
+                .help("A CSV file of the places the venues can be at, in the format of --places. Anywhere by default.")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("territories")
+            .about("Assigns every customer to one of the offices, minimising the total distance without going over their capacities")
+            .arg(Arg::with_name("office")
+                .long("office")
+                .value_name("LOCATION:CAPACITY")
+                .help("An office and the most customers it can take, e.g. Dublin:100 or 53.34,-6.26:100. Can be given several times.")
+                .required(true)
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true))
+            .arg(Arg::with_name("max-distance")
+                .long("max-distance")
+                .value_name("DISTANCE")
+                .help("The furthest a customer can be from its office, in --unit. Customers further from every office are left unassigned.")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("route")
+            .about("Plans the visits to the customers of the search, starting and ending every day at --location, printed in the --output format")
+            .arg(Arg::with_name("max-daily-distance")
+                .long("max-daily-distance")
+                .value_name("DISTANCE")
+                .help("The furthest to travel on a day, in --unit. The visits are split into as few days as possible and customers too far to come back from within a day are left out.")
+                .takes_value(true))
+            .arg(Arg::with_name("gpx")
+                .long("gpx")
+                .value_name("FILE")
+                .help("Also write the route to this file as GPX, to load it on a GPS device")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("matrix")
+            .about("Writes the distances in Km from every origin to every customer of the search instead of printing it")
+            .arg(Arg::with_name("origin")
+                .long("origin")
+                .value_name("LOCATION")
+                .help("A row of the matrix, --location when none is given. Can be given several times.")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true))
+            .arg(Arg::with_name("between-customers")
+                .long("between-customers")
+                .conflicts_with("origin")
+                .help("The distances between the customers themselves instead, only keeping the upper triangle of the matrix"))
+            .arg(Arg::with_name("matrix-format")
+                .long("matrix-format")
+                .value_name("FORMAT")
+                .help("The format of the matrix")
+                .possible_values(MatrixFormat::NAMES)
+                .default_value(DEFAULT_ARG_MATRIX_FORMAT)
+                .takes_value(true))
+            .arg(Arg::with_name("threads")
+                .long("threads")
+                .value_name("COUNT")
+                .help("The threads computing the distances [default: one per CPU]")
+                .takes_value(true))
+            .arg(Arg::with_name("block-cells")
+                .long("block-cells")
+                .value_name("COUNT")
+                .help("How many distances are computed and kept in memory at once before being written, whole rows at a time [default: 1048576]")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("repl")
+            .about("Opens an interactive shell to run searches one after another on customers loaded once"))
         .get_matches();
 
-    // Parsing input file name
-    let input_file_path = matches.value_of("file").unwrap();
-
-    // Parsing radius of the search
-    let radius_str = matches.value_of("radius").unwrap();
-    let radius = match f64::from_str(radius_str) {
-        Ok(radius) => radius,
+    // Merging the flags with the config files and the environment
+    let mut flags = ConfigLayer::default();
+    if matches.is_present("mmap") {
+        flags.format = Some(InputFormat::JsonMmap);
+    }
+    for key in config::KEYS {
+        if let Some(value) = matches.value_of(key.replace('_', "-")) {
+            if let Err(err) = flags.set(key, value) {
+                println!("Invalid --{}: {}", key.replace('_', "-"), err);
+                return;
+            }
+        }
+    }
+    let config = match Config::load(flags) {
+        Ok(config) => config,
         Err(err) => {
             println!("{}", err);
             return;
         }
     };
+    if matches.is_present("print-config") {
+        print!("{}", config);
+        return;
+    }
 
-    // Parsing the location
-    let location_str = matches.value_of("location").unwrap();
-    let location = match Location::from_str(location_str) {
+    // Loading the places the location can be given by name
+    let mut gazetteer = Gazetteer::bundled();
+    if let Some(ref path) = config.places {
+        match Gazetteer::read_places(path) {
+            Ok(places) => gazetteer = gazetteer.with_places(places),
+            Err(err) => {
+                println!("Unable to read the places file {}: {}", path.display(), err);
+                return;
+            }
+        }
+    }
+    let _ = Gazetteer::install(gazetteer);
+
+    // Parsing the radius of the search and the location
+    let radius = config.unit.to_kilometers(config.radius);
+    let location = match Gazetteer::global().locate(&config.location) {
         Ok(location) => location,
         Err(err) => {
             println!("{}", err);
@@ -92,33 +493,753 @@ fn main() {
         }
     };
 
-    // Building our datasource (A JSON file in this case)
-    let customers_json_file = CustomerJsonFile::new(Path::new(input_file_path));
+    // Parsing the ring and the bands of the search
+    let min_radius = match matches.value_of("min-radius").map(f64::from_str) {
+        Some(Ok(min_radius)) => config.unit.to_kilometers(min_radius),
+        Some(Err(err)) => {
+            println!("Invalid --min-radius: {}", err);
+            return;
+        }
+        None => Kilometers(0f64),
+    };
+    let (min_boundary, max_boundary) = (
+        if matches.is_present("exclude-min-radius") { Boundary::Exclusive } else { Boundary::Inclusive },
+        if matches.is_present("include-radius") { Boundary::Inclusive } else { Boundary::Exclusive },
+    );
+    let bands = match matches.value_of("bands").map(|edges| parse_band_edges(edges, min_radius, config.unit)) {
+        Some(Ok(edges)) => {
+            let mut bands = Ring::bands(&edges, max_boundary);
+            bands[0].min_boundary = min_boundary;
+            Some(bands)
+        }
+        Some(Err(err)) => {
+            println!("Invalid --bands: {}", err);
+            return;
+        }
+        None => None,
+    };
+    let radius = bands.as_ref().map_or(radius, |bands| bands[bands.len() - 1].max_radius);
+    let ring = Ring::new(min_radius, radius)
+        .with_min_boundary(min_boundary)
+        .with_max_boundary(max_boundary);
+
+    // Parsing the centres of a search around several of them
+    let centre_names: Vec<String> = matches.values_of("centre").map_or(Vec::new(), |centres| centres.map(String::from).collect());
+    let mut centres = Vec::new();
+    for centre in &centre_names {
+        match Gazetteer::global().locate(centre) {
+            Ok(location) => centres.push(location),
+            Err(err) => {
+                println!("Invalid --centre: {}", err);
+                return;
+            }
+        }
+    }
+    let centre_match = CentreMatch::from_str(matches.value_of("match-centres").unwrap_or(DEFAULT_ARG_MATCH_CENTRES)).unwrap();
+
+    // Parsing the attributes to print
+    let output_format = config.output;
+    let attributes = match matches.value_of("attributes") {
+        Some(list) => AttributeSelection::from_list(list),
+        None => AttributeSelection::All,
+    };
 
-    // Building the locator
-    let locator = match CustomerLocator::from_source(customers_json_file) {
-        Ok(locator) => locator,
+    // Building our datasource and the locator
+    let inputs: Vec<PathBuf> = match matches.values_of("input") {
+        Some(inputs) => inputs.map(PathBuf::from).collect(),
+        None => vec![config.file.clone()],
+    };
+    let mut exclude = Vec::new();
+    for user_id in matches.value_of("exclude").map_or(Vec::new(), |user_ids| user_ids.split(',').collect()) {
+        match i64::from_str(user_id.trim()) {
+            Ok(user_id) => exclude.push(user_id),
+            Err(err) => {
+                println!("Invalid --exclude {}: {}", user_id, err);
+                return;
+            }
+        }
+    }
+    let round_coordinates = match matches.value_of("round-coordinates").map(i32::from_str) {
+        Some(Ok(decimals)) => Some(decimals),
+        Some(Err(err)) => {
+            println!("Invalid --round-coordinates: {}", err);
+            return;
+        }
+        None => None,
+    };
+    let limit = match matches.value_of("limit").map(usize::from_str) {
+        Some(Ok(limit)) => Some(limit),
+        Some(Err(err)) => {
+            println!("Invalid --limit: {}", err);
+            return;
+        }
+        None => None,
+    };
+    let selection = InputSelection { merge: matches.is_present("merge"), exclude, round_coordinates, limit };
+    let cache_path = matches.value_of("cache").map(PathBuf::from);
+    let http_cache_path = matches.value_of("http-cache").map(PathBuf::from);
+    let http_timeout = match matches.value_of("http-timeout").map(u64::from_str) {
+        Some(Ok(seconds)) => Some(Duration::from_secs(seconds)),
+        Some(Err(err)) => {
+            println!("Invalid --http-timeout: {}", err);
+            return;
+        }
+        None => None,
+    };
+    let http_retries = match matches.value_of("http-retries").map(u32::from_str) {
+        Some(Ok(retries)) => Some(retries),
+        Some(Err(err)) => {
+            println!("Invalid --http-retries: {}", err);
+            return;
+        }
+        None => None,
+    };
+    let format = config.format;
+    let sql_source = match (matches.value_of("table"), matches.value_of("query")) {
+        (_, Some(query)) => SqlSource::Query(query.to_string()),
+        (Some(table), None) => SqlSource::Table(table.to_string()),
+        (None, None) => SqlSource::Table(String::from("customers")),
+    };
+    let columns = match matches.value_of("columns").map(ColumnMapping::from_str) {
+        Some(Ok(columns)) => columns,
+        Some(Err(err)) => {
+            println!("{}", err);
+            return;
+        }
+        None => ColumnMapping::default(),
+    };
+    // a single search only needs the customers around its location, the
+    // server and the shell search anywhere. Statistics count every customer.
+    let pushed_radius = config.distance_model.haversine_radius_covering(radius);
+    let within = match matches.subcommand_name() {
+        Some("route") | Some("matrix") => Some((pushed_radius, location)),
+        Some(_) => None,
+        None if matches.is_present("stats") || !centres.is_empty() => None,
+        None => Some((pushed_radius, location)),
+    };
+    let http_format = matches.value_of("http-format").map(|format| BodyFormat::from_str(format).unwrap());
+    let http = HttpOptions { cache_path: http_cache_path, timeout: http_timeout, retries: http_retries, format: http_format };
+    let postgis = if matches.is_present("no-postgis") { Some(false) } else { None };
+    let database = DatabaseOptions { source: sql_source, columns, postgis };
+    let distance_model = config.distance_model;
+    let locator = match load_locator(format, &inputs, cache_path.as_deref(), &http, &database, &selection, within) {
+        Ok(locator) => locator.with_model(distance_model),
         Err(err) => {
             println!("{}", err);
             return;
         }
     };
 
-    let mut customers = locator.locate_within(&Kilometers(radius), &location);
-    customers.sort_by_user_id();
+    let precision = if matches.is_present("quantise") {
+        CoordinatePrecision::MicroDegrees
+    } else {
+        CoordinatePrecision::Exact
+    };
+
+    if let Some(serve_matches) = matches.subcommand_matches("serve") {
+        let bind = serve_matches.value_of("bind").unwrap();
+        let workers = match usize::from_str(serve_matches.value_of("workers").unwrap()) {
+            Ok(workers) => workers,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+
+        let watch = match (serve_matches.is_present("watch"), inputs.as_slice()) {
+            (true, [input]) => Some((input.clone(), Duration::from_secs(WATCH_INTERVAL_IN_SECS))),
+            (true, _) => {
+                println!("--watch only applies to a single input");
+                return;
+            }
+            (false, _) => None,
+        };
+        let reload = move || {
+            load_locator(format, &inputs, cache_path.as_deref(), &http, &database, &selection, None)
+                .map(|locator| locator.with_model(distance_model))
+        };
+
+        let served = if matches.is_present("compact") {
+            let reload_compact = move || reload().map(|locator| locator.into_compact(precision));
+            serve(locator.into_compact(precision), reload_compact, watch, bind, workers)
+        } else {
+            serve(locator, reload, watch, bind, workers)
+        };
+        if let Err(err) = served {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    if let Some(cluster_matches) = matches.subcommand_matches("cluster") {
+        let algorithm = ClusterAlgorithm::from_str(cluster_matches.value_of("algorithm").unwrap()).unwrap();
+        let eps = f64::from_str(cluster_matches.value_of("eps").unwrap());
+        let min_points = usize::from_str(cluster_matches.value_of("min-points").unwrap());
+        let (eps, min_points) = match (eps, min_points) {
+            (Ok(eps), Ok(min_points)) => (config.unit.to_kilometers(eps), min_points),
+            (Err(err), _) => {
+                println!("Invalid --eps: {}", err);
+                return;
+            }
+            (_, Err(err)) => {
+                println!("Invalid --min-points: {}", err);
+                return;
+            }
+        };
+
+        let min_samples = match cluster_matches.value_of("min-samples").map(usize::from_str) {
+            Some(Ok(min_samples)) => Some(min_samples),
+            Some(Err(err)) => {
+                println!("Invalid --min-samples: {}", err);
+                return;
+            }
+            None => None,
+        };
+
+        let clustering = match algorithm {
+            ClusterAlgorithm::Dbscan => Dbscan::new(eps, min_points).with_model(config.distance_model).cluster(locator.customers()),
+            ClusterAlgorithm::Hdbscan => {
+                let mut hdbscan = Hdbscan::new(min_points).with_model(config.distance_model);
+                if let Some(min_samples) = min_samples {
+                    hdbscan = hdbscan.with_min_samples(min_samples);
+                }
+                hdbscan.cluster(locator.customers())
+            }
+        };
+        if matches.is_present("quiet") { return; }
+
+        let stdout = io::stdout();
+        if let Err(err) = customer_output::write_clusters(stdout.lock(), &clustering, output_format, &attributes, config.unit) {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    if let Some(pairs_matches) = matches.subcommand_matches("pairs") {
+        let join = match f64::from_str(pairs_matches.value_of("within").unwrap()) {
+            Ok(within) if within > 0f64 => ProximityJoin::new(config.unit.to_kilometers(within)).with_model(config.distance_model),
+            Ok(within) => {
+                println!("Invalid --within: {} isn't positive", within);
+                return;
+            }
+            Err(err) => {
+                println!("Invalid --within: {}", err);
+                return;
+            }
+        };
+
+        let stdout = io::stdout();
+        let written = if pairs_matches.is_present("components") {
+            let components = join.components(locator.customers());
+            if matches.is_present("quiet") { return; }
+            customer_output::write_clusters(stdout.lock(), &components, output_format, &attributes, config.unit)
+        } else {
+            let pairs = join.pairs(locator.customers());
+            if matches.is_present("quiet") { return; }
+            customer_output::write_pairs(stdout.lock(), &pairs, output_format, config.unit)
+        };
+        if let Err(err) = written {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    if let Some(venue_matches) = matches.subcommand_matches("venue") {
+        let customers = locator.customers();
+        let (venues, uncovered) = match venue_matches.value_of("venues").map(usize::from_str) {
+            Some(Ok(count)) => {
+                let mut placement = FacilityPlacement::new(count, radius).with_model(config.distance_model);
+                let mut places = Vec::new();
+                if let Some(path) = venue_matches.value_of("candidates") {
+                    places = match Gazetteer::read_places(Path::new(path)) {
+                        Ok(places) => places,
+                        Err(err) => {
+                            println!("Unable to read the candidates file {}: {}", path, err);
+                            return;
+                        }
+                    };
+                    placement = placement.with_candidates(places.iter().map(|place| place.location()).collect());
+                }
+                let mut placement = placement.place(customers);
+                for venue in &mut placement.venues {
+                    if let Some(candidate) = venue.candidate {
+                        venue.name = places[candidate].name.clone();
+                    }
+                }
+                (placement.venues, placement.uncovered)
+            }
+            Some(Err(err)) => {
+                println!("Invalid --venues: {}", err);
+                return;
+            }
+            None => {
+                let centres = [("Centroid", customers.centroid()),
+                               ("Geometric median", customer_venue::geometric_median(customers)),
+                               ("Minimax centre", customer_venue::minimax_centre(customers))];
+                let venues = centres.iter()
+                    .filter_map(|(name, location)| location.map(|location| Venue {
+                        name: name.to_string(),
+                        location,
+                        candidate: None,
+                        customers: customers.iter()
+                            .map(|customer| LocatedCustomer {
+                                customer: customer.clone(),
+                                distance: config.distance_model.distance(&customer.location(), &location),
+                            })
+                            .collect(),
+                    }))
+                    .collect();
+                (venues, CustomerList::default())
+            }
+        };
+        if matches.is_present("quiet") { return; }
+
+        let stdout = io::stdout();
+        if let Err(err) = customer_output::write_venues(stdout.lock(), &venues, &uncovered, output_format, config.unit) {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    if let Some(territories_matches) = matches.subcommand_matches("territories") {
+        let mut offices = Vec::new();
+        for office in territories_matches.values_of("office").unwrap() {
+            match parse_office(office) {
+                Ok(office) => offices.push(office),
+                Err(err) => {
+                    println!("Invalid --office {}: {}", office, err);
+                    return;
+                }
+            }
+        }
+        let mut assignment = TerritoryAssignment::new(offices).with_model(config.distance_model);
+        if let Some(max_distance) = territories_matches.value_of("max-distance") {
+            match f64::from_str(max_distance) {
+                Ok(max_distance) if max_distance >= 0f64 => {
+                    assignment = assignment.with_max_distance(config.unit.to_kilometers(max_distance));
+                }
+                Ok(max_distance) => {
+                    println!("Invalid --max-distance: {} is negative", max_distance);
+                    return;
+                }
+                Err(err) => {
+                    println!("Invalid --max-distance: {}", err);
+                    return;
+                }
+            }
+        }
+
+        let territories = assignment.assign(locator.customers());
+        if matches.is_present("quiet") { return; }
+
+        let stdout = io::stdout();
+        if let Err(err) = customer_output::write_territories(stdout.lock(), &territories, output_format, &attributes, config.unit) {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    if let Some(route_matches) = matches.subcommand_matches("route") {
+        let mut planner = RoutePlanner::new(location).with_model(config.distance_model);
+        if let Some(max_daily_distance) = route_matches.value_of("max-daily-distance") {
+            match f64::from_str(max_daily_distance) {
+                Ok(max_daily_distance) if max_daily_distance > 0f64 => {
+                    planner = planner.with_max_daily_distance(config.unit.to_kilometers(max_daily_distance));
+                }
+                Ok(max_daily_distance) => {
+                    println!("Invalid --max-daily-distance: {} isn't positive", max_daily_distance);
+                    return;
+                }
+                Err(err) => {
+                    println!("Invalid --max-daily-distance: {}", err);
+                    return;
+                }
+            }
+        }
+
+        let customers = locator.locate_in_ring(&ring, &location).into_iter().map(|located| located.customer).collect();
+        let route = planner.plan(&customers);
+        if let Some(path) = route_matches.value_of("gpx") {
+            let written = File::create(path)
+                .map_err(CustomerOutputError::from)
+                .and_then(|file| customer_output::write_route_gpx(BufWriter::new(file), &route, config.unit));
+            if let Err(err) = written {
+                println!("Unable to write the GPX file {}: {}", path, err);
+                return;
+            }
+        }
+        if matches.is_present("quiet") { return; }
+
+        let stdout = io::stdout();
+        if let Err(err) = customer_output::write_route(stdout.lock(), &route, output_format, &attributes, config.unit) {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    if let Some(matrix_matches) = matches.subcommand_matches("matrix") {
+        let format = MatrixFormat::from_str(matrix_matches.value_of("matrix-format").unwrap()).unwrap();
+        let customers = locator.locate_in_ring(&ring, &location);
+        let locations: Vec<Location> = customers.iter().map(|located| located.customer.location()).collect();
+        let user_ids: Vec<String> = customers.iter().map(|located| located.customer.user_id.to_string()).collect();
+
+        let (mut matrix, origin_labels) = if matrix_matches.is_present("between-customers") {
+            (DistanceMatrix::upper_triangle(locations), user_ids.clone())
+        } else {
+            let origin_labels: Vec<String> = matrix_matches.values_of("origin")
+                .map_or(vec![config.location.clone()], |origins| origins.map(String::from).collect());
+            let mut origins = Vec::new();
+            for origin in &origin_labels {
+                match Gazetteer::global().locate(origin) {
+                    Ok(location) => origins.push(location),
+                    Err(err) => {
+                        println!("Invalid --origin: {}", err);
+                        return;
+                    }
+                }
+            }
+            (DistanceMatrix::new(origins, locations), origin_labels)
+        };
+        matrix = matrix.with_model(config.distance_model);
+        match matrix_matches.value_of("threads").map(usize::from_str) {
+            Some(Ok(threads)) => matrix = matrix.with_threads(threads),
+            Some(Err(err)) => {
+                println!("Invalid --threads: {}", err);
+                return;
+            }
+            None => {}
+        }
+        match matrix_matches.value_of("block-cells").map(usize::from_str) {
+            Some(Ok(block_cells)) => matrix = matrix.with_block_cells(block_cells),
+            Some(Err(err)) => {
+                println!("Invalid --block-cells: {}", err);
+                return;
+            }
+            None => {}
+        }
+        if matches.is_present("quiet") { return; }
+
+        let stdout = io::stdout();
+        if let Err(err) = matrix.write(BufWriter::new(stdout.lock()), format, &origin_labels, &user_ids) {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    if matches.subcommand_matches("repl").is_some() {
+        let history_path = env::var_os("HOME").map(|home| Path::new(&home).join(REPL_HISTORY_FILE));
+        let result = if matches.is_present("compact") {
+            CustomerRepl::new(locator.into_compact(precision), location, output_format, attributes)
+                .with_unit(config.unit)
+                .run(history_path.as_deref())
+        } else {
+            CustomerRepl::new(locator, location, output_format, attributes)
+                .with_unit(config.unit)
+                .run(history_path.as_deref())
+        };
+        if let Err(err) = result {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    let bucket_width = match f64::from_str(matches.value_of("bucket-width").unwrap_or(DEFAULT_ARG_BUCKET_WIDTH)) {
+        Ok(width) if width > 0f64 => config.unit.to_kilometers(width),
+        Ok(width) => {
+            println!("Invalid --bucket-width: {} isn't positive", width);
+            return;
+        }
+        Err(err) => {
+            println!("Invalid --bucket-width: {}", err);
+            return;
+        }
+    };
+    let mut report = DistanceReport::new(location)
+        .with_model(locator.model())
+        .with_bucket_width(bucket_width)
+        .with_loaded(locator.customers().len(), locator.rejected());
+    if let Some(list) = matches.value_of("percentiles") {
+        match parse_percentiles(list) {
+            Ok(percentiles) => report = report.with_percentiles(&percentiles),
+            Err(err) => {
+                println!("Invalid --percentiles: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(bands) = bands {
+        let banded = if matches.is_present("compact") {
+            locator.into_compact(precision).locate_in_bands(&bands, &location)
+        } else {
+            locator.locate_in_bands(&bands, &location)
+        };
+        if matches.is_present("quiet") { return; }
+
+        let stdout = io::stdout();
+        if let Err(err) = customer_output::write_bands(stdout.lock(), &bands, &banded, output_format, &attributes, config.unit) {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    if !centres.is_empty() {
+        let mut attributed = if matches.is_present("compact") {
+            locator.into_compact(precision).locate_by_centres(centre_match, &ring, &centres)
+        } else {
+            locator.locate_by_centres(centre_match, &ring, &centres)
+        };
+        attributed.sort_by_key(|attributed| attributed.customer.user_id);
+        if matches.is_present("quiet") { return; }
+
+        let stdout = io::stdout();
+        if let Err(err) = customer_output::write_attributed(stdout.lock(), &attributed, &centre_names, output_format, &attributes, config.unit) {
+            println!("{}", err);
+        }
+        return;
+    }
+
+    let mut customers = if matches.is_present("compact") {
+        locator.into_compact(precision).locate_in_ring(&ring, &location)
+    } else {
+        locator.locate_in_ring(&ring, &location)
+    };
+    customers.sort_by_key(|located| located.customer.user_id);
+    if matches.is_present("geocode") {
+        let reverse_geocoder = ReverseGeocoder::global();
+        customers = customers.into_iter()
+            .map(|LocatedCustomer { customer, distance }| LocatedCustomer { customer: reverse_geocoder.annotate(customer), distance })
+            .collect();
+    }
+
+    if let Some(database_path) = matches.value_of("save-sqlite") {
+        let table = matches.value_of("save-table").unwrap();
+        let database = CustomerSqlite::new(Path::new(database_path))
+            .with_source(SqlSource::Table(table.to_string()))
+            .with_columns(database.columns);
+        if let Err(err) = database.write_customers(&customers.iter().map(|located| located.customer.clone()).collect()) {
+            println!("{}", err);
+            return;
+        }
+    }
 
     // this is just to be able to measure raw perf of customer parsing and actual
     // calculations excluding IO at the end.
     if matches.is_present("quiet") { return; }
 
-    if location.is_dublin() {
-        println!("Location is (Dublin, Ireland) {}.", location);
-    } else {
-        println!("Location is {}.", location);
+    let stdout = io::stdout();
+    if matches.is_present("stats") {
+        if let Err(err) = customer_output::write_stats(stdout.lock(), &report.report_located(&customers), output_format, config.unit) {
+            println!("{}", err);
+        }
+        return;
+    }
+    if let Err(err) = customer_output::write_search(stdout.lock(), &customers, &location, Some(&ring), output_format, &attributes, config.unit) {
+        println!("{}", err);
+    }
+}
+
+// Parses the comma separated `edges` of the bands in `unit`, starting the
+// first band at `min_radius`.
+fn parse_band_edges(edges: &str, min_radius: Kilometers, unit: DistanceUnit) -> Result<Vec<Kilometers>, String> {
+    let mut parsed = vec![min_radius];
+    for edge in edges.split(',') {
+        let distance = unit.to_kilometers(f64::from_str(edge.trim()).map_err(|err| err.to_string())?);
+        if distance.0.is_nan() || distance <= parsed[parsed.len() - 1] {
+            return Err(format!("{} is not further than the edge before it", edge.trim()));
+        }
+        parsed.push(distance);
+    }
+
+    Ok(parsed)
+}
+
+// Parses an `office` given as its location and capacity separated by the
+// last ':', so the location can be anything the gazetteer locates.
+fn parse_office(office: &str) -> Result<Office, String> {
+    let separator = office.rfind(':').ok_or_else(|| "expected LOCATION:CAPACITY".to_string())?;
+    let (name, capacity) = (office[..separator].trim(), &office[separator + 1..]);
+    let capacity = usize::from_str(capacity.trim()).map_err(|err| err.to_string())?;
+    let location = Gazetteer::global().locate(name).map_err(|err| err.to_string())?;
+
+    Ok(Office::new(name, location, capacity))
+}
+
+// Parses a comma separated list of percentiles, each one between 0 and 100.
+fn parse_percentiles(list: &str) -> Result<Vec<f64>, String> {
+    list.split(',')
+        .map(|percentile| match f64::from_str(percentile.trim()) {
+            Ok(percentile) if (0f64..=100f64).contains(&percentile) => Ok(percentile),
+            Ok(percentile) => Err(format!("{} isn't between 0 and 100", percentile)),
+            Err(err) => Err(format!("'{}': {}", percentile.trim(), err)),
+        })
+        .collect()
+}
+
+// How the customers of a URL input are downloaded.
+struct HttpOptions {
+    cache_path: Option<PathBuf>,
+    timeout: Option<Duration>,
+    retries: Option<u32>,
+    format: Option<BodyFormat>,
+}
+
+// How the customers of a SQLite or Postgres input are read.
+struct DatabaseOptions {
+    source: SqlSource,
+    columns: ColumnMapping,
+    postgis: Option<bool>,
+}
+
+// How the customers of the inputs are combined and which ones are kept.
+struct InputSelection {
+    merge: bool,
+    exclude: Vec<i64>,
+    round_coordinates: Option<i32>,
+    limit: Option<usize>,
+}
+
+// Loads the locator from the inputs in `format`, read one after another
+// and merged, filtered, rounded and limited as `selection` says.
+fn load_locator(format: InputFormat,
+                inputs: &[PathBuf],
+                cache_path: Option<&Path>,
+                http: &HttpOptions,
+                database: &DatabaseOptions,
+                selection: &InputSelection,
+                within: Option<(Kilometers, Location)>)
+                -> Result<CustomerLocator, String> {
+    if cache_path.is_some() && inputs.len() > 1 {
+        return Err("--cache only applies to a single input".to_string());
+    }
+    let mut datasource = open_input(format, &inputs[0], cache_path, http, database, within)?;
+    for input in &inputs[1..] {
+        let next = open_input(format, input, cache_path, http, database, within)?;
+        datasource = if selection.merge { datasource.merge(next).boxed() } else { datasource.chain(next).boxed() };
     }
+    if !selection.exclude.is_empty() {
+        let exclude = selection.exclude.clone();
+        datasource = datasource.filter(move |customer| !exclude.contains(&customer.user_id)).boxed();
+    }
+    if let Some(decimals) = selection.round_coordinates {
+        let factor = 10f64.powi(decimals);
+        datasource = datasource.map(move |mut customer| {
+                customer.latitude = (customer.latitude * factor).round() / factor;
+                customer.longitude = (customer.longitude * factor).round() / factor;
+                customer
+            })
+            .boxed();
+    }
+    if let Some(limit) = selection.limit {
+        datasource = datasource.limit(limit).boxed();
+    }
+    let locator = CustomerLocator::from_source(datasource).map_err(|err| err.to_string())?;
 
-    for customer in customers {
-        let dist_from_location = customer.distance_from(&location);
-        println!("{} is {} from provided location.", customer, dist_from_location);
+    // customers off the earth are left out, which shouldn't go unnoticed.
+    if locator.rejected() > 0 {
+        eprintln!("Rejected {} customers with invalid coordinates.", locator.rejected());
+    }
+    Ok(locator)
+}
+
+// Opens the datasource of an input in `format`. Databases are already
+// indexed so they skip the snapshot cache, and only read the customers
+// around the location of the search `within` when there's one. URLs have
+// their own cache, revalidated with the server, so neither takes `cache_path`.
+fn open_input<'a>(format: InputFormat,
+                  input: &'a Path,
+                  cache_path: Option<&'a Path>,
+                  http: &HttpOptions,
+                  database: &DatabaseOptions,
+                  within: Option<(Kilometers, Location)>)
+                  -> Result<Boxed<'a>, String> {
+    let url = input.to_str().filter(|input| customer_http::is_url(input));
+    if cache_path.is_some() {
+        match format {
+            _ if url.is_some() => return Err("--cache doesn't apply to URLs, use --http-cache".to_string()),
+            InputFormat::Sqlite | InputFormat::Postgres => return Err("--cache doesn't apply to databases".to_string()),
+            InputFormat::Json | InputFormat::JsonMmap | InputFormat::Csv => {}
+        }
     }
-}
\ No newline at end of file
+    let datasource = match format {
+        InputFormat::Json | InputFormat::JsonMmap | InputFormat::Csv if url.is_some() => {
+            let mut datasource = CustomerHttp::new(url.unwrap());
+            if let Some(ref cache_path) = http.cache_path {
+                datasource = datasource.with_cache_dir(cache_path);
+            }
+            if let Some(timeout) = http.timeout {
+                datasource = datasource.with_timeout(timeout);
+            }
+            if let Some(retries) = http.retries {
+                datasource = datasource.with_retries(retries);
+            }
+            if let Some(format) = http.format {
+                datasource = datasource.with_format(format);
+            }
+            datasource.boxed()
+        }
+        InputFormat::Json => cached(CustomerJsonFile::new(input), input, cache_path),
+        InputFormat::JsonMmap => cached(CustomerJsonMmapFile::new(input), input, cache_path),
+        InputFormat::Csv => cached(CustomerCsvFile::new(input), input, cache_path),
+        InputFormat::Sqlite => {
+            let mut sqlite = CustomerSqlite::new(input)
+                .with_source(database.source.clone())
+                .with_columns(database.columns.clone());
+            if let Some((radius, location)) = within {
+                sqlite = sqlite.within(BoundingBox::around(&location, &radius));
+            }
+            sqlite.boxed()
+        }
+        InputFormat::Postgres => {
+            let mut postgres = CustomerPostgres::new(&input.to_string_lossy())
+                .with_source(database.source.clone())
+                .with_columns(database.columns.clone());
+            if let Some(postgis) = database.postgis {
+                postgres = postgres.with_postgis(postgis);
+            }
+            if let Some((radius, location)) = within {
+                postgres = postgres.within(radius, location);
+            }
+            postgres.boxed()
+        }
+    };
+
+    Ok(datasource)
+}
+
+// Reads `source` through the snapshot cache if there's one.
+fn cached<'a, S>(source: S, input: &'a Path, cache_path: Option<&'a Path>) -> Boxed<'a>
+    where S: CustomerDatasource + 'a,
+          S::Err: 'static
+{
+    match cache_path {
+        Some(cache_path) => CustomerSnapshotCache::new(source, input, cache_path).boxed(),
+        None => source.boxed(),
+    }
+}
+
+// Serves the customers of `locator` on `bind` until a SIGINT or a SIGTERM,
+// reloading them with `reload` on `POST /reload` or when the watched file changes.
+fn serve<S, F>(locator: CustomerLocator<S>,
+               reload: F,
+               watch: Option<(PathBuf, Duration)>,
+               bind: &str,
+               workers: usize)
+               -> Result<(), String>
+    where S: CustomerStore + Send + Sync,
+          F: Fn() -> Result<CustomerLocator<S>, String> + Send + Sync + 'static
+{
+    let mut server = CustomerServer::bind(bind, locator)
+        .map_err(|err| err.to_string())?
+        .with_reloader(reload)
+        .with_shutdown_on_signals();
+    if let Some((path, interval)) = watch {
+        server = server.with_watch(path, interval);
+    }
+    if let Some(address) = server.local_addr() {
+        println!("Serving customers on http://{}", address);
+    }
+    server.run(workers);
+
+    Ok(())
+}
@@ -0,0 +1,365 @@
+// Copyright 2017 Jose Narvaez. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//!
+//! Module containing an offline reverse geocoder: it turns a `Location`
+//! into the nearest settlement, its admin region and the ISO code of the
+//! country it is in, so reports can say "near Wicklow, IE" instead of
+//! printing raw coordinates.
+//!
+//! The settlements come bundled on `data/settlements.csv.gz`, the places of
+//! more than 1000 inhabitants from [GeoNames](https://www.geonames.org/)
+//! (CC BY 4.0). The country is looked up on the country boundaries bundled
+//! with the `country-boundaries` crate (ODbL, © OpenStreetMap contributors)
+//! and only taken from the nearest settlement out on international waters.
+//!
+//! # Examples
+//!
+//! ```
+//! let locality = ReverseGeocoder::global().locate(&Location::new(52.98, -6.05)).unwrap();
+//! assert_eq!(locality.to_string(), "Wicklow, IE");
+//! assert_eq!(locality.admin_region, "Leinster");
+//! ```
+//!
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::sync::OnceLock;
+
+use country_boundaries::{CountryBoundaries, LatLon, BOUNDARIES_ODBL_360X180};
+use csv;
+use flate2::read::GzDecoder;
+
+use customer::Customer;
+use location::Location;
+use units::Kilometers;
+
+const BUNDLED_SETTLEMENTS: &[u8] = include_bytes!("../data/settlements.csv.gz");
+
+// The settlements are indexed on a grid of cells of one degree side.
+const LATITUDE_CELLS: i32 = 180;
+const LONGITUDE_CELLS: i32 = 360;
+
+static GLOBAL_REVERSE_GEOCODER: OnceLock<ReverseGeocoder> = OnceLock::new();
+
+/// A populated place a `Location` can be near to.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Settlement {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub name: String,
+    pub admin_region: String,
+    pub country_code: String,
+}
+
+impl Settlement {
+    /// Returns the `Location` of the settlement.
+    pub fn location(&self) -> Location {
+        Location::new(self.latitude, self.longitude)
+    }
+}
+
+/// Where a `Location` is: the nearest settlement, its first level admin
+/// region (a province, a state) and the ISO 3166-1 alpha-2 code of the
+/// country.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Locality {
+    pub settlement: String,
+    pub admin_region: String,
+    pub country_code: String,
+    /// The distance to the settlement.
+    pub distance: Kilometers,
+}
+
+impl fmt::Display for Locality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {}", self.settlement, self.country_code)
+    }
+}
+
+/// Struct holding the settlements and the country boundaries `Location`s
+/// are reverse geocoded with.
+pub struct ReverseGeocoder {
+    settlements: Vec<Settlement>,
+    // (row, column) of a grid cell -> indexes of the settlements in it.
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    country_codes: HashSet<String>,
+    boundaries: CountryBoundaries,
+}
+
+impl ReverseGeocoder {
+    /// Constructs a new `ReverseGeocoder` with `settlements`.
+    pub fn new(settlements: Vec<Settlement>) -> ReverseGeocoder {
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (index, settlement) in settlements.iter().enumerate() {
+            cells.entry(cell_of(&settlement.location())).or_default().push(index);
+        }
+        let country_codes = settlements.iter().map(|settlement| settlement.country_code.clone()).collect();
+        let boundaries = CountryBoundaries::from_reader(BOUNDARIES_ODBL_360X180).expect("the bundled boundaries are valid");
+
+        ReverseGeocoder {
+            settlements,
+            cells,
+            country_codes,
+            boundaries,
+        }
+    }
+
+    /// Constructs a new `ReverseGeocoder` with the bundled settlements.
+    pub fn bundled() -> ReverseGeocoder {
+        let settlements = read_settlements_from(GzDecoder::new(BUNDLED_SETTLEMENTS)).expect("the bundled settlements are valid");
+        ReverseGeocoder::new(settlements)
+    }
+
+    /// Returns the bundled reverse geocoder, loading it on the first call.
+    pub fn global() -> &'static ReverseGeocoder {
+        GLOBAL_REVERSE_GEOCODER.get_or_init(ReverseGeocoder::bundled)
+    }
+
+    /// Returns the `Locality` of `location`, `None` when there are no
+    /// settlements at all.
+    ///
+    /// The nearest settlement is looked for in the country `location` is in,
+    /// so a customer by a border is not placed on the town across it.
+    pub fn locate(&self, location: &Location) -> Option<Locality> {
+        let country_code = self.country_code(location);
+        let in_country = country_code.as_ref()
+            .filter(|country_code| self.country_codes.contains(*country_code))
+            .and_then(|country_code| self.nearest(location, |settlement| settlement.country_code == *country_code));
+        let (settlement, distance) = in_country.or_else(|| self.nearest(location, |_| true))?;
+
+        Some(Locality {
+            settlement: settlement.name.clone(),
+            admin_region: settlement.admin_region.clone(),
+            country_code: country_code.unwrap_or_else(|| settlement.country_code.clone()),
+            distance,
+        })
+    }
+
+    /// Returns `customer` with the `settlement`, `admin_region` and
+    /// `country_code` attributes of its `Locality` set.
+    pub fn annotate(&self, customer: Customer) -> Customer {
+        match self.locate(&customer.location()) {
+            Some(locality) => {
+                customer.with_attribute("settlement", locality.settlement)
+                    .with_attribute("admin_region", locality.admin_region)
+                    .with_attribute("country_code", locality.country_code)
+            }
+            None => customer,
+        }
+    }
+
+    // The code of the country whose boundaries contain `location`, the
+    // smallest one when they overlap, like the overseas territories do.
+    fn country_code(&self, location: &Location) -> Option<String> {
+        let position = LatLon::new(location.latitude, location.longitude).ok()?;
+        self.boundaries
+            .ids(position)
+            .into_iter()
+            .find(|id| !id.contains('-'))
+            .map(String::from)
+    }
+
+    // Looks for the nearest settlement on rings of cells around the one of
+    // `location`, until no cell on the next ring can be closer than the best
+    // settlement found.
+    fn nearest<P>(&self, location: &Location, predicate: P) -> Option<(&Settlement, Kilometers)>
+        where P: Fn(&Settlement) -> bool
+    {
+        let (row, column) = cell_of(location);
+        let mut best: Option<(&Settlement, Kilometers)> = None;
+
+        for ring in 0..LATITUDE_CELLS {
+            let cells = ring_cells(row, column, ring);
+            let closest_cell = cells.iter()
+                .map(|cell| cell_distance(*cell, location))
+                .fold(Kilometers(f64::INFINITY), |closest, distance| if distance < closest { distance } else { closest });
+            if best.is_some_and(|(_, distance)| distance <= closest_cell) {
+                break;
+            }
+
+            for cell in cells {
+                let indexes = match self.cells.get(&cell) {
+                    Some(indexes) => indexes,
+                    None => continue,
+                };
+                for settlement in indexes.iter().map(|index| &self.settlements[*index]) {
+                    if !predicate(settlement) {
+                        continue;
+                    }
+                    let distance = settlement.location().distance_from(location);
+                    if best.is_none_or(|(_, best_distance)| distance < best_distance) {
+                        best = Some((settlement, distance));
+                    }
+                }
+            }
+        }
+
+        best
+    }
+}
+
+fn read_settlements_from<R: Read>(reader: R) -> Result<Vec<Settlement>, csv::Error> {
+    csv::Reader::from_reader(reader).deserialize().collect()
+}
+
+fn cell_of(location: &Location) -> (i32, i32) {
+    let row = (location.latitude.floor() as i32).clamp(-LATITUDE_CELLS / 2, LATITUDE_CELLS / 2 - 1);
+    (row, wrap_column(location.longitude.floor() as i32))
+}
+
+fn wrap_column(column: i32) -> i32 {
+    (column + LONGITUDE_CELLS / 2).rem_euclid(LONGITUDE_CELLS) - LONGITUDE_CELLS / 2
+}
+
+// The cells `ring` cells away from (row, column), wrapping around the
+// antimeridian but not over the poles.
+fn ring_cells(row: i32, column: i32, ring: i32) -> Vec<(i32, i32)> {
+    let column_offsets = ring.min(LONGITUDE_CELLS / 2);
+    let mut cells = Vec::new();
+    for row_offset in -ring..=ring {
+        let cell_row = row + row_offset;
+        if !(-LATITUDE_CELLS / 2..LATITUDE_CELLS / 2).contains(&cell_row) {
+            continue;
+        }
+        for column_offset in -column_offsets..=column_offsets {
+            if row_offset.abs() != ring && column_offset.abs() != ring {
+                continue;
+            }
+            // -180 and 180 degrees away are the same column.
+            if column_offset == -LONGITUDE_CELLS / 2 {
+                continue;
+            }
+            cells.push((cell_row, wrap_column(column + column_offset)));
+        }
+    }
+
+    cells
+}
+
+// The smallest distance from `location` to a point in the cell.
+fn cell_distance((row, column): (i32, i32), location: &Location) -> Kilometers {
+    let (south, north) = (row as f64, row as f64 + 1f64);
+    let (west, east) = (column as f64, column as f64 + 1f64);
+    if (west..=east).contains(&location.longitude) {
+        let latitude = location.latitude.max(south).min(north);
+        return Location::new(latitude, location.longitude).distance_from(location);
+    }
+
+    // outside the columns of the cell the closest point is on one of its
+    // meridian edges, all its points are closer than the ones in between.
+    let west_distance = meridian_distance(location, west, south, north);
+    let east_distance = meridian_distance(location, east, south, north);
+    if west_distance < east_distance { west_distance } else { east_distance }
+}
+
+// The smallest distance from `location` to the meridian `longitude` between
+// the `south` and `north` latitudes.
+fn meridian_distance(location: &Location, longitude: f64, south: f64, north: f64) -> Kilometers {
+    let delta_longitude = (longitude - location.longitude).to_radians();
+    let mut candidates = vec![south, north];
+    // the closest point of the whole meridian, when it is on the same half of the globe.
+    if delta_longitude.cos() > 0f64 {
+        let foot = (location.latitude.to_radians().tan() / delta_longitude.cos()).atan().to_degrees();
+        candidates.push(foot.max(south).min(north));
+    }
+
+    candidates.into_iter()
+        .map(|latitude| Location::new(latitude, longitude).distance_from(location))
+        .fold(Kilometers(f64::INFINITY), |closest, distance| if distance < closest { distance } else { closest })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use customer_attribute::AttributeValue;
+
+    fn generate_reverse_geocoder() -> ReverseGeocoder {
+        let settlement = |name: &str, admin_region: &str, country_code: &str, latitude: f64, longitude: f64| {
+            Settlement {
+                latitude,
+                longitude,
+                name: name.to_string(),
+                admin_region: admin_region.to_string(),
+                country_code: country_code.to_string(),
+            }
+        };
+
+        ReverseGeocoder::new(vec![
+            settlement("Wicklow", "Leinster", "IE", 52.975, -6.049),
+            settlement("Lifford", "Ulster", "IE", 54.834, -7.486),
+            settlement("Derry", "Northern Ireland", "GB", 54.998, -7.309),
+            settlement("Suva", "Central", "FJ", -18.142, 178.442),
+        ])
+    }
+
+    #[test]
+    fn locate_finds_the_nearest_settlement() {
+        let locality = generate_reverse_geocoder().locate(&Location::new(53.0, -6.1)).unwrap();
+
+        assert_eq!(locality.settlement, "Wicklow");
+        assert_eq!(locality.admin_region, "Leinster");
+        assert_eq!(locality.country_code, "IE");
+        assert_eq!(locality.to_string(), "Wicklow, IE");
+        assert!(locality.distance < Kilometers(5f64));
+    }
+
+    #[test]
+    fn locate_prefers_settlements_in_the_same_country() {
+        // closer to Derry, but on the Donegal side of the border.
+        let locality = generate_reverse_geocoder().locate(&Location::new(54.95, -7.45)).unwrap();
+
+        assert_eq!(locality.settlement, "Lifford");
+        assert_eq!(locality.country_code, "IE");
+    }
+
+    #[test]
+    fn locate_searches_across_the_antimeridian() {
+        let locality = generate_reverse_geocoder().locate(&Location::new(-17.5, -179.5)).unwrap();
+
+        assert_eq!(locality.settlement, "Suva");
+    }
+
+    #[test]
+    fn locate_takes_the_country_of_the_settlement_on_international_waters() {
+        let locality = generate_reverse_geocoder().locate(&Location::new(53.0, -15.0)).unwrap();
+
+        assert_eq!(locality.settlement, "Lifford");
+        assert_eq!(locality.country_code, "IE");
+        assert!(ReverseGeocoder::new(Vec::new()).locate(&Location::new(53.0, -15.0)).is_none());
+    }
+
+    #[test]
+    fn annotate_sets_the_locality_attributes() {
+        let customer = Customer::new(12, "Christina McArdle", &Location::new(52.986375, -6.043701));
+        let customer = generate_reverse_geocoder().annotate(customer);
+
+        assert_eq!(customer.attribute("settlement"), Some(&AttributeValue::from("Wicklow")));
+        assert_eq!(customer.attribute("admin_region"), Some(&AttributeValue::from("Leinster")));
+        assert_eq!(customer.attribute("country_code"), Some(&AttributeValue::from("IE")));
+    }
+
+    #[test]
+    fn bundled_settlements_cover_the_customers() {
+        let reverse_geocoder = ReverseGeocoder::global();
+
+        assert_eq!(reverse_geocoder.locate(&Location::new(52.986375, -6.043701)).unwrap().to_string(), "Wicklow, IE");
+        assert_eq!(reverse_geocoder.locate(&Location::new(40.7128, -74.006)).unwrap().country_code, "US");
+    }
+
+    #[test]
+    fn cell_distance_is_never_more_than_the_distance_to_a_point_in_it() {
+        let location = Location::new(60.5, 10.5);
+        for &(latitude, longitude) in &[(62.5, 14.5), (58.2, 3.9), (70.9, -170.1), (-10.5, 10.5)] {
+            let point = Location::new(latitude, longitude);
+            assert!(cell_distance(cell_of(&point), &location) <= point.distance_from(&location));
+        }
+    }
+}
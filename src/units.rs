@@ -22,7 +22,12 @@
 //!````
 //!
 
+use std::convert::From;
+use std::error;
 use std::fmt;
+use std::str::FromStr;
+
+const KILOMETERS_PER_MILE: f64 = 1.609344f64;
 
 /// Struct representing a distance in Kilometers.
 ///
@@ -44,7 +49,7 @@ use std::fmt;
 /// let kilometers = Kilometers(10f64);
 /// ```
 ///
-#[derive(Debug, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Kilometers(pub f64);
 
 impl fmt::Display for Kilometers {
@@ -53,6 +58,113 @@ impl fmt::Display for Kilometers {
     }
 }
 
+impl From<Miles> for Kilometers {
+    fn from(miles: Miles) -> Self {
+        Kilometers(miles.0 * KILOMETERS_PER_MILE)
+    }
+}
+
+/// Struct representing a distance in statute Miles.
+///
+/// Like `Kilometers` it's a "NewType", calculations are still done in
+/// `Kilometers` and converted with `From` when a distance is read from
+/// or shown to someone used to miles.
+///
+/// # Examples
+///
+/// ```
+/// let miles = Miles::from(Kilometers(1.609344f64));
+/// assert_eq!(miles, Miles(1f64));
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Miles(pub f64);
+
+impl fmt::Display for Miles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.*} mi", 3, self.0)
+    }
+}
+
+impl From<Kilometers> for Miles {
+    fn from(kilometers: Kilometers) -> Self {
+        Miles(kilometers.0 / KILOMETERS_PER_MILE)
+    }
+}
+
+/// The units in which distances can be given and shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceUnit {
+    Kilometers,
+    Miles,
+}
+
+impl DistanceUnit {
+    /// The names accepted by `DistanceUnit::from_str`.
+    pub const NAMES: &'static [&'static str] = &["km", "mi"];
+
+    /// Returns `value`, a distance in this unit, as `Kilometers`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// assert_eq!(DistanceUnit::Miles.to_kilometers(1f64), Kilometers(1.609344f64));
+    /// ```
+    pub fn to_kilometers(self, value: f64) -> Kilometers {
+        match self {
+            DistanceUnit::Kilometers => Kilometers(value),
+            DistanceUnit::Miles => Kilometers::from(Miles(value)),
+        }
+    }
+
+    /// Formats `distance` in this unit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// assert_eq!(DistanceUnit::Miles.format(Kilometers(1.609344f64)), "1.000 mi");
+    /// ```
+    pub fn format(self, distance: Kilometers) -> String {
+        match self {
+            DistanceUnit::Kilometers => distance.to_string(),
+            DistanceUnit::Miles => Miles::from(distance).to_string(),
+        }
+    }
+}
+
+/// An error when trying to build a `DistanceUnit` from a `&str`.
+#[derive(Debug, PartialEq)]
+pub struct ParseDistanceUnitError(String);
+
+impl FromStr for DistanceUnit {
+    type Err = ParseDistanceUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "km" | "kilometers" | "kilometres" => Ok(DistanceUnit::Kilometers),
+            "mi" | "miles" => Ok(DistanceUnit::Miles),
+            other => Err(ParseDistanceUnitError(format!("unknown distance unit '{}'", other))),
+        }
+    }
+}
+
+impl fmt::Display for DistanceUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DistanceUnit::Kilometers => write!(f, "km"),
+            DistanceUnit::Miles => write!(f, "mi"),
+        }
+    }
+}
+
+impl fmt::Display for ParseDistanceUnitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Distance unit parse error: {}", self.0)
+    }
+}
+
+impl error::Error for ParseDistanceUnitError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -62,4 +174,17 @@ mod tests {
         let kms = Kilometers(10.2156789f64);
         assert_eq!("10.216 Km", format!("{}", kms));
     }
+
+    #[test]
+    fn miles_convert_to_and_from_kilometers() {
+        assert_eq!(Kilometers::from(Miles(10f64)), Kilometers(16.09344f64));
+        assert_eq!(format!("{}", Miles::from(Kilometers(16.09344f64))), "10.000 mi");
+    }
+
+    #[test]
+    fn distance_unit_from_str_parses_known_units() {
+        assert_eq!(DistanceUnit::from_str("MI"), Ok(DistanceUnit::Miles));
+        assert_eq!(DistanceUnit::from_str("kilometres"), Ok(DistanceUnit::Kilometers));
+        assert_eq!(DistanceUnit::from_str("ly"), Err(ParseDistanceUnitError(String::from("unknown distance unit 'ly'"))));
+    }
 }
\ No newline at end of file